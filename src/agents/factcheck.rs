@@ -21,6 +21,7 @@ use crate::handlers::neutralize_closing_tag;
 use crate::handlers::qa::resolve_default_text_model_for_request;
 use crate::llm::media::MediaFile;
 use crate::llm::runtime_models::runtime_model_config;
+use crate::llm::sampling::{active_sampling, with_chat_sampling};
 use crate::llm::web_search::{self, web_search_tool};
 use crate::llm::LlmAuditContext;
 use crate::utils::progress::ProgressReporter;
@@ -128,7 +129,8 @@ pub async fn run_factcheck_pipeline(
         &user_content,
         "Fact Check",
         false,
-        media_summary.total > 0,
+        CONFIG.gemini_use_pro_for("factcheck", media_summary.total > 0),
+        false,
         (!media_files.is_empty()).then(|| media_files.to_vec()),
         Some("FACTCHECK_SYNTHESIS_PROMPT"),
         audit_context,
@@ -257,6 +259,7 @@ async fn research_claims(
     let mut pending = claims.into_iter().enumerate().collect::<Vec<_>>();
     pending.reverse(); // pop() admits claims in original order
     let mut done = 0usize;
+    let sampling = active_sampling();
 
     loop {
         while join_set.len() < CONFIG.factcheck_claim_concurrency {
@@ -266,7 +269,7 @@ async fn research_claims(
             let Some((index, claim)) = pending.pop() else {
                 break;
             };
-            join_set.spawn(async move {
+            join_set.spawn(with_chat_sampling(sampling, async move {
                 let blocks = research_single_claim(&claim).await;
                 (
                     index,
@@ -275,7 +278,7 @@ async fn research_claims(
                         evidence_blocks: blocks,
                     },
                 )
-            });
+            }));
         }
 
         let Some(joined) = join_set.join_next().await else {
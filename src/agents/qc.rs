@@ -190,7 +190,8 @@ pub(super) async fn compose_final_answer(
     audit_context: Option<&LlmAuditContext>,
 ) -> Result<(String, Option<String>)> {
     if model_name == crate::handlers::qa::MODEL_GEMINI {
-        let use_pro = !media_files.is_empty() || !youtube_urls.is_empty();
+        let use_pro =
+            CONFIG.gemini_use_pro_for("qc", !media_files.is_empty() || !youtube_urls.is_empty());
         let result = call_gemini(
             system_prompt,
             user_content,
@@ -203,9 +204,12 @@ pub(super) async fn compose_final_answer(
             Some(youtube_urls.to_vec()),
             Some("QC_SYSTEM_PROMPT"),
             audit_context,
+            None,
+            None,
         )
         .await?;
-        Ok((result.text, Some(result.model_used)))
+        let model_used = result.model_display();
+        Ok((result.text, Some(model_used)))
     } else {
         let answer = call_third_party(
             system_prompt,
@@ -13,6 +13,7 @@ use crate::config::CONFIG;
 use crate::db::database::Database;
 use crate::db::models::{MessageRow, TopicWindowSpec};
 use crate::handlers::neutralize_closing_tag;
+use crate::llm::sampling::{active_sampling, with_chat_sampling};
 use crate::llm::tool_runtime::ToolRuntime;
 use crate::llm::LlmAuditContext;
 use crate::utils::progress::ProgressReporter;
@@ -579,6 +580,7 @@ fn format_topic_chunk(messages: &[MessageRow]) -> String {
                 "message_id": message.message_id,
                 "date_utc": message.date.to_rfc3339(),
                 "username": message.username.as_deref(),
+                "forward_origin": message.forward_origin.as_deref(),
                 "text": text,
                 "link": build_message_link(message.chat_id, message.message_id),
             })
@@ -646,6 +648,7 @@ async fn map_topic_chunks(
     let mut results = Vec::with_capacity(total);
     let mut join_failures = 0usize;
     let mut completed = 0usize;
+    let sampling = active_sampling();
 
     loop {
         while join_set.len() < MAX_TOPIC_MAP_CONCURRENCY {
@@ -654,7 +657,7 @@ async fn map_topic_chunks(
             };
             let step_model = step_model.clone();
             let audit_context = audit_context.cloned();
-            join_set.spawn(async move {
+            join_set.spawn(with_chat_sampling(sampling, async move {
                 let chunk_len = chunk.len();
                 let allowed = chunk
                     .iter()
@@ -680,7 +683,7 @@ async fn map_topic_chunks(
                     Err(error) => Err(error),
                 };
                 (chunk_index, chunk_len, result)
-            });
+            }));
         }
 
         if join_set.is_empty() {
@@ -915,6 +918,7 @@ mod tests {
             asks_ai: false,
             ai_command: None,
             is_synthetic_record: false,
+            forward_origin: None,
         }
     }
 
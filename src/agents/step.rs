@@ -116,6 +116,8 @@ fn resolve_step_model_value(
                     video: false,
                     audio: false,
                     tools: false,
+                    max_tokens: None,
+                    stop: Vec::new(),
                 },
                 reasoning_override: reasoning,
             });
@@ -309,6 +311,8 @@ mod tests {
             video: false,
             audio: false,
             tools: true,
+            max_tokens: None,
+            stop: Vec::new(),
         }
     }
 
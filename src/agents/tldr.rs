@@ -5,6 +5,7 @@
 //! output requirements.
 
 use anyhow::{anyhow, Result};
+use chrono_tz::Tz;
 use tracing::{info, warn};
 
 use crate::agents::step::{call_step_text, resolve_step_model, StepModel, WallClock};
@@ -42,6 +43,7 @@ struct ChunkSummary {
 /// summary text and the display name of the model that produced the merge.
 pub async fn summarize_messages_map_reduce(
     messages: &[MessageRow],
+    timezone: Tz,
     audit_context: Option<&LlmAuditContext>,
     progress: &mut ProgressReporter,
 ) -> Result<TldrOutcome> {
@@ -81,11 +83,11 @@ pub async fn summarize_messages_map_reduce(
                 "map-reduce /tldr wall-clock budget exhausted at chunk {}/{total}; degrading remaining chunks",
                 index + 1
             );
-            chunk_summaries.push(degraded_chunk_summary(chunk, "时间预算耗尽"));
+            chunk_summaries.push(degraded_chunk_summary(chunk, timezone, "时间预算耗尽"));
             continue;
         }
 
-        match summarize_chunk(&step_model, chunk, audit_context).await {
+        match summarize_chunk(&step_model, chunk, timezone, audit_context).await {
             Ok(summary) => chunk_summaries.push(ChunkSummary {
                 text: summary,
                 degraded: false,
@@ -95,7 +97,7 @@ pub async fn summarize_messages_map_reduce(
                     "map-reduce /tldr chunk {}/{total} failed; degrading to raw excerpt: {err}",
                     index + 1
                 );
-                chunk_summaries.push(degraded_chunk_summary(chunk, "自动摘要失败"));
+                chunk_summaries.push(degraded_chunk_summary(chunk, timezone, "自动摘要失败"));
             }
         }
     }
@@ -122,7 +124,8 @@ pub async fn summarize_messages_map_reduce(
         &merge_input,
         "Message Summary",
         true,
-        true,
+        CONFIG.gemini_use_pro_for("tldr", true),
+        false,
         None,
         Some("TLDR_MERGE_PROMPT"),
         audit_context,
@@ -138,9 +141,10 @@ pub async fn summarize_messages_map_reduce(
 async fn summarize_chunk(
     step_model: &StepModel,
     chunk: &[MessageRow],
+    timezone: Tz,
     audit_context: Option<&LlmAuditContext>,
 ) -> Result<String> {
-    let content = wrap_chat_history(&format_tldr_chat_content(chunk));
+    let content = wrap_chat_history(&format_tldr_chat_content(chunk, timezone));
 
     let mut last_error: Option<anyhow::Error> = None;
     for attempt in 0..2 {
@@ -171,9 +175,9 @@ async fn summarize_chunk(
 
 /// When a chunk cannot be summarized, hand the merge step a labeled raw
 /// excerpt of the chunk tail so that period of the chat is still represented.
-fn degraded_chunk_summary(chunk: &[MessageRow], reason: &str) -> ChunkSummary {
+fn degraded_chunk_summary(chunk: &[MessageRow], timezone: Tz, reason: &str) -> ChunkSummary {
     let tail_start = chunk.len().saturating_sub(DEGRADED_TAIL_MESSAGES);
-    let excerpt = format_tldr_chat_content(&chunk[tail_start..]);
+    let excerpt = format_tldr_chat_content(&chunk[tail_start..], timezone);
     ChunkSummary {
         text: format!(
             "（本段{}，以下为该段最后 {} 条原始消息节选，请直接从中提炼要点）\n{}",
@@ -231,6 +235,7 @@ mod tests {
             asks_ai: false,
             ai_command: None,
             is_synthetic_record: false,
+            forward_origin: None,
         }
     }
 
@@ -247,7 +252,7 @@ mod tests {
     #[test]
     fn degraded_summary_contains_labeled_excerpt() {
         let messages: Vec<MessageRow> = (1..=60).map(|id| message(id, "text")).collect();
-        let degraded = degraded_chunk_summary(&messages, "自动摘要失败");
+        let degraded = degraded_chunk_summary(&messages, Tz::UTC, "自动摘要失败");
         assert!(degraded.degraded);
         assert!(degraded.text.contains("自动摘要失败"));
         assert!(degraded.text.contains("30 条原始消息节选"));
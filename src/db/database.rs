@@ -1,7 +1,8 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::Path;
+use std::str::FromStr;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -9,18 +10,24 @@ use std::sync::{
 use std::time::Duration;
 
 use crate::config::CONFIG;
+use crate::db::migrations::run_migrations;
 use crate::db::models::{
-    AnalyticsRow, ChatSearchHit, LlmInvocationInsert, LlmRequestInsert, MessageInsert, MessageRow,
-    ModelTokenStat, TokenUserStat, TopicWindow, TopicWindowSpec,
+    AnalyticsRow, ChatSearchHit, ChatSettingsRow, LlmInvocationInsert, LlmRequestInsert,
+    MessageInsert, MessageRow, ModelTokenStat, ModelUsageStat, TokenUserStat, TopicWindow,
+    TopicWindowSpec, UserActivityStats, UserDailyUsage,
 };
 use crate::db::search::{
     clean_text_for_display, normalize_message_document, normalize_search_query, SearchMatchStage,
     SearchProvenance, CURRENT_SEARCH_SCHEMA_VERSION, SEARCH_INDEX_REBUILDING_ERROR,
 };
+use crate::db::store::Store;
+use crate::llm::audit::LLM_TRIGGER_KIND_TRANSCRIPTION;
+use crate::utils::redaction::redact_for_storage;
 use crate::utils::telegram::build_message_link;
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use serde::Serialize;
-use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
 use sqlx::{FromRow, SqlitePool};
 use teloxide::types::Message;
 use tokio::sync::mpsc;
@@ -34,12 +41,32 @@ const SEARCH_REBUILD_BATCH_SIZE: i64 = 5_000;
 const SEARCH_INDEX_META_KEY: &str = "search_index_schema_version";
 const TOKEN_TOTAL_EXPR: &str = "COALESCE(r.total_tokens, r.input_tokens + r.output_tokens, 0)";
 const DB_WRITE_RETRY_DELAY_MS: u64 = 100;
+const CHAT_SETTINGS_COLUMNS: &str = "chat_id, digest_enabled, digest_hour, digest_last_sent_on, \
+     telegraph_author_name, telegraph_author_url, pinned_summary_message_id, \
+     extract_youtube, extract_twitter, extract_telegraph, disabled_commands, \
+     temperature, top_p, timezone, quiet_mode";
+const USER_ACTIVITY_TOP_HOURS: i64 = 3;
 const DB_WRITE_DEAD_LETTER_PATH: &str = "data/db_writer_dead_letters.jsonl";
 
 fn topic_window_is_capped(total_eligible: i64, selected_messages: usize) -> bool {
     total_eligible > selected_messages as i64
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: usize,
+    pub in_use: usize,
+    pub max: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PragmaState {
+    pub journal_mode: String,
+    pub synchronous: &'static str,
+    pub busy_timeout_ms: i64,
+}
+
 #[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
@@ -75,8 +102,13 @@ struct RebuildRow {
 }
 
 #[derive(Debug, Clone, FromRow)]
-struct TableInfoRow {
-    name: String,
+struct UserActivityTotalsRow {
+    username: Option<String>,
+    message_count: i64,
+    command_count: i64,
+    ai_request_count: i64,
+    first_seen: Option<DateTime<Utc>>,
+    last_seen: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone)]
@@ -125,33 +157,30 @@ fn build_snippet(text: &str, terms: &[String]) -> String {
 
 impl Database {
     pub async fn init(database_url: &str) -> Result<Self> {
+        Self::init_with_pool_size(database_url, CONFIG.db_max_connections).await
+    }
+
+    pub async fn init_with_pool_size(database_url: &str, max_connections: u32) -> Result<Self> {
+        // Pragmas other than journal_mode are per connection, so they go on the
+        // connect options rather than a one-off query against the pool.
+        let connect_options = SqliteConnectOptions::from_str(database_url)?
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(Duration::from_millis(5_000))
+            .pragma("cache_size", "-65536")
+            .pragma("mmap_size", "134217728")
+            .foreign_keys(true);
         let pool = SqlitePoolOptions::new()
-            .max_connections(CONFIG.db_max_connections)
-            .connect(database_url)
+            .max_connections(max_connections.max(1))
+            .connect_with(connect_options)
             .await?;
         let search_ready = Arc::new(AtomicBool::new(false));
 
-        sqlx::query("PRAGMA journal_mode = WAL")
-            .execute(&pool)
-            .await?;
-        sqlx::query("PRAGMA synchronous = NORMAL")
-            .execute(&pool)
-            .await?;
-        sqlx::query("PRAGMA busy_timeout = 5000")
-            .execute(&pool)
-            .await?;
-        sqlx::query("PRAGMA cache_size = -65536")
-            .execute(&pool)
-            .await?;
-        sqlx::query("PRAGMA mmap_size = 134217728")
-            .execute(&pool)
-            .await?;
-        sqlx::query("PRAGMA foreign_keys = ON")
-            .execute(&pool)
-            .await?;
         ensure_messages_schema(&pool).await?;
         ensure_search_support_schema(&pool).await?;
         ensure_llm_audit_schema(&pool).await?;
+        ensure_chat_settings_schema(&pool).await?;
+        run_migrations(&pool).await?;
         sqlx::query("PRAGMA optimize").execute(&pool).await?;
 
         let schema_version = current_search_schema_version(&pool).await?;
@@ -266,6 +295,12 @@ impl Database {
     }
 
     pub async fn insert_llm_request(&self, insert: LlmRequestInsert) -> Result<()> {
+        let invocation_id = insert.invocation_id;
+        let usage_day = insert.completed_at.format("%Y-%m-%d").to_string();
+        let usage_tokens = insert
+            .total_tokens
+            .or_else(|| Some(insert.input_tokens? + insert.output_tokens?))
+            .unwrap_or(0);
         sqlx::query(
             "INSERT INTO llm_requests (\
                  invocation_id, \
@@ -301,9 +336,55 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        sqlx::query(
+            "INSERT INTO user_usage (user_id, day, total_tokens, request_count) \
+             SELECT user_id, ?, ?, 1 FROM llm_invocations \
+             WHERE id = ? AND user_id IS NOT NULL AND trigger_kind != ? \
+             ON CONFLICT(user_id, day) DO UPDATE SET \
+                 total_tokens = user_usage.total_tokens + excluded.total_tokens, \
+                 request_count = user_usage.request_count + 1",
+        )
+        .bind(usage_day)
+        .bind(usage_tokens)
+        .bind(invocation_id)
+        .bind(LLM_TRIGGER_KIND_TRANSCRIPTION)
+        .execute(&self.pool)
+        .await?;
+
         Ok(())
     }
 
+    /// One user's daily token rollups from `since` (a `YYYY-MM-DD` UTC day)
+    /// onward, newest first.
+    pub async fn select_user_daily_usage(
+        &self,
+        user_id: i64,
+        since: &str,
+    ) -> Result<Vec<UserDailyUsage>> {
+        sqlx::query_as::<_, UserDailyUsage>(
+            "SELECT day, total_tokens, request_count FROM user_usage \
+             WHERE user_id = ? AND day >= ? \
+             ORDER BY day DESC",
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(Into::into)
+    }
+
+    pub async fn select_user_tokens_for_day(&self, user_id: i64, day: &str) -> Result<i64> {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COALESCE(SUM(total_tokens), 0) FROM user_usage \
+             WHERE user_id = ? AND day = ?",
+        )
+        .bind(user_id)
+        .bind(day)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(Into::into)
+    }
+
     pub async fn select_chat_token_total_for_user(
         &self,
         chat_id: i64,
@@ -324,6 +405,89 @@ impl Database {
             .map_err(Into::into)
     }
 
+    /// Counts and first/last timestamps for one user's messages in a chat,
+    /// plus their busiest UTC hours. Synthetic records (bot replies stored
+    /// on the user's behalf) are excluded.
+    pub async fn user_activity_stats(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<UserActivityStats> {
+        let totals = sqlx::query_as::<_, UserActivityTotalsRow>(
+            "SELECT \
+                 ( \
+                     SELECT m.username FROM messages m \
+                     WHERE m.chat_id = ? AND m.user_id = ? AND m.username IS NOT NULL \
+                     ORDER BY m.date DESC, m.message_id DESC \
+                     LIMIT 1 \
+                 ) AS username, \
+                 COUNT(*) AS message_count, \
+                 COALESCE(SUM(is_command), 0) AS command_count, \
+                 COALESCE(SUM(asks_ai), 0) AS ai_request_count, \
+                 MIN(date) AS first_seen, \
+                 MAX(date) AS last_seen \
+             FROM messages \
+             WHERE chat_id = ? AND user_id = ? AND is_synthetic_record = 0",
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(chat_id)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let active_hours = sqlx::query_as::<_, (i64, i64)>(&format!(
+            "SELECT {} AS hour, COUNT(*) AS message_count \
+             FROM messages \
+             WHERE chat_id = ? AND user_id = ? AND is_synthetic_record = 0 \
+             GROUP BY hour \
+             ORDER BY message_count DESC, hour ASC \
+             LIMIT ?",
+            self.dialect().hour_of_day("date")
+        ))
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(USER_ACTIVITY_TOP_HOURS)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .filter_map(|(hour, count)| u32::try_from(hour).ok().map(|hour| (hour, count)))
+        .collect();
+
+        Ok(UserActivityStats {
+            user_id,
+            username: totals.username,
+            message_count: totals.message_count,
+            command_count: totals.command_count,
+            ai_request_count: totals.ai_request_count,
+            first_seen: totals.first_seen,
+            last_seen: totals.last_seen,
+            active_hours,
+        })
+    }
+
+    /// Resolves a display name or `@handle` to the most recent matching
+    /// sender in the chat. Names are compared case-insensitively.
+    pub async fn find_chat_user_id_by_name(&self, chat_id: i64, name: &str) -> Result<Option<i64>> {
+        let name = name.trim().trim_start_matches('@');
+        if name.is_empty() {
+            return Ok(None);
+        }
+        sqlx::query_scalar::<_, i64>(
+            "SELECT user_id FROM messages \
+             WHERE chat_id = ? AND user_id IS NOT NULL \
+               AND (LOWER(username) = LOWER(?) OR LOWER(username) = LOWER(?)) \
+             ORDER BY date DESC, message_id DESC \
+             LIMIT 1",
+        )
+        .bind(chat_id)
+        .bind(name)
+        .bind(format!("@{name}"))
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Into::into)
+    }
+
     pub async fn select_top_chat_token_users(
         &self,
         chat_id: i64,
@@ -439,7 +603,7 @@ impl Database {
         let select_sql = format!(
             "SELECT m.id, m.message_id, m.chat_id, m.user_id, m.username, m.text, \
              m.language, m.date, m.reply_to_message_id, m.asks_ai, m.ai_command, \
-             m.is_synthetic_record{where_sql} ORDER BY m.date DESC, m.message_id DESC LIMIT ?"
+             m.is_synthetic_record, m.forward_origin{where_sql} ORDER BY m.date DESC, m.message_id DESC LIMIT ?"
         );
         let mut select_query = sqlx::query_as::<_, MessageRow>(&select_sql)
             .bind(chat_id)
@@ -523,6 +687,62 @@ impl Database {
             .map_err(Into::into)
     }
 
+    /// Input/output token sums per provider and model, optionally scoped to a
+    /// chat and to requests completed at or after `since`. Used for cost estimates.
+    pub async fn select_token_usage_by_model(
+        &self,
+        chat_id: Option<i64>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelUsageStat>> {
+        let mut query = String::from(
+            "SELECT \
+                 r.provider AS provider, \
+                 r.model AS model, \
+                 COALESCE(SUM(r.input_tokens), 0) AS input_tokens, \
+                 COALESCE(SUM(r.output_tokens), 0) AS output_tokens \
+             FROM llm_requests r \
+             JOIN llm_invocations i ON i.id = r.invocation_id \
+             WHERE 1 = 1",
+        );
+        if chat_id.is_some() {
+            query.push_str(" AND i.chat_id = ?");
+        }
+        if since.is_some() {
+            query.push_str(" AND r.completed_at >= ?");
+        }
+        query.push_str(" GROUP BY r.provider, r.model ORDER BY r.provider ASC, r.model ASC");
+
+        let mut statement = sqlx::query_as::<_, ModelUsageStat>(&query);
+        if let Some(chat_id) = chat_id {
+            statement = statement.bind(chat_id);
+        }
+        if let Some(since) = since {
+            statement = statement.bind(since);
+        }
+        statement.fetch_all(&self.pool).await.map_err(Into::into)
+    }
+
+    pub async fn select_invocation_token_usage(
+        &self,
+        invocation_id: i64,
+    ) -> Result<Vec<ModelUsageStat>> {
+        sqlx::query_as::<_, ModelUsageStat>(
+            "SELECT \
+                 r.provider AS provider, \
+                 r.model AS model, \
+                 COALESCE(SUM(r.input_tokens), 0) AS input_tokens, \
+                 COALESCE(SUM(r.output_tokens), 0) AS output_tokens \
+             FROM llm_requests r \
+             WHERE r.invocation_id = ? \
+             GROUP BY r.provider, r.model \
+             ORDER BY r.provider ASC, r.model ASC",
+        )
+        .bind(invocation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(Into::into)
+    }
+
     pub fn queue_max_capacity(&self) -> usize {
         self.sender.max_capacity()
     }
@@ -536,12 +756,55 @@ impl Database {
             .saturating_sub(self.queue_available_capacity())
     }
 
+    pub fn pool_stats(&self) -> PoolStats {
+        let size = self.pool.size();
+        let idle = self.pool.num_idle();
+        PoolStats {
+            size,
+            idle,
+            in_use: (size as usize).saturating_sub(idle),
+            max: self.pool.options().get_max_connections(),
+        }
+    }
+
+    /// Journal mode, synchronous level, and busy timeout as SQLite reports
+    /// them on one pooled connection, to confirm the pragmas from `init`.
+    pub async fn pragma_state(&self) -> Result<PragmaState> {
+        let mut connection = self.pool.acquire().await?;
+        let journal_mode = sqlx::query_scalar::<_, String>("PRAGMA journal_mode")
+            .fetch_one(&mut *connection)
+            .await?;
+        let synchronous = sqlx::query_scalar::<_, i64>("PRAGMA synchronous")
+            .fetch_one(&mut *connection)
+            .await?;
+        let busy_timeout_ms = sqlx::query_scalar::<_, i64>("PRAGMA busy_timeout")
+            .fetch_one(&mut *connection)
+            .await?;
+        Ok(PragmaState {
+            journal_mode,
+            synchronous: match synchronous {
+                0 => "off",
+                1 => "normal",
+                2 => "full",
+                3 => "extra",
+                _ => "unknown",
+            },
+            busy_timeout_ms,
+        })
+    }
+
     pub fn is_search_ready(&self) -> bool {
         self.search_ready.load(Ordering::Relaxed)
     }
 
-    pub async fn select_messages(&self, chat_id: i64, limit: i64) -> Result<Vec<MessageRow>> {
-        self.get_last_n_text_messages(chat_id, limit, true).await
+    pub async fn select_messages(
+        &self,
+        chat_id: i64,
+        limit: i64,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<MessageRow>> {
+        self.get_last_n_text_messages(chat_id, limit, true, since)
+            .await
     }
 
     pub async fn select_messages_by_user(
@@ -552,7 +815,7 @@ impl Database {
         exclude_commands: bool,
     ) -> Result<Vec<MessageRow>> {
         let mut query = String::from(
-            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record \
+            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record, forward_origin \
              FROM messages WHERE chat_id = ? AND user_id = ? AND text IS NOT NULL",
         );
         if exclude_commands {
@@ -574,8 +837,106 @@ impl Database {
         &self,
         chat_id: i64,
         message_id: i64,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<MessageRow>> {
+        self.get_messages_from_id(chat_id, message_id, true, since)
+            .await
+    }
+
+    /// Non-command text messages dated before `before`, optionally only those
+    /// at or after `from_message_id`. Used to tell users an age cutoff applied.
+    pub async fn count_text_messages_before(
+        &self,
+        chat_id: i64,
+        before: DateTime<Utc>,
+        from_message_id: Option<i64>,
+    ) -> Result<i64> {
+        let mut query = String::from(
+            "SELECT COUNT(*) FROM messages \
+             WHERE chat_id = ? AND date < ? AND text IS NOT NULL AND text NOT LIKE '/%'",
+        );
+        if from_message_id.is_some() {
+            query.push_str(" AND message_id >= ?");
+        }
+        let mut statement = sqlx::query_scalar::<_, i64>(&query)
+            .bind(chat_id)
+            .bind(before);
+        if let Some(from_message_id) = from_message_id {
+            statement = statement.bind(from_message_id);
+        }
+        statement.fetch_one(&self.pool).await.map_err(Into::into)
+    }
+
+    pub async fn select_messages_since(
+        &self,
+        chat_id: i64,
+        since: DateTime<Utc>,
+        limit: i64,
     ) -> Result<Vec<MessageRow>> {
-        self.get_messages_from_id(chat_id, message_id, true).await
+        let rows = sqlx::query_as::<_, MessageRow>(
+            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record, forward_origin \
+             FROM messages WHERE chat_id = ? AND date >= ? AND text IS NOT NULL AND text NOT LIKE '/%' \
+             ORDER BY date DESC LIMIT ?",
+        )
+        .bind(chat_id)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().rev().collect())
+    }
+
+    /// Every stored chat settings row, for the in-memory cache loaded at
+    /// startup.
+    pub async fn select_chat_settings(&self) -> Result<Vec<ChatSettingsRow>> {
+        sqlx::query_as::<_, ChatSettingsRow>(&format!(
+            "SELECT {CHAT_SETTINGS_COLUMNS} FROM chat_settings ORDER BY chat_id ASC"
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Writes every column of `settings`, inserting the chat's row if needed.
+    pub async fn upsert_chat_settings(&self, settings: &ChatSettingsRow) -> Result<()> {
+        sqlx::query(&format!(
+            "INSERT INTO chat_settings({CHAT_SETTINGS_COLUMNS}) \
+             VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT(chat_id) DO UPDATE SET \
+                 digest_enabled = excluded.digest_enabled, \
+                 digest_hour = excluded.digest_hour, \
+                 digest_last_sent_on = excluded.digest_last_sent_on, \
+                 telegraph_author_name = excluded.telegraph_author_name, \
+                 telegraph_author_url = excluded.telegraph_author_url, \
+                 pinned_summary_message_id = excluded.pinned_summary_message_id, \
+                 extract_youtube = excluded.extract_youtube, \
+                 extract_twitter = excluded.extract_twitter, \
+                 extract_telegraph = excluded.extract_telegraph, \
+                 disabled_commands = excluded.disabled_commands, \
+                 temperature = excluded.temperature, \
+                 top_p = excluded.top_p, \
+                 timezone = excluded.timezone, \
+                 quiet_mode = excluded.quiet_mode"
+        ))
+        .bind(settings.chat_id)
+        .bind(settings.digest_enabled)
+        .bind(settings.digest_hour)
+        .bind(&settings.digest_last_sent_on)
+        .bind(&settings.telegraph_author_name)
+        .bind(&settings.telegraph_author_url)
+        .bind(settings.pinned_summary_message_id)
+        .bind(settings.extract_youtube)
+        .bind(settings.extract_twitter)
+        .bind(settings.extract_telegraph)
+        .bind(&settings.disabled_commands)
+        .bind(settings.temperature)
+        .bind(settings.top_p)
+        .bind(&settings.timezone)
+        .bind(settings.quiet_mode)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
     }
 
     pub async fn get_last_n_text_messages(
@@ -583,21 +944,25 @@ impl Database {
         chat_id: i64,
         limit: i64,
         exclude_commands: bool,
+        since: Option<DateTime<Utc>>,
     ) -> Result<Vec<MessageRow>> {
         let mut query = String::from(
-            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record \
+            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record, forward_origin \
              FROM messages WHERE chat_id = ? AND text IS NOT NULL",
         );
         if exclude_commands {
             query.push_str(" AND text NOT LIKE '/%'");
         }
+        if since.is_some() {
+            query.push_str(" AND date >= ?");
+        }
         query.push_str(" ORDER BY date DESC LIMIT ?");
 
-        let rows = sqlx::query_as::<_, MessageRow>(&query)
-            .bind(chat_id)
-            .bind(limit)
-            .fetch_all(&self.pool)
-            .await?;
+        let mut statement = sqlx::query_as::<_, MessageRow>(&query).bind(chat_id);
+        if let Some(since) = since {
+            statement = statement.bind(since);
+        }
+        let rows = statement.bind(limit).fetch_all(&self.pool).await?;
 
         Ok(rows.into_iter().rev().collect())
     }
@@ -607,21 +972,27 @@ impl Database {
         chat_id: i64,
         from_message_id: i64,
         exclude_commands: bool,
+        since: Option<DateTime<Utc>>,
     ) -> Result<Vec<MessageRow>> {
         let mut query = String::from(
-            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record \
+            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record, forward_origin \
              FROM messages WHERE chat_id = ? AND message_id >= ? AND text IS NOT NULL",
         );
         if exclude_commands {
             query.push_str(" AND text NOT LIKE '/%'");
         }
+        if since.is_some() {
+            query.push_str(" AND date >= ?");
+        }
         query.push_str(" ORDER BY date DESC");
 
-        let rows = sqlx::query_as::<_, MessageRow>(&query)
+        let mut statement = sqlx::query_as::<_, MessageRow>(&query)
             .bind(chat_id)
-            .bind(from_message_id)
-            .fetch_all(&self.pool)
-            .await?;
+            .bind(from_message_id);
+        if let Some(since) = since {
+            statement = statement.bind(since);
+        }
+        let rows = statement.fetch_all(&self.pool).await?;
 
         Ok(rows.into_iter().rev().collect())
     }
@@ -719,32 +1090,12 @@ impl Database {
         match_stage: SearchMatchStage,
         snippet_terms: &[String],
     ) -> Result<Vec<StageHit>> {
-        let rows = sqlx::query_as::<_, SearchRow>(
-            "SELECT \
-                 m.id, \
-                 m.message_id, \
-                 m.chat_id, \
-                 m.user_id, \
-                 m.username, \
-                 m.text, \
-                 m.language, \
-                 m.date, \
-                 m.reply_to_message_id, \
-                 m.asks_ai, \
-                 m.ai_command, \
-                 m.is_synthetic_record, \
-                 bm25(messages_fts, 1.0, 0.2) AS score \
-             FROM messages_fts \
-             JOIN messages m ON m.id = messages_fts.rowid \
-             WHERE m.chat_id = ? AND messages_fts MATCH ? \
-             ORDER BY score ASC, m.date DESC, m.message_id DESC \
-             LIMIT ?",
-        )
-        .bind(chat_id)
-        .bind(stage_query)
-        .bind(limit)
-        .fetch_all(&self.pool)
-        .await?;
+        let rows = sqlx::query_as::<_, SearchRow>(self.dialect().search_stage_sql())
+            .bind(chat_id)
+            .bind(stage_query)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
 
         Ok(rows
             .into_iter()
@@ -789,7 +1140,7 @@ impl Database {
         let context_after = context_after.clamp(0, WINDOW_LIMIT_MAX);
 
         let center = sqlx::query_as::<_, MessageRow>(
-            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record \
+            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record, forward_origin \
              FROM messages \
              WHERE chat_id = ? AND message_id = ? AND text IS NOT NULL",
         )
@@ -803,7 +1154,7 @@ impl Database {
         };
 
         let mut before = sqlx::query_as::<_, MessageRow>(
-            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record \
+            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record, forward_origin \
              FROM messages \
              WHERE chat_id = ? AND message_id < ? AND text IS NOT NULL \
              ORDER BY message_id DESC LIMIT ?",
@@ -816,7 +1167,7 @@ impl Database {
         before.reverse();
 
         let after = sqlx::query_as::<_, MessageRow>(
-            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record \
+            "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record, forward_origin \
              FROM messages \
              WHERE chat_id = ? AND message_id > ? AND text IS NOT NULL \
              ORDER BY message_id ASC LIMIT ?",
@@ -833,6 +1184,56 @@ impl Database {
         Ok(Some(messages))
     }
 
+    /// Up to `window` stored text messages on each side of `message_id`,
+    /// oldest first and including the message itself. Empty when the message
+    /// is not stored.
+    pub async fn get_messages_around(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        window: i64,
+    ) -> Result<Vec<MessageRow>> {
+        Ok(self
+            .get_message_window(chat_id, message_id, window, window)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Follows `reply_to_message_id` upwards from `message_id`, returning at
+    /// most `max_messages` stored rows oldest first. The walk stops at the
+    /// first message that is not stored or replies to nothing.
+    pub async fn get_reply_thread(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        max_messages: usize,
+    ) -> Result<Vec<MessageRow>> {
+        let mut thread = Vec::new();
+        let mut seen = HashSet::new();
+        let mut next = Some(message_id);
+        while let Some(current) = next {
+            if thread.len() >= max_messages || !seen.insert(current) {
+                break;
+            }
+            let row = sqlx::query_as::<_, MessageRow>(
+                "SELECT id, message_id, chat_id, user_id, username, text, language, date, reply_to_message_id, asks_ai, ai_command, is_synthetic_record, forward_origin \
+                 FROM messages \
+                 WHERE chat_id = ? AND message_id = ?",
+            )
+            .bind(chat_id)
+            .bind(current)
+            .fetch_optional(&self.pool)
+            .await?;
+            let Some(row) = row else {
+                break;
+            };
+            next = row.reply_to_message_id;
+            thread.push(row);
+        }
+        thread.reverse();
+        Ok(thread)
+    }
+
     #[allow(dead_code)]
     pub fn pool(&self) -> &SqlitePool {
         &self.pool
@@ -994,20 +1395,14 @@ async fn ensure_messages_schema(pool: &SqlitePool) -> Result<()> {
             asks_ai INTEGER NOT NULL DEFAULT 0,\
             ai_command TEXT,\
             is_synthetic_record INTEGER NOT NULL DEFAULT 0,\
+            is_redacted INTEGER NOT NULL DEFAULT 0,\
+            forward_origin TEXT,\
             UNIQUE(chat_id, message_id)\
         );",
     )
     .execute(pool)
     .await?;
 
-    ensure_messages_column(pool, "search_text", "TEXT").await?;
-    ensure_messages_column(pool, "search_tags", "TEXT").await?;
-    ensure_messages_column(pool, "search_version", "INTEGER NOT NULL DEFAULT 0").await?;
-    ensure_messages_column(pool, "is_command", "INTEGER NOT NULL DEFAULT 0").await?;
-    ensure_messages_column(pool, "asks_ai", "INTEGER NOT NULL DEFAULT 0").await?;
-    ensure_messages_column(pool, "ai_command", "TEXT").await?;
-    ensure_messages_column(pool, "is_synthetic_record", "INTEGER NOT NULL DEFAULT 0").await?;
-
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_chat_id ON messages(chat_id);")
         .execute(pool)
         .await?;
@@ -1117,25 +1512,59 @@ async fn ensure_llm_audit_schema(pool: &SqlitePool) -> Result<()> {
     )
     .execute(pool)
     .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS user_usage (\
+            user_id INTEGER NOT NULL,\
+            day TEXT NOT NULL,\
+            total_tokens INTEGER NOT NULL DEFAULT 0,\
+            request_count INTEGER NOT NULL DEFAULT 0,\
+            PRIMARY KEY (user_id, day)\
+        );",
+    )
+    .execute(pool)
+    .await?;
+    // Databases that predate the rollup table start from their request history.
+    let rollups_empty = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM user_usage")
+        .fetch_one(pool)
+        .await?
+        == 0;
+    if rollups_empty {
+        sqlx::query(&format!(
+            "INSERT INTO user_usage (user_id, day, total_tokens, request_count) \
+             SELECT i.user_id, substr(r.completed_at, 1, 10), SUM({TOKEN_TOTAL_EXPR}), COUNT(*) \
+             FROM llm_requests r \
+             JOIN llm_invocations i ON i.id = r.invocation_id \
+             WHERE i.user_id IS NOT NULL AND i.trigger_kind != ? \
+             GROUP BY i.user_id, substr(r.completed_at, 1, 10)"
+        ))
+        .bind(LLM_TRIGGER_KIND_TRANSCRIPTION)
+        .execute(pool)
+        .await?;
+    }
 
     Ok(())
 }
 
-async fn ensure_messages_column(
-    pool: &SqlitePool,
-    column_name: &str,
-    column_sql: &str,
-) -> Result<()> {
-    let columns = sqlx::query_as::<_, TableInfoRow>("PRAGMA table_info(messages)")
-        .fetch_all(pool)
-        .await?;
-    if columns.iter().any(|column| column.name == column_name) {
-        return Ok(());
-    }
-
-    sqlx::query(&format!(
-        "ALTER TABLE messages ADD COLUMN {column_name} {column_sql}"
-    ))
+async fn ensure_chat_settings_schema(pool: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS chat_settings (\
+            chat_id INTEGER PRIMARY KEY,\
+            digest_enabled INTEGER NOT NULL DEFAULT 0,\
+            digest_hour INTEGER NOT NULL DEFAULT 9,\
+            digest_last_sent_on TEXT,\
+            telegraph_author_name TEXT,\
+            telegraph_author_url TEXT,\
+            pinned_summary_message_id INTEGER,\
+            extract_youtube INTEGER NOT NULL DEFAULT 1,\
+            extract_twitter INTEGER NOT NULL DEFAULT 1,\
+            extract_telegraph INTEGER NOT NULL DEFAULT 1,\
+            disabled_commands TEXT,\
+            temperature REAL,\
+            top_p REAL,\
+            timezone TEXT,\
+            quiet_mode INTEGER\
+        );",
+    )
     .execute(pool)
     .await?;
     Ok(())
@@ -1410,8 +1839,10 @@ async fn write_message_batch(pool: &SqlitePool, batch: &[MessageInsert]) -> Resu
                  is_command, \
                  asks_ai, \
                  ai_command, \
-                 is_synthetic_record\
-             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) \
+                 is_synthetic_record, \
+                 is_redacted, \
+                 forward_origin\
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) \
              ON CONFLICT(chat_id, message_id) DO UPDATE SET \
              user_id = excluded.user_id, \
              username = excluded.username, \
@@ -1425,7 +1856,9 @@ async fn write_message_batch(pool: &SqlitePool, batch: &[MessageInsert]) -> Resu
              is_command = excluded.is_command, \
              asks_ai = excluded.asks_ai, \
              ai_command = excluded.ai_command, \
-             is_synthetic_record = excluded.is_synthetic_record",
+             is_synthetic_record = excluded.is_synthetic_record, \
+             is_redacted = excluded.is_redacted, \
+             forward_origin = excluded.forward_origin",
         )
         .bind(message.message_id)
         .bind(message.chat_id)
@@ -1442,6 +1875,8 @@ async fn write_message_batch(pool: &SqlitePool, batch: &[MessageInsert]) -> Resu
         .bind(document.provenance.asks_ai)
         .bind(document.provenance.ai_command)
         .bind(document.provenance.is_synthetic_record)
+        .bind(message.is_redacted)
+        .bind(message.forward_origin.clone())
         .execute(&mut *tx)
         .await?;
     }
@@ -1467,6 +1902,8 @@ pub fn build_message_insert(
 ) -> MessageInsert {
     let resolved_user_id = user_id.unwrap_or_default();
     let resolved_chat_id = chat_id.unwrap_or(resolved_user_id);
+    let (text, text_redacted) = redact_for_storage(text);
+    let (search_source_text, search_source_redacted) = redact_for_storage(search_source_text);
     MessageInsert {
         message_id: message_id.unwrap_or_default(),
         chat_id: resolved_chat_id,
@@ -1481,6 +1918,8 @@ pub fn build_message_insert(
         ai_command,
         is_command,
         is_synthetic_record,
+        is_redacted: text_redacted || search_source_redacted,
+        forward_origin: None,
     }
 }
 
@@ -1490,6 +1929,7 @@ mod tests {
     use crate::db::models::{
         LlmInvocationInsert, LlmInvocationRow, LlmRequestInsert, LlmRequestRow, TopicWindowSpec,
     };
+    use crate::db::store::SqlDialect;
     use chrono::Utc;
     use std::path::PathBuf;
     use std::sync::atomic::Ordering;
@@ -1550,6 +1990,38 @@ mod tests {
         panic!("message row did not become visible in time");
     }
 
+    #[tokio::test]
+    async fn fresh_database_records_every_schema_migration() {
+        let db = init_test_db("schema-version").await;
+        let latest = crate::db::migrations::MIGRATIONS
+            .last()
+            .map(|migration| migration.version)
+            .unwrap_or_default();
+        assert_eq!(
+            crate::db::migrations::current_schema_version(db.pool())
+                .await
+                .expect("schema version should load"),
+            latest
+        );
+    }
+
+    #[tokio::test]
+    async fn configured_pool_size_is_applied() {
+        let path = test_db_path("pool-size");
+        let db = Database::init_with_pool_size(&sqlite_url_for_path(&path), 3)
+            .await
+            .expect("test database should initialize");
+        let stats = db.pool_stats();
+        assert_eq!(stats.max, 3);
+        assert!(stats.size <= 3);
+        assert_eq!(stats.in_use + stats.idle, stats.size as usize);
+
+        let pragmas = db.pragma_state().await.expect("pragmas should be readable");
+        assert_eq!(pragmas.journal_mode, "wal");
+        assert_eq!(pragmas.synchronous, "normal");
+        assert_eq!(pragmas.busy_timeout_ms, 5000);
+    }
+
     #[tokio::test]
     async fn failed_message_batches_are_dead_lettered() {
         let path = test_db_path("db-writer-dead-letter");
@@ -1590,6 +2062,46 @@ mod tests {
         assert!(dead_letter.contains("\"chat_id\":-1001374348669"));
     }
 
+    #[tokio::test]
+    async fn forward_origin_is_stored_apart_from_the_text() {
+        let db = init_test_db("forward-origin").await;
+        let chat_id = -1001470000001_i64;
+        let mut insert = build_message_insert(
+            Some(123_i64),
+            Some("alice".to_string()),
+            Some("Prices double tomorrow".to_string()),
+            Some("en".to_string()),
+            Utc::now(),
+            None,
+            Some(chat_id),
+            Some(1),
+            None,
+            false,
+            None,
+            false,
+            false,
+        );
+        insert.forward_origin = Some("forwarded from channel \"Daily News\"".to_string());
+        db.queue_message_insert(insert)
+            .await
+            .expect("message queue should succeed");
+        wait_for_message_row(&db, chat_id, 1).await;
+        queue_message(&db, 2, chat_id, "bob", "not forwarded").await;
+
+        let rows = db
+            .select_messages(chat_id, 10, None)
+            .await
+            .expect("selection should succeed");
+        let forwarded = rows.iter().find(|row| row.message_id == 1).unwrap();
+        assert_eq!(forwarded.text.as_deref(), Some("Prices double tomorrow"));
+        assert_eq!(
+            forwarded.forward_origin.as_deref(),
+            Some("forwarded from channel \"Daily News\"")
+        );
+        let plain = rows.iter().find(|row| row.message_id == 2).unwrap();
+        assert_eq!(plain.forward_origin, None);
+    }
+
     #[tokio::test]
     async fn llm_audit_rows_persist_and_link() {
         let db = init_test_db("llm-audit").await;
@@ -1790,6 +2302,56 @@ mod tests {
         );
     }
 
+    async fn history_through_store<S: Store>(
+        store: &S,
+        chat_id: i64,
+    ) -> (Vec<MessageRow>, Vec<ChatSearchHit>, Option<Vec<MessageRow>>) {
+        let thread = store
+            .get_reply_thread(chat_id, 3, 10)
+            .await
+            .expect("reply thread should load");
+        let hits = store
+            .search_chat_messages(chat_id, "lighthouse", 10, 0)
+            .await
+            .expect("search should succeed");
+        let window = store
+            .get_message_window(chat_id, 2, 1, 1)
+            .await
+            .expect("window should load");
+        (thread, hits, window)
+    }
+
+    #[tokio::test]
+    async fn sqlite_store_serves_threads_search_and_windows_through_the_trait() {
+        let db = init_test_db("store-trait").await;
+        assert_eq!(db.dialect(), SqlDialect::Sqlite);
+        queue_message(&db, 1, -1001850001, "alice", "The lighthouse keeper waved").await;
+        queue_message(&db, 2, -1001850001, "bob", "/q what time is it").await;
+        queue_message(
+            &db,
+            3,
+            -1001850001,
+            "carol",
+            "Lighthouse tours start at noon",
+        )
+        .await;
+        queue_message(&db, 4, -1001850002, "dave", "Another lighthouse elsewhere").await;
+
+        let (thread, hits, window) = history_through_store(&db, -1001850001).await;
+
+        let thread_ids = thread.iter().map(|row| row.message_id).collect::<Vec<_>>();
+        assert_eq!(thread_ids, vec![3]);
+        let mut hit_ids = hits.iter().map(|hit| hit.message_id).collect::<Vec<_>>();
+        hit_ids.sort_unstable();
+        assert_eq!(hit_ids, vec![1, 3]);
+        let window_ids = window
+            .expect("message 2 should be stored")
+            .iter()
+            .map(|row| row.message_id)
+            .collect::<Vec<_>>();
+        assert_eq!(window_ids, vec![1, 2, 3]);
+    }
+
     #[tokio::test]
     async fn search_chat_messages_stays_within_the_requested_chat() {
         let db = init_test_db("chat-scope").await;
@@ -1826,8 +2388,84 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn staged_retrieval_orders_phrase_then_and_then_or_prefix() {
-        let db = init_test_db("stage-order").await;
+    async fn get_messages_around_returns_neighbours_on_both_sides() {
+        let db = init_test_db("messages-around").await;
+        let chat_id = -1001374348669;
+        for message_id in 1..=7 {
+            queue_message(
+                &db,
+                message_id,
+                chat_id,
+                "alice",
+                &format!("line {message_id}"),
+            )
+            .await;
+        }
+        queue_message(&db, 8, -1002631835259, "mallory", "other chat").await;
+
+        let ids = |rows: Vec<MessageRow>| rows.iter().map(|row| row.message_id).collect::<Vec<_>>();
+        let around = db.get_messages_around(chat_id, 4, 2).await.unwrap();
+        assert_eq!(ids(around), vec![2, 3, 4, 5, 6]);
+        let edge = db.get_messages_around(chat_id, 7, 2).await.unwrap();
+        assert_eq!(ids(edge), vec![5, 6, 7]);
+        assert!(db
+            .get_messages_around(chat_id, 8, 2)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_reply_thread_walks_reply_chain_oldest_first() {
+        let db = init_test_db("reply-thread").await;
+        let chat_id = -1001374348669;
+        let replies = [(10, None), (11, Some(10)), (12, Some(11)), (13, Some(12))];
+        for (message_id, reply_to) in replies {
+            let insert = build_message_insert(
+                Some(123_i64),
+                Some("alice".to_string()),
+                Some(format!("turn {message_id}")),
+                None,
+                Utc::now(),
+                reply_to,
+                Some(chat_id),
+                Some(message_id),
+                None,
+                false,
+                None,
+                false,
+                false,
+            );
+            db.queue_message_insert(insert)
+                .await
+                .expect("message queue should succeed");
+            wait_for_message_row(&db, chat_id, message_id).await;
+        }
+        queue_message(&db, 14, chat_id, "bob", "unrelated").await;
+
+        let ids = |rows: Vec<MessageRow>| rows.iter().map(|row| row.message_id).collect::<Vec<_>>();
+        let thread = db
+            .get_reply_thread(chat_id, 13, 10)
+            .await
+            .expect("thread lookup should succeed");
+        assert_eq!(ids(thread), vec![10, 11, 12, 13]);
+
+        let capped = db
+            .get_reply_thread(chat_id, 13, 2)
+            .await
+            .expect("thread lookup should succeed");
+        assert_eq!(ids(capped), vec![12, 13]);
+
+        assert!(db
+            .get_reply_thread(-1002631835259, 13, 10)
+            .await
+            .expect("thread lookup should succeed")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn staged_retrieval_orders_phrase_then_and_then_or_prefix() {
+        let db = init_test_db("stage-order").await;
         queue_message(&db, 1, -1001374348669, "alice", "alpha beta exact phrase").await;
         queue_message(&db, 2, -1001374348669, "bob", "alpha noise beta context").await;
         queue_message(&db, 3, -1001374348669, "carol", "alpha only fallback").await;
@@ -1973,6 +2611,486 @@ mod tests {
         assert_eq!(user_totals[1].total_tokens, 80);
     }
 
+    #[tokio::test]
+    async fn token_usage_by_model_splits_input_and_output_per_scope() {
+        let db = init_test_db("token-usage-by-model").await;
+        let chat_a = -1001374348669_i64;
+        let chat_b = -1002631835259_i64;
+
+        insert_invocation_with_usage(
+            &db,
+            chat_a,
+            Some(101),
+            Some("Alice"),
+            10,
+            "gemini",
+            "gemini-2.5-pro",
+            Some(45),
+            Some(55),
+            Some(100),
+        )
+        .await;
+        insert_invocation_with_usage(
+            &db,
+            chat_a,
+            Some(202),
+            Some("Bob"),
+            11,
+            "gemini",
+            "gemini-2.5-pro",
+            Some(5),
+            None,
+            Some(5),
+        )
+        .await;
+        insert_invocation_with_usage(
+            &db,
+            chat_b,
+            Some(101),
+            Some("Alice"),
+            12,
+            "openrouter",
+            "openai/gpt-4.1",
+            Some(150),
+            Some(150),
+            Some(300),
+        )
+        .await;
+
+        let global = db
+            .select_token_usage_by_model(None, None)
+            .await
+            .expect("global usage should succeed");
+        assert_eq!(
+            global,
+            vec![
+                ModelUsageStat {
+                    provider: "gemini".to_string(),
+                    model: "gemini-2.5-pro".to_string(),
+                    input_tokens: 50,
+                    output_tokens: 55,
+                },
+                ModelUsageStat {
+                    provider: "openrouter".to_string(),
+                    model: "openai/gpt-4.1".to_string(),
+                    input_tokens: 150,
+                    output_tokens: 150,
+                },
+            ]
+        );
+
+        let chat_only = db
+            .select_token_usage_by_model(Some(chat_b), None)
+            .await
+            .expect("chat usage should succeed");
+        assert_eq!(chat_only.len(), 1);
+        assert_eq!(chat_only[0].model, "openai/gpt-4.1");
+
+        let future_only = db
+            .select_token_usage_by_model(None, Some(Utc::now() + chrono::Duration::hours(1)))
+            .await
+            .expect("windowed usage should succeed");
+        assert!(future_only.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tldr_selection_excludes_messages_older_than_cutoff() {
+        let db = init_test_db("tldr-age-cutoff").await;
+        let chat = -1001374348669_i64;
+        for (message_id, age_days, text) in [(1, 30, "ancient"), (2, 0, "fresh"), (3, 0, "newer")] {
+            let insert = build_message_insert(
+                Some(123_i64),
+                Some("alice".to_string()),
+                Some(text.to_string()),
+                Some("en".to_string()),
+                Utc::now() - chrono::Duration::days(age_days),
+                None,
+                Some(chat),
+                Some(message_id),
+                None,
+                false,
+                None,
+                false,
+                false,
+            );
+            db.queue_message_insert(insert)
+                .await
+                .expect("message queue should succeed");
+            wait_for_message_row(&db, chat, message_id).await;
+        }
+        let cutoff_at = Utc::now() - chrono::Duration::days(7);
+        let cutoff = Some(cutoff_at);
+
+        let by_count = db
+            .select_messages(chat, 10, cutoff)
+            .await
+            .expect("count-based selection should succeed");
+        assert_eq!(
+            by_count
+                .iter()
+                .map(|row| row.message_id)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+
+        let by_reply = db
+            .select_messages_from_id(chat, 1, cutoff)
+            .await
+            .expect("reply-based selection should succeed");
+        assert_eq!(
+            by_reply
+                .iter()
+                .map(|row| row.message_id)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+
+        assert_eq!(
+            db.count_text_messages_before(chat, cutoff_at, None)
+                .await
+                .expect("count should succeed"),
+            1
+        );
+        assert_eq!(
+            db.count_text_messages_before(chat, cutoff_at, Some(2))
+                .await
+                .expect("count should succeed"),
+            0
+        );
+        assert_eq!(
+            db.select_messages(chat, 10, None)
+                .await
+                .expect("unbounded selection should succeed")
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn user_activity_stats_aggregate_one_user_in_one_chat() {
+        let db = init_test_db("user-activity").await;
+        let chat_id = -1001374348669_i64;
+        let rows = [
+            (
+                1,
+                chat_id,
+                42,
+                "Alice",
+                "2026-03-01T09:15:00+00:00",
+                false,
+                false,
+                false,
+            ),
+            (
+                2,
+                chat_id,
+                42,
+                "Alice",
+                "2026-03-02T21:05:00+00:00",
+                true,
+                true,
+                false,
+            ),
+            (
+                3,
+                chat_id,
+                42,
+                "Alice W",
+                "2026-03-03T21:45:00+00:00",
+                false,
+                false,
+                false,
+            ),
+            (
+                4,
+                chat_id,
+                42,
+                "Alice W",
+                "2026-03-04T22:00:00+00:00",
+                false,
+                false,
+                true,
+            ),
+            (
+                5,
+                chat_id,
+                7,
+                "Bob",
+                "2026-03-01T09:00:00+00:00",
+                false,
+                false,
+                false,
+            ),
+            (
+                6,
+                -1002631835259,
+                42,
+                "Alice",
+                "2026-02-01T08:00:00+00:00",
+                false,
+                false,
+                false,
+            ),
+        ];
+        for (message_id, chat, user_id, username, date, is_command, asks_ai, synthetic) in rows {
+            sqlx::query(
+                "INSERT INTO messages (message_id, chat_id, user_id, username, text, date, \
+                 is_command, asks_ai, is_synthetic_record) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(message_id)
+            .bind(chat)
+            .bind(user_id)
+            .bind(username)
+            .bind("not part of the stats")
+            .bind(date)
+            .bind(is_command)
+            .bind(asks_ai)
+            .bind(synthetic)
+            .execute(db.pool())
+            .await
+            .expect("seed insert should succeed");
+        }
+
+        let stats = db
+            .user_activity_stats(chat_id, 42)
+            .await
+            .expect("activity stats should load");
+        assert_eq!(stats.username.as_deref(), Some("Alice W"));
+        assert_eq!(stats.message_count, 3);
+        assert_eq!(stats.command_count, 1);
+        assert_eq!(stats.ai_request_count, 1);
+        assert_eq!(
+            stats.first_seen.map(|value| value.to_rfc3339()),
+            Some("2026-03-01T09:15:00+00:00".to_string())
+        );
+        assert_eq!(
+            stats.last_seen.map(|value| value.to_rfc3339()),
+            Some("2026-03-03T21:45:00+00:00".to_string())
+        );
+        assert_eq!(stats.active_hours, vec![(21, 2), (9, 1)]);
+
+        let empty = db
+            .user_activity_stats(chat_id, 999)
+            .await
+            .expect("stats for an unknown user should load");
+        assert_eq!(empty.message_count, 0);
+        assert!(empty.first_seen.is_none() && empty.active_hours.is_empty());
+
+        assert_eq!(
+            db.find_chat_user_id_by_name(chat_id, "@bob")
+                .await
+                .expect("name lookup should succeed"),
+            Some(7)
+        );
+        assert_eq!(
+            db.find_chat_user_id_by_name(chat_id, "carol")
+                .await
+                .expect("name lookup should succeed"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_settings_upsert_round_trips_every_column() {
+        let db = init_test_db("chat-settings-upsert").await;
+        let chat = -1001374348669_i64;
+
+        assert!(db
+            .select_chat_settings()
+            .await
+            .expect("settings should load")
+            .is_empty());
+
+        let mut settings = ChatSettingsRow {
+            digest_enabled: true,
+            digest_hour: 21,
+            digest_last_sent_on: Some("2026-03-10".to_string()),
+            telegraph_author_name: Some("Rust".to_string()),
+            extract_twitter: false,
+            disabled_commands: Some("img,vid".to_string()),
+            temperature: Some(0.5),
+            timezone: Some("Asia/Shanghai".to_string()),
+            quiet_mode: Some(true),
+            ..ChatSettingsRow::new(chat)
+        };
+        db.upsert_chat_settings(&settings)
+            .await
+            .expect("settings insert should succeed");
+        assert_eq!(
+            db.select_chat_settings()
+                .await
+                .expect("settings should load"),
+            vec![settings.clone()]
+        );
+
+        settings.digest_enabled = false;
+        settings.quiet_mode = None;
+        settings.extract_twitter = true;
+        db.upsert_chat_settings(&settings)
+            .await
+            .expect("settings update should succeed");
+        assert_eq!(
+            db.select_chat_settings()
+                .await
+                .expect("settings should load"),
+            vec![settings]
+        );
+    }
+
+    #[tokio::test]
+    async fn select_messages_since_skips_commands_and_keeps_chronological_order() {
+        let db = init_test_db("messages-since").await;
+        let chat = -1001374348669_i64;
+        queue_message(&db, 1, chat, "alice", "first").await;
+        queue_message(&db, 2, chat, "bob", "/tldr").await;
+        queue_message(&db, 3, chat, "carol", "second").await;
+
+        let rows = db
+            .select_messages_since(chat, Utc::now() - chrono::Duration::hours(1), 10)
+            .await
+            .expect("messages since should load");
+        let ids = rows.iter().map(|row| row.message_id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 3]);
+
+        let none = db
+            .select_messages_since(chat, Utc::now() + chrono::Duration::hours(1), 10)
+            .await
+            .expect("messages since should load");
+        assert!(none.is_empty());
+    }
+
+    #[tokio::test]
+    async fn transcription_tokens_stay_out_of_the_daily_quota() {
+        let db = init_test_db("user-usage-transcription").await;
+        let invocation_id = db
+            .insert_llm_invocation(LlmInvocationInsert {
+                trigger_kind: LLM_TRIGGER_KIND_TRANSCRIPTION.to_string(),
+                trigger_name: "voice".to_string(),
+                chat_id: -1001374348669,
+                user_id: Some(7002),
+                username: Some("Bob".to_string()),
+                message_id: 20,
+                reply_to_message_id: None,
+                message_text: None,
+                created_at: Utc::now(),
+            })
+            .await
+            .expect("invocation insert should succeed");
+        db.insert_llm_request(LlmRequestInsert {
+            invocation_id,
+            provider: "gemini".to_string(),
+            model: "gemini-2.5-flash".to_string(),
+            operation: "call_gemini".to_string(),
+            response_id: None,
+            started_at: Utc::now(),
+            completed_at: Utc::now(),
+            duration_ms: 50,
+            input_tokens: None,
+            output_tokens: None,
+            total_tokens: Some(400),
+            reasoning_tokens: None,
+            cached_input_tokens: None,
+            raw_usage_json: None,
+        })
+        .await
+        .expect("request insert should succeed");
+
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        assert_eq!(
+            db.select_user_tokens_for_day(7002, &today)
+                .await
+                .expect("today's usage should load"),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn user_usage_rolls_up_tokens_per_utc_day() {
+        let db = init_test_db("user-usage-rollups").await;
+        let chat = -1001374348669_i64;
+
+        insert_invocation_with_usage(
+            &db,
+            chat,
+            Some(7001),
+            Some("Alice"),
+            10,
+            "gemini",
+            "gemini-2.5-pro",
+            Some(10),
+            Some(20),
+            None,
+        )
+        .await;
+        insert_invocation_with_usage(
+            &db,
+            chat,
+            Some(7001),
+            Some("Alice"),
+            11,
+            "gemini",
+            "gemini-2.5-pro",
+            None,
+            None,
+            Some(70),
+        )
+        .await;
+        insert_invocation_with_usage(
+            &db,
+            chat,
+            None,
+            None,
+            12,
+            "gemini",
+            "gemini-2.5-pro",
+            None,
+            None,
+            Some(500),
+        )
+        .await;
+        sqlx::query(
+            "INSERT INTO user_usage (user_id, day, total_tokens, request_count) \
+             VALUES (7001, '2000-01-01', 999, 4)",
+        )
+        .execute(db.pool())
+        .await
+        .expect("older rollup should insert");
+
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        assert_eq!(
+            db.select_user_tokens_for_day(7001, &today)
+                .await
+                .expect("today's usage should load"),
+            100
+        );
+        let days = db
+            .select_user_daily_usage(7001, "1999-12-31")
+            .await
+            .expect("daily usage should load");
+        assert_eq!(
+            days,
+            vec![
+                UserDailyUsage {
+                    day: today.clone(),
+                    total_tokens: 100,
+                    request_count: 2,
+                },
+                UserDailyUsage {
+                    day: "2000-01-01".to_string(),
+                    total_tokens: 999,
+                    request_count: 4,
+                },
+            ]
+        );
+        assert_eq!(
+            db.select_user_daily_usage(7001, &today)
+                .await
+                .expect("recent usage should load")
+                .len(),
+            1
+        );
+    }
+
     #[tokio::test]
     async fn token_usage_queries_prefer_latest_username_from_messages() {
         let db = init_test_db("token-usage-usernames").await;
@@ -2978,7 +4096,7 @@ mod tests {
         queue_message_with_user(&db, 3, chat, 1003, "Alice", "Hello from Alice").await;
 
         let messages = db
-            .select_messages(chat, 10)
+            .select_messages(chat, 10, None)
             .await
             .expect("select should work");
         assert_eq!(messages.len(), 3);
@@ -2994,7 +4112,7 @@ mod tests {
         // Alice is unique — no suffix.
         assert_eq!(label_map[&1003], "Alice");
 
-        let chat_content = format_tldr_chat_content(&messages);
+        let chat_content = format_tldr_chat_content(&messages, chrono_tz::Tz::UTC);
 
         assert!(chat_content.contains("[message_id=1] John (1): Hello from first John"));
         assert!(chat_content.contains("[message_id=2] John (2): Hello from second John"));
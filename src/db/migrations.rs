@@ -0,0 +1,290 @@
+//! Ordered schema migrations recorded in a `schema_version` table.
+//!
+//! `Database::init` first creates any missing tables with their current
+//! columns, then runs every migration newer than the recorded version. Steps
+//! are idempotent (an added column that already exists is skipped), so a
+//! fresh database, whose tables already have every column, only records the
+//! versions. New columns get a new entry at the end of [`MIGRATIONS`];
+//! existing entries never change.
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use sqlx::{FromRow, Sqlite, SqlitePool, Transaction};
+use tracing::info;
+
+#[derive(Debug, Clone, Copy)]
+pub enum MigrationStep {
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+    /// Statement that is safe to re-run, e.g. `CREATE INDEX IF NOT EXISTS`.
+    #[allow(dead_code)]
+    Sql(&'static str),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub steps: &'static [MigrationStep],
+}
+
+const fn add_column(
+    table: &'static str,
+    column: &'static str,
+    definition: &'static str,
+) -> MigrationStep {
+    MigrationStep::AddColumn {
+        table,
+        column,
+        definition,
+    }
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "messages search and classification columns",
+        steps: &[
+            add_column("messages", "search_text", "TEXT"),
+            add_column("messages", "search_tags", "TEXT"),
+            add_column("messages", "search_version", "INTEGER NOT NULL DEFAULT 0"),
+            add_column("messages", "is_command", "INTEGER NOT NULL DEFAULT 0"),
+            add_column("messages", "asks_ai", "INTEGER NOT NULL DEFAULT 0"),
+            add_column("messages", "ai_command", "TEXT"),
+            add_column(
+                "messages",
+                "is_synthetic_record",
+                "INTEGER NOT NULL DEFAULT 0",
+            ),
+            add_column("messages", "is_redacted", "INTEGER NOT NULL DEFAULT 0"),
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "chat_settings per-chat overrides",
+        steps: &[
+            add_column("chat_settings", "telegraph_author_name", "TEXT"),
+            add_column("chat_settings", "telegraph_author_url", "TEXT"),
+            add_column("chat_settings", "pinned_summary_message_id", "INTEGER"),
+            add_column(
+                "chat_settings",
+                "extract_youtube",
+                "INTEGER NOT NULL DEFAULT 1",
+            ),
+            add_column(
+                "chat_settings",
+                "extract_twitter",
+                "INTEGER NOT NULL DEFAULT 1",
+            ),
+            add_column(
+                "chat_settings",
+                "extract_telegraph",
+                "INTEGER NOT NULL DEFAULT 1",
+            ),
+            add_column("chat_settings", "disabled_commands", "TEXT"),
+            add_column("chat_settings", "temperature", "REAL"),
+            add_column("chat_settings", "top_p", "REAL"),
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "chat_settings timezone",
+        steps: &[add_column("chat_settings", "timezone", "TEXT")],
+    },
+    Migration {
+        version: 4,
+        description: "chat_settings quiet mode",
+        steps: &[add_column("chat_settings", "quiet_mode", "INTEGER")],
+    },
+    Migration {
+        version: 5,
+        description: "messages forward origin",
+        steps: &[add_column("messages", "forward_origin", "TEXT")],
+    },
+];
+
+#[derive(Debug, FromRow)]
+struct TableInfoRow {
+    name: String,
+}
+
+async fn ensure_schema_version_table(pool: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (\
+            version INTEGER PRIMARY KEY,\
+            description TEXT NOT NULL,\
+            applied_at TEXT NOT NULL\
+        );",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn current_schema_version(pool: &SqlitePool) -> Result<i64> {
+    ensure_schema_version_table(pool).await?;
+    let version = sqlx::query_scalar::<_, Option<i64>>("SELECT MAX(version) FROM schema_version")
+        .fetch_one(pool)
+        .await?;
+    Ok(version.unwrap_or(0))
+}
+
+async fn apply_step(tx: &mut Transaction<'_, Sqlite>, step: &MigrationStep) -> Result<()> {
+    match step {
+        MigrationStep::AddColumn {
+            table,
+            column,
+            definition,
+        } => {
+            let columns = sqlx::query_as::<_, TableInfoRow>(&format!("PRAGMA table_info({table})"))
+                .fetch_all(&mut **tx)
+                .await?;
+            if columns.is_empty() {
+                return Err(anyhow!("migration targets missing table '{table}'"));
+            }
+            if columns.iter().any(|existing| existing.name == *column) {
+                return Ok(());
+            }
+            sqlx::query(&format!(
+                "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+            ))
+            .execute(&mut **tx)
+            .await?;
+        }
+        MigrationStep::Sql(statement) => {
+            sqlx::query(statement).execute(&mut **tx).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies `migrations` newer than the recorded version, each in its own
+/// transaction, and returns the resulting version.
+pub async fn run_migrations_from(pool: &SqlitePool, migrations: &[Migration]) -> Result<i64> {
+    let recorded = current_schema_version(pool).await?;
+    let mut version = recorded;
+    for migration in migrations
+        .iter()
+        .filter(|migration| migration.version > recorded)
+    {
+        let mut tx = pool.begin().await?;
+        for step in migration.steps {
+            apply_step(&mut tx, step).await.map_err(|err| {
+                anyhow!(
+                    "schema migration {} ({}) failed: {err}",
+                    migration.version,
+                    migration.description
+                )
+            })?;
+        }
+        sqlx::query(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?, ?, ?)",
+        )
+        .bind(migration.version)
+        .bind(migration.description)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        info!(
+            "Applied schema migration {} ({})",
+            migration.version, migration.description
+        );
+        version = migration.version;
+    }
+    Ok(version)
+}
+
+pub async fn run_migrations(pool: &SqlitePool) -> Result<i64> {
+    run_migrations_from(pool, MIGRATIONS).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("in-memory pool should open")
+    }
+
+    async fn column_names(pool: &SqlitePool, table: &str) -> Vec<String> {
+        sqlx::query_as::<_, TableInfoRow>(&format!("PRAGMA table_info({table})"))
+            .fetch_all(pool)
+            .await
+            .expect("table info should load")
+            .into_iter()
+            .map(|row| row.name)
+            .collect()
+    }
+
+    async fn recorded_versions(pool: &SqlitePool) -> Vec<i64> {
+        sqlx::query_scalar::<_, i64>("SELECT version FROM schema_version ORDER BY version")
+            .fetch_all(pool)
+            .await
+            .expect("versions should load")
+    }
+
+    #[test]
+    fn migration_versions_are_strictly_increasing() {
+        assert!(MIGRATIONS
+            .windows(2)
+            .all(|pair| pair[0].version < pair[1].version));
+        assert!(MIGRATIONS.first().is_some_and(|first| first.version > 0));
+    }
+
+    #[tokio::test]
+    async fn migrations_upgrade_a_legacy_schema_and_then_do_nothing() {
+        let pool = memory_pool().await;
+        sqlx::query(
+            "CREATE TABLE messages (id INTEGER PRIMARY KEY, message_id INTEGER, chat_id INTEGER)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("CREATE TABLE chat_settings (chat_id INTEGER PRIMARY KEY)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let latest = MIGRATIONS.last().unwrap().version;
+        assert_eq!(run_migrations(&pool).await.unwrap(), latest);
+        let messages = column_names(&pool, "messages").await;
+        assert!(messages.iter().any(|name| name == "is_redacted"));
+        assert!(messages.iter().any(|name| name == "forward_origin"));
+        let settings = column_names(&pool, "chat_settings").await;
+        assert!(settings.iter().any(|name| name == "top_p"));
+
+        assert_eq!(run_migrations(&pool).await.unwrap(), latest);
+        assert_eq!(recorded_versions(&pool).await.len(), MIGRATIONS.len());
+        assert_eq!(column_names(&pool, "messages").await, messages);
+    }
+
+    #[tokio::test]
+    async fn migrations_on_a_current_schema_only_record_versions() {
+        let pool = memory_pool().await;
+        sqlx::query("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        const NOTES: &[Migration] = &[Migration {
+            version: 1,
+            description: "notes body",
+            steps: &[
+                add_column("notes", "body", "TEXT"),
+                MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_notes_body ON notes(body)"),
+            ],
+        }];
+
+        assert_eq!(run_migrations_from(&pool, NOTES).await.unwrap(), 1);
+        assert_eq!(column_names(&pool, "notes").await, vec!["id", "body"]);
+        assert_eq!(recorded_versions(&pool).await, vec![1]);
+    }
+}
@@ -1,3 +1,5 @@
 pub mod database;
+pub mod migrations;
 pub mod models;
 pub mod search;
+pub mod store;
@@ -18,6 +18,10 @@ pub struct MessageRow {
     pub asks_ai: bool,
     pub ai_command: Option<String>,
     pub is_synthetic_record: bool,
+    /// Where a forwarded message came from, e.g. `forwarded from channel
+    /// "Daily News", 2026-03-01 09:15 UTC`. Kept out of `text` and added
+    /// only when the message is rendered into a prompt.
+    pub forward_origin: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -52,6 +56,8 @@ pub struct MessageInsert {
     pub ai_command: Option<String>,
     pub is_command: bool,
     pub is_synthetic_record: bool,
+    pub is_redacted: bool,
+    pub forward_origin: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -146,6 +152,29 @@ pub struct TokenUserStat {
     pub total_tokens: i64,
 }
 
+/// One user's token consumption on one UTC day, from `user_usage`.
+#[derive(Debug, Clone, FromRow, PartialEq, Eq)]
+pub struct UserDailyUsage {
+    pub day: String,
+    pub total_tokens: i64,
+    pub request_count: i64,
+}
+
+/// Aggregate activity for one user in one chat, behind `/whois`. Only counts
+/// and timestamps are collected; message text is never read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserActivityStats {
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub message_count: i64,
+    pub command_count: i64,
+    pub ai_request_count: i64,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    /// `(utc_hour, message_count)` pairs, busiest first.
+    pub active_hours: Vec<(u32, i64)>,
+}
+
 #[derive(Debug, Clone, FromRow)]
 pub struct AnalyticsRow {
     pub group_user_id: Option<i64>,
@@ -160,3 +189,55 @@ pub struct ModelTokenStat {
     pub model: String,
     pub total_tokens: i64,
 }
+
+#[derive(Debug, Clone, FromRow, PartialEq, Eq)]
+pub struct ModelUsageStat {
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+}
+
+/// One chat's `/digest`, `/telegraphauthor`, `/extraction`, `/command`,
+/// `/temperature`, `/timezone` and `/quiet` settings.
+#[derive(Debug, Clone, FromRow, PartialEq)]
+pub struct ChatSettingsRow {
+    pub chat_id: i64,
+    pub digest_enabled: bool,
+    pub digest_hour: i64,
+    pub digest_last_sent_on: Option<String>,
+    pub telegraph_author_name: Option<String>,
+    pub telegraph_author_url: Option<String>,
+    pub pinned_summary_message_id: Option<i64>,
+    pub extract_youtube: bool,
+    pub extract_twitter: bool,
+    pub extract_telegraph: bool,
+    pub disabled_commands: Option<String>,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub timezone: Option<String>,
+    pub quiet_mode: Option<bool>,
+}
+
+impl ChatSettingsRow {
+    /// Settings of a chat with no stored row, matching the column defaults.
+    pub fn new(chat_id: i64) -> Self {
+        Self {
+            chat_id,
+            digest_enabled: false,
+            digest_hour: 9,
+            digest_last_sent_on: None,
+            telegraph_author_name: None,
+            telegraph_author_url: None,
+            pinned_summary_message_id: None,
+            extract_youtube: true,
+            extract_twitter: true,
+            extract_telegraph: true,
+            disabled_commands: None,
+            temperature: None,
+            top_p: None,
+            timezone: None,
+            quiet_mode: None,
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Storage backend abstraction.
+//!
+//! [`Store`] covers only the chat-history reads the agent tool runtime relies
+//! on: full-text search, context windows, reply threads, and analytics.
+//! [`ToolRuntime`](crate::llm::tool_runtime::ToolRuntime) and the `/q` thread
+//! loader are generic over it. Message inserts and the handlers' history
+//! selects still go to [`Database`] directly, so they are not yet
+//! backend-neutral. [`Database`] is the SQLite implementation; a
+//! Postgres store is stubbed behind the `postgres` feature. SQL that differs
+//! between backends (full-text matching and date-part extraction) comes from
+//! [`SqlDialect`] so both implementations can share the rest of their queries.
+
+use std::future::Future;
+
+use anyhow::Result;
+
+use crate::db::database::Database;
+use crate::db::models::{AnalyticsRow, ChatSearchHit, MessageRow};
+use crate::llm::analytics::QuerySpec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlDialect {
+    Sqlite,
+    #[cfg(feature = "postgres")]
+    #[allow(dead_code)] // Only the unwired Postgres stub uses it.
+    Postgres,
+}
+
+impl SqlDialect {
+    /// One full-text stage query returning the `SearchRow` columns plus a
+    /// `score` where lower ranks first. Binds chat id, match expression, and
+    /// limit in that order.
+    pub fn search_stage_sql(self) -> &'static str {
+        match self {
+            Self::Sqlite => {
+                "SELECT \
+                     m.id, m.message_id, m.chat_id, m.user_id, m.username, m.text, m.language, \
+                     m.date, m.reply_to_message_id, m.asks_ai, m.ai_command, \
+                     m.is_synthetic_record, \
+                     bm25(messages_fts, 1.0, 0.2) AS score \
+                 FROM messages_fts \
+                 JOIN messages m ON m.id = messages_fts.rowid \
+                 WHERE m.chat_id = ? AND messages_fts MATCH ? \
+                 ORDER BY score ASC, m.date DESC, m.message_id DESC \
+                 LIMIT ?"
+            }
+            #[cfg(feature = "postgres")]
+            Self::Postgres => {
+                "SELECT \
+                     m.id, m.message_id, m.chat_id, m.user_id, m.username, m.text, m.language, \
+                     m.date, m.reply_to_message_id, m.asks_ai, m.ai_command, \
+                     m.is_synthetic_record, \
+                     -ts_rank(m.search_vector, websearch_to_tsquery('simple', $2)) AS score \
+                 FROM messages m \
+                 WHERE m.chat_id = $1 AND m.search_vector @@ websearch_to_tsquery('simple', $2) \
+                 ORDER BY score ASC, m.date DESC, m.message_id DESC \
+                 LIMIT $3"
+            }
+        }
+    }
+
+    /// Integer hour of day (0-23) of a timestamp column.
+    pub fn hour_of_day(self, column: &str) -> String {
+        match self {
+            Self::Sqlite => format!("CAST(strftime('%H', {column}) AS INTEGER)"),
+            #[cfg(feature = "postgres")]
+            Self::Postgres => format!("CAST(EXTRACT(HOUR FROM {column}) AS INTEGER)"),
+        }
+    }
+}
+
+/// Chat-history storage used by the tool runtime. Futures are `Send` so
+/// callers can hold a store across `tokio::spawn`.
+pub trait Store: Clone + Send + Sync + 'static {
+    fn dialect(&self) -> SqlDialect;
+
+    fn get_message_window(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        context_before: i64,
+        context_after: i64,
+    ) -> impl Future<Output = Result<Option<Vec<MessageRow>>>> + Send;
+
+    fn get_reply_thread(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        max_messages: usize,
+    ) -> impl Future<Output = Result<Vec<MessageRow>>> + Send;
+
+    fn search_chat_messages(
+        &self,
+        chat_id: i64,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> impl Future<Output = Result<Vec<ChatSearchHit>>> + Send;
+
+    /// Runs a validated analytics query; returns the clamped spec and rows.
+    fn run_chat_analytics(
+        &self,
+        chat_id: i64,
+        spec: &QuerySpec,
+    ) -> impl Future<Output = Result<(QuerySpec, Vec<AnalyticsRow>)>> + Send;
+}
+
+impl Store for Database {
+    fn dialect(&self) -> SqlDialect {
+        SqlDialect::Sqlite
+    }
+
+    fn get_message_window(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        context_before: i64,
+        context_after: i64,
+    ) -> impl Future<Output = Result<Option<Vec<MessageRow>>>> + Send {
+        Database::get_message_window(self, chat_id, message_id, context_before, context_after)
+    }
+
+    fn get_reply_thread(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        max_messages: usize,
+    ) -> impl Future<Output = Result<Vec<MessageRow>>> + Send {
+        Database::get_reply_thread(self, chat_id, message_id, max_messages)
+    }
+
+    fn search_chat_messages(
+        &self,
+        chat_id: i64,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> impl Future<Output = Result<Vec<ChatSearchHit>>> + Send {
+        Database::search_chat_messages(self, chat_id, query, limit, offset)
+    }
+
+    fn run_chat_analytics(
+        &self,
+        chat_id: i64,
+        spec: &QuerySpec,
+    ) -> impl Future<Output = Result<(QuerySpec, Vec<AnalyticsRow>)>> + Send {
+        Database::run_chat_analytics(self, chat_id, spec)
+    }
+}
+
+/// Placeholder for a Postgres-backed store. Every operation fails until the
+/// schema, writer, and `tsvector` search index are ported, and nothing
+/// outside tests constructs it until it is wired into startup.
+#[cfg(feature = "postgres")]
+#[allow(dead_code)]
+pub mod postgres {
+    use super::*;
+    use anyhow::anyhow;
+
+    /// Holds no connection yet; the URL is accepted so call sites can be
+    /// written against the final constructor.
+    #[derive(Debug, Clone)]
+    pub struct PostgresStore;
+
+    impl PostgresStore {
+        pub fn new(_database_url: impl Into<String>) -> Self {
+            Self
+        }
+    }
+
+    fn unsupported<T>(operation: &str) -> Result<T> {
+        Err(anyhow!(
+            "Postgres storage does not implement {operation} yet"
+        ))
+    }
+
+    impl Store for PostgresStore {
+        fn dialect(&self) -> SqlDialect {
+            SqlDialect::Postgres
+        }
+
+        async fn get_message_window(
+            &self,
+            _chat_id: i64,
+            _message_id: i64,
+            _context_before: i64,
+            _context_after: i64,
+        ) -> Result<Option<Vec<MessageRow>>> {
+            unsupported("get_message_window")
+        }
+
+        async fn get_reply_thread(
+            &self,
+            _chat_id: i64,
+            _message_id: i64,
+            _max_messages: usize,
+        ) -> Result<Vec<MessageRow>> {
+            unsupported("get_reply_thread")
+        }
+
+        async fn search_chat_messages(
+            &self,
+            _chat_id: i64,
+            _query: &str,
+            _limit: i64,
+            _offset: i64,
+        ) -> Result<Vec<ChatSearchHit>> {
+            unsupported("search_chat_messages")
+        }
+
+        async fn run_chat_analytics(
+            &self,
+            _chat_id: i64,
+            _spec: &QuerySpec,
+        ) -> Result<(QuerySpec, Vec<AnalyticsRow>)> {
+            unsupported("run_chat_analytics")
+        }
+    }
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use super::postgres::PostgresStore;
+    use super::*;
+
+    #[tokio::test]
+    async fn postgres_stub_reports_every_operation_as_unsupported() {
+        let store = PostgresStore::new("postgres://localhost/bot");
+        assert_eq!(store.dialect(), SqlDialect::Postgres);
+        let err = store
+            .search_chat_messages(-1001, "hello", 10, 0)
+            .await
+            .expect_err("stub should not search");
+        assert!(err.to_string().contains("search_chat_messages"));
+    }
+}
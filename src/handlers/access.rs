@@ -2,13 +2,17 @@ use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use teloxide::prelude::*;
 use teloxide::types::ReplyParameters;
+use teloxide::utils::command::BotCommands;
 use tracing::{info, warn};
 
 use crate::config::CONFIG;
+use crate::db::models::ChatSettingsRow;
+use crate::state::{AppState, InFlightCommandGuard};
 
 static RATE_LIMITS: Lazy<Mutex<HashMap<i64, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 static WHITELIST_CACHE: Lazy<Mutex<Option<HashSet<i64>>>> = Lazy::new(|| Mutex::new(None));
@@ -19,6 +23,21 @@ fn prune_rate_limits(limits: &mut HashMap<i64, Instant>, now: Instant) {
     limits.retain(|_, last_seen| now.duration_since(*last_seen) <= ttl);
 }
 
+/// Id that rate limits, quotas, and access checks apply to. Posts made on
+/// behalf of a chat (channel posts, anonymous group admins, linked channels)
+/// carry a placeholder `from` shared by every such sender, so the sending chat
+/// is used instead; a message with neither falls back to its own chat.
+pub fn command_subject_id(message: &Message) -> i64 {
+    if let Some(sender_chat) = message.sender_chat.as_ref() {
+        return sender_chat.id.0;
+    }
+    message
+        .from
+        .as_ref()
+        .and_then(|user| i64::try_from(user.id.0).ok())
+        .unwrap_or(message.chat.id.0)
+}
+
 pub fn is_rate_limited(user_id: i64) -> bool {
     let mut limits = RATE_LIMITS.lock();
     let now = Instant::now();
@@ -34,6 +53,66 @@ pub fn is_rate_limited(user_id: i64) -> bool {
     false
 }
 
+/// Remaining cooldown for `user_id`, or `None` when the user may send now.
+/// Cooldowns are tracked per user across all chats.
+pub fn rate_limit_remaining(user_id: i64) -> Option<Duration> {
+    let limits = RATE_LIMITS.lock();
+    let elapsed = limits.get(&user_id)?.elapsed();
+    Duration::from_secs(CONFIG.rate_limit_seconds)
+        .checked_sub(elapsed)
+        .filter(|remaining| !remaining.is_zero())
+}
+
+/// Clears `user_id`'s cooldown. Returns whether the user was still limited.
+pub fn reset_rate_limit(user_id: i64) -> bool {
+    let was_limited = rate_limit_remaining(user_id).is_some();
+    RATE_LIMITS.lock().remove(&user_id);
+    was_limited
+}
+
+const NO_LLM_PROVIDER_MESSAGE: &str = "No AI model provider is configured for this bot yet. An administrator needs to set GEMINI_API_KEY or configure a third-party model (for example OPENROUTER_API_KEY plus a models file), then restart the bot. /status shows which providers are ready.";
+
+/// Returns the setup-required reply when no text model provider is usable.
+fn llm_setup_required_message(gemini_ready: bool, third_party_ready: bool) -> Option<&'static str> {
+    (!gemini_ready && !third_party_ready).then_some(NO_LLM_PROVIDER_MESSAGE)
+}
+
+/// Preflight for LLM-backed commands. Replies with a setup hint and returns
+/// `false` when neither Gemini nor any third-party model is available.
+pub async fn ensure_llm_available(bot: &Bot, message: &Message) -> bool {
+    let Some(reply) = llm_setup_required_message(
+        CONFIG.gemini_api_available(),
+        crate::llm::runtime_models::any_third_party_model_ready(),
+    ) else {
+        return true;
+    };
+
+    warn!(
+        "LLM command rejected: no provider configured (chat_id={})",
+        message.chat.id.0
+    );
+    let _ = bot
+        .send_message(message.chat.id, reply)
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await;
+    false
+}
+
+pub(crate) fn parse_whitelist_content(content: &str) -> HashSet<i64> {
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| match line.parse::<i64>() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                warn!("Ignoring invalid whitelist entry '{}'", line);
+                None
+            }
+        })
+        .collect()
+}
+
 pub fn load_whitelist() {
     if WHITELIST_LOADED.swap(true, Ordering::SeqCst) {
         return;
@@ -45,19 +124,7 @@ pub fn load_whitelist() {
 
     match file {
         Ok(content) => {
-            let ids = content
-                .lines()
-                .map(|line| line.trim())
-                .filter(|line| !line.is_empty() && !line.starts_with('#'))
-                .filter_map(|line| match line.parse::<i64>() {
-                    Ok(id) => Some(id),
-                    Err(_) => {
-                        warn!("Ignoring invalid whitelist entry '{}'", line);
-                        None
-                    }
-                })
-                .collect::<HashSet<_>>();
-            *cache = Some(ids);
+            *cache = Some(parse_whitelist_content(&content));
             info!("Loaded whitelist file {}", path);
         }
         Err(err) => {
@@ -70,6 +137,12 @@ pub fn load_whitelist() {
     }
 }
 
+/// Re-reads the whitelist file, replacing the cached entries.
+pub fn reload_whitelist() {
+    WHITELIST_LOADED.store(false, Ordering::SeqCst);
+    load_whitelist();
+}
+
 pub fn is_user_whitelisted(user_id: i64) -> bool {
     if !WHITELIST_LOADED.load(Ordering::SeqCst) {
         load_whitelist();
@@ -111,16 +184,84 @@ pub fn requires_access_control(command: &str) -> bool {
         .any(|entry| normalize_command_name(entry) == command)
 }
 
-pub async fn check_access_control(bot: &Bot, message: &Message, command: &str) -> bool {
+/// Commands an admin turned off with `/command` in the chat.
+pub fn chat_disabled_commands(settings: &ChatSettingsRow) -> HashSet<String> {
+    settings
+        .disabled_commands
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(normalize_command_name)
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Bot commands whose handlers never reach [`check_access_control`], so the
+/// per-chat disabled list would not apply to them. Admin commands are gated
+/// by [`check_admin_access`] instead.
+const UNTOGGLEABLE_COMMANDS: &[&str] = &[
+    "start",
+    "stats_tokens",
+    "status",
+    "diagnose",
+    "queue",
+    "token_stats",
+    "stats_providers",
+    "digest",
+    "whitelist",
+    "ratelimit",
+    "whois",
+    "telegraphauthor",
+    "extraction",
+    "command",
+    "temperature",
+    "timezone",
+    "quiet",
+    "codexlogin",
+    "codexlogout",
+    "codexmodel",
+    "codexreasoning",
+    "codexusage",
+];
+
+/// Commands `/command` can turn off in a chat: every bot command except
+/// [`UNTOGGLEABLE_COMMANDS`].
+static TOGGLEABLE_COMMANDS: Lazy<HashSet<String>> = Lazy::new(|| {
+    crate::Command::bot_commands()
+        .into_iter()
+        .map(|command| normalize_command_name(&command.command))
+        .filter(|name| !UNTOGGLEABLE_COMMANDS.contains(&name.as_str()))
+        .collect()
+});
+
+pub fn is_toggleable_command(command: &str) -> bool {
+    TOGGLEABLE_COMMANDS.contains(&normalize_command_name(command))
+}
+
+pub fn is_command_disabled(settings: &ChatSettingsRow, command: &str) -> bool {
+    chat_disabled_commands(settings).contains(&normalize_command_name(command))
+}
+
+/// Rejects commands disabled in the chat, then applies the
+/// `ACCESS_CONTROLLED_COMMANDS` allow list.
+pub async fn check_access_control(
+    bot: &Bot,
+    state: &AppState,
+    message: &Message,
+    command: &str,
+) -> bool {
+    if is_command_disabled(&state.chat_settings.get(message.chat.id.0), command) {
+        let _ = bot
+            .send_message(message.chat.id, "This command is disabled in this chat.")
+            .reply_parameters(ReplyParameters::new(message.id))
+            .await;
+        return false;
+    }
     if !requires_access_control(command) {
         return true;
     }
 
-    let user_id = message
-        .from
-        .as_ref()
-        .and_then(|user| i64::try_from(user.id.0).ok())
-        .unwrap_or_default();
+    let user_id = command_subject_id(message);
     let chat_id = message.chat.id.0;
 
     if !is_access_allowed(user_id, chat_id) {
@@ -137,12 +278,134 @@ pub async fn check_access_control(bot: &Bot, message: &Message, command: &str) -
     true
 }
 
+/// UTC calendar day used for `user_usage` rollups and the daily quota.
+pub fn usage_day(now: DateTime<Utc>) -> String {
+    now.format("%Y-%m-%d").to_string()
+}
+
+/// Time left until the daily quota resets at the next UTC midnight.
+pub fn time_until_usage_reset(now: DateTime<Utc>) -> Duration {
+    let next_midnight = (now.date_naive() + chrono::Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc();
+    (next_midnight - now).to_std().unwrap_or_default()
+}
+
+/// Whitelisted user ids; unlike [`is_user_whitelisted`], nobody qualifies
+/// when no whitelist is configured.
+fn is_whitelist_owner(user_id: i64) -> bool {
+    if !WHITELIST_LOADED.load(Ordering::SeqCst) {
+        load_whitelist();
+    }
+    WHITELIST_CACHE
+        .lock()
+        .as_ref()
+        .is_some_and(|list| list.contains(&user_id))
+}
+
+fn token_quota_exceeded(used_today: i64, quota: u64, is_owner: bool) -> bool {
+    quota > 0 && !is_owner && used_today >= i64::try_from(quota).unwrap_or(i64::MAX)
+}
+
+/// Refuses a heavy command while the same user's previous run of it in this
+/// chat is still going (`DEDUP_IN_FLIGHT_COMMANDS`). Hold the returned guard
+/// for the whole run; `None` means the user was already told to wait.
+pub async fn ensure_not_in_flight(
+    bot: &Bot,
+    state: &AppState,
+    message: &Message,
+    command: &str,
+) -> Option<InFlightCommandGuard> {
+    if !CONFIG.dedup_in_flight_commands {
+        return Some(InFlightCommandGuard::untracked());
+    }
+    let user_id = command_subject_id(message);
+    if let Some(guard) = state
+        .in_flight_commands
+        .try_begin(message.chat.id.0, user_id, command)
+    {
+        return Some(guard);
+    }
+
+    info!("Duplicate /{command} ignored while the previous run is in flight (user_id={user_id})");
+    let _ = bot
+        .send_message(
+            message.chat.id,
+            format!("Still working on your previous /{command}..."),
+        )
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await;
+    None
+}
+
+/// Checks `user_id` against `DAILY_TOKEN_QUOTA` when `ENFORCE_DAILY_TOKEN_QUOTA`
+/// is on and returns the refusal text once today's quota is used up. Lookup
+/// failures let the request through.
+pub async fn token_quota_refusal(state: &AppState, user_id: i64) -> Option<String> {
+    if !CONFIG.enforce_daily_token_quota || CONFIG.daily_token_quota == 0 {
+        return None;
+    }
+
+    let now = Utc::now();
+    let used_today = match state
+        .db
+        .select_user_tokens_for_day(user_id, &usage_day(now))
+        .await
+    {
+        Ok(used) => used,
+        Err(err) => {
+            warn!("Failed to load daily token usage for user {user_id}: {err}");
+            return None;
+        }
+    };
+    if !token_quota_exceeded(
+        used_today,
+        CONFIG.daily_token_quota,
+        is_whitelist_owner(user_id),
+    ) {
+        return None;
+    }
+
+    let reset_minutes = time_until_usage_reset(now).as_secs().div_ceil(60);
+    warn!("LLM request rejected: daily token quota used (user_id={user_id}, used={used_today})");
+    Some(format!(
+        "You have used your daily token quota ({used_today} of {} tokens). It resets in {}h {}m, at midnight UTC. /stats_tokens shows your usage.",
+        CONFIG.daily_token_quota,
+        reset_minutes / 60,
+        reset_minutes % 60
+    ))
+}
+
+/// Preflight for LLM-backed commands. Replies with [`token_quota_refusal`]'s
+/// text and returns `false` once the sender has used today's quota.
+pub async fn ensure_token_quota(bot: &Bot, state: &AppState, message: &Message) -> bool {
+    let Some(refusal) = token_quota_refusal(state, command_subject_id(message)).await else {
+        return true;
+    };
+    let _ = bot
+        .send_message(message.chat.id, refusal)
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await;
+    false
+}
+
 #[cfg(test)]
 #[allow(clippy::items_after_test_module)]
 mod tests {
     use std::collections::HashSet;
 
-    use super::{codex_admin_access_decision, normalize_command_name, CodexAdminAccessDecision};
+    use super::{
+        chat_disabled_commands, codex_admin_access_decision, command_subject_id,
+        is_command_disabled, is_rate_limited, is_toggleable_command, llm_setup_required_message,
+        normalize_command_name, rate_limit_remaining, reset_rate_limit, time_until_usage_reset,
+        token_quota_exceeded, usage_day, CodexAdminAccessDecision, NO_LLM_PROVIDER_MESSAGE,
+        UNTOGGLEABLE_COMMANDS,
+    };
+    use crate::db::models::ChatSettingsRow;
+    use chrono::{TimeZone, Utc};
+    use std::time::Duration;
+    use teloxide::utils::command::BotCommands;
 
     #[test]
     fn normalize_command_name_trims_slash_and_case() {
@@ -150,6 +413,121 @@ mod tests {
         assert_eq!(normalize_command_name("mysong"), "mysong");
     }
 
+    #[test]
+    fn disabled_commands_are_read_from_the_chat_settings() {
+        let mut settings = ChatSettingsRow::new(-100_163_001);
+        settings.disabled_commands = Some("img, VID,,".to_string());
+        assert!(is_command_disabled(&settings, "/IMG"));
+        assert!(is_command_disabled(&settings, "vid"));
+        assert!(!is_command_disabled(&settings, "q"));
+        assert_eq!(chat_disabled_commands(&settings).len(), 2);
+
+        settings.disabled_commands = None;
+        assert!(!is_command_disabled(&settings, "img"));
+        assert!(chat_disabled_commands(&settings).is_empty());
+    }
+
+    #[test]
+    fn toggleable_commands_exclude_admin_commands() {
+        let registered: HashSet<String> = crate::Command::bot_commands()
+            .into_iter()
+            .map(|command| normalize_command_name(&command.command))
+            .collect();
+        for name in UNTOGGLEABLE_COMMANDS {
+            assert!(registered.contains(*name), "unknown command {name}");
+            assert!(!is_toggleable_command(name));
+        }
+        assert!(is_toggleable_command("/Img"));
+        assert!(is_toggleable_command("portraitme"));
+        assert!(!is_toggleable_command("nosuchcommand"));
+    }
+
+    #[test]
+    fn q_without_any_provider_gets_setup_message() {
+        assert_eq!(
+            llm_setup_required_message(false, false),
+            Some(NO_LLM_PROVIDER_MESSAGE)
+        );
+        assert_eq!(llm_setup_required_message(true, false), None);
+        assert_eq!(llm_setup_required_message(false, true), None);
+    }
+
+    fn message(value: serde_json::Value) -> teloxide::types::Message {
+        serde_json::from_value(value).expect("test message should deserialize")
+    }
+
+    #[test]
+    fn command_subject_uses_the_sending_chat_for_channel_posts() {
+        let channel_post = message(serde_json::json!({
+            "message_id": 1,
+            "date": 1,
+            "chat": { "id": -100_182_001, "type": "channel", "title": "news" },
+            "sender_chat": { "id": -100_182_001, "type": "channel", "title": "news" },
+            "text": "/q what changed?"
+        }));
+        assert_eq!(command_subject_id(&channel_post), -100_182_001);
+
+        let anonymous_admin = message(serde_json::json!({
+            "message_id": 2,
+            "date": 1,
+            "chat": { "id": -100_182_002, "type": "supergroup", "title": "group" },
+            "from": { "id": 1_087_968_824_u64, "is_bot": true, "first_name": "Group" },
+            "sender_chat": { "id": -100_182_002, "type": "supergroup", "title": "group" },
+            "text": "/q hi"
+        }));
+        assert_eq!(command_subject_id(&anonymous_admin), -100_182_002);
+
+        let member = message(serde_json::json!({
+            "message_id": 3,
+            "date": 1,
+            "chat": { "id": -100_182_002, "type": "supergroup", "title": "group" },
+            "from": { "id": 182_003, "is_bot": false, "first_name": "Alice" },
+            "text": "/q hi"
+        }));
+        assert_eq!(command_subject_id(&member), 182_003);
+
+        assert!(!is_rate_limited(command_subject_id(&channel_post)));
+        assert!(!is_rate_limited(command_subject_id(&anonymous_admin)));
+        assert!(is_rate_limited(command_subject_id(&channel_post)));
+    }
+
+    #[test]
+    fn reset_rate_limit_clears_limited_user() {
+        let user_id = 128_000_001;
+        assert!(!is_rate_limited(user_id));
+        assert!(is_rate_limited(user_id));
+        assert!(rate_limit_remaining(user_id).is_some());
+
+        assert!(reset_rate_limit(user_id));
+        assert_eq!(rate_limit_remaining(user_id), None);
+        assert!(!is_rate_limited(user_id));
+        assert!(reset_rate_limit(user_id));
+        assert!(!reset_rate_limit(user_id));
+    }
+
+    #[test]
+    fn daily_usage_rolls_over_at_utc_midnight() {
+        let before = Utc.with_ymd_and_hms(2026, 3, 31, 23, 59, 30).unwrap();
+        let after = Utc.with_ymd_and_hms(2026, 4, 1, 0, 0, 0).unwrap();
+
+        assert_eq!(usage_day(before), "2026-03-31");
+        assert_eq!(usage_day(after), "2026-04-01");
+        assert_eq!(time_until_usage_reset(before), Duration::from_secs(30));
+        assert_eq!(
+            time_until_usage_reset(after),
+            Duration::from_secs(24 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn token_quota_blocks_at_cap_except_for_owners() {
+        assert!(!token_quota_exceeded(99_999, 100_000, false));
+        assert!(token_quota_exceeded(100_000, 100_000, false));
+        assert!(token_quota_exceeded(250_000, 100_000, false));
+        assert!(!token_quota_exceeded(250_000, 100_000, true));
+        assert!(!token_quota_exceeded(250_000, 0, false));
+    }
+
     #[test]
     fn codex_admin_requires_whitelisted_user_in_private_chat() {
         let whitelist = HashSet::from([42, -100_123]);
@@ -278,11 +656,7 @@ pub async fn check_admin_access(bot: &Bot, message: &Message, command: &str) ->
         return false;
     };
 
-    let user_id = message
-        .from
-        .as_ref()
-        .and_then(|user| i64::try_from(user.id.0).ok())
-        .unwrap_or_default();
+    let user_id = command_subject_id(message);
     let chat_id = message.chat.id.0;
 
     let allowed = whitelist.contains(&user_id) || whitelist.contains(&chat_id);
@@ -300,3 +674,25 @@ pub async fn check_admin_access(bot: &Bot, message: &Message, command: &str) ->
 
     true
 }
+
+/// Stricter than [`check_admin_access`]: only whitelisted *user* ids pass, so a
+/// whitelisted group cannot be used to edit the whitelist itself.
+pub async fn check_whitelist_owner_access(bot: &Bot, message: &Message, command: &str) -> bool {
+    let allowed = message
+        .from
+        .as_ref()
+        .and_then(|user| i64::try_from(user.id.0).ok())
+        .is_some_and(is_whitelist_owner);
+
+    if !allowed {
+        let _ = bot
+            .send_message(
+                message.chat.id,
+                "This command is restricted to whitelisted administrators.",
+            )
+            .reply_parameters(ReplyParameters::new(message.id))
+            .await;
+        warn!("Whitelist owner command '{}' denied", command);
+    }
+    allowed
+}
@@ -0,0 +1,720 @@
+//! Per-chat overrides stored in `chat_settings`.
+//!
+//! `/telegraphauthor` sets the byline used on Telegraph pages created for the
+//! chat. Chats without an override use `TELEGRAPH_AUTHOR_NAME` and
+//! `TELEGRAPH_AUTHOR_URL`. `/extraction` turns the YouTube, Twitter, and
+//! Telegraph link extractors off or back on for the chat. `/command` turns a
+//! command off for everyone in the chat, whatever the access control says.
+//! `/temperature` overrides the sampling temperature and top_p for the
+//! chat's LLM calls. `/timezone` sets the zone timestamps are shown in.
+//! `/quiet` overrides `QUIET_MODE` for the chat.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use chrono_tz::Tz;
+use teloxide::prelude::*;
+use teloxide::types::ReplyParameters;
+
+use crate::handlers::access::{chat_disabled_commands, check_admin_access, is_toggleable_command};
+use crate::handlers::content::{ChatExtractionSettings, TelegraphAuthor};
+use crate::handlers::responses::chat_quiet_mode;
+use crate::llm::sampling::ChatSampling;
+use crate::state::AppState;
+use crate::utils::timezone::{chat_timezone, parse_timezone};
+
+/// Telegraph accepts author names up to 128 characters and URLs up to 512.
+const TELEGRAPH_AUTHOR_NAME_MAX_CHARS: usize = 128;
+const TELEGRAPH_AUTHOR_URL_MAX_CHARS: usize = 512;
+const TELEGRAPH_AUTHOR_USAGE: &str =
+    "Usage: /telegraphauthor, /telegraphauthor <name> [| <url>], or /telegraphauthor reset";
+const EXTRACTION_USAGE: &str =
+    "Usage: /extraction or /extraction <youtube|twitter|telegraph|all> <on|off>";
+const COMMAND_TOGGLE_USAGE: &str =
+    "Usage: /command or /command <name> <on|off>. Admin commands and /start cannot be turned off.";
+const TEMPERATURE_USAGE: &str =
+    "Usage: /temperature, /temperature <0.0-1.0> [top_p 0.0-1.0], or /temperature reset";
+const TIMEZONE_USAGE: &str =
+    "Usage: /timezone, /timezone <IANA name, e.g. Asia/Shanghai>, or /timezone reset";
+const QUIET_USAGE: &str = "Usage: /quiet, /quiet <on|off>, or /quiet reset";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TelegraphAuthorCommand {
+    Show,
+    Set(TelegraphAuthor),
+    Reset,
+}
+
+fn parse_telegraph_author_command(arg: Option<&str>) -> Option<TelegraphAuthorCommand> {
+    let Some(arg) = arg.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Some(TelegraphAuthorCommand::Show);
+    };
+    if matches!(arg.to_lowercase().as_str(), "reset" | "default" | "off") {
+        return Some(TelegraphAuthorCommand::Reset);
+    }
+
+    let (name, url) = match arg.split_once('|') {
+        Some((name, url)) => (name.trim(), url.trim()),
+        None => (arg, ""),
+    };
+    if name.is_empty() || name.chars().count() > TELEGRAPH_AUTHOR_NAME_MAX_CHARS {
+        return None;
+    }
+    if !url.is_empty()
+        && (url.chars().count() > TELEGRAPH_AUTHOR_URL_MAX_CHARS
+            || !(url.starts_with("https://") || url.starts_with("http://")))
+    {
+        return None;
+    }
+    Some(TelegraphAuthorCommand::Set(TelegraphAuthor {
+        name: name.to_string(),
+        url: url.to_string(),
+    }))
+}
+
+fn describe_telegraph_author(author: Option<&TelegraphAuthor>) -> String {
+    match author {
+        Some(author) if author.url.is_empty() => {
+            format!(
+                "Telegraph pages for this chat are signed as \"{}\".",
+                author.name
+            )
+        }
+        Some(author) => format!(
+            "Telegraph pages for this chat are signed as \"{}\" ({}).",
+            author.name, author.url
+        ),
+        None => "Telegraph pages for this chat use the default author.".to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExtractionSource {
+    YouTube,
+    Twitter,
+    Telegraph,
+    All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExtractionCommand {
+    Show,
+    Set(ExtractionSource, bool),
+}
+
+fn parse_extraction_command(arg: Option<&str>) -> Option<ExtractionCommand> {
+    let Some(arg) = arg.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Some(ExtractionCommand::Show);
+    };
+    let lowered = arg.to_lowercase();
+    let mut parts = lowered.split_whitespace();
+    let source = match parts.next()? {
+        "youtube" | "yt" => ExtractionSource::YouTube,
+        "twitter" | "x" => ExtractionSource::Twitter,
+        "telegraph" => ExtractionSource::Telegraph,
+        "all" => ExtractionSource::All,
+        _ => return None,
+    };
+    let enabled = match parts.next()? {
+        "on" | "enable" | "true" => true,
+        "off" | "disable" | "false" => false,
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(ExtractionCommand::Set(source, enabled))
+}
+
+fn apply_extraction_toggle(
+    mut settings: ChatExtractionSettings,
+    source: ExtractionSource,
+    enabled: bool,
+) -> ChatExtractionSettings {
+    match source {
+        ExtractionSource::YouTube => settings.youtube = enabled,
+        ExtractionSource::Twitter => settings.twitter = enabled,
+        ExtractionSource::Telegraph => settings.telegraph = enabled,
+        ExtractionSource::All => {
+            settings = ChatExtractionSettings {
+                youtube: enabled,
+                twitter: enabled,
+                telegraph: enabled,
+            }
+        }
+    }
+    settings
+}
+
+fn describe_extraction_settings(settings: ChatExtractionSettings) -> String {
+    let state = |enabled: bool| if enabled { "on" } else { "off" };
+    format!(
+        "Link extraction for this chat:\nYouTube: {}\nTwitter: {}\nTelegraph: {}",
+        state(settings.youtube),
+        state(settings.twitter),
+        state(settings.telegraph)
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CommandToggle {
+    Show,
+    Set(String, bool),
+}
+
+fn parse_command_toggle(arg: Option<&str>) -> Option<CommandToggle> {
+    let Some(arg) = arg.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Some(CommandToggle::Show);
+    };
+    let lowered = arg.to_lowercase();
+    let mut parts = lowered.split_whitespace();
+    let name = parts.next()?.trim_start_matches('/');
+    let name = name.split_once('@').map_or(name, |(name, _)| name);
+    if !is_toggleable_command(name) {
+        return None;
+    }
+    let enabled = match parts.next()? {
+        "on" | "enable" | "true" => true,
+        "off" | "disable" | "false" => false,
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(CommandToggle::Set(name.to_string(), enabled))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TemperatureCommand {
+    Show,
+    Set(ChatSampling),
+    Reset,
+}
+
+fn parse_unit_interval(value: &str) -> Option<f32> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|value| (0.0..=1.0).contains(value))
+}
+
+fn parse_temperature_command(arg: Option<&str>) -> Option<TemperatureCommand> {
+    let Some(arg) = arg.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Some(TemperatureCommand::Show);
+    };
+    if matches!(arg.to_lowercase().as_str(), "reset" | "default" | "off") {
+        return Some(TemperatureCommand::Reset);
+    }
+    let mut parts = arg.split_whitespace();
+    let temperature = parse_unit_interval(parts.next()?)?;
+    let top_p = match parts.next() {
+        Some(value) => Some(parse_unit_interval(value)?),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(TemperatureCommand::Set(ChatSampling {
+        temperature: Some(temperature),
+        top_p,
+    }))
+}
+
+fn describe_chat_sampling(sampling: ChatSampling) -> String {
+    let value = |value: Option<f32>| value.map_or("global default".to_string(), |v| v.to_string());
+    format!(
+        "Sampling for this chat:\nTemperature: {}\nTop P: {}",
+        value(sampling.temperature),
+        value(sampling.top_p)
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimezoneCommand {
+    Show,
+    Set(Tz),
+    Reset,
+}
+
+fn parse_timezone_command(arg: Option<&str>) -> Option<TimezoneCommand> {
+    let Some(arg) = arg.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Some(TimezoneCommand::Show);
+    };
+    if matches!(arg.to_lowercase().as_str(), "reset" | "default" | "off") {
+        return Some(TimezoneCommand::Reset);
+    }
+    parse_timezone(arg).map(TimezoneCommand::Set)
+}
+
+fn describe_chat_timezone(timezone: Tz) -> String {
+    format!("Timestamps in this chat are shown in {}.", timezone.name())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuietCommand {
+    Show,
+    Set(bool),
+    Reset,
+}
+
+fn parse_quiet_command(arg: Option<&str>) -> Option<QuietCommand> {
+    let Some(arg) = arg.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Some(QuietCommand::Show);
+    };
+    match arg.to_lowercase().as_str() {
+        "on" | "true" | "yes" => Some(QuietCommand::Set(true)),
+        "off" | "false" | "no" => Some(QuietCommand::Set(false)),
+        "reset" | "default" => Some(QuietCommand::Reset),
+        _ => None,
+    }
+}
+
+fn describe_chat_quiet_mode(quiet: bool) -> &'static str {
+    if quiet {
+        "Quiet mode is on: commands in this chat send only their final answer."
+    } else {
+        "Quiet mode is off: commands in this chat show a processing message while they work."
+    }
+}
+
+fn join_disabled_commands(commands: &HashSet<String>) -> String {
+    let mut names: Vec<&str> = commands.iter().map(String::as_str).collect();
+    names.sort_unstable();
+    names.join(",")
+}
+
+fn describe_disabled_commands(commands: &HashSet<String>) -> String {
+    if commands.is_empty() {
+        return "No commands are disabled in this chat.".to_string();
+    }
+    let names = join_disabled_commands(commands)
+        .split(',')
+        .map(|name| format!("/{name}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("Disabled in this chat: {names}")
+}
+
+pub async fn quiet_handler(
+    bot: Bot,
+    state: AppState,
+    message: Message,
+    arg: Option<String>,
+) -> Result<()> {
+    if !check_admin_access(&bot, &message, "quiet").await {
+        return Ok(());
+    }
+
+    let Some(command) = parse_quiet_command(arg.as_deref()) else {
+        bot.send_message(message.chat.id, QUIET_USAGE)
+            .reply_parameters(ReplyParameters::new(message.id))
+            .await?;
+        return Ok(());
+    };
+
+    let chat_id = message.chat.id.0;
+    let settings = match command {
+        QuietCommand::Show => state.chat_settings.get(chat_id),
+        QuietCommand::Set(quiet) => {
+            state
+                .update_chat_settings(chat_id, |settings| settings.quiet_mode = Some(quiet))
+                .await?
+        }
+        QuietCommand::Reset => {
+            state
+                .update_chat_settings(chat_id, |settings| settings.quiet_mode = None)
+                .await?
+        }
+    };
+
+    bot.send_message(
+        message.chat.id,
+        describe_chat_quiet_mode(chat_quiet_mode(&settings)),
+    )
+    .reply_parameters(ReplyParameters::new(message.id))
+    .await?;
+    Ok(())
+}
+
+pub async fn timezone_handler(
+    bot: Bot,
+    state: AppState,
+    message: Message,
+    arg: Option<String>,
+) -> Result<()> {
+    if !check_admin_access(&bot, &message, "timezone").await {
+        return Ok(());
+    }
+
+    let Some(command) = parse_timezone_command(arg.as_deref()) else {
+        bot.send_message(message.chat.id, TIMEZONE_USAGE)
+            .reply_parameters(ReplyParameters::new(message.id))
+            .await?;
+        return Ok(());
+    };
+
+    let chat_id = message.chat.id.0;
+    let timezone = match command {
+        TimezoneCommand::Show => chat_timezone(&state.chat_settings.get(chat_id)),
+        TimezoneCommand::Set(timezone) => timezone,
+        TimezoneCommand::Reset => Tz::UTC,
+    };
+    if command != TimezoneCommand::Show {
+        let stored = Some(timezone.name().to_string()).filter(|_| timezone != Tz::UTC);
+        state
+            .update_chat_settings(chat_id, |settings| settings.timezone = stored)
+            .await?;
+    }
+
+    bot.send_message(message.chat.id, describe_chat_timezone(timezone))
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+    Ok(())
+}
+
+pub async fn temperature_handler(
+    bot: Bot,
+    state: AppState,
+    message: Message,
+    arg: Option<String>,
+) -> Result<()> {
+    if !check_admin_access(&bot, &message, "temperature").await {
+        return Ok(());
+    }
+
+    let Some(command) = parse_temperature_command(arg.as_deref()) else {
+        bot.send_message(message.chat.id, TEMPERATURE_USAGE)
+            .reply_parameters(ReplyParameters::new(message.id))
+            .await?;
+        return Ok(());
+    };
+
+    let chat_id = message.chat.id.0;
+    let sampling = match command {
+        TemperatureCommand::Show => ChatSampling::from(&state.chat_settings.get(chat_id)),
+        TemperatureCommand::Set(sampling) => sampling,
+        TemperatureCommand::Reset => ChatSampling::default(),
+    };
+    if command != TemperatureCommand::Show {
+        state
+            .update_chat_settings(chat_id, |settings| {
+                settings.temperature = sampling.temperature.map(f64::from);
+                settings.top_p = sampling.top_p.map(f64::from);
+            })
+            .await?;
+    }
+
+    bot.send_message(message.chat.id, describe_chat_sampling(sampling))
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+    Ok(())
+}
+
+pub async fn command_toggle_handler(
+    bot: Bot,
+    state: AppState,
+    message: Message,
+    arg: Option<String>,
+) -> Result<()> {
+    if !check_admin_access(&bot, &message, "command").await {
+        return Ok(());
+    }
+
+    let Some(toggle) = parse_command_toggle(arg.as_deref()) else {
+        bot.send_message(message.chat.id, COMMAND_TOGGLE_USAGE)
+            .reply_parameters(ReplyParameters::new(message.id))
+            .await?;
+        return Ok(());
+    };
+
+    let chat_id = message.chat.id.0;
+    let settings = match toggle {
+        CommandToggle::Show => state.chat_settings.get(chat_id),
+        CommandToggle::Set(name, enabled) => {
+            state
+                .update_chat_settings(chat_id, |settings| {
+                    let mut commands = chat_disabled_commands(settings);
+                    if enabled {
+                        commands.remove(&name);
+                    } else {
+                        commands.insert(name);
+                    }
+                    settings.disabled_commands =
+                        Some(join_disabled_commands(&commands)).filter(|value| !value.is_empty());
+                })
+                .await?
+        }
+    };
+    let commands = chat_disabled_commands(&settings);
+
+    bot.send_message(message.chat.id, describe_disabled_commands(&commands))
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+    Ok(())
+}
+
+pub async fn extraction_handler(
+    bot: Bot,
+    state: AppState,
+    message: Message,
+    arg: Option<String>,
+) -> Result<()> {
+    if !check_admin_access(&bot, &message, "extraction").await {
+        return Ok(());
+    }
+
+    let Some(command) = parse_extraction_command(arg.as_deref()) else {
+        bot.send_message(message.chat.id, EXTRACTION_USAGE)
+            .reply_parameters(ReplyParameters::new(message.id))
+            .await?;
+        return Ok(());
+    };
+
+    let chat_id = message.chat.id.0;
+    let row = match command {
+        ExtractionCommand::Show => state.chat_settings.get(chat_id),
+        ExtractionCommand::Set(source, enabled) => {
+            state
+                .update_chat_settings(chat_id, |row| {
+                    let settings = apply_extraction_toggle(
+                        ChatExtractionSettings::from(&*row),
+                        source,
+                        enabled,
+                    );
+                    row.extract_youtube = settings.youtube;
+                    row.extract_twitter = settings.twitter;
+                    row.extract_telegraph = settings.telegraph;
+                })
+                .await?
+        }
+    };
+    let settings = ChatExtractionSettings::from(&row);
+
+    bot.send_message(message.chat.id, describe_extraction_settings(settings))
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+    Ok(())
+}
+
+pub async fn telegraph_author_handler(
+    bot: Bot,
+    state: AppState,
+    message: Message,
+    arg: Option<String>,
+) -> Result<()> {
+    if !check_admin_access(&bot, &message, "telegraphauthor").await {
+        return Ok(());
+    }
+
+    let Some(command) = parse_telegraph_author_command(arg.as_deref()) else {
+        bot.send_message(message.chat.id, TELEGRAPH_AUTHOR_USAGE)
+            .reply_parameters(ReplyParameters::new(message.id))
+            .await?;
+        return Ok(());
+    };
+
+    let chat_id = message.chat.id.0;
+    let reply = match command {
+        TelegraphAuthorCommand::Show => describe_telegraph_author(
+            TelegraphAuthor::chat_override(&state.chat_settings.get(chat_id)).as_ref(),
+        ),
+        TelegraphAuthorCommand::Set(author) => {
+            let url = Some(author.url.clone()).filter(|url| !url.is_empty());
+            let reply = describe_telegraph_author(Some(&author));
+            state
+                .update_chat_settings(chat_id, |settings| {
+                    settings.telegraph_author_name = Some(author.name);
+                    settings.telegraph_author_url = url;
+                })
+                .await?;
+            reply
+        }
+        TelegraphAuthorCommand::Reset => {
+            state
+                .update_chat_settings(chat_id, |settings| {
+                    settings.telegraph_author_name = None;
+                    settings.telegraph_author_url = None;
+                })
+                .await?;
+            describe_telegraph_author(None)
+        }
+    };
+
+    bot.send_message(message.chat.id, reply)
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_telegraph_author_command_accepts_name_and_optional_url() {
+        assert_eq!(
+            parse_telegraph_author_command(None),
+            Some(TelegraphAuthorCommand::Show)
+        );
+        assert_eq!(
+            parse_telegraph_author_command(Some(" Reset ")),
+            Some(TelegraphAuthorCommand::Reset)
+        );
+        assert_eq!(
+            parse_telegraph_author_command(Some("Rust 中文社区 | https://example.org")),
+            Some(TelegraphAuthorCommand::Set(TelegraphAuthor {
+                name: "Rust 中文社区".to_string(),
+                url: "https://example.org".to_string(),
+            }))
+        );
+        assert_eq!(
+            parse_telegraph_author_command(Some("Book Club")),
+            Some(TelegraphAuthorCommand::Set(TelegraphAuthor {
+                name: "Book Club".to_string(),
+                url: String::new(),
+            }))
+        );
+        assert_eq!(
+            parse_telegraph_author_command(Some("Club | javascript:alert(1)")),
+            None
+        );
+        assert_eq!(parse_telegraph_author_command(Some("| https://x.y")), None);
+    }
+
+    #[test]
+    fn parse_extraction_command_toggles_one_or_all_sources() {
+        assert_eq!(
+            parse_extraction_command(Some("  ")),
+            Some(ExtractionCommand::Show)
+        );
+        assert_eq!(
+            parse_extraction_command(Some("Twitter OFF")),
+            Some(ExtractionCommand::Set(ExtractionSource::Twitter, false))
+        );
+        assert_eq!(parse_extraction_command(Some("youtube")), None);
+        assert_eq!(parse_extraction_command(Some("reddit off")), None);
+        assert_eq!(parse_extraction_command(Some("telegraph on now")), None);
+
+        let settings = apply_extraction_toggle(
+            ChatExtractionSettings::default(),
+            ExtractionSource::Telegraph,
+            false,
+        );
+        assert!(settings.youtube && settings.twitter && !settings.telegraph);
+        assert_eq!(
+            apply_extraction_toggle(settings, ExtractionSource::All, true),
+            ChatExtractionSettings::default()
+        );
+    }
+
+    #[test]
+    fn parse_command_toggle_accepts_command_names_only() {
+        assert_eq!(parse_command_toggle(None), Some(CommandToggle::Show));
+        assert_eq!(
+            parse_command_toggle(Some("/IMG@GroupBot off")),
+            Some(CommandToggle::Set("img".to_string(), false))
+        );
+        assert_eq!(
+            parse_command_toggle(Some("portraitme on")),
+            Some(CommandToggle::Set("portraitme".to_string(), true))
+        );
+        assert_eq!(
+            parse_command_toggle(Some("qq off")),
+            Some(CommandToggle::Set("qq".to_string(), false))
+        );
+        assert_eq!(parse_command_toggle(Some("img")), None);
+        assert_eq!(parse_command_toggle(Some("command off")), None);
+        assert_eq!(parse_command_toggle(Some("im-g off")), None);
+        assert_eq!(parse_command_toggle(Some("imgg off")), None);
+        assert_eq!(parse_command_toggle(Some("start off")), None);
+        assert_eq!(parse_command_toggle(Some("quiet off")), None);
+
+        let settings = crate::db::models::ChatSettingsRow {
+            disabled_commands: Some(" vid,IMG,,img ".to_string()),
+            ..crate::db::models::ChatSettingsRow::new(-1)
+        };
+        let commands = chat_disabled_commands(&settings);
+        assert_eq!(join_disabled_commands(&commands), "img,vid");
+        assert_eq!(
+            describe_disabled_commands(&commands),
+            "Disabled in this chat: /img, /vid"
+        );
+    }
+
+    #[test]
+    fn parse_temperature_command_validates_range() {
+        assert_eq!(
+            parse_temperature_command(None),
+            Some(TemperatureCommand::Show)
+        );
+        assert_eq!(
+            parse_temperature_command(Some("reset")),
+            Some(TemperatureCommand::Reset)
+        );
+        assert_eq!(
+            parse_temperature_command(Some("0.2")),
+            Some(TemperatureCommand::Set(ChatSampling {
+                temperature: Some(0.2),
+                top_p: None,
+            }))
+        );
+        assert_eq!(
+            parse_temperature_command(Some("1 0.9")),
+            Some(TemperatureCommand::Set(ChatSampling {
+                temperature: Some(1.0),
+                top_p: Some(0.9),
+            }))
+        );
+        assert_eq!(parse_temperature_command(Some("1.5")), None);
+        assert_eq!(parse_temperature_command(Some("-0.1")), None);
+        assert_eq!(parse_temperature_command(Some("0.5 2")), None);
+        assert_eq!(parse_temperature_command(Some("warm")), None);
+        assert_eq!(
+            describe_chat_sampling(ChatSampling {
+                temperature: Some(0.3),
+                top_p: None,
+            }),
+            "Sampling for this chat:\nTemperature: 0.3\nTop P: global default"
+        );
+    }
+
+    #[test]
+    fn parse_timezone_command_accepts_iana_names() {
+        assert_eq!(parse_timezone_command(None), Some(TimezoneCommand::Show));
+        assert_eq!(
+            parse_timezone_command(Some("UTC")),
+            Some(TimezoneCommand::Set(Tz::UTC))
+        );
+        assert_eq!(
+            parse_timezone_command(Some(" Europe/Berlin ")),
+            Some(TimezoneCommand::Set(Tz::Europe__Berlin))
+        );
+        assert_eq!(
+            parse_timezone_command(Some("reset")),
+            Some(TimezoneCommand::Reset)
+        );
+        assert_eq!(parse_timezone_command(Some("GMT+25")), None);
+        assert_eq!(
+            describe_chat_timezone(Tz::Asia__Shanghai),
+            "Timestamps in this chat are shown in Asia/Shanghai."
+        );
+    }
+
+    #[test]
+    fn parse_quiet_command_accepts_on_off_and_reset() {
+        assert_eq!(parse_quiet_command(None), Some(QuietCommand::Show));
+        assert_eq!(
+            parse_quiet_command(Some(" ON ")),
+            Some(QuietCommand::Set(true))
+        );
+        assert_eq!(
+            parse_quiet_command(Some("off")),
+            Some(QuietCommand::Set(false))
+        );
+        assert_eq!(
+            parse_quiet_command(Some("reset")),
+            Some(QuietCommand::Reset)
+        );
+        assert_eq!(parse_quiet_command(Some("sometimes")), None);
+    }
+}
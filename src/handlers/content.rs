@@ -1,10 +1,12 @@
 use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
-use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
 use regex::Regex;
 use reqwest::header::CONTENT_TYPE;
 use serde::Deserialize;
@@ -15,13 +17,17 @@ use tokio::task::JoinSet;
 use tracing::{debug, warn};
 
 use crate::config::CONFIG;
+use crate::db::models::ChatSettingsRow;
 use crate::llm::media::{detect_mime_type, download_media, MediaFile, MediaKind};
 use crate::tools::telegraph_extractor::{extract_telegraph_content, TelegraphContent};
 use crate::tools::twitter_extractor::{extract_twitter_content, TwitterContent};
-use crate::utils::http::get_http_client;
+use crate::utils::http::{extraction_get, get_http_client};
 
 const EXTRACTION_CACHE_TTL: Duration = Duration::from_secs(900);
 const EXTRACTION_CACHE_MAX_ENTRIES: usize = 64;
+/// Telegraph rejects page content over 64 KB; the rest is headroom for the
+/// "continued" link appended to every page but the last.
+const TELEGRAPH_PAGE_CONTENT_MAX_BYTES: usize = 60 * 1024;
 
 static YOUTUBE_URL_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
@@ -38,6 +44,8 @@ static TWITTER_URL_REGEX: Lazy<Regex> = Lazy::new(|| {
     )
     .expect("valid twitter url regex")
 });
+static BARE_URL_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"https?://[^\s\)>"]+"#).expect("valid bare url regex"));
 static MARKDOWN_LINK_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r#"\[[^\]]*\]\((https?://[^)]+)\)"#).expect("valid markdown link regex")
 });
@@ -62,11 +70,46 @@ enum ExternalMediaKind {
     Video,
 }
 
+tokio::task_local! {
+    static EXTRACTION_BUDGET: Arc<AtomicUsize>;
+}
+
 static TELEGRAPH_CACHE: Lazy<Mutex<HashMap<String, TelegraphCacheEntry>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 static TWITTER_CACHE: Lazy<Mutex<HashMap<String, TwitterCacheEntry>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+/// Runs `future` with a `MAX_EXTRACTED_URLS_TOTAL` link budget shared by the
+/// Telegraph, Twitter, and YouTube extractors. Outside a scope, or with the
+/// setting at `0`, only each extractor's own cap applies.
+pub async fn with_extraction_budget<F: Future>(future: F) -> F::Output {
+    match CONFIG.max_extracted_urls_total {
+        0 => future.await,
+        total => with_extraction_budget_of(total, future).await,
+    }
+}
+
+async fn with_extraction_budget_of<F: Future>(total: usize, future: F) -> F::Output {
+    EXTRACTION_BUDGET
+        .scope(Arc::new(AtomicUsize::new(total)), future)
+        .await
+}
+
+/// Takes up to `requested` links from the active budget and returns how many
+/// the caller may extract.
+pub(crate) fn reserve_extraction_budget(requested: usize) -> usize {
+    EXTRACTION_BUDGET
+        .try_with(|budget| {
+            let previous = budget
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |remaining| {
+                    Some(remaining.saturating_sub(requested))
+                })
+                .unwrap_or_default();
+            previous.min(requested)
+        })
+        .unwrap_or(requested)
+}
+
 fn truncate_for_log(value: &str, limit: usize) -> String {
     if value.chars().count() <= limit {
         return value.to_string();
@@ -393,11 +436,28 @@ fn markdown_to_telegraph_nodes(content: &str) -> Vec<serde_json::Value> {
                     attrs: None,
                     children: Vec::new(),
                 })),
-                Tag::CodeBlock(_kind) => stack.push(StackEntry::Node(NodeBuilder {
-                    tag: "pre".to_string(),
-                    attrs: None,
-                    children: Vec::new(),
-                })),
+                Tag::CodeBlock(kind) => {
+                    // Telegraph has no attribute for the language, so a
+                    // fence's label goes in a line above the block.
+                    if let CodeBlockKind::Fenced(language) = &kind {
+                        let language = language.split_whitespace().next().unwrap_or_default();
+                        if !language.is_empty() {
+                            push_value(
+                                &mut stack,
+                                &mut root,
+                                json!({
+                                    "tag": "p",
+                                    "children": [{ "tag": "code", "children": [language] }]
+                                }),
+                            );
+                        }
+                    }
+                    stack.push(StackEntry::Node(NodeBuilder {
+                        tag: "pre".to_string(),
+                        attrs: None,
+                        children: Vec::new(),
+                    }))
+                }
                 Tag::Link(_, dest, _) => {
                     let mut attrs = serde_json::Map::new();
                     attrs.insert(
@@ -522,31 +582,244 @@ struct TelegraphCreateResult {
     url: String,
 }
 
-pub async fn create_telegraph_page(title: &str, content: &str) -> Option<String> {
-    if CONFIG.telegraph_access_token.trim().is_empty() {
-        warn!("Telegraph access token missing; skipping page creation");
-        return None;
+/// Byline shown on generated Telegraph pages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelegraphAuthor {
+    pub name: String,
+    pub url: String,
+}
+
+impl TelegraphAuthor {
+    pub fn global() -> Self {
+        Self {
+            name: CONFIG.telegraph_author_name.clone(),
+            url: CONFIG.telegraph_author_url.clone(),
+        }
+    }
+
+    /// The chat's `/telegraphauthor` byline, if it set one.
+    pub fn chat_override(settings: &ChatSettingsRow) -> Option<Self> {
+        settings.telegraph_author_name.as_ref().map(|name| Self {
+            name: name.clone(),
+            url: settings.telegraph_author_url.clone().unwrap_or_default(),
+        })
+    }
+
+    /// Byline for pages created for the chat: its override, else the global
+    /// `TELEGRAPH_AUTHOR_NAME`/`TELEGRAPH_AUTHOR_URL`.
+    pub fn for_chat(settings: &ChatSettingsRow) -> Self {
+        Self::chat_override(settings).unwrap_or_else(Self::global)
+    }
+}
+
+/// Which link extractors may run for a chat. Every source is on unless an
+/// admin turned it off with `/extraction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChatExtractionSettings {
+    pub youtube: bool,
+    pub twitter: bool,
+    pub telegraph: bool,
+}
+
+impl Default for ChatExtractionSettings {
+    fn default() -> Self {
+        Self {
+            youtube: true,
+            twitter: true,
+            telegraph: true,
+        }
     }
+}
 
+impl From<&ChatSettingsRow> for ChatExtractionSettings {
+    fn from(settings: &ChatSettingsRow) -> Self {
+        Self {
+            youtube: settings.extract_youtube,
+            twitter: settings.extract_twitter,
+            telegraph: settings.extract_telegraph,
+        }
+    }
+}
+
+fn telegraph_node_text(node: &serde_json::Value, out: &mut String) {
+    match node {
+        serde_json::Value::String(text) => out.push_str(text),
+        serde_json::Value::Object(obj) => {
+            if let Some(serde_json::Value::Array(children)) = obj.get("children") {
+                for child in children {
+                    telegraph_node_text(child, out);
+                }
+            }
+            if obj.get("tag").and_then(|tag| tag.as_str()) == Some("img") {
+                out.push_str("[image]");
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Lines of code inside fenced code blocks in `content`.
+pub fn fenced_code_line_count(content: &str) -> usize {
+    let mut in_fence = false;
+    let mut code = String::new();
+    for event in Parser::new(content) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(_))) => in_fence = true,
+            Event::End(Tag::CodeBlock(_)) => in_fence = false,
+            Event::Text(text) if in_fence => code.push_str(&text),
+            _ => {}
+        }
+    }
+    code.lines().count()
+}
+
+/// Converts `content` to Telegraph nodes, falling back to the raw text in a
+/// single paragraph when the conversion yields nothing visible.
+fn telegraph_nodes_or_fallback(content: &str) -> Vec<serde_json::Value> {
     let nodes = markdown_to_telegraph_nodes(content);
-    let content_json = serde_json::to_string(&nodes).unwrap_or_else(|_| "[]".to_string());
-    let form = vec![
-        (
-            "access_token".to_string(),
-            CONFIG.telegraph_access_token.clone(),
-        ),
-        (
-            "author_name".to_string(),
-            CONFIG.telegraph_author_name.clone(),
-        ),
-        (
-            "author_url".to_string(),
-            CONFIG.telegraph_author_url.clone(),
-        ),
+    let mut visible = String::new();
+    for node in &nodes {
+        telegraph_node_text(node, &mut visible);
+    }
+    if !visible.trim().is_empty() {
+        return nodes;
+    }
+    let raw = content.trim();
+    let raw = if raw.is_empty() { "(empty)" } else { raw };
+    vec![json!({ "tag": "p", "children": [raw] })]
+}
+
+fn telegraph_node_size(node: &serde_json::Value) -> usize {
+    serde_json::to_string(node).map_or(0, |json| json.len()) + 1
+}
+
+/// Splits `text` into pieces of at most `max_bytes`, on char boundaries.
+fn chunk_text_by_bytes(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for ch in text.chars() {
+        if !current.is_empty() && current.len() + ch.len_utf8() > max_bytes {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push(ch);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Groups top-level nodes into pages whose serialized content stays under
+/// `max_bytes`. A node that is too large on its own is flattened to plain
+/// text and split across paragraphs.
+fn split_telegraph_nodes(
+    nodes: Vec<serde_json::Value>,
+    max_bytes: usize,
+) -> Vec<Vec<serde_json::Value>> {
+    let mut pieces = Vec::new();
+    for node in nodes {
+        if telegraph_node_size(&node) <= max_bytes {
+            pieces.push(node);
+            continue;
+        }
+        let mut text = String::new();
+        telegraph_node_text(&node, &mut text);
+        // JSON escaping can grow text; half the budget keeps chunks safe.
+        for chunk in chunk_text_by_bytes(&text, (max_bytes / 2).max(1)) {
+            pieces.push(json!({ "tag": "p", "children": [chunk] }));
+        }
+    }
+
+    let mut pages = Vec::new();
+    let mut current = Vec::new();
+    let mut current_bytes = 2;
+    for node in pieces {
+        let size = telegraph_node_size(&node);
+        if !current.is_empty() && current_bytes + size > max_bytes {
+            pages.push(std::mem::take(&mut current));
+            current_bytes = 2;
+        }
+        current_bytes += size;
+        current.push(node);
+    }
+    if !current.is_empty() || pages.is_empty() {
+        pages.push(current);
+    }
+    pages
+}
+
+fn telegraph_continued_node(next_url: &str) -> serde_json::Value {
+    json!({
+        "tag": "p",
+        "children": [{
+            "tag": "a",
+            "attrs": { "href": next_url },
+            "children": ["Continued on the next page →"]
+        }]
+    })
+}
+
+fn telegraph_page_title(title: &str, index: usize, total: usize) -> String {
+    if total <= 1 {
+        title.to_string()
+    } else {
+        format!("{title} ({}/{total})", index + 1)
+    }
+}
+
+fn build_telegraph_form(
+    access_token: &str,
+    author: &TelegraphAuthor,
+    title: &str,
+    content_json: String,
+) -> Vec<(String, String)> {
+    vec![
+        ("access_token".to_string(), access_token.to_string()),
+        ("author_name".to_string(), author.name.clone()),
+        ("author_url".to_string(), author.url.clone()),
         ("title".to_string(), title.to_string()),
         ("content".to_string(), content_json),
         ("return_content".to_string(), "false".to_string()),
-    ];
+    ]
+}
+
+/// Publishes `content` as a Telegraph page signed by `author`. Content over
+/// Telegraph's size limit is split across pages chained by "continued" links,
+/// and the first page's URL is returned.
+pub async fn create_telegraph_page(
+    title: &str,
+    content: &str,
+    author: &TelegraphAuthor,
+) -> Option<String> {
+    if CONFIG.telegraph_access_token.trim().is_empty() {
+        warn!("Telegraph access token missing; skipping page creation");
+        return None;
+    }
+
+    let pages = split_telegraph_nodes(
+        telegraph_nodes_or_fallback(content),
+        TELEGRAPH_PAGE_CONTENT_MAX_BYTES,
+    );
+    let total = pages.len();
+    // Publish back to front so each page can link to the one after it.
+    let mut next_url: Option<String> = None;
+    for (index, mut nodes) in pages.into_iter().enumerate().rev() {
+        if let Some(url) = next_url.as_deref() {
+            nodes.push(telegraph_continued_node(url));
+        }
+        let page_title = telegraph_page_title(title, index, total);
+        next_url = Some(publish_telegraph_nodes(&page_title, &nodes, author).await?);
+    }
+    next_url
+}
+
+async fn publish_telegraph_nodes(
+    title: &str,
+    nodes: &[serde_json::Value],
+    author: &TelegraphAuthor,
+) -> Option<String> {
+    let content_json = serde_json::to_string(nodes).unwrap_or_else(|_| "[]".to_string());
+    let form = build_telegraph_form(&CONFIG.telegraph_access_token, author, title, content_json);
 
     let client = get_http_client();
     let response = client
@@ -580,6 +853,7 @@ pub fn extract_youtube_urls(text: &str, max_urls: usize) -> (String, Vec<String>
     }
 
     let mut matches = YOUTUBE_URL_REGEX.captures_iter(text).collect::<Vec<_>>();
+    let max_urls = reserve_extraction_budget(matches.len().min(max_urls));
     let mut urls = Vec::new();
     let mut new_text = text.to_string();
     let mut count = 0;
@@ -606,6 +880,17 @@ pub fn extract_youtube_urls(text: &str, max_urls: usize) -> (String, Vec<String>
     (new_text, urls)
 }
 
+/// Whether `text` links to pages that none of the Telegraph, Twitter/X, or
+/// YouTube extractors handle, so Gemini's `url_context` tool should fetch them.
+pub fn has_unextracted_urls(text: &str) -> bool {
+    BARE_URL_REGEX.find_iter(text).any(|m| {
+        let url = clean_url_candidate(m.as_str());
+        !is_telegraph_url(url)
+            && !TWITTER_URL_REGEX.is_match(url)
+            && !YOUTUBE_URL_REGEX.is_match(url)
+    })
+}
+
 fn clean_url_candidate(url: &str) -> &str {
     url.trim_end_matches(|ch: char| {
         matches!(
@@ -749,7 +1034,8 @@ pub async fn extract_telegraph_urls_and_content(
     urls.sort();
     urls.dedup();
 
-    let ordered_urls = urls.into_iter().take(max_urls).collect::<Vec<_>>();
+    let allowed = reserve_extraction_budget(urls.len().min(max_urls));
+    let ordered_urls = urls.into_iter().take(allowed).collect::<Vec<_>>();
     let semaphore = Arc::new(Semaphore::new(CONFIG.external_enrich_fanout));
     let mut join_set = JoinSet::new();
     for url in ordered_urls.iter().cloned() {
@@ -806,6 +1092,85 @@ pub async fn extract_telegraph_urls_and_content(
     (new_text, extracted)
 }
 
+/// Runs the Telegraph extractor unless the chat disabled it, in which case
+/// the text (and its links) is returned untouched.
+pub async fn extract_telegraph_for_chat(
+    extraction: ChatExtractionSettings,
+    text: &str,
+    message_entities: Option<&[MessageEntityRef<'_>]>,
+    max_urls: usize,
+) -> (String, Vec<TelegraphContent>) {
+    if !extraction.telegraph {
+        return (text.to_string(), Vec::new());
+    }
+    extract_telegraph_urls_and_content(text, message_entities, max_urls).await
+}
+
+/// Twitter counterpart of [`extract_telegraph_for_chat`].
+pub async fn extract_twitter_for_chat(
+    extraction: ChatExtractionSettings,
+    text: &str,
+    message_entities: Option<&[MessageEntityRef<'_>]>,
+    max_urls: usize,
+) -> (String, Vec<TwitterContent>) {
+    if !extraction.twitter {
+        return (text.to_string(), Vec::new());
+    }
+    extract_twitter_urls_and_content(text, message_entities, max_urls).await
+}
+
+/// Keeps the Telegraph result and appends what the Twitter extractor added
+/// after `text`, so sections land in the same order as a sequential run.
+fn merge_extracted_text(text: &str, telegraph_text: String, twitter_text: &str) -> String {
+    let mut merged = telegraph_text;
+    merged.push_str(twitter_text.strip_prefix(text).unwrap_or_default());
+    merged
+}
+
+async fn join_link_extractions<T, W>(
+    text: &str,
+    telegraph: impl Future<Output = (String, Vec<T>)>,
+    twitter: impl Future<Output = (String, Vec<W>)>,
+) -> (String, Vec<T>, Vec<W>) {
+    let ((telegraph_text, pages), (twitter_text, tweets)) = tokio::join!(telegraph, twitter);
+    (
+        merge_extracted_text(text, telegraph_text, &twitter_text),
+        pages,
+        tweets,
+    )
+}
+
+/// Runs the Telegraph and Twitter extractors on `text` concurrently. Each
+/// extractor reports its own failures inline, so one failing source never
+/// drops the other's content.
+pub async fn extract_links_and_content(
+    text: &str,
+    message_entities: Option<&[MessageEntityRef<'_>]>,
+    max_urls: usize,
+) -> (String, Vec<TelegraphContent>, Vec<TwitterContent>) {
+    join_link_extractions(
+        text,
+        extract_telegraph_urls_and_content(text, message_entities, max_urls),
+        extract_twitter_urls_and_content(text, message_entities, max_urls),
+    )
+    .await
+}
+
+/// [`extract_links_and_content`] honoring the chat's `/extraction` settings.
+pub async fn extract_links_for_chat(
+    extraction: ChatExtractionSettings,
+    text: &str,
+    message_entities: Option<&[MessageEntityRef<'_>]>,
+    max_urls: usize,
+) -> (String, Vec<TelegraphContent>, Vec<TwitterContent>) {
+    join_link_extractions(
+        text,
+        extract_telegraph_for_chat(extraction, text, message_entities, max_urls),
+        extract_twitter_for_chat(extraction, text, message_entities, max_urls),
+    )
+    .await
+}
+
 pub async fn extract_twitter_urls_and_content(
     text: &str,
     message_entities: Option<&[MessageEntityRef<'_>]>,
@@ -854,7 +1219,8 @@ pub async fn extract_twitter_urls_and_content(
     urls.sort();
     urls.dedup();
 
-    let ordered_urls = urls.into_iter().take(max_urls).collect::<Vec<_>>();
+    let allowed = reserve_extraction_budget(urls.len().min(max_urls));
+    let ordered_urls = urls.into_iter().take(allowed).collect::<Vec<_>>();
     let semaphore = Arc::new(Semaphore::new(CONFIG.external_enrich_fanout));
     let mut join_set = JoinSet::new();
     for url in ordered_urls.iter().cloned() {
@@ -940,8 +1306,7 @@ fn image_mime_from_url(url: &str) -> Option<&'static str> {
 }
 
 async fn download_image_with_content_type(url: &str, source: &str) -> Option<(Vec<u8>, String)> {
-    let client = get_http_client();
-    let response = match client.get(url).send().await {
+    let response = match extraction_get(url).send().await {
         Ok(resp) => resp,
         Err(err) => {
             warn!(
@@ -1208,6 +1573,67 @@ pub async fn download_twitter_media(
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn disabled_chat_extractors_leave_links_in_the_text() {
+        let mut settings = ChatSettingsRow::new(-1_000_141);
+        assert_eq!(
+            ChatExtractionSettings::from(&settings),
+            ChatExtractionSettings::default()
+        );
+        settings.extract_twitter = false;
+        settings.extract_telegraph = false;
+        let extraction = ChatExtractionSettings::from(&settings);
+        assert!(extraction.youtube);
+
+        let text = "see https://x.com/rustlang/status/1 and https://telegra.ph/Some-Page-01-01";
+        let (twitter_text, tweets) = extract_twitter_for_chat(extraction, text, None, 5).await;
+        assert_eq!(twitter_text, text);
+        assert!(tweets.is_empty());
+        let (telegraph_text, pages) = extract_telegraph_for_chat(extraction, text, None, 5).await;
+        assert_eq!(telegraph_text, text);
+        assert!(pages.is_empty());
+    }
+
+    #[test]
+    fn has_unextracted_urls_ignores_links_the_extractors_handle() {
+        assert!(has_unextracted_urls("see https://example.com/post."));
+        assert!(has_unextracted_urls(
+            "https://telegra.ph/abc and https://www.rust-lang.org/learn"
+        ));
+        assert!(!has_unextracted_urls(
+            "https://x.com/user/status/1 https://youtu.be/dQw4w9WgXcQ https://t.me/channel/5"
+        ));
+        assert!(!has_unextracted_urls("no links here"));
+    }
+
+    #[test]
+    fn telegraph_form_uses_per_chat_author_when_configured() {
+        let mut settings = ChatSettingsRow::new(-100_127_001);
+        settings.telegraph_author_name = Some("Rust 中文社区".to_string());
+        settings.telegraph_author_url = Some("https://example.org/community".to_string());
+
+        let form = build_telegraph_form(
+            "token",
+            &TelegraphAuthor::for_chat(&settings),
+            "Title",
+            "[]".to_string(),
+        );
+        let field = |name: &str| {
+            form.iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(field("author_name"), Some("Rust 中文社区"));
+        assert_eq!(field("author_url"), Some("https://example.org/community"));
+
+        settings.telegraph_author_name = None;
+        assert_eq!(TelegraphAuthor::chat_override(&settings), None);
+        assert_eq!(
+            TelegraphAuthor::for_chat(&settings),
+            TelegraphAuthor::global()
+        );
+    }
+
     #[test]
     fn markdown_tables_render_as_readable_telegraph_lists() {
         let nodes = markdown_to_telegraph_nodes(
@@ -1237,4 +1663,129 @@ mod tests {
 
         assert_eq!(tags, vec!["p", "ul", "p"]);
     }
+
+    #[test]
+    fn fenced_code_blocks_render_as_pre_with_language_labels() {
+        let answer = "Two ways:\n\n```rust\nfn main() {}\n```\n\nor\n\n```\necho hi\n```\n";
+        assert_eq!(fenced_code_line_count(answer), 2);
+        assert_eq!(
+            fenced_code_line_count("Inline `code` only.\n\n    indented"),
+            0
+        );
+
+        let nodes = markdown_to_telegraph_nodes(answer);
+        assert_eq!(
+            nodes,
+            vec![
+                json!({ "tag": "p", "children": ["Two ways:"] }),
+                json!({ "tag": "p", "children": [{ "tag": "code", "children": ["rust"] }] }),
+                json!({ "tag": "pre", "children": ["fn main() {}\n"] }),
+                json!({ "tag": "p", "children": ["or"] }),
+                json!({ "tag": "pre", "children": ["echo hi\n"] }),
+            ]
+        );
+    }
+
+    #[test]
+    fn telegraph_nodes_fall_back_to_raw_text_when_conversion_is_empty() {
+        let header_only = "| A | B |\n|---|---|";
+        assert!(markdown_to_telegraph_nodes(header_only).is_empty());
+        assert_eq!(
+            telegraph_nodes_or_fallback(header_only),
+            vec![json!({ "tag": "p", "children": [header_only] })]
+        );
+        assert_eq!(
+            telegraph_nodes_or_fallback("  \n"),
+            vec![json!({ "tag": "p", "children": ["(empty)"] })]
+        );
+        assert_eq!(
+            telegraph_nodes_or_fallback("Hello"),
+            markdown_to_telegraph_nodes("Hello")
+        );
+    }
+
+    #[test]
+    fn oversized_telegraph_content_splits_into_pages_under_the_limit() {
+        let paragraphs = (0..40)
+            .map(|index| format!("Paragraph {index} {}", "word ".repeat(20)))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let giant = format!("{paragraphs}\n\n{}", "长".repeat(2_000));
+        let max_bytes = 1_024;
+        let pages = split_telegraph_nodes(telegraph_nodes_or_fallback(&giant), max_bytes);
+
+        assert!(pages.len() > 1);
+        for page in &pages {
+            assert!(!page.is_empty());
+            let size = serde_json::to_string(page).unwrap().len();
+            assert!(size <= max_bytes, "page of {size} bytes exceeds the limit");
+        }
+        let mut text = String::new();
+        for node in pages.iter().flatten() {
+            telegraph_node_text(node, &mut text);
+        }
+        assert!(text.contains("Paragraph 0 ") && text.contains("Paragraph 39 "));
+        assert_eq!(text.matches('长').count(), 2_000);
+
+        assert_eq!(split_telegraph_nodes(Vec::new(), max_bytes).len(), 1);
+        assert_eq!(telegraph_page_title("Summary", 1, 3), "Summary (2/3)");
+        assert_eq!(telegraph_page_title("Summary", 0, 1), "Summary");
+    }
+
+    #[tokio::test]
+    async fn shared_extraction_budget_caps_links_across_extractors() {
+        let videos = |ids: &[&str]| {
+            ids.iter()
+                .map(|id| format!("https://youtu.be/{id}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let first = videos(&["aaaaaaaaaaa", "bbbbbbbbbbb"]);
+        let second = videos(&["ccccccccccc", "ddddddddddd", "eeeeeeeeeee"]);
+        let tweet = "see https://x.com/rustlang/status/1";
+
+        let (first_urls, second_urls, (tweet_text, tweets)) = with_extraction_budget_of(3, async {
+            let first_urls = extract_youtube_urls(&first, 5).1;
+            let second_urls = extract_youtube_urls(&second, 5).1;
+            let twitter = extract_twitter_urls_and_content(tweet, None, 5).await;
+            (first_urls, second_urls, twitter)
+        })
+        .await;
+
+        assert_eq!(first_urls.len(), 2);
+        assert_eq!(second_urls.len(), 1);
+        assert_eq!(tweet_text, tweet);
+        assert!(tweets.is_empty());
+        assert_eq!(reserve_extraction_budget(5), 5);
+        assert_eq!(extract_youtube_urls(&second, 5).1.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn link_extractors_run_concurrently_and_keep_section_order() {
+        let delay = Duration::from_millis(300);
+        let text = "links";
+        let telegraph = async {
+            tokio::time::sleep(delay).await;
+            (format!("{text}\n[telegraph]"), vec!["page"])
+        };
+        let twitter = async {
+            tokio::time::sleep(delay).await;
+            (
+                format!("{text}\n[Twitter content extraction failed]"),
+                Vec::<&str>::new(),
+            )
+        };
+
+        let started = tokio::time::Instant::now();
+        let (merged, pages, tweets) = join_link_extractions(text, telegraph, twitter).await;
+
+        // Virtual time only advances by `delay` when both sleeps overlap.
+        assert_eq!(started.elapsed(), delay, "extractors ran sequentially");
+        assert_eq!(
+            merged,
+            "links\n[telegraph]\n[Twitter content extraction failed]"
+        );
+        assert_eq!(pages, vec!["page"]);
+        assert!(tweets.is_empty());
+    }
 }
@@ -0,0 +1,379 @@
+//! Scheduled daily digests.
+//!
+//! Chats opt in with `/digest on [hour]`. A background task wakes every minute,
+//! loads the opted-in chats, and posts a `/tldr`-style summary of the last 24
+//! hours once the configured hour has passed in the chat's `/timezone` (UTC
+//! when unset). The last posting date, also in the chat's zone, is persisted in
+//! `chat_settings`, so due-ness is derived from the wall clock and survives
+//! restarts without double-posting.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use teloxide::prelude::*;
+use teloxide::types::{ParseMode, ReplyParameters};
+use tracing::{error, info, warn};
+
+use crate::config::CONFIG;
+use crate::db::models::{ChatSettingsRow, LlmInvocationInsert};
+use crate::handlers::access::check_admin_access;
+use crate::handlers::commands::summarize_chat_messages;
+use crate::handlers::content::TelegraphAuthor;
+use crate::handlers::responses::send_response;
+use crate::llm::audit::{LlmAuditContext, LLM_TRIGGER_KIND_SCHEDULED};
+use crate::llm::sampling::{with_chat_sampling, ChatSampling};
+use crate::state::AppState;
+use crate::utils::progress::ProgressReporter;
+use crate::utils::telegram::{start_command_chat_action, CommandStage};
+use crate::utils::timezone::chat_timezone;
+
+const DIGEST_TICK: Duration = Duration::from_secs(60);
+const DIGEST_WINDOW_HOURS: i64 = 24;
+const DIGEST_DEFAULT_HOUR: i64 = 9;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DigestCommand {
+    Show,
+    Enable(i64),
+    Disable,
+}
+
+fn parse_digest_command(arg: Option<&str>) -> Option<DigestCommand> {
+    let Some(arg) = arg.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Some(DigestCommand::Show);
+    };
+    let mut parts = arg.split_whitespace();
+    let action = parts.next()?.to_lowercase();
+    let hour = parts.next();
+    if parts.next().is_some() {
+        return None;
+    }
+    match action.as_str() {
+        "on" | "enable" => match hour {
+            None => Some(DigestCommand::Enable(DIGEST_DEFAULT_HOUR)),
+            Some(value) => value
+                .parse::<i64>()
+                .ok()
+                .filter(|hour| (0..24).contains(hour))
+                .map(DigestCommand::Enable),
+        },
+        "off" | "disable" if hour.is_none() => Some(DigestCommand::Disable),
+        _ => None,
+    }
+}
+
+fn digest_date_key(now: DateTime<Utc>, timezone: Tz) -> String {
+    now.with_timezone(&timezone).format("%Y-%m-%d").to_string()
+}
+
+/// A chat is due once its hour has been reached today in `timezone` and no
+/// digest has been recorded for that local date yet.
+fn digest_is_due(settings: &ChatSettingsRow, timezone: Tz, now: DateTime<Utc>) -> bool {
+    if !settings.digest_enabled {
+        return false;
+    }
+    if i64::from(now.with_timezone(&timezone).hour()) < settings.digest_hour {
+        return false;
+    }
+    settings.digest_last_sent_on.as_deref() != Some(digest_date_key(now, timezone).as_str())
+}
+
+fn describe_digest_settings(settings: &ChatSettingsRow, timezone: Tz) -> String {
+    if settings.digest_enabled {
+        format!(
+            "Daily digest is enabled for this chat at {:02}:00 {}.",
+            settings.digest_hour,
+            timezone.name()
+        )
+    } else {
+        "Daily digest is disabled for this chat. Use /digest on [hour] to enable it.".to_string()
+    }
+}
+
+pub async fn digest_handler(
+    bot: Bot,
+    state: AppState,
+    message: Message,
+    arg: Option<String>,
+) -> Result<()> {
+    if !check_admin_access(&bot, &message, "digest").await {
+        return Ok(());
+    }
+
+    let Some(command) = parse_digest_command(arg.as_deref()) else {
+        bot.send_message(
+            message.chat.id,
+            "Usage: /digest, /digest on [hour 0-23, chat timezone], or /digest off",
+        )
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+        return Ok(());
+    };
+
+    let chat_id = message.chat.id.0;
+    let settings = state.chat_settings.get(chat_id);
+    let timezone = chat_timezone(&settings);
+    let reply = match command {
+        DigestCommand::Show => describe_digest_settings(&settings, timezone),
+        DigestCommand::Enable(hour) => {
+            state
+                .update_chat_settings(chat_id, |settings| {
+                    settings.digest_enabled = true;
+                    settings.digest_hour = hour.clamp(0, 23);
+                })
+                .await?;
+            format!(
+                "Daily digest enabled. I will post a summary of the last 24 hours at {hour:02}:00 {}.",
+                timezone.name()
+            )
+        }
+        DigestCommand::Disable => {
+            state
+                .update_chat_settings(chat_id, |settings| settings.digest_enabled = false)
+                .await?;
+            "Daily digest disabled.".to_string()
+        }
+    };
+
+    bot.send_message(message.chat.id, reply)
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+    Ok(())
+}
+
+pub fn spawn_digest_scheduler(bot: Bot, state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DIGEST_TICK);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            if let Err(err) = run_due_digests(&bot, &state).await {
+                warn!("Digest scheduler tick failed: {err:#}");
+            }
+        }
+    });
+    info!("Digest scheduler started");
+}
+
+async fn run_due_digests(bot: &Bot, state: &AppState) -> Result<()> {
+    let now = Utc::now();
+    let due_chats = state
+        .chat_settings
+        .matching(|settings| digest_is_due(settings, chat_timezone(settings), now));
+
+    for settings in due_chats {
+        // Record the attempt before generating so a failing chat is retried
+        // tomorrow instead of every minute.
+        let date_key = digest_date_key(now, chat_timezone(&settings));
+        state
+            .update_chat_settings(settings.chat_id, |settings| {
+                settings.digest_last_sent_on = Some(date_key)
+            })
+            .await?;
+        if let Err(err) = post_digest(bot, state, settings.chat_id, now).await {
+            error!(
+                "Daily digest failed: chat_id={}, error={err:#}",
+                settings.chat_id
+            );
+        }
+    }
+    Ok(())
+}
+
+#[allow(deprecated)]
+async fn post_digest(bot: &Bot, state: &AppState, chat_id: i64, now: DateTime<Utc>) -> Result<()> {
+    let since = now - chrono::Duration::hours(DIGEST_WINDOW_HOURS);
+    let messages = state
+        .db
+        .select_messages_since(chat_id, since, CONFIG.tldr_max_messages as i64)
+        .await?;
+    if messages.is_empty() {
+        info!("Skipping daily digest for chat_id={chat_id}: no messages in the last 24 hours");
+        return Ok(());
+    }
+
+    let _heavy_permit = state.acquire_heavy_command_permit(chat_id).await;
+    let processing_message = bot
+        .send_message(ChatId(chat_id), "Preparing the daily digest...")
+        .await?;
+    let _chat_action = start_command_chat_action(
+        bot.clone(),
+        ChatId(chat_id),
+        "digest",
+        CommandStage::Thinking,
+    );
+
+    let audit_context = match state
+        .db
+        .insert_llm_invocation(LlmInvocationInsert {
+            trigger_kind: LLM_TRIGGER_KIND_SCHEDULED.to_string(),
+            trigger_name: "digest".to_string(),
+            chat_id,
+            user_id: None,
+            username: None,
+            message_id: processing_message.id.0 as i64,
+            reply_to_message_id: None,
+            message_text: None,
+            created_at: now,
+        })
+        .await
+    {
+        Ok(invocation_id) => Some(LlmAuditContext::new(state.db.clone(), invocation_id)),
+        Err(err) => {
+            warn!("Failed to create digest invocation record: chat_id={chat_id}, error={err}");
+            None
+        }
+    };
+
+    let mut progress_reporter =
+        ProgressReporter::new(bot.clone(), ChatId(chat_id), processing_message.id);
+    let settings = state.chat_settings.get(chat_id);
+    let (summary_text, summary_model) = match with_chat_sampling(
+        ChatSampling::from(&settings),
+        summarize_chat_messages(
+            &mut progress_reporter,
+            &messages,
+            chat_timezone(&settings),
+            audit_context.as_ref(),
+        ),
+    )
+    .await
+    {
+        Ok(summary) => summary,
+        Err(err) => {
+            let _ = bot
+                .edit_message_text(
+                    ChatId(chat_id),
+                    processing_message.id,
+                    "Failed to generate today's digest.",
+                )
+                .await;
+            return Err(err);
+        }
+    };
+    if summary_text.trim().is_empty() {
+        let _ = bot
+            .edit_message_text(
+                ChatId(chat_id),
+                processing_message.id,
+                "Failed to generate today's digest.",
+            )
+            .await;
+        return Ok(());
+    }
+
+    let response = format!(
+        "Daily digest ({} messages, last 24h)\n\n{}\n\nModel: {}",
+        messages.len(),
+        summary_text,
+        summary_model
+    );
+    send_response(
+        bot,
+        ChatId(chat_id),
+        processing_message.id,
+        &response,
+        "Daily Digest",
+        ParseMode::Markdown,
+        &TelegraphAuthor::for_chat(&settings),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(enabled: bool, hour: i64, last_sent_on: Option<&str>) -> ChatSettingsRow {
+        ChatSettingsRow {
+            digest_enabled: enabled,
+            digest_hour: hour,
+            digest_last_sent_on: last_sent_on.map(str::to_string),
+            ..ChatSettingsRow::new(-1001)
+        }
+    }
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .expect("timestamp should parse")
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parse_digest_command_accepts_on_off_and_hour() {
+        assert_eq!(parse_digest_command(None), Some(DigestCommand::Show));
+        assert_eq!(
+            parse_digest_command(Some("on")),
+            Some(DigestCommand::Enable(DIGEST_DEFAULT_HOUR))
+        );
+        assert_eq!(
+            parse_digest_command(Some("ON 21")),
+            Some(DigestCommand::Enable(21))
+        );
+        assert_eq!(
+            parse_digest_command(Some("off")),
+            Some(DigestCommand::Disable)
+        );
+        assert_eq!(parse_digest_command(Some("on 24")), None);
+        assert_eq!(parse_digest_command(Some("off 3")), None);
+        assert_eq!(parse_digest_command(Some("sometimes")), None);
+    }
+
+    #[test]
+    fn digest_is_due_after_hour_once_per_day() {
+        let now = at("2026-03-10T09:30:00Z");
+        let utc = Tz::UTC;
+        assert!(digest_is_due(&settings(true, 9, None), utc, now));
+        assert!(digest_is_due(
+            &settings(true, 8, Some("2026-03-09")),
+            utc,
+            now
+        ));
+        assert!(!digest_is_due(
+            &settings(true, 9, Some("2026-03-10")),
+            utc,
+            now
+        ));
+        assert!(!digest_is_due(&settings(true, 10, None), utc, now));
+        assert!(!digest_is_due(&settings(false, 9, None), utc, now));
+    }
+
+    #[test]
+    fn digest_uses_the_chat_local_date_and_hour() {
+        // 01:30 on the 11th in Shanghai is still the 10th in UTC.
+        let now = at("2026-03-10T17:30:00Z");
+        let shanghai: Tz = "Asia/Shanghai".parse().unwrap();
+        assert_eq!(digest_date_key(now, shanghai), "2026-03-11");
+        assert_eq!(digest_date_key(now, Tz::UTC), "2026-03-10");
+
+        // Yesterday's local digest went out; today's local hour is not reached.
+        assert!(!digest_is_due(
+            &settings(true, 9, Some("2026-03-10")),
+            shanghai,
+            now
+        ));
+        assert!(digest_is_due(
+            &settings(true, 1, Some("2026-03-10")),
+            shanghai,
+            now
+        ));
+        assert!(!digest_is_due(
+            &settings(true, 1, Some("2026-03-11")),
+            shanghai,
+            now
+        ));
+        // In UTC the same settings would count as already sent today.
+        assert!(!digest_is_due(
+            &settings(true, 1, Some("2026-03-10")),
+            Tz::UTC,
+            now
+        ));
+
+        assert_eq!(
+            describe_digest_settings(&settings(true, 9, None), shanghai),
+            "Daily digest is enabled for this chat at 09:00 Asia/Shanghai."
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Footer appended to `/q`, `/tldr`, and `/factcheck` answers.
+//!
+//! The layout comes from `RESPONSE_FOOTER_TEMPLATE`. Placeholders are
+//! `{model}`, `{tokens}`, `{cost}`, and `{time_ms}`; a `[...]` group is left
+//! out when any placeholder inside it has no value, so `Model: {model}[ ≈
+//! {cost}]` only shows a cost when one was estimated. A literal `\n` in the
+//! template starts a new line. An empty template disables the footer.
+
+use std::time::Instant;
+
+use tracing::warn;
+
+use crate::config::CONFIG;
+use crate::handlers::access::is_user_whitelisted;
+use crate::llm::audit::LlmAuditContext;
+use crate::llm::pricing;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FooterValues {
+    pub model: String,
+    pub tokens: Option<i64>,
+    pub cost: Option<String>,
+    pub time_ms: Option<u128>,
+}
+
+impl FooterValues {
+    fn value(&self, placeholder: &str) -> Option<Option<String>> {
+        let value = match placeholder {
+            "model" => Some(self.model.clone()).filter(|model| !model.is_empty()),
+            "tokens" => self.tokens.map(|tokens| tokens.to_string()),
+            "cost" => self.cost.clone(),
+            "time_ms" => self.time_ms.map(|ms| ms.to_string()),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Renders `template`, returning the text and whether every placeholder in
+/// it had a value. Unknown placeholders are kept verbatim.
+fn render_segment(template: &str, values: &FooterValues) -> (String, bool) {
+    let mut rendered = String::new();
+    let mut complete = true;
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            rendered.push_str(&rest[start..]);
+            return (rendered, complete);
+        };
+        match values.value(&after[..end]) {
+            Some(Some(value)) => rendered.push_str(&value),
+            Some(None) => complete = false,
+            None => rendered.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+    rendered.push_str(rest);
+    (rendered, complete)
+}
+
+pub fn render_footer(template: &str, values: &FooterValues) -> String {
+    let template = template.replace("\\n", "\n");
+    let mut rendered = String::new();
+    let mut rest = template.as_str();
+    while let Some(start) = rest.find('[') {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        rendered.push_str(&render_segment(&rest[..start], values).0);
+        let (group, complete) = render_segment(&rest[start + 1..start + len], values);
+        if complete {
+            rendered.push_str(&group);
+        }
+        rest = &rest[start + len + 1..];
+    }
+    rendered.push_str(&render_segment(rest, values).0);
+    rendered.trim().to_string()
+}
+
+/// Collects the footer values for an answer and renders the configured
+/// template. Token counts come from the invocation's audit records; the cost
+/// is only filled in for whitelisted requesters when `SHOW_ANSWER_COST` is
+/// on. Returns `None` when the footer is disabled.
+pub async fn response_footer(
+    model: &str,
+    user_id: i64,
+    audit_context: Option<&LlmAuditContext>,
+    started: Instant,
+) -> Option<String> {
+    if CONFIG.response_footer_template.trim().is_empty() {
+        return None;
+    }
+    let mut values = FooterValues {
+        model: model.to_string(),
+        time_ms: Some(started.elapsed().as_millis()),
+        ..FooterValues::default()
+    };
+
+    if let Some(audit_context) = audit_context {
+        match audit_context
+            .db
+            .select_invocation_token_usage(audit_context.invocation_id)
+            .await
+        {
+            Ok(usage) if !usage.is_empty() => {
+                values.tokens = Some(
+                    usage
+                        .iter()
+                        .map(|row| row.input_tokens + row.output_tokens)
+                        .sum(),
+                );
+                let show_cost = CONFIG.show_answer_cost
+                    && pricing::cost_estimation_enabled()
+                    && is_user_whitelisted(user_id);
+                let estimate = pricing::estimate_cost(&usage);
+                if show_cost && estimate.unpriced_models < usage.len() {
+                    values.cost = Some(pricing::format_usd(estimate.usd));
+                }
+            }
+            Ok(_) => {}
+            Err(err) => warn!(
+                "Failed to load invocation usage for response footer: invocation_id={}, error={err}",
+                audit_context.invocation_id
+            ),
+        }
+    }
+
+    let footer = render_footer(&CONFIG.response_footer_template, &values);
+    (!footer.is_empty()).then_some(footer)
+}
+
+/// `text` followed by a blank line and the footer, or `text` unchanged.
+pub fn with_footer(text: &str, footer: Option<&str>) -> String {
+    match footer {
+        Some(footer) => format!("{text}\n\n{footer}"),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_placeholders_and_drops_groups_without_values() {
+        let values = FooterValues {
+            model: "gemini-2.5-pro".to_string(),
+            tokens: Some(1_234),
+            cost: Some("$0.0021".to_string()),
+            time_ms: Some(850),
+        };
+        assert_eq!(
+            render_footer("Model: {model}[ ≈ {cost}]", &values),
+            "Model: gemini-2.5-pro ≈ $0.0021"
+        );
+        assert_eq!(
+            render_footer(
+                "_{model}_ · {tokens} tokens · {time_ms} ms\\n[Cost: {cost}] {unknown}",
+                &values
+            ),
+            "_gemini-2.5-pro_ · 1234 tokens · 850 ms\nCost: $0.0021 {unknown}"
+        );
+
+        let partial = FooterValues {
+            model: "gpt-4.1".to_string(),
+            ..FooterValues::default()
+        };
+        assert_eq!(
+            render_footer("Model: {model}[ ≈ {cost}][ · {tokens} tokens]", &partial),
+            "Model: gpt-4.1"
+        );
+        assert_eq!(render_footer("[{cost}]", &partial), "");
+        assert_eq!(with_footer("answer", None), "answer");
+        assert_eq!(
+            with_footer("answer", Some("Model: x")),
+            "answer\n\nModel: x"
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Inline-mode quick answers (`@bot question` from any chat).
+//!
+//! Telegram sends an inline query for nearly every keystroke, so each user's
+//! query is debounced before it reaches the model. The per-user rate limit,
+//! `/q`'s access control and the daily token quota apply on top. Answers run
+//! on the cheap step model derived from `DEFAULT_Q_MODEL` and come back as a
+//! single article result.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineQuery, InlineQueryResult, InlineQueryResultArticle, InputMessageContent,
+    InputMessageContentText,
+};
+use tracing::warn;
+
+use crate::agents::step::{call_step_text, resolve_step_model};
+use crate::config::{CONFIG, LANGUAGE_POLICY};
+use crate::db::models::LlmInvocationInsert;
+use crate::handlers::access::{
+    is_access_allowed, is_rate_limited, requires_access_control, token_quota_refusal,
+};
+use crate::llm::audit::{LlmAuditContext, LLM_TRIGGER_KIND_INLINE};
+use crate::state::AppState;
+
+const INLINE_DEBOUNCE: Duration = Duration::from_millis(900);
+const INLINE_ANSWER_TIMEOUT: Duration = Duration::from_secs(12);
+const INLINE_MESSAGE_MAX_CHARS: usize = 4000;
+const INLINE_DESCRIPTION_MAX_CHARS: usize = 120;
+const INLINE_SYSTEM_PROMPT: &str = "You answer quick questions sent through Telegram inline mode. Reply in a few short sentences of plain text without Markdown. If the question needs more depth, give the key point and suggest asking with /q in a chat with the bot.";
+
+/// Latest inline query id per user, used to drop superseded keystrokes.
+static LATEST_INLINE_QUERY: Lazy<Mutex<HashMap<u64, String>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum InlinePlan {
+    Help,
+    TooLong { chars: usize },
+    Ask(String),
+}
+
+fn plan_inline_query(query: &str, max_chars: usize) -> InlinePlan {
+    let query = query.trim();
+    if query.is_empty() {
+        return InlinePlan::Help;
+    }
+    let chars = query.chars().count();
+    if chars > max_chars {
+        return InlinePlan::TooLong { chars };
+    }
+    InlinePlan::Ask(query.to_string())
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated = text
+        .chars()
+        .take(max_chars.saturating_sub(1))
+        .collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+fn article(id: &str, title: &str, body: &str) -> InlineQueryResult {
+    let body = truncate_chars(body, INLINE_MESSAGE_MAX_CHARS);
+    let description = truncate_chars(
+        &body.split_whitespace().collect::<Vec<_>>().join(" "),
+        INLINE_DESCRIPTION_MAX_CHARS,
+    );
+    InlineQueryResult::Article(
+        InlineQueryResultArticle::new(
+            id,
+            title,
+            InputMessageContent::Text(InputMessageContentText::new(body)),
+        )
+        .description(description),
+    )
+}
+
+fn is_latest_query(user_id: u64, query_id: &str) -> bool {
+    LATEST_INLINE_QUERY
+        .lock()
+        .get(&user_id)
+        .is_some_and(|latest| latest == query_id)
+}
+
+pub async fn inline_query_handler(bot: Bot, state: AppState, query: InlineQuery) -> Result<()> {
+    let user_key = query.from.id.0;
+    let query_id = query.id.0.clone();
+
+    let result = match plan_inline_query(&query.query, CONFIG.inline_query_max_chars) {
+        InlinePlan::Help => article(
+            "help",
+            "Ask a quick question",
+            "Type a question after the bot's username to get a short answer. For media, chat history, or longer answers, use /q in a chat with the bot.",
+        ),
+        InlinePlan::TooLong { chars } => article(
+            "too_long",
+            "Question too long",
+            &format!(
+                "Inline questions are limited to {} characters (got {chars}). Use /q in a chat with the bot for longer prompts.",
+                CONFIG.inline_query_max_chars
+            ),
+        ),
+        InlinePlan::Ask(question) => {
+            LATEST_INLINE_QUERY
+                .lock()
+                .insert(user_key, query_id.clone());
+            tokio::time::sleep(INLINE_DEBOUNCE).await;
+            if !is_latest_query(user_key, &query_id) {
+                return Ok(());
+            }
+            LATEST_INLINE_QUERY.lock().remove(&user_key);
+
+            let user_id = i64::try_from(user_key).unwrap_or_default();
+            // Inline answers run the /q path, so /q's access list and the
+            // daily quota apply. There is no chat, so only the user can match.
+            if requires_access_control("q") && !is_access_allowed(user_id, user_id) {
+                article(
+                    "access_denied",
+                    "Not available",
+                    "You don't have access to quick answers.",
+                )
+            } else if is_rate_limited(user_id) {
+                article(
+                    "rate_limited",
+                    "Slow down",
+                    "Rate limit exceeded. Please try again in a moment.",
+                )
+            } else if let Some(refusal) = token_quota_refusal(&state, user_id).await {
+                article("quota_exceeded", "Daily quota used", &refusal)
+            } else {
+                match answer_inline_question(&state, &query, user_id, &question).await {
+                    Ok(answer) => article("answer", &truncate_chars(&question, 64), &answer),
+                    Err(err) => {
+                        warn!("Inline query answer failed: user_id={user_id}, error={err:#}");
+                        article(
+                            "error",
+                            "No answer available",
+                            "Sorry, I couldn't answer that right now. Please try again later.",
+                        )
+                    }
+                }
+            }
+        }
+    };
+
+    bot.answer_inline_query(query.id, vec![result])
+        .cache_time(0)
+        .is_personal(true)
+        .await?;
+    Ok(())
+}
+
+async fn answer_inline_question(
+    state: &AppState,
+    query: &InlineQuery,
+    user_id: i64,
+    question: &str,
+) -> Result<String> {
+    let step_model = resolve_step_model(&CONFIG.default_q_model)?;
+    let audit_context = match state
+        .db
+        .insert_llm_invocation(LlmInvocationInsert {
+            trigger_kind: LLM_TRIGGER_KIND_INLINE.to_string(),
+            trigger_name: "inline".to_string(),
+            // Inline queries have no chat; key them on the user's private chat.
+            chat_id: user_id,
+            user_id: Some(user_id),
+            username: Some(query.from.full_name()),
+            message_id: 0,
+            reply_to_message_id: None,
+            message_text: Some(question.to_string()),
+            created_at: chrono::Utc::now(),
+        })
+        .await
+    {
+        Ok(invocation_id) => Some(LlmAuditContext::new(state.db.clone(), invocation_id)),
+        Err(err) => {
+            warn!("Failed to create inline invocation record: {err}");
+            None
+        }
+    };
+
+    let system_prompt = format!("{INLINE_SYSTEM_PROMPT}\n\n{LANGUAGE_POLICY}");
+    let answer = tokio::time::timeout(
+        INLINE_ANSWER_TIMEOUT,
+        call_step_text(
+            &step_model,
+            &system_prompt,
+            question,
+            &[],
+            None,
+            "inline",
+            Some("INLINE_SYSTEM_PROMPT"),
+            audit_context.as_ref(),
+        ),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("inline answer timed out"))??;
+
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Err(anyhow::anyhow!("model returned an empty answer"));
+    }
+    Ok(format!("{question}\n\n{answer}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_inline_query_handles_empty_long_and_normal_queries() {
+        assert_eq!(plan_inline_query("   ", 10), InlinePlan::Help);
+        assert_eq!(
+            plan_inline_query("eleven char", 10),
+            InlinePlan::TooLong { chars: 11 }
+        );
+        assert_eq!(
+            plan_inline_query(" 为什么天是蓝的 ", 10),
+            InlinePlan::Ask("为什么天是蓝的".to_string())
+        );
+    }
+
+    #[test]
+    fn truncate_chars_respects_char_boundaries() {
+        assert_eq!(truncate_chars("hello", 10), "hello");
+        assert_eq!(truncate_chars("你好世界", 3), "你好…");
+    }
+}
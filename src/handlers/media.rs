@@ -6,6 +6,7 @@ use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use teloxide::prelude::*;
 use teloxide::types::FileId;
+use tracing::warn;
 
 use crate::config::CONFIG;
 use crate::llm::media::{detect_mime_type, download_media, kind_for_mime, MediaFile, MediaKind};
@@ -27,6 +28,39 @@ static FILE_URL_CACHE: Lazy<Mutex<HashMap<String, FileUrlCacheEntry>>> =
 #[derive(Debug, Default, Clone)]
 pub struct MediaCollection {
     pub files: Vec<MediaFile>,
+    /// Attachments left out because they exceed `MAX_MEDIA_DOWNLOAD_BYTES`.
+    pub skipped_oversized: usize,
+}
+
+impl MediaCollection {
+    /// User-facing note about attachments skipped for size, if any.
+    pub fn oversized_notice(&self) -> Option<String> {
+        (self.skipped_oversized > 0).then(|| {
+            format!(
+                "Skipped {} attachment(s) larger than {}; answering without them.",
+                self.skipped_oversized,
+                format_byte_limit(CONFIG.max_media_download_bytes)
+            )
+        })
+    }
+}
+
+fn format_byte_limit(bytes: u64) -> String {
+    const MIB: u64 = 1024 * 1024;
+    if bytes >= MIB && bytes.is_multiple_of(MIB) {
+        format!("{} MB", bytes / MIB)
+    } else {
+        format!("{bytes} bytes")
+    }
+}
+
+/// Whether a file must be skipped under `max_bytes` (`0` disables the check).
+/// Telegram reports `u32::MAX` when it omits the size, which counts as unknown.
+pub(crate) fn exceeds_media_download_limit(size: Option<u64>, max_bytes: u64) -> bool {
+    max_bytes > 0
+        && size
+            .filter(|size| *size != u64::from(u32::MAX))
+            .is_some_and(|size| size > max_bytes)
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -189,20 +223,39 @@ async fn add_file_from_file_id(
     mime_type_hint: Option<&str>,
     display_name: Option<&str>,
     kind_hint: Option<MediaKind>,
-) {
+    reported_size: Option<u32>,
+) -> bool {
     if collection.files.len() >= options.max_files {
-        return;
+        return false;
     }
     if !seen_file_ids.insert(file_id.clone()) {
-        return;
+        return false;
+    }
+    let max_bytes = CONFIG.max_media_download_bytes;
+    if exceeds_media_download_limit(reported_size.map(u64::from), max_bytes) {
+        warn!(
+            "Skipping oversized media {file_id}: size={} bytes exceeds MAX_MEDIA_DOWNLOAD_BYTES={max_bytes}",
+            reported_size.unwrap_or_default()
+        );
+        collection.skipped_oversized += 1;
+        return false;
     }
 
     let Ok(url) = get_file_url(bot, file_id).await else {
-        return;
+        return false;
     };
     let Some(bytes) = download_media(&url).await else {
-        return;
+        return false;
     };
+    // Media-group items carry no size hint, so check again after download.
+    if exceeds_media_download_limit(Some(bytes.len() as u64), max_bytes) {
+        warn!(
+            "Dropping oversized media {file_id}: downloaded {} bytes exceeds MAX_MEDIA_DOWNLOAD_BYTES={max_bytes}",
+            bytes.len()
+        );
+        collection.skipped_oversized += 1;
+        return false;
+    }
 
     let mut mime_type = mime_type_hint.map(|value| value.to_string());
     if mime_type.is_none() {
@@ -222,6 +275,103 @@ async fn add_file_from_file_id(
         kind,
         display_name.map(|value| value.to_string()),
     ));
+    true
+}
+
+/// One downloadable rendition of an animated attachment.
+#[derive(Debug, Clone, PartialEq)]
+struct AnimatedSource {
+    file_id: FileId,
+    mime_type: Option<String>,
+    display_name: Option<String>,
+    kind: MediaKind,
+    size: u32,
+}
+
+/// Renditions of a GIF/animation or sticker in preference order: the full
+/// clip first, then its thumbnail as a still first frame. Lottie stickers
+/// have no format Gemini reads, so only their thumbnail is offered.
+fn animated_media_sources(message: &Message) -> Vec<AnimatedSource> {
+    let mut sources = Vec::new();
+    let thumbnail = if let Some(animation) = message.animation() {
+        let (mime_type, kind) = animation_media_hint(
+            animation.mime_type.as_ref().map(|mime| mime.essence_str()),
+            animation.file_name.as_deref(),
+        );
+        sources.push(AnimatedSource {
+            file_id: animation.file.id.clone(),
+            mime_type: Some(mime_type),
+            display_name: animation.file_name.clone(),
+            kind,
+            size: animation.file.size,
+        });
+        animation.thumbnail.as_ref()
+    } else if let Some(sticker) = message.sticker() {
+        if let Some((mime_type, kind)) =
+            sticker_media_hint(sticker.flags.is_animated, sticker.flags.is_video)
+        {
+            sources.push(AnimatedSource {
+                file_id: sticker.file.id.clone(),
+                mime_type: Some(mime_type.to_string()),
+                display_name: None,
+                kind,
+                size: sticker.file.size,
+            });
+        }
+        sticker
+            .thumbnail
+            .as_ref()
+            .filter(|_| sticker.flags.is_animated || sticker.flags.is_video)
+    } else {
+        None
+    };
+    if let Some(thumbnail) = thumbnail {
+        sources.push(AnimatedSource {
+            file_id: thumbnail.file.id.clone(),
+            mime_type: None,
+            display_name: None,
+            kind: MediaKind::Image,
+            size: thumbnail.file.size,
+        });
+    }
+    sources
+}
+
+/// Adds the first rendition that downloads within limits. A clip that was
+/// too large but whose first frame made it in is not reported as skipped.
+async fn add_animated_media(
+    bot: &Bot,
+    message: &Message,
+    collection: &mut MediaCollection,
+    options: MediaCollectionOptions,
+    seen_file_ids: &mut HashSet<FileId>,
+) {
+    let sources = animated_media_sources(message);
+    if sources
+        .first()
+        .is_some_and(|source| seen_file_ids.contains(&source.file_id))
+    {
+        return;
+    }
+    let skipped_before = collection.skipped_oversized;
+    for source in sources {
+        if add_file_from_file_id(
+            bot,
+            &source.file_id,
+            collection,
+            options,
+            seen_file_ids,
+            source.mime_type.as_deref(),
+            source.display_name.as_deref(),
+            Some(source.kind),
+            Some(source.size),
+        )
+        .await
+        {
+            collection.skipped_oversized = skipped_before;
+            return;
+        }
+    }
 }
 
 async fn collect_from_message(
@@ -246,6 +396,7 @@ async fn collect_from_message(
                 None,
                 None,
                 Some(MediaKind::Image),
+                Some(photo.file.size),
             )
             .await;
         }
@@ -267,6 +418,7 @@ async fn collect_from_message(
             mime_hint,
             name_hint,
             None,
+            Some(document.file.size),
         )
         .await;
     }
@@ -290,6 +442,7 @@ async fn collect_from_message(
             mime_hint,
             None,
             Some(MediaKind::Video),
+            Some(video.file.size),
         )
         .await;
     }
@@ -298,22 +451,8 @@ async fn collect_from_message(
         return;
     }
 
-    if let Some(animation) = message.animation() {
-        let (mime_hint, kind_hint) = animation_media_hint(
-            animation.mime_type.as_ref().map(|mime| mime.essence_str()),
-            animation.file_name.as_deref(),
-        );
-        add_file_from_file_id(
-            bot,
-            &animation.file.id,
-            collection,
-            options,
-            seen_file_ids,
-            Some(&mime_hint),
-            animation.file_name.as_deref(),
-            Some(kind_hint),
-        )
-        .await;
+    if message.animation().is_some() {
+        add_animated_media(bot, message, collection, options, seen_file_ids).await;
     }
 
     if collection.files.len() >= options.max_files {
@@ -335,6 +474,7 @@ async fn collect_from_message(
             mime_hint,
             audio.file_name.as_deref(),
             Some(MediaKind::Audio),
+            Some(audio.file.size),
         )
         .await;
     }
@@ -353,6 +493,7 @@ async fn collect_from_message(
             Some("audio/ogg"),
             None,
             Some(MediaKind::Audio),
+            Some(voice.file.size),
         )
         .await;
     }
@@ -361,34 +502,8 @@ async fn collect_from_message(
         return;
     }
 
-    if let Some(sticker) = message.sticker() {
-        if let Some((mime_hint, kind_hint)) =
-            sticker_media_hint(sticker.flags.is_animated, sticker.flags.is_video)
-        {
-            add_file_from_file_id(
-                bot,
-                &sticker.file.id,
-                collection,
-                options,
-                seen_file_ids,
-                Some(mime_hint),
-                None,
-                Some(kind_hint),
-            )
-            .await;
-        } else if let Some(thumbnail) = sticker.thumbnail.as_ref() {
-            add_file_from_file_id(
-                bot,
-                &thumbnail.file.id,
-                collection,
-                options,
-                seen_file_ids,
-                None,
-                None,
-                Some(MediaKind::Image),
-            )
-            .await;
-        }
+    if message.sticker().is_some() {
+        add_animated_media(bot, message, collection, options, seen_file_ids).await;
     }
 }
 
@@ -442,6 +557,7 @@ pub async fn collect_message_media(
                     None,
                     None,
                     Some(MediaKind::Image),
+                    None,
                 )
                 .await;
             }
@@ -454,6 +570,110 @@ pub async fn collect_message_media(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+
+    fn message_with(field: &str, attachment: serde_json::Value) -> Message {
+        let mut message = json!({
+            "message_id": 7,
+            "date": 1,
+            "chat": { "id": -100123, "type": "group", "title": "test group" },
+            "from": { "id": 1001, "is_bot": false, "first_name": "Human" }
+        });
+        message[field] = attachment;
+        serde_json::from_value(message).expect("test message should deserialize")
+    }
+
+    fn thumbnail(file_id: &str) -> serde_json::Value {
+        json!({
+            "file_id": file_id,
+            "file_unique_id": format!("{file_id}-unique"),
+            "width": 320,
+            "height": 320,
+            "file_size": 4096
+        })
+    }
+
+    #[test]
+    fn animation_offers_clip_then_first_frame() {
+        let message = message_with(
+            "animation",
+            json!({
+                "file_id": "gif-clip",
+                "file_unique_id": "gif-clip-unique",
+                "width": 480,
+                "height": 270,
+                "duration": 3,
+                "thumbnail": thumbnail("gif-frame"),
+                "file_name": "reaction.gif.mp4",
+                "mime_type": "video/mp4",
+                "file_size": 900000
+            }),
+        );
+        let sources = animated_media_sources(&message);
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].file_id, FileId("gif-clip".to_string()));
+        assert_eq!(sources[0].mime_type.as_deref(), Some("video/mp4"));
+        assert_eq!(sources[0].kind, MediaKind::Video);
+        assert_eq!(sources[0].size, 900000);
+        assert_eq!(sources[1].file_id, FileId("gif-frame".to_string()));
+        assert_eq!(sources[1].kind, MediaKind::Image);
+    }
+
+    #[test]
+    fn stickers_offer_a_readable_rendition() {
+        let sticker = |is_animated: bool, is_video: bool| {
+            message_with(
+                "sticker",
+                json!({
+                    "file_id": "sticker-file",
+                    "file_unique_id": "sticker-unique",
+                    "type": "regular",
+                    "width": 512,
+                    "height": 512,
+                    "is_animated": is_animated,
+                    "is_video": is_video,
+                    "thumbnail": thumbnail("sticker-frame"),
+                    "file_size": 20000
+                }),
+            )
+        };
+        let file_ids = |message: &Message| {
+            animated_media_sources(message)
+                .into_iter()
+                .map(|source| (source.file_id.0, source.kind))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            file_ids(&sticker(true, false)),
+            vec![("sticker-frame".to_string(), MediaKind::Image)]
+        );
+        assert_eq!(
+            file_ids(&sticker(false, true)),
+            vec![
+                ("sticker-file".to_string(), MediaKind::Video),
+                ("sticker-frame".to_string(), MediaKind::Image)
+            ]
+        );
+        assert_eq!(
+            file_ids(&sticker(false, false)),
+            vec![("sticker-file".to_string(), MediaKind::Image)]
+        );
+    }
+
+    #[test]
+    fn media_download_limit_skips_oversized_and_keeps_small_files() {
+        let max = 20 * 1024 * 1024;
+        assert!(exceeds_media_download_limit(Some(max + 1), max));
+        assert!(!exceeds_media_download_limit(Some(512 * 1024), max));
+        assert!(!exceeds_media_download_limit(Some(max), max));
+        assert!(!exceeds_media_download_limit(None, max));
+        assert!(!exceeds_media_download_limit(
+            Some(u64::from(u32::MAX)),
+            max
+        ));
+        assert!(!exceeds_media_download_limit(Some(max * 10), 0));
+    }
 
     #[test]
     fn animation_media_hint_is_video() {
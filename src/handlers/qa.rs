@@ -1,29 +1,42 @@
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::future::IntoFuture;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Result};
 use serde::Deserialize;
 use serde_json::{json, Value};
 use teloxide::prelude::*;
 use teloxide::types::{
-    ChatAction, InlineKeyboardButton, InlineKeyboardMarkup, MessageEntityKind, MessageEntityRef,
-    MessageId, ParseMode, ReplyParameters,
+    InlineKeyboardButton, InlineKeyboardMarkup, MaybeInaccessibleMessage, MessageEntityKind,
+    MessageEntityRef, MessageId, ParseMode, ReplyParameters,
 };
-use teloxide::RequestError;
 
 use crate::config::{
-    parse_third_party_model_id, ThirdPartyModelConfig, ThirdPartyProvider, CONFIG, Q_SYSTEM_PROMPT,
+    parse_third_party_model_id, ThirdPartyModelConfig, ThirdPartyProvider, CONFIG,
+    CONTINUE_SYSTEM_PROMPT, Q_SYSTEM_PROMPT,
 };
 use crate::db::database::build_message_insert;
-use crate::handlers::access::{check_access_control, is_rate_limited};
-use crate::handlers::commands::message_has_image;
+use crate::db::models::MessageRow;
+use crate::db::store::Store;
+use crate::handlers::access::{
+    check_access_control, command_subject_id, ensure_llm_available, ensure_not_in_flight,
+    ensure_token_quota, is_rate_limited,
+};
+use crate::handlers::commands::{call_configured_text_model, message_has_image};
 use crate::handlers::content::{
-    download_telegraph_media, download_twitter_media, extract_telegraph_urls_and_content,
-    extract_twitter_urls_and_content, extract_youtube_urls,
+    create_telegraph_page, download_telegraph_media, download_twitter_media,
+    extract_links_for_chat, extract_youtube_urls, fenced_code_line_count, has_unextracted_urls,
+    ChatExtractionSettings, TelegraphAuthor,
 };
+use crate::handlers::footer::{response_footer, with_footer};
 use crate::handlers::media::{
     collect_message_media, summarize_media_files, MediaCollectionOptions, MediaSummary,
 };
-use crate::handlers::responses::send_response;
+use crate::handlers::responses::{
+    acknowledge_command, clear_ack_reaction, reply_response, send_response, StatusMessage,
+};
+use crate::handlers::{
+    prefix_forward_origin, prompt_message_text, prompt_row_text, sanitize_prompt_username,
+};
 use crate::llm::audit::{
     audit_context_from_id, create_audit_context_from_message, LlmAuditContext,
     LLM_TRIGGER_KIND_AUTO_Q, LLM_TRIGGER_KIND_COMMAND,
@@ -33,14 +46,24 @@ use crate::llm::runtime_models::{
     runtime_model_config, runtime_model_count, runtime_models, selected_codex_model_record,
     OPENAI_CODEX_SELECTED_MODEL_ID,
 };
+use crate::llm::sampling::{with_chat_sampling, ChatSampling};
 use crate::llm::tool_runtime::ToolRuntime;
 use crate::llm::{
-    call_gemini, call_gemini_with_tool_runtime, call_third_party,
-    call_third_party_with_tool_runtime,
+    call_gemini, call_gemini_with_tool_runtime, call_third_party_with_reasoning_config,
+    call_third_party_with_tool_runtime, configured_max_tokens, resolve_third_party_model_config,
+};
+use crate::state::{AnswerLength, AppState, CommandAck, PendingQRequest, QaCommandMode};
+use crate::utils::language::response_language_retry_instruction;
+use crate::utils::progress::{ProgressForwarder, ProgressReporter};
+use crate::utils::prompt_budget::{
+    fit_context_sections, fit_question_and_reply, CONTEXT_TRUNCATED_MARKER,
 };
-use crate::state::{AppState, PendingQRequest, QaCommandMode};
-use crate::utils::progress::ProgressReporter;
-use crate::utils::telegram::{build_message_link, start_chat_action_heartbeat};
+use crate::utils::request_id::{
+    current_request_id, new_request_id, run_traced, spawn_in_current_request, with_request_id,
+};
+use crate::utils::retry::{retry_async, telegram_retry_decision, telegram_retry_policy};
+use crate::utils::send_guard::DUPLICATE_SENDS;
+use crate::utils::telegram::{build_message_link, start_command_chat_action, CommandStage};
 use crate::utils::timing::{complete_command_timer, start_command_timer, CommandTimer};
 use tracing::{error, info, warn};
 
@@ -48,8 +71,9 @@ pub const MODEL_CALLBACK_PREFIX: &str = "model_select:";
 pub const MODEL_GEMINI: &str = "gemini";
 const MODEL_CALLBACK_COMPACT_PREFIX: &str = "m:";
 const TELEGRAM_CALLBACK_DATA_LIMIT: usize = 64;
-const SEND_MESSAGE_RETRY_ATTEMPTS: usize = 3;
 const USER_ERROR_DETAIL_LIMIT: usize = 400;
+const GEMINI_DISABLED_TEXT: &str =
+    "Gemini is disabled or not configured. Please choose another model.";
 const CHAT_SEARCH_MESSAGE_LIMIT: usize = 3500;
 const NO_VIDEO_CAPABLE_MODEL_MESSAGE: &str =
     "No video-capable AI model is available. Enable Gemini or configure a ready third-party model with video=true.";
@@ -405,46 +429,36 @@ async fn send_message_with_retry(
     parse_mode: Option<ParseMode>,
     reply_markup: Option<InlineKeyboardMarkup>,
 ) -> Result<Message> {
-    let text = text.to_string();
-    let mut delay = Duration::from_secs_f32(1.5);
-    let mut last_err: Option<RequestError> = None;
-
-    for attempt in 0..SEND_MESSAGE_RETRY_ATTEMPTS {
-        let mut request = bot.send_message(chat_id, text.clone());
-        if let Some(reply_to) = reply_to {
-            request = request.reply_parameters(ReplyParameters::new(reply_to));
-        }
-        if let Some(mode) = parse_mode {
-            request = request.parse_mode(mode);
-        }
-        if let Some(markup) = reply_markup.clone() {
-            request = request.reply_markup(markup);
-        }
-
-        match request.await {
-            Ok(message) => return Ok(message),
-            Err(err) => {
-                let retryable = matches!(
-                    err,
-                    RequestError::Network(_) | RequestError::RetryAfter(_) | RequestError::Io(_)
-                );
-                if !retryable || attempt + 1 == SEND_MESSAGE_RETRY_ATTEMPTS {
-                    return Err(err.into());
+    // Messages with buttons belong to one request; never reuse them.
+    let guarded = reply_markup.is_none();
+    let send = || async {
+        let message = retry_async(
+            &telegram_retry_policy(),
+            |_| {
+                let mut request = bot.send_message(chat_id, text.to_string());
+                if let Some(reply_to) = reply_to {
+                    request = request.reply_parameters(ReplyParameters::new(reply_to));
                 }
-
-                warn!("send_message attempt {} failed: {err}", attempt + 1);
-                if let RequestError::RetryAfter(wait) = err {
-                    tokio::time::sleep(wait.duration()).await;
-                } else {
-                    tokio::time::sleep(delay).await;
-                    delay *= 2;
+                if let Some(mode) = parse_mode {
+                    request = request.parse_mode(mode);
                 }
-                last_err = Some(err);
-            }
-        }
+                if let Some(markup) = reply_markup.clone() {
+                    request = request.reply_markup(markup);
+                }
+                request.into_future()
+            },
+            |err, attempt| telegram_retry_decision("send_message", err, attempt),
+        )
+        .await?;
+        Ok(message)
+    };
+    if guarded {
+        DUPLICATE_SENDS
+            .send_once(chat_id.0, text, reply_to, send)
+            .await
+    } else {
+        send().await
     }
-
-    Err(last_err.expect("send_message retry exhausted").into())
 }
 
 fn resolve_exact_model_identifier_with_models(
@@ -1063,6 +1077,41 @@ fn build_prompt_from_template(template: &str, telegram_user_language_hint: Optio
         )
 }
 
+/// Strips a leading `short`/`long` flag from a `/q` query. The flag only counts
+/// when more text follows, so `/q short` alone is still a question.
+fn format_reply_context_query(reply_text: &str, query_text: &str) -> String {
+    format!(
+        "Context from replied message: \"{}\"\n\nQuestion: {}",
+        reply_text, query_text
+    )
+}
+
+fn split_answer_length_flag(query: &str) -> (AnswerLength, String) {
+    let trimmed = query.trim_start();
+    let Some((first, rest)) = trimmed.split_once(char::is_whitespace) else {
+        return (AnswerLength::Default, query.to_string());
+    };
+    let length = match first.to_ascii_lowercase().as_str() {
+        "short" => AnswerLength::Short,
+        "long" => AnswerLength::Long,
+        _ => return (AnswerLength::Default, query.to_string()),
+    };
+    let rest = rest.trim_start();
+    if rest.is_empty() {
+        return (AnswerLength::Default, query.to_string());
+    }
+    (length, rest.to_string())
+}
+
+fn with_answer_length_instruction(system_prompt: String, answer_length: AnswerLength) -> String {
+    match answer_length.prompt_instruction() {
+        Some(instruction) if !system_prompt.is_empty() => {
+            format!("{system_prompt}\n\n{instruction}")
+        }
+        _ => system_prompt,
+    }
+}
+
 fn build_system_prompt(telegram_user_language_hint: Option<&str>) -> String {
     build_prompt_from_template(Q_SYSTEM_PROMPT, telegram_user_language_hint)
 }
@@ -1311,13 +1360,20 @@ async fn send_chat_search_response(
 }
 
 async fn run_chat_search_model(
+    bot: &Bot,
     state: &AppState,
     request: &PendingQRequest,
     query: &str,
     model_name: &str,
     audit_context: Option<&LlmAuditContext>,
 ) -> Result<(ChatSearchModelResponse, ToolRuntime)> {
-    let mut runtime = ToolRuntime::for_search(state.db.clone(), request.chat_id);
+    let progress = ProgressForwarder::spawn(ProgressReporter::new(
+        bot.clone(),
+        ChatId(request.chat_id),
+        MessageId(request.selection_message_id as i32),
+    ));
+    let mut runtime =
+        ToolRuntime::for_search(state.db.clone(), request.chat_id).with_progress(progress.sender());
     let chat_search_prompt = CHAT_SEARCH_SYSTEM_PROMPT.replace(
         "{result_target}",
         &CONFIG.max_tool_context_items.to_string(),
@@ -1341,9 +1397,9 @@ async fn run_chat_search_model(
         )
         .await
         .map(|result| ChatSearchModelResponse {
+            model_used: result.model_display(),
             text: result.text,
-            model_used: result.model_used,
-        })?
+        })
     } else {
         let third_party_prompt = format!(
             "{}\n\n{}",
@@ -1358,14 +1414,15 @@ async fn run_chat_search_model(
             &mut runtime,
             audit_context,
         )
-        .await?;
-        ChatSearchModelResponse {
-            text: response,
+        .await;
+        response.map(|text| ChatSearchModelResponse {
+            text,
             model_used: result_model_display_name(model_name, None),
-        }
+        })
     };
+    progress.finish().await;
 
-    Ok((response, runtime))
+    Ok((response?, runtime))
 }
 
 async fn process_chat_search_request(
@@ -1377,10 +1434,10 @@ async fn process_chat_search_request(
     audit_context: Option<&LlmAuditContext>,
 ) -> Result<()> {
     let (response, runtime) =
-        match run_chat_search_model(state, request, query, model_name, audit_context).await {
+        match run_chat_search_model(bot, state, request, query, model_name, audit_context).await {
             Ok(response) => response,
             Err(err) => {
-                let message = format_llm_error_message(model_name, &err);
+                let message = with_request_id(&format_llm_error_message(model_name, &err));
                 bot.edit_message_text(
                     ChatId(request.chat_id),
                     MessageId(request.selection_message_id as i32),
@@ -1466,21 +1523,187 @@ fn build_chat_search_pending_request(
         timestamp: now_unix_seconds(),
         command_timer,
         mode: QaCommandMode::ChatSearch,
+        answer_length: AnswerLength::Default,
+        command_name: "s".to_string(),
+        use_url_context: false,
+        ack: CommandAck::Message,
+        request_id: current_request_id(),
     }
 }
 
-#[allow(deprecated)]
+async fn call_standard_qa_model(
+    request: &PendingQRequest,
+    system_prompt: &str,
+    query: &str,
+    model_name: &str,
+    supports_tools: bool,
+    audit_context: Option<&LlmAuditContext>,
+) -> Result<(String, Option<String>)> {
+    if model_name == MODEL_GEMINI {
+        let use_pro = CONFIG.gemini_use_pro_for(
+            &request.command_name,
+            !request.media_files.is_empty() || !request.youtube_urls.is_empty(),
+        );
+        call_gemini(
+            system_prompt,
+            query,
+            true,
+            request.use_url_context,
+            Some(&CONFIG.gemini_thinking_level),
+            None,
+            use_pro,
+            Some(request.media_files.clone()),
+            Some(request.youtube_urls.clone()),
+            Some("Q_SYSTEM_PROMPT"),
+            audit_context,
+            Some(
+                request
+                    .answer_length
+                    .max_output_tokens(CONFIG.gemini_max_output_tokens),
+            ),
+            None,
+        )
+        .await
+        .map(|result| {
+            let model_used = result.model_display();
+            (result.text, Some(model_used))
+        })
+    } else {
+        let mut model_config = resolve_third_party_model_config(model_name)?;
+        model_config.max_tokens = request
+            .answer_length
+            .max_tokens(configured_max_tokens(&model_config));
+        call_third_party_with_reasoning_config(
+            system_prompt,
+            query,
+            &model_config,
+            "Answer to Your Question",
+            &request.media_files,
+            supports_tools,
+            audit_context,
+            None,
+        )
+        .await
+        .map(|result| (result, None))
+    }
+}
+
+/// `ENFORCE_RESPONSE_LANGUAGE`: re-asks once with a stronger instruction when
+/// the answer is not in the language of the question. Keeps the first answer
+/// if the re-ask fails.
+async fn enforce_response_language(
+    request: &PendingQRequest,
+    system_prompt: &str,
+    query: &str,
+    model_name: &str,
+    supports_tools: bool,
+    audit_context: Option<&LlmAuditContext>,
+    answer: (String, Option<String>),
+) -> Result<(String, Option<String>)> {
+    let Some(instruction) = response_language_retry_instruction(
+        &request.original_query,
+        request.telegram_language_code.as_deref(),
+        &answer.0,
+    ) else {
+        return Ok(answer);
+    };
+
+    info!(
+        "Answer language did not match the question; re-asking once: chat_id={}, message_id={}",
+        request.chat_id, request.message_id
+    );
+    let stricter_prompt = format!("{system_prompt}\n\n{instruction}");
+    match call_standard_qa_model(
+        request,
+        &stricter_prompt,
+        query,
+        model_name,
+        supports_tools,
+        audit_context,
+    )
+    .await
+    {
+        Ok(retried) => Ok(retried),
+        Err(err) => {
+            warn!("Language re-ask failed; keeping the first answer: {err}");
+            Ok(answer)
+        }
+    }
+}
+
+/// Runs the request with the chat's `/temperature` override applied; the
+/// model callback and timeout paths reach here outside any command scope.
 async fn process_request(
     bot: &Bot,
     state: &AppState,
     request: PendingQRequest,
     model_name: &str,
 ) -> Result<()> {
+    let chat_id = request.chat_id;
+    let acked_command =
+        (request.ack == CommandAck::Reaction).then_some(MessageId(request.message_id as i32));
+    let sampling = ChatSampling::from(&state.chat_settings.get(chat_id));
+    let result = with_chat_sampling(
+        sampling,
+        process_request_in_chat(bot, state, request, model_name),
+    )
+    .await;
+    if let Some(command_message_id) = acked_command {
+        clear_ack_reaction(bot, ChatId(chat_id), command_message_id).await;
+    }
+    result
+}
+
+/// Shows a status or error as the request's outcome: edits the processing
+/// message, or replies to the command when there is none.
+async fn show_q_outcome(bot: &Bot, request: &PendingQRequest, text: String) -> Result<()> {
+    if request.ack != CommandAck::Message {
+        send_message_with_retry(
+            bot,
+            ChatId(request.chat_id),
+            &text,
+            Some(MessageId(request.message_id as i32)),
+            None,
+            None,
+        )
+        .await?;
+    } else {
+        bot.edit_message_text(
+            ChatId(request.chat_id),
+            MessageId(request.selection_message_id as i32),
+            text,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Progress edits go to the processing message; without one they are dropped.
+fn q_progress_reporter(bot: &Bot, request: &PendingQRequest) -> ProgressReporter {
+    ProgressReporter::for_message(
+        bot.clone(),
+        ChatId(request.chat_id),
+        (request.ack == CommandAck::Message)
+            .then_some(MessageId(request.selection_message_id as i32)),
+    )
+}
+
+#[allow(deprecated)]
+async fn process_request_in_chat(
+    bot: &Bot,
+    state: &AppState,
+    mut request: PendingQRequest,
+    model_name: &str,
+) -> Result<()> {
+    let started = Instant::now();
     if model_name == MODEL_GEMINI && !CONFIG.gemini_api_available() {
+        if request.ack != CommandAck::Message {
+            return show_q_outcome(bot, &request, GEMINI_DISABLED_TEXT.to_string()).await;
+        }
         bot.edit_message_text(
             ChatId(request.chat_id),
             MessageId(request.selection_message_id as i32),
-            "Gemini is disabled or not configured. Please choose another model.",
+            GEMINI_DISABLED_TEXT,
         )
         .reply_markup(InlineKeyboardMarkup::new(
             Vec::<Vec<InlineKeyboardButton>>::new(),
@@ -1489,16 +1712,15 @@ async fn process_request(
         return Ok(());
     }
 
-    let _heavy_permit = state.acquire_heavy_command_permit().await;
+    let _heavy_permit = state.acquire_heavy_command_permit(request.chat_id).await;
     let audit_context = audit_context_from_id(&state.db, request.llm_invocation_id);
     if request.mode.requires_chat_search_index() && !state.db.is_search_ready() {
-        bot.edit_message_text(
-            ChatId(request.chat_id),
-            MessageId(request.selection_message_id as i32),
+        return show_q_outcome(
+            bot,
+            &request,
             chat_search_rebuilding_message(qa_mode_command_name(request.mode)),
         )
-        .await?;
-        return Ok(());
+        .await;
     }
 
     let system_prompt = match request.mode {
@@ -1508,13 +1730,22 @@ async fn process_request(
         }
         QaCommandMode::ChatSearch => String::new(),
     };
+    let system_prompt = with_answer_length_instruction(system_prompt, request.answer_length);
 
     let mut query = request.query.clone();
-    for content in &request.telegraph_contents {
-        query.push_str("\n\n");
-        query.push_str(content);
-    }
-    for content in &request.twitter_contents {
+    let mut extracted_contents = request
+        .telegraph_contents
+        .iter()
+        .chain(&request.twitter_contents)
+        .cloned()
+        .collect::<Vec<_>>();
+    let separator_chars = 2 * extracted_contents.len();
+    fit_context_sections(
+        &mut extracted_contents,
+        query.chars().count() + separator_chars,
+        CONFIG.max_prompt_chars,
+    );
+    for content in &extracted_contents {
         query.push_str("\n\n");
         query.push_str(content);
     }
@@ -1554,8 +1785,12 @@ async fn process_request(
         query.chars().count()
     );
 
-    let _chat_action =
-        start_chat_action_heartbeat(bot.clone(), ChatId(request.chat_id), ChatAction::Typing);
+    let _chat_action = start_command_chat_action(
+        bot.clone(),
+        ChatId(request.chat_id),
+        "q",
+        CommandStage::Thinking,
+    );
 
     let mut qc_valid_message_ids: Vec<i64> = Vec::new();
     let response = match request.mode {
@@ -1571,45 +1806,35 @@ async fn process_request(
             .await;
         }
         QaCommandMode::Standard => {
-            if model_name == MODEL_GEMINI {
-                let use_pro = !request.media_files.is_empty() || !request.youtube_urls.is_empty();
-                call_gemini(
-                    &system_prompt,
-                    &query,
-                    true,
-                    false,
-                    Some(&CONFIG.gemini_thinking_level),
-                    None,
-                    use_pro,
-                    Some(request.media_files.clone()),
-                    Some(request.youtube_urls.clone()),
-                    Some("Q_SYSTEM_PROMPT"),
-                    audit_context.as_ref(),
-                )
-                .await
-                .map(|result| (result.text, Some(result.model_used)))
-            } else {
-                call_third_party(
-                    &system_prompt,
-                    &query,
-                    model_name,
-                    "Answer to Your Question",
-                    &request.media_files,
-                    supports_tools,
-                    audit_context.as_ref(),
-                )
-                .await
-                .map(|result| (result, None))
+            let answer = call_standard_qa_model(
+                &request,
+                &system_prompt,
+                &query,
+                model_name,
+                supports_tools,
+                audit_context.as_ref(),
+            )
+            .await;
+            match answer {
+                Ok((text, model_used)) if CONFIG.enforce_response_language => {
+                    enforce_response_language(
+                        &request,
+                        &system_prompt,
+                        &query,
+                        model_name,
+                        supports_tools,
+                        audit_context.as_ref(),
+                        (text, model_used),
+                    )
+                    .await
+                }
+                other => other,
             }
         }
         QaCommandMode::ChatContext => {
             let mut agentic_result: Option<Result<(String, Option<String>)>> = None;
             if CONFIG.enable_agentic_qc {
-                let mut progress_reporter = ProgressReporter::new(
-                    bot.clone(),
-                    ChatId(request.chat_id),
-                    MessageId(request.selection_message_id as i32),
-                );
+                let mut progress_reporter = q_progress_reporter(bot, &request);
                 match crate::agents::qc::run_qc_pipeline(
                     &state.db,
                     request.chat_id,
@@ -1639,10 +1864,14 @@ async fn process_request(
             if let Some(result) = agentic_result {
                 result
             } else {
-                let mut runtime = ToolRuntime::for_qc(state.db.clone(), request.chat_id);
+                let progress = ProgressForwarder::spawn(q_progress_reporter(bot, &request));
+                let mut runtime = ToolRuntime::for_qc(state.db.clone(), request.chat_id)
+                    .with_progress(progress.sender());
                 let qc_result = if model_name == MODEL_GEMINI {
-                    let use_pro =
-                        !request.media_files.is_empty() || !request.youtube_urls.is_empty();
+                    let use_pro = CONFIG.gemini_use_pro_for(
+                        "qc",
+                        !request.media_files.is_empty() || !request.youtube_urls.is_empty(),
+                    );
                     call_gemini_with_tool_runtime(
                         &format!("{}\n\n{}", system_prompt, runtime.tool_limit_guidance()),
                         &query,
@@ -1655,7 +1884,10 @@ async fn process_request(
                         audit_context.as_ref(),
                     )
                     .await
-                    .map(|result| (result.text, Some(result.model_used)))
+                    .map(|result| {
+                        let model_used = result.model_display();
+                        (result.text, Some(model_used))
+                    })
                 } else {
                     call_third_party_with_tool_runtime(
                         &system_prompt,
@@ -1669,6 +1901,7 @@ async fn process_request(
                     .await
                     .map(|result| (result, None))
                 };
+                progress.finish().await;
                 qc_valid_message_ids = runtime.accumulated_message_ids();
                 qc_result
             }
@@ -1695,20 +1928,20 @@ async fn process_request(
                 query.chars().count(),
                 err
             );
-            let message = format_llm_error_message(model_name, &err);
-            bot.edit_message_text(
-                ChatId(request.chat_id),
-                MessageId(request.selection_message_id as i32),
-                message,
-            )
-            .await?;
+            let message = with_request_id(&format_llm_error_message(model_name, &err));
+            show_q_outcome(bot, &request, message).await?;
             return Err(err);
         }
     };
 
     if response.trim().is_empty() {
-        bot.edit_message_text(ChatId(request.chat_id), MessageId(request.selection_message_id as i32), "I couldn't find an answer to your question. Please try rephrasing or asking something else.")
-            .await?;
+        show_q_outcome(
+            bot,
+            &request,
+            "I couldn't find an answer to your question. Please try rephrasing or asking something else."
+                .to_string(),
+        )
+        .await?;
         return Ok(());
     }
 
@@ -1721,38 +1954,678 @@ async fn process_request(
         );
     }
 
-    let mut response_text = response;
-    if !model_name.is_empty() {
-        let display_model = result_model_display_name(model_name, gemini_model_used.as_deref());
-        response_text.push_str(&format!("\n\nModel: {}", display_model));
+    let display_model = if model_name.is_empty() {
+        String::new()
+    } else {
+        result_model_display_name(model_name, gemini_model_used.as_deref())
+    };
+    let footer = response_footer(
+        &display_model,
+        request.user_id,
+        audit_context.as_ref(),
+        started,
+    )
+    .await;
+    let response_text = with_footer(&response, footer.as_deref());
+    let title = if request.mode == QaCommandMode::ChatContext {
+        "Answer about Chat"
+    } else {
+        "Answer to Your Question"
+    };
+    let author = TelegraphAuthor::for_chat(&state.chat_settings.get(request.chat_id));
+    let response_text = if answer_code_needs_telegraph(&response, CONFIG.q_code_telegraph_min_lines)
+    {
+        match create_telegraph_page(title, &response_text, &author).await {
+            Some(url) => with_footer(
+                &format!("The answer includes code. [View it here]({url})"),
+                footer.as_deref(),
+            ),
+            None => response_text,
+        }
+    } else {
+        response_text
+    };
+
+    if request.ack != CommandAck::Message {
+        let answer_id = reply_response(
+            bot,
+            ChatId(request.chat_id),
+            MessageId(request.message_id as i32),
+            &response_text,
+            title,
+            ParseMode::Markdown,
+            &author,
+        )
+        .await?;
+        request.selection_message_id = answer_id.0 as i64;
+    } else {
+        send_response(
+            bot,
+            ChatId(request.chat_id),
+            MessageId(request.selection_message_id as i32),
+            &response_text,
+            title,
+            ParseMode::Markdown,
+            &author,
+        )
+        .await?;
+    }
+    log_q_answer(state, &request, &response).await;
+
+    Ok(())
+}
+
+/// Code renders plainly in Telegram, so answers with at least `min_lines`
+/// lines of fenced code go to Telegraph instead. `0` disables this.
+fn answer_code_needs_telegraph(answer: &str, min_lines: usize) -> bool {
+    min_lines > 0 && fenced_code_line_count(answer) >= min_lines
+}
+
+/// Stores a sent answer as a reply to the question, so a later `/q` replying
+/// to the answer can rebuild the conversation with
+/// [`Database::get_reply_thread`](crate::db::database::Database::get_reply_thread).
+async fn log_q_answer(state: &AppState, request: &PendingQRequest, answer: &str) {
+    let insert = build_message_insert(
+        Some(state.bot_user_id),
+        Some(state.bot_username_lower.clone()),
+        Some(answer.to_string()),
+        None,
+        chrono::Utc::now(),
+        Some(request.message_id),
+        Some(request.chat_id),
+        Some(request.selection_message_id),
+        None,
+        false,
+        None,
+        false,
+        true,
+    );
+    if let Err(err) = state.db.queue_message_insert(insert).await {
+        error!("Failed to queue /q answer insert: {err}");
+    }
+}
+
+/// Earlier turns of the reply chain a `/q` continues, as a
+/// `<conversation_history>` block. The answer being replied to is left out
+/// because it is already the reply context.
+async fn load_q_thread_history<S: Store>(
+    store: &S,
+    bot_user_id: i64,
+    message: &Message,
+) -> Option<String> {
+    if CONFIG.q_thread_max_turns == 0 || !is_reply_to_this_bot(message, bot_user_id) {
+        return None;
+    }
+    let reply = message.reply_to_message()?;
+    let rows = match store
+        .get_reply_thread(
+            message.chat.id.0,
+            reply.id.0 as i64,
+            CONFIG.q_thread_max_turns * 2 + 1,
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(err) => {
+            warn!("Failed to load /q reply thread: {err}");
+            return None;
+        }
+    };
+    let (_, earlier) = rows.split_last()?;
+    format_q_thread_history(earlier, bot_user_id)
+}
+
+/// Messages around the one a `/q` replies to, as a
+/// `<surrounding_messages>` block. Replies to the bot's own answers get
+/// [`load_q_thread_history`] instead.
+async fn load_q_reply_surroundings(state: &AppState, message: &Message) -> Option<String> {
+    if CONFIG.q_reply_context_window == 0 || is_reply_to_this_bot(message, state.bot_user_id) {
+        return None;
+    }
+    let reply = message.reply_to_message()?;
+    let rows = match state
+        .db
+        .get_messages_around(
+            message.chat.id.0,
+            reply.id.0 as i64,
+            CONFIG.q_reply_context_window as i64,
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(err) => {
+            warn!("Failed to load /q reply surroundings: {err}");
+            return None;
+        }
+    };
+    format_reply_surroundings(
+        &rows,
+        reply.id.0 as i64,
+        message.id.0 as i64,
+        CONFIG.q_reply_context_max_chars,
+    )
+}
+
+const REPLIED_MESSAGE_MARKER: &str = "[replied-to message]";
+
+/// Lists `rows` other than the replied-to message and the question itself,
+/// dropping the messages farthest from the reply first until the lines fit
+/// in `max_chars`.
+fn format_reply_surroundings(
+    rows: &[MessageRow],
+    reply_message_id: i64,
+    question_message_id: i64,
+    max_chars: usize,
+) -> Option<String> {
+    let center = rows
+        .iter()
+        .position(|row| row.message_id == reply_message_id)?;
+    let mut candidates = rows
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| {
+            row.message_id != reply_message_id && row.message_id != question_message_id
+        })
+        .filter_map(|(index, row)| {
+            let text = prompt_row_text(row);
+            let text = text.trim();
+            (!text.is_empty()).then(|| {
+                let speaker = sanitize_prompt_username(row.username.as_deref().unwrap_or("User"));
+                (index, format!("{speaker}: {text}"))
+            })
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(index, _)| (index.abs_diff(center), *index));
+
+    let mut used = 0;
+    let mut kept = Vec::new();
+    for (index, line) in candidates {
+        let line_chars = line.chars().count() + 1;
+        if used + line_chars > max_chars {
+            break;
+        }
+        used += line_chars;
+        kept.push((index, line));
+    }
+    if kept.is_empty() {
+        return None;
+    }
+    kept.sort_by_key(|(index, _)| *index);
+    let split = kept.partition_point(|(index, _)| *index < center);
+    let mut lines = kept.into_iter().map(|(_, line)| line).collect::<Vec<_>>();
+    lines.insert(split, REPLIED_MESSAGE_MARKER.to_string());
+    Some(format!(
+        "<surrounding_messages>\n{}\n</surrounding_messages>",
+        lines.join("\n")
+    ))
+}
+
+/// Questions are stored as sent, e.g. `/q@bot what is ...`; this keeps only
+/// the question.
+fn strip_command_prefix(text: &str) -> &str {
+    match text.strip_prefix('/') {
+        Some(command) => command
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, rest)| rest.trim_start()),
+        None => text,
+    }
+}
+
+fn format_q_thread_history(rows: &[MessageRow], bot_user_id: i64) -> Option<String> {
+    let mut turns = Vec::new();
+    for row in rows {
+        let text = row.text.as_deref().unwrap_or_default().trim();
+        let (speaker, text) = if row.user_id == Some(bot_user_id) {
+            ("Assistant".to_string(), text.to_string())
+        } else {
+            (
+                sanitize_prompt_username(row.username.as_deref().unwrap_or("User")),
+                prefix_forward_origin(
+                    &prompt_message_text(strip_command_prefix(text)),
+                    row.forward_origin.as_deref(),
+                ),
+            )
+        };
+        if !text.is_empty() {
+            turns.push(format!("{speaker}: {text}"));
+        }
+    }
+    if turns.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "<conversation_history>\n{}\n</conversation_history>",
+        turns.join("\n\n")
+    ))
+}
+
+/// What `/continue` needs to resume an answer: the question that started
+/// the thread, everything the bot has sent for it so far, and how many
+/// continuations that already includes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ContinueContext {
+    question: String,
+    partial_answer: String,
+    continuations: usize,
+}
+
+/// Builds the resume context from a reply thread (oldest first) that ends at
+/// the bot message being continued. Continuations are stored as replies to
+/// the answer they extend, so the thread is the question followed by one or
+/// more bot messages.
+fn build_continue_context(rows: &[MessageRow], bot_user_id: i64) -> Option<ContinueContext> {
+    let is_bot = |row: &MessageRow| row.user_id == Some(bot_user_id);
+    if !rows.last().is_some_and(is_bot) {
+        return None;
+    }
+    let answer_start = rows
+        .iter()
+        .rposition(|row| !is_bot(row))
+        .map_or(0, |index| index + 1);
+    let question_row = answer_start.checked_sub(1).map(|index| &rows[index])?;
+    let answers = &rows[answer_start..];
+    let partial_answer = answers
+        .iter()
+        .filter_map(|row| row.text.as_deref())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if partial_answer.trim().is_empty() {
+        return None;
+    }
+    Some(ContinueContext {
+        question: strip_command_prefix(question_row.text.as_deref().unwrap_or_default().trim())
+            .to_string(),
+        partial_answer,
+        continuations: answers.len() - 1,
+    })
+}
+
+/// The resume prompt. When over `max_chars` (`0` disables the limit) the
+/// start of the partial answer is dropped, since the model needs its end to
+/// know where to pick up.
+fn build_continue_prompt(context: &ContinueContext, max_chars: usize) -> String {
+    let question = if context.question.is_empty() {
+        "(not available)"
+    } else {
+        context.question.as_str()
+    };
+    let wrap = |partial_answer: &str| {
+        format!(
+            "<original_question>\n{question}\n</original_question>\n\n<partial_answer>\n{partial_answer}\n</partial_answer>\n\nContinue the answer from where it stops."
+        )
+    };
+    let prompt = wrap(&context.partial_answer);
+    let overflow = prompt.chars().count().saturating_sub(max_chars);
+    if max_chars == 0 || overflow == 0 {
+        return prompt;
+    }
+    let marker = format!("{CONTEXT_TRUNCATED_MARKER}\n");
+    let skip = overflow + marker.chars().count();
+    let tail: String = context.partial_answer.chars().skip(skip).collect();
+    wrap(&format!("{marker}{tail}"))
+}
+
+#[allow(deprecated)]
+pub async fn continue_handler(bot: Bot, state: AppState, message: Message) -> Result<()> {
+    if !check_access_control(&bot, &state, &message, "continue").await {
+        return Ok(());
+    }
+    if CONFIG.continue_max_rounds == 0 {
+        send_message_with_retry(
+            &bot,
+            message.chat.id,
+            "/continue is turned off.",
+            Some(message.id),
+            None,
+            None,
+        )
+        .await?;
+        return Ok(());
+    }
+    let Some(reply) = message
+        .reply_to_message()
+        .filter(|_| is_reply_to_this_bot(&message, state.bot_user_id))
+    else {
+        send_message_with_retry(
+            &bot,
+            message.chat.id,
+            "Reply to one of my answers with /continue to extend it.",
+            Some(message.id),
+            None,
+            None,
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let rows = state
+        .db
+        .get_reply_thread(
+            message.chat.id.0,
+            reply.id.0 as i64,
+            CONFIG.continue_max_rounds + 2,
+        )
+        .await?;
+    let Some(context) = build_continue_context(&rows, state.bot_user_id) else {
+        send_message_with_retry(
+            &bot,
+            message.chat.id,
+            "I can't find the question behind that message, so there is nothing to continue.",
+            Some(message.id),
+            None,
+            None,
+        )
+        .await?;
+        return Ok(());
+    };
+    if context.continuations >= CONFIG.continue_max_rounds {
+        send_message_with_retry(
+            &bot,
+            message.chat.id,
+            &format!(
+                "This answer has already been continued {} times, the most allowed.",
+                context.continuations
+            ),
+            Some(message.id),
+            None,
+            None,
+        )
+        .await?;
+        return Ok(());
+    }
+    if !ensure_llm_available(&bot, &message).await {
+        return Ok(());
+    }
+    if !ensure_token_quota(&bot, &state, &message).await {
+        return Ok(());
+    }
+
+    let user_id = command_subject_id(&message);
+    if is_rate_limited(user_id) {
+        send_message_with_retry(
+            &bot,
+            message.chat.id,
+            "You're sending commands too quickly. Please wait a moment before trying again.",
+            Some(message.id),
+            None,
+            None,
+        )
+        .await?;
+        return Ok(());
+    }
+    let Some(_in_flight) = ensure_not_in_flight(&bot, &state, &message, "continue").await else {
+        return Ok(());
+    };
+    let _heavy_permit = state.acquire_heavy_command_permit(message.chat.id.0).await;
+
+    let mut status = StatusMessage::start(
+        &bot,
+        &state.chat_settings.get(message.chat.id.0),
+        reply.id,
+        "Continuing the answer...",
+    )
+    .await?;
+    let _chat_action = start_command_chat_action(
+        bot.clone(),
+        message.chat.id,
+        "continue",
+        CommandStage::Thinking,
+    );
+    let audit_context = create_q_audit_context(&state, &message, "continue").await;
+    let result = call_configured_text_model(
+        CONTINUE_SYSTEM_PROMPT,
+        &build_continue_prompt(&context, CONFIG.max_prompt_chars),
+        "Continued Answer",
+        false,
+        false,
+        false,
+        None,
+        Some("CONTINUE_SYSTEM_PROMPT"),
+        audit_context.as_ref(),
+    )
+    .await;
+    let continuation = match result {
+        Ok((text, _model_used)) => text,
+        Err(err) => {
+            error!("continue model call failed: {err:#}");
+            status
+                .finish(with_request_id("Sorry, I couldn't continue that answer."))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let answer_id = status
+        .finish_response(&continuation, "Continued Answer", ParseMode::Markdown)
+        .await?;
+    let insert = build_message_insert(
+        Some(state.bot_user_id),
+        Some(state.bot_username_lower.clone()),
+        Some(continuation),
+        None,
+        chrono::Utc::now(),
+        Some(reply.id.0 as i64),
+        Some(message.chat.id.0),
+        Some(answer_id.0 as i64),
+        None,
+        false,
+        None,
+        false,
+        true,
+    );
+    if let Err(err) = state.db.queue_message_insert(insert).await {
+        error!("Failed to queue /continue answer insert: {err}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::items_after_test_module)]
+mod tests {
+    use super::*;
+    use crate::handlers::media::MediaSummary;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    #[test]
+    fn format_context_preview_lists_links_media_and_counts() {
+        let preview = ContextPreview {
+            query_chars: 12,
+            reply_chars: 40,
+            telegraph_pages: vec![(1200, 2, 0)],
+            twitter_posts: vec![("https://x.com/a/status/1".to_string(), 80, 1, 1)],
+            youtube_urls: Vec::new(),
+            youtube_dropped_without_gemini: true,
+            media: MediaSummary {
+                total: 2,
+                images: 1,
+                videos: 0,
+                audios: 1,
+                documents: 0,
+            },
+            media_skipped_oversized: 1,
+            prompt_chars: 90,
+        };
+
+        let report = format_context_preview(&preview);
+
+        assert!(report.contains("question text: 12 chars"));
+        assert!(report.contains("telegraph pages: 1\n  1. 1200 chars, 2 image(s), 0 video(s)"));
+        assert!(report.contains("https://x.com/a/status/1: 80 chars"));
+        assert!(report.contains("Gemini, which is not configured"));
+        assert!(report.contains("attached media: 2 (images=1, videos=0, audio=1, documents=0)"));
+        assert!(report.contains("skipped as too large: 1"));
+        assert!(report.ends_with("prompt text after extraction: 90 chars"));
+    }
+
+    #[test]
+    fn split_answer_length_flag_strips_leading_flag_only() {
+        assert_eq!(
+            split_answer_length_flag("short why is the sky blue?"),
+            (AnswerLength::Short, "why is the sky blue?".to_string())
+        );
+        assert_eq!(
+            split_answer_length_flag("LONG  explain TCP"),
+            (AnswerLength::Long, "explain TCP".to_string())
+        );
+        assert_eq!(
+            split_answer_length_flag("shortest path in a graph"),
+            (
+                AnswerLength::Default,
+                "shortest path in a graph".to_string()
+            )
+        );
+        assert_eq!(
+            split_answer_length_flag("short"),
+            (AnswerLength::Default, "short".to_string())
+        );
+    }
+
+    #[test]
+    fn continue_context_joins_answers_after_the_question() {
+        let row = |message_id: i64, user_id: i64, text: &str| MessageRow {
+            id: message_id,
+            message_id,
+            chat_id: -100,
+            user_id: Some(user_id),
+            username: Some(if user_id == 99 { "groupbot" } else { "Alice" }.to_string()),
+            text: Some(text.to_string()),
+            language: None,
+            date: chrono::Utc::now(),
+            reply_to_message_id: None,
+            asks_ai: false,
+            ai_command: None,
+            is_synthetic_record: false,
+            forward_origin: None,
+        };
+        let rows = [
+            row(1, 7, "/q@groupbot explain the borrow checker"),
+            row(2, 99, "The borrow checker enforces"),
+            row(3, 99, "that references never outlive"),
+        ];
+        let context = build_continue_context(&rows, 99).expect("thread should resume");
+        assert_eq!(
+            context,
+            ContinueContext {
+                question: "explain the borrow checker".to_string(),
+                partial_answer: "The borrow checker enforces\nthat references never outlive"
+                    .to_string(),
+                continuations: 1,
+            }
+        );
+
+        let prompt = build_continue_prompt(&context, 0);
+        assert!(prompt
+            .contains("<original_question>\nexplain the borrow checker\n</original_question>"));
+        assert!(prompt.contains("that references never outlive\n</partial_answer>"));
+
+        let limited = build_continue_prompt(&context, prompt.chars().count() - 10);
+        assert_eq!(limited.chars().count(), prompt.chars().count() - 10);
+        assert!(limited.contains(CONTEXT_TRUNCATED_MARKER));
+        assert!(limited.contains("never outlive\n</partial_answer>"));
+
+        // The replied-to message must be the bot's, and a question must exist.
+        assert_eq!(build_continue_context(&rows[..1], 99), None);
+        assert_eq!(build_continue_context(&rows[1..], 99), None);
+    }
+
+    #[test]
+    fn format_q_thread_history_labels_turns_and_strips_commands() {
+        let row = |message_id: i64, user_id: i64, username: &str, text: &str| MessageRow {
+            id: message_id,
+            message_id,
+            chat_id: -100,
+            user_id: Some(user_id),
+            username: Some(username.to_string()),
+            text: Some(text.to_string()),
+            language: None,
+            date: chrono::Utc::now(),
+            reply_to_message_id: None,
+            asks_ai: false,
+            ai_command: None,
+            is_synthetic_record: false,
+            forward_origin: None,
+        };
+        let rows = [
+            row(1, 7, "Alice", "/q@groupbot what is Rust?"),
+            row(2, 99, "groupbot", "A systems programming language."),
+            row(3, 7, "Alice", "/q"),
+            row(4, 7, "Alice", "Who made it?"),
+        ];
+        assert_eq!(
+            format_q_thread_history(&rows, 99).as_deref(),
+            Some(
+                "<conversation_history>\nAlice: what is Rust?\n\nAssistant: A systems programming language.\n\nAlice: Who made it?\n</conversation_history>"
+            )
+        );
+        assert_eq!(format_q_thread_history(&rows[2..3], 99), None);
+    }
+
+    #[test]
+    fn reply_surroundings_keep_the_closest_messages_within_the_char_cap() {
+        let row = |message_id: i64, username: &str, text: &str| MessageRow {
+            id: message_id,
+            message_id,
+            chat_id: -100,
+            user_id: Some(message_id),
+            username: Some(username.to_string()),
+            text: Some(text.to_string()),
+            language: None,
+            date: chrono::Utc::now(),
+            reply_to_message_id: None,
+            asks_ai: false,
+            ai_command: None,
+            is_synthetic_record: false,
+            forward_origin: None,
+        };
+        let rows = [
+            row(1, "Dan", "anyone up for lunch?"),
+            row(2, "Bob", "the deploy broke prod"),
+            row(3, "Alice", "it was the config change"),
+            row(4, "Carol", "rolling back now"),
+            row(5, "Alice", "/q what broke?"),
+        ];
+        assert_eq!(
+            format_reply_surroundings(&rows, 3, 5, 1000).as_deref(),
+            Some(
+                "<surrounding_messages>\nDan: anyone up for lunch?\nBob: the deploy broke prod\n[replied-to message]\nCarol: rolling back now\n</surrounding_messages>"
+            )
+        );
+        assert_eq!(
+            format_reply_surroundings(&rows, 3, 5, 60).as_deref(),
+            Some(
+                "<surrounding_messages>\nBob: the deploy broke prod\n[replied-to message]\nCarol: rolling back now\n</surrounding_messages>"
+            )
+        );
+        assert_eq!(format_reply_surroundings(&rows, 3, 5, 5), None);
+        assert_eq!(format_reply_surroundings(&rows[3..], 3, 5, 1000), None);
+    }
+
+    #[test]
+    fn short_answer_length_adds_brevity_instruction_and_caps_tokens() {
+        let prompt =
+            with_answer_length_instruction(build_system_prompt(Some("en")), AnswerLength::Short);
+        assert!(prompt.ends_with(
+            AnswerLength::Short
+                .prompt_instruction()
+                .expect("short has an instruction")
+        ));
+        assert_eq!(AnswerLength::Short.max_output_tokens(2048), 512);
+        assert_eq!(AnswerLength::Short.max_output_tokens(256), 256);
+        assert_eq!(AnswerLength::Long.max_output_tokens(2048), 8192);
+        assert_eq!(AnswerLength::Default.max_output_tokens(2048), 2048);
+        assert_eq!(AnswerLength::Short.max_tokens(None), Some(512));
+        assert_eq!(AnswerLength::Short.max_tokens(Some(256)), Some(256));
+        assert_eq!(AnswerLength::Long.max_tokens(Some(2048)), Some(8192));
+        assert_eq!(AnswerLength::Long.max_tokens(None), None);
+        assert_eq!(AnswerLength::Default.max_tokens(Some(2048)), Some(2048));
+        assert_eq!(
+            with_answer_length_instruction("base".to_string(), AnswerLength::Default),
+            "base"
+        );
     }
 
-    send_response(
-        bot,
-        ChatId(request.chat_id),
-        MessageId(request.selection_message_id as i32),
-        &response_text,
-        if request.mode == QaCommandMode::ChatContext {
-            "Answer about Chat"
-        } else {
-            "Answer to Your Question"
-        },
-        ParseMode::Markdown,
-    )
-    .await?;
-
-    Ok(())
-}
-
-#[cfg(test)]
-#[allow(clippy::items_after_test_module)]
-mod tests {
-    use super::*;
-    use crate::handlers::media::MediaSummary;
-    use serde_json::json;
-    use std::collections::HashMap;
-    use teloxide::types::InlineKeyboardButtonKind;
-
     #[test]
     fn q_system_prompt_renders_without_placeholders() {
         let rendered = build_system_prompt(Some("en"));
@@ -1826,6 +2699,8 @@ mod tests {
             video: false,
             audio: false,
             tools: true,
+            max_tokens: None,
+            stop: Vec::new(),
         }
     }
 
@@ -1850,6 +2725,11 @@ mod tests {
             timestamp,
             command_timer: None,
             mode: QaCommandMode::Standard,
+            answer_length: AnswerLength::Default,
+            command_name: "q".to_string(),
+            use_url_context: false,
+            ack: CommandAck::Message,
+            request_id: None,
         }
     }
 
@@ -2125,6 +3005,62 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn model_info_resolves_aliases_and_lists_models_for_unknown_ones() {
+        let mut grok = model(ThirdPartyProvider::OpenRouter, "Grok 4", "x-ai/grok-4");
+        grok.image = true;
+        let models = vec![
+            grok,
+            model(
+                ThirdPartyProvider::Nvidia,
+                "Gemma 3n",
+                "google/gemma-3n-e4b-it",
+            ),
+        ];
+        let default_model = "openrouter:x-ai/grok-4";
+
+        assert_eq!(
+            resolve_model_info_target_with_models("grok", &models).as_deref(),
+            Some("openrouter:x-ai/grok-4")
+        );
+        assert_eq!(
+            resolve_model_info_target_with_models("gemma", &models).as_deref(),
+            Some("nvidia:google/gemma-3n-e4b-it")
+        );
+        assert_eq!(
+            resolve_model_info_target_with_models("GEMINI", &models).as_deref(),
+            Some(MODEL_GEMINI)
+        );
+
+        let grok_info = model_info_text_with_models("grok", &models, default_model, true);
+        assert!(grok_info.contains("Model: Grok 4\nID: openrouter:x-ai/grok-4"));
+        assert!(grok_info.contains("Images: yes, Video: no, Audio: no"));
+        assert!(grok_info.contains("Tools: yes"));
+        assert!(grok_info.ends_with("Default: yes"));
+
+        let gemini_info = model_info_text_with_models("gemini", &models, default_model, true);
+        assert!(gemini_info.contains(&format!("Flash model: {}", CONFIG.gemini_model)));
+        assert!(gemini_info.contains(&format!("Pro model: {}", CONFIG.gemini_pro_model)));
+        assert!(gemini_info.ends_with("Default: no"));
+
+        assert_eq!(
+            model_info_text_with_models("claude", &models, default_model, true),
+            "Unknown model 'claude'. Known models: gemini, openrouter:x-ai/grok-4, nvidia:google/gemma-3n-e4b-it"
+        );
+    }
+
+    #[test]
+    fn code_heavy_answers_route_to_telegraph() {
+        let multi_block = "Server:\n\n```rust\nuse std::net::TcpListener;\n\nfn main() {\n    let listener = TcpListener::bind(\"127.0.0.1:80\").unwrap();\n}\n```\n\nClient:\n\n```python\nimport socket\nsocket.create_connection((\"127.0.0.1\", 80))\n```\n";
+        assert!(answer_code_needs_telegraph(multi_block, 7));
+        assert!(!answer_code_needs_telegraph(multi_block, 8));
+        assert!(!answer_code_needs_telegraph(multi_block, 0));
+        assert!(!answer_code_needs_telegraph(
+            "Run `cargo build` and then:\n\n```sh\ncargo test\n```",
+            8
+        ));
+    }
+
     #[test]
     fn normalize_model_identifier_prefers_alias_mapping() {
         let models = vec![
@@ -2627,15 +3563,17 @@ async fn q_handler_internal(
     command_name: &str,
     mode: QaCommandMode,
 ) -> Result<()> {
-    if !check_access_control(&bot, &message, command_name).await {
+    if !check_access_control(&bot, &state, &message, command_name).await {
+        return Ok(());
+    }
+    if !ensure_llm_available(&bot, &message).await {
+        return Ok(());
+    }
+    if !ensure_token_quota(&bot, &state, &message).await {
         return Ok(());
     }
 
-    let user_id = message
-        .from
-        .as_ref()
-        .and_then(|user| i64::try_from(user.id.0).ok())
-        .unwrap_or_default();
+    let user_id = command_subject_id(&message);
     if is_rate_limited(user_id) {
         send_message_with_retry(
             &bot,
@@ -2648,9 +3586,13 @@ async fn q_handler_internal(
         .await?;
         return Ok(());
     }
-    let _heavy_permit = state.acquire_heavy_command_permit().await;
+    let Some(_in_flight) = ensure_not_in_flight(&bot, &state, &message, command_name).await else {
+        return Ok(());
+    };
+    let _heavy_permit = state.acquire_heavy_command_permit(message.chat.id.0).await;
+    let extraction = ChatExtractionSettings::from(&state.chat_settings.get(message.chat.id.0));
 
-    let query_text_raw = query.unwrap_or_default();
+    let (answer_length, query_text_raw) = split_answer_length_flag(&query.unwrap_or_default());
     let query_entities = message_entities_for_text(&message);
     let reply_message = message.reply_to_message();
     let mut reply_text_raw = String::new();
@@ -2666,15 +3608,9 @@ async fn q_handler_internal(
             .unwrap_or_default();
         if !reply_text_raw.trim().is_empty() {
             let reply_entities = message_entities_for_text(reply);
-            let (reply_text_processed, reply_telegraph) =
-                extract_telegraph_urls_and_content(&reply_text_raw, reply_entities.as_deref(), 5)
+            let (reply_text_processed, reply_telegraph, reply_twitter) =
+                extract_links_for_chat(extraction, &reply_text_raw, reply_entities.as_deref(), 5)
                     .await;
-            let (reply_text_processed, reply_twitter) = extract_twitter_urls_and_content(
-                &reply_text_processed,
-                reply_entities.as_deref(),
-                5,
-            )
-            .await;
             telegraph_contents.extend(reply_telegraph);
             twitter_contents.extend(reply_twitter);
             reply_text = reply_text_processed;
@@ -2684,6 +3620,10 @@ async fn q_handler_internal(
     let media_options = MediaCollectionOptions::for_qa();
     let max_files = media_options.max_files;
     let media = collect_message_media(&bot, &state, &message, media_options).await;
+    if let Some(notice) = media.oversized_notice() {
+        send_message_with_retry(&bot, message.chat.id, &notice, Some(message.id), None, None)
+            .await?;
+    }
     let mut media_files = media.files;
     let initial_media_summary = summarize_media_files(&media_files);
 
@@ -2726,18 +3666,25 @@ async fn q_handler_internal(
         return Ok(());
     }
 
+    let use_url_context =
+        has_unextracted_urls(&query_text_raw) || has_unextracted_urls(&reply_text_raw);
     let mut query_text = query_text_raw.clone();
     if !query_text.trim().is_empty() {
-        let (query_text_processed, query_telegraph) =
-            extract_telegraph_urls_and_content(&query_text, query_entities.as_deref(), 5).await;
-        let (query_text_processed, query_twitter) =
-            extract_twitter_urls_and_content(&query_text_processed, query_entities.as_deref(), 5)
-                .await;
+        let (query_text_processed, query_telegraph, query_twitter) =
+            extract_links_for_chat(extraction, &query_text, query_entities.as_deref(), 5).await;
         telegraph_contents.extend(query_telegraph);
         twitter_contents.extend(query_twitter);
         query_text = query_text_processed;
     }
 
+    let (query_text, reply_text) = fit_question_and_reply(
+        &query_text,
+        &query_text_raw,
+        &reply_text,
+        &reply_text_raw,
+        format_reply_context_query("", "").chars().count(),
+        CONFIG.max_prompt_chars,
+    );
     let query_base = if query_text.trim().is_empty() {
         if reply_text.trim().is_empty() {
             original_query.clone()
@@ -2747,14 +3694,31 @@ async fn q_handler_internal(
     } else if reply_text.trim().is_empty() {
         query_text.clone()
     } else {
-        format!(
-            "Context from replied message: \"{}\"\n\nQuestion: {}",
-            reply_text, query_text
-        )
+        format_reply_context_query(&reply_text, &query_text)
     };
 
-    let (query_text, youtube_urls) =
-        extract_youtube_urls_for_available_models(&query_base, CONFIG.gemini_api_available());
+    let reply_history = match load_q_thread_history(&state.db, state.bot_user_id, &message).await {
+        Some(history) => Some(history),
+        None => load_q_reply_surroundings(&state, &message).await,
+    };
+    let query_base = match reply_history {
+        Some(history) => {
+            let mut sections = [history];
+            fit_context_sections(
+                &mut sections,
+                query_base.chars().count() + 2,
+                CONFIG.max_prompt_chars,
+            );
+            let [history] = sections;
+            format!("{history}\n\n{query_base}")
+        }
+        None => query_base,
+    };
+
+    let (query_text, youtube_urls) = extract_youtube_urls_for_available_models(
+        &query_base,
+        CONFIG.gemini_api_available() && extraction.youtube,
+    );
 
     let user_language_code = message
         .from
@@ -2894,50 +3858,56 @@ async fn q_handler_internal(
 
     if let Some((selected_model, timer_detail)) = direct_model {
         let display_name = configured_model_display_name(&selected_model);
-        let processing_message_text = if has_video {
-            format!(
-                "Analyzing video and processing your question with {}...",
-                display_name
-            )
-        } else if has_audio {
-            format!(
-                "Analyzing audio and processing your question with {}...",
-                display_name
-            )
-        } else if has_images {
-            format!(
-                "Analyzing {} image(s) and processing your question with {}...",
-                media_summary.images, display_name
-            )
-        } else if has_documents {
-            format!(
-                "Analyzing {} document(s) and processing your question with {}...",
-                media_summary.documents, display_name
-            )
-        } else if !twitter_contents.is_empty() {
-            format!(
-                "Analyzing {} Twitter post(s) and processing your question with {}...",
-                twitter_contents.len(),
-                display_name
-            )
-        } else if !youtube_urls.is_empty() {
-            format!(
-                "Analyzing {} YouTube video(s) and processing your question with {}...",
-                youtube_urls.len(),
-                display_name
-            )
+        let ack = acknowledge_command(&bot, &state, &message, command_name).await;
+        let selection_message_id = if ack != CommandAck::Message {
+            message.id
         } else {
-            format!("Processing your question with {}...", display_name)
+            let processing_message_text = if has_video {
+                format!(
+                    "Analyzing video and processing your question with {}...",
+                    display_name
+                )
+            } else if has_audio {
+                format!(
+                    "Analyzing audio and processing your question with {}...",
+                    display_name
+                )
+            } else if has_images {
+                format!(
+                    "Analyzing {} image(s) and processing your question with {}...",
+                    media_summary.images, display_name
+                )
+            } else if has_documents {
+                format!(
+                    "Analyzing {} document(s) and processing your question with {}...",
+                    media_summary.documents, display_name
+                )
+            } else if !twitter_contents.is_empty() {
+                format!(
+                    "Analyzing {} Twitter post(s) and processing your question with {}...",
+                    twitter_contents.len(),
+                    display_name
+                )
+            } else if !youtube_urls.is_empty() {
+                format!(
+                    "Analyzing {} YouTube video(s) and processing your question with {}...",
+                    youtube_urls.len(),
+                    display_name
+                )
+            } else {
+                format!("Processing your question with {}...", display_name)
+            };
+            let processing_message = send_message_with_retry(
+                &bot,
+                message.chat.id,
+                &processing_message_text,
+                Some(message.id),
+                None,
+                None,
+            )
+            .await?;
+            processing_message.id
         };
-        let processing_message = send_message_with_retry(
-            &bot,
-            message.chat.id,
-            &processing_message_text,
-            Some(message.id),
-            None,
-            None,
-        )
-        .await?;
         let mut timer = start_command_timer(command_name, &message);
         let pending_request = PendingQRequest {
             user_id,
@@ -2958,13 +3928,18 @@ async fn q_handler_internal(
                 .collect(),
             chat_id: message.chat.id.0,
             message_id: message.id.0 as i64,
-            selection_message_id: processing_message.id.0 as i64,
+            selection_message_id: selection_message_id.0 as i64,
             original_user_id: user_id,
             reply_to_message_id: message.reply_to_message().map(|msg| msg.id.0 as i64),
             llm_invocation_id: audit_context.as_ref().map(|context| context.invocation_id),
             timestamp: now_unix_seconds(),
             command_timer: None,
             mode,
+            answer_length,
+            command_name: command_name.to_string(),
+            use_url_context,
+            ack,
+            request_id: current_request_id(),
         };
 
         let result = process_request(&bot, &state, pending_request, &selected_model).await;
@@ -3026,6 +4001,11 @@ async fn q_handler_internal(
         timestamp: now_unix_seconds(),
         command_timer: Some(timer),
         mode,
+        answer_length,
+        command_name: command_name.to_string(),
+        use_url_context,
+        ack: CommandAck::Message,
+        request_id: current_request_id(),
     };
 
     state
@@ -3035,7 +4015,7 @@ async fn q_handler_internal(
 
     let bot_clone = bot.clone();
     let state_clone = state.clone();
-    tokio::spawn(async move {
+    spawn_in_current_request(async move {
         handle_model_timeout(bot_clone, state_clone, request_key).await;
     });
 
@@ -3063,6 +4043,307 @@ async fn q_handler_internal(
     Ok(())
 }
 
+/// What `/q` would gather for a request, as reported by `/context`.
+#[derive(Debug, Default)]
+struct ContextPreview {
+    query_chars: usize,
+    reply_chars: usize,
+    telegraph_pages: Vec<(usize, usize, usize)>,
+    twitter_posts: Vec<(String, usize, usize, usize)>,
+    youtube_urls: Vec<String>,
+    youtube_dropped_without_gemini: bool,
+    media: MediaSummary,
+    media_skipped_oversized: usize,
+    prompt_chars: usize,
+}
+
+fn format_context_preview(preview: &ContextPreview) -> String {
+    let mut report = String::from("Context a /q would include (no model call made)\n");
+    report.push_str(&format!(
+        "question text: {} chars\nreplied message text: {} chars\n",
+        preview.query_chars, preview.reply_chars
+    ));
+
+    report.push_str(&format!(
+        "telegraph pages: {}\n",
+        preview.telegraph_pages.len()
+    ));
+    for (index, (chars, images, videos)) in preview.telegraph_pages.iter().enumerate() {
+        report.push_str(&format!(
+            "  {}. {chars} chars, {images} image(s), {videos} video(s)\n",
+            index + 1
+        ));
+    }
+
+    report.push_str(&format!(
+        "twitter/x posts: {}\n",
+        preview.twitter_posts.len()
+    ));
+    for (url, chars, images, videos) in &preview.twitter_posts {
+        report.push_str(&format!(
+            "  - {url}: {chars} chars, {images} image(s), {videos} video(s)\n"
+        ));
+    }
+
+    report.push_str(&format!("youtube links: {}\n", preview.youtube_urls.len()));
+    for url in &preview.youtube_urls {
+        report.push_str(&format!("  - {url}\n"));
+    }
+    if preview.youtube_dropped_without_gemini {
+        report.push_str("  (YouTube links are only passed to Gemini, which is not configured)\n");
+    }
+
+    report.push_str(&format!(
+        "attached media: {} (images={}, videos={}, audio={}, documents={})\n",
+        preview.media.total,
+        preview.media.images,
+        preview.media.videos,
+        preview.media.audios,
+        preview.media.documents
+    ));
+    if preview.media_skipped_oversized > 0 {
+        report.push_str(&format!(
+            "  skipped as too large: {}\n",
+            preview.media_skipped_oversized
+        ));
+    }
+    report.push_str(&format!(
+        "prompt text after extraction: {} chars",
+        preview.prompt_chars
+    ));
+    report
+}
+
+/// `/context`: runs the `/q` extraction pipeline for the message (and its
+/// reply) and reports what would be sent, without calling a model.
+/// Model id `/model_info` describes: the selected Codex model, then keyword
+/// aliases (`grok`, `qwen`, ...), then ids, model names, and unique partial
+/// matches.
+fn resolve_model_info_target_with_models(
+    identifier: &str,
+    models: &[ThirdPartyModelConfig],
+) -> Option<String> {
+    resolve_runtime_model_identifier(identifier)
+        .or_else(|| resolve_keyword_alias_with_models(identifier, models))
+        .or_else(|| resolve_alias_to_model_id_with_models(identifier, models, &[]))
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn model_info_text_with_models(
+    identifier: &str,
+    models: &[ThirdPartyModelConfig],
+    default_model: &str,
+    gemini_available: bool,
+) -> String {
+    let identifier = identifier.trim();
+    let known_ids = || {
+        std::iter::once(MODEL_GEMINI.to_string())
+            .chain(models.iter().map(|config| config.id.clone()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if identifier.is_empty() {
+        return format!("Usage: /model_info <model>\nKnown models: {}", known_ids());
+    }
+    let Some(model_id) = resolve_model_info_target_with_models(identifier, models) else {
+        return format!(
+            "Unknown model '{identifier}'. Known models: {}",
+            known_ids()
+        );
+    };
+    let is_default = model_id == default_model;
+
+    if model_id == MODEL_GEMINI {
+        return [
+            "Model: Gemini".to_string(),
+            format!("ID: {MODEL_GEMINI}"),
+            format!("Flash model: {}", CONFIG.gemini_model),
+            format!("Pro model: {}", CONFIG.gemini_pro_model),
+            "Images: yes, Video: yes, Audio: yes, Documents: yes".to_string(),
+            "Tools: yes".to_string(),
+            format!("Available: {}", yes_no(gemini_available)),
+            format!("Default: {}", yes_no(is_default)),
+        ]
+        .join("\n");
+    }
+
+    let Some(config) = models.iter().find(|config| config.id == model_id) else {
+        return format!(
+            "Unknown model '{identifier}'. Known models: {}",
+            known_ids()
+        );
+    };
+    [
+        format!("Model: {}", config.name),
+        format!("ID: {}", config.id),
+        format!(
+            "Provider: {} ({})",
+            third_party_provider_label(config.provider),
+            config.model
+        ),
+        format!(
+            "Images: {}, Video: {}, Audio: {}, Documents: no",
+            yes_no(config.image),
+            yes_no(config.video),
+            yes_no(config.audio)
+        ),
+        format!("Tools: {}", yes_no(config.tools)),
+        format!(
+            "Available: {}",
+            yes_no(is_runtime_provider_ready(config.provider))
+        ),
+        format!("Default: {}", yes_no(is_default)),
+    ]
+    .join("\n")
+}
+
+pub async fn model_info_handler(
+    bot: Bot,
+    state: AppState,
+    message: Message,
+    identifier: String,
+) -> Result<()> {
+    if !check_access_control(&bot, &state, &message, "model_info").await {
+        return Ok(());
+    }
+    let models = runtime_models();
+    let default_model = normalize_model_identifier(&CONFIG.default_text_model);
+    let text = model_info_text_with_models(
+        &identifier,
+        &models,
+        &default_model,
+        CONFIG.gemini_api_available(),
+    );
+    send_message_with_retry(&bot, message.chat.id, &text, Some(message.id), None, None).await?;
+    Ok(())
+}
+
+pub async fn context_handler(
+    bot: Bot,
+    state: AppState,
+    message: Message,
+    query: Option<String>,
+) -> Result<()> {
+    if !check_access_control(&bot, &state, &message, "context").await {
+        return Ok(());
+    }
+
+    let user_id = command_subject_id(&message);
+    if is_rate_limited(user_id) {
+        send_message_with_retry(
+            &bot,
+            message.chat.id,
+            "You're sending commands too quickly. Please wait a moment before trying again.",
+            Some(message.id),
+            None,
+            None,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let extraction = ChatExtractionSettings::from(&state.chat_settings.get(message.chat.id.0));
+    let (_, query_text_raw) = split_answer_length_flag(&query.unwrap_or_default());
+    let mut preview = ContextPreview {
+        query_chars: query_text_raw.chars().count(),
+        ..ContextPreview::default()
+    };
+    let mut telegraph_contents = Vec::new();
+    let mut twitter_contents = Vec::new();
+
+    let mut reply_text = String::new();
+    if let Some(reply) = message.reply_to_message() {
+        let reply_text_raw = reply
+            .text()
+            .or_else(|| reply.caption())
+            .unwrap_or_default()
+            .to_string();
+        preview.reply_chars = reply_text_raw.chars().count();
+        if !reply_text_raw.trim().is_empty() {
+            let reply_entities = message_entities_for_text(reply);
+            let (processed, telegraph, twitter) =
+                extract_links_for_chat(extraction, &reply_text_raw, reply_entities.as_deref(), 5)
+                    .await;
+            telegraph_contents.extend(telegraph);
+            twitter_contents.extend(twitter);
+            reply_text = processed;
+        }
+    }
+
+    let mut query_text = query_text_raw.clone();
+    if !query_text.trim().is_empty() {
+        let query_entities = message_entities_for_text(&message);
+        let (processed, telegraph, twitter) =
+            extract_links_for_chat(extraction, &query_text, query_entities.as_deref(), 5).await;
+        telegraph_contents.extend(telegraph);
+        twitter_contents.extend(twitter);
+        query_text = processed;
+    }
+
+    let query_base = match (query_text.trim().is_empty(), reply_text.trim().is_empty()) {
+        (true, _) => reply_text,
+        (false, true) => query_text,
+        (false, false) => format!(
+            "Context from replied message: \"{}\"\n\nQuestion: {}",
+            reply_text, query_text
+        ),
+    };
+    let gemini_available = CONFIG.gemini_api_available();
+    let (prompt_text, youtube_urls) = extract_youtube_urls_for_available_models(
+        &query_base,
+        gemini_available && extraction.youtube,
+    );
+    preview.youtube_dropped_without_gemini =
+        !gemini_available && !extract_youtube_urls(&query_base, 10).1.is_empty();
+    preview.youtube_urls = youtube_urls;
+    preview.prompt_chars = prompt_text.chars().count();
+
+    preview.telegraph_pages = telegraph_contents
+        .iter()
+        .map(|page| {
+            (
+                page.text_content.chars().count(),
+                page.image_urls.len(),
+                page.video_urls.len(),
+            )
+        })
+        .collect();
+    preview.twitter_posts = twitter_contents
+        .iter()
+        .map(|post| {
+            (
+                post.url.clone(),
+                post.text_content.chars().count(),
+                post.image_urls.len(),
+                post.video_urls.len(),
+            )
+        })
+        .collect();
+
+    let media =
+        collect_message_media(&bot, &state, &message, MediaCollectionOptions::for_qa()).await;
+    preview.media = summarize_media_files(&media.files);
+    preview.media_skipped_oversized = media.skipped_oversized;
+
+    send_message_with_retry(
+        &bot,
+        message.chat.id,
+        &format_context_preview(&preview),
+        Some(message.id),
+        None,
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
 pub async fn q_handler(
     bot: Bot,
     state: AppState,
@@ -3107,15 +4388,17 @@ pub async fn s_handler(
     message: Message,
     query: Option<String>,
 ) -> Result<()> {
-    if !check_access_control(&bot, &message, "s").await {
+    if !check_access_control(&bot, &state, &message, "s").await {
+        return Ok(());
+    }
+    if !ensure_llm_available(&bot, &message).await {
+        return Ok(());
+    }
+    if !ensure_token_quota(&bot, &state, &message).await {
         return Ok(());
     }
 
-    let user_id = message
-        .from
-        .as_ref()
-        .and_then(|user| i64::try_from(user.id.0).ok())
-        .unwrap_or_default();
+    let user_id = command_subject_id(&message);
     if is_rate_limited(user_id) {
         send_message_with_retry(
             &bot,
@@ -3279,7 +4562,7 @@ pub async fn s_handler(
 
     let bot_clone = bot.clone();
     let state_clone = state.clone();
-    tokio::spawn(async move {
+    spawn_in_current_request(async move {
         handle_model_timeout(bot_clone, state_clone, request_key).await;
     });
 
@@ -3427,20 +4710,36 @@ pub async fn model_selection_callback(
         )
     };
 
-    let mut request = match action {
-        PendingQRequestCallbackAction::UseSelected(request) => request,
-        PendingQRequestCallbackAction::UseDefault(request) => {
-            process_timed_out_q_request_with_default_model(&bot, &state, request).await;
-            return Ok(());
-        }
+    let (request, use_default) = match action {
+        PendingQRequestCallbackAction::UseSelected(request) => (request, false),
+        PendingQRequestCallbackAction::UseDefault(request) => (request, true),
         PendingQRequestCallbackAction::Missing
         | PendingQRequestCallbackAction::Ignored
         | PendingQRequestCallbackAction::InvalidSelection => return Ok(()),
     };
 
+    // Continue under the id the original command logged and replied with.
+    let request_id = request.request_id.clone().unwrap_or_else(new_request_id);
+    run_traced("q", request_id, async move {
+        if use_default {
+            process_timed_out_q_request_with_default_model(&bot, &state, request).await;
+            return Ok(());
+        }
+        run_selected_model(&bot, &state, &message, request, &selected_model).await
+    })
+    .await
+}
+
+async fn run_selected_model(
+    bot: &Bot,
+    state: &AppState,
+    message: &MaybeInaccessibleMessage,
+    mut request: PendingQRequest,
+    selected_model: &str,
+) -> Result<()> {
     let summary = summarize_media_files(&request.media_files);
 
-    let display_name = configured_model_display_name(&selected_model);
+    let display_name = configured_model_display_name(selected_model);
 
     let processing_text = if request.mode == QaCommandMode::ChatSearch {
         format!("Searching this chat with {}...", display_name)
@@ -3475,7 +4774,7 @@ pub async fn model_selection_callback(
         .await?;
 
     let command_timer = request.command_timer.take();
-    let result = process_request(&bot, &state, request, &selected_model).await;
+    let result = process_request(bot, state, request, selected_model).await;
     if let Some(mut timer) = command_timer {
         let status = if result.is_ok() { "success" } else { "error" };
         complete_command_timer(&mut timer, status, None);
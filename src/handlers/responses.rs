@@ -1,15 +1,48 @@
-use std::time::Duration;
+use std::future::{Future, IntoFuture};
 
 use anyhow::Result;
 use teloxide::prelude::*;
-use teloxide::types::{MessageId, ParseMode};
+use teloxide::types::{Chat, MessageId, MessageOrigin, ParseMode, ReactionType, ReplyParameters};
+use teloxide::{ApiError, RequestError};
 use tracing::{error, warn};
 
 use crate::config::CONFIG;
 use crate::db::database::build_message_insert;
+use crate::db::models::ChatSettingsRow;
 use crate::db::search::derive_search_provenance;
-use crate::handlers::content::create_telegraph_page;
-use crate::state::AppState;
+use crate::handlers::content::{create_telegraph_page, TelegraphAuthor};
+use crate::handlers::prefix_forward_origin;
+use crate::state::{AppState, CommandAck};
+use crate::utils::markup::sanitize_markup;
+use crate::utils::progress::ProgressReporter;
+use crate::utils::retry::{retry_async, telegram_retry_policy, RetryDecision};
+
+/// Whether Telegram rejected the text's Markdown or HTML entities.
+pub(crate) fn is_entity_parse_error(err: &RequestError) -> bool {
+    matches!(err, RequestError::Api(ApiError::CantParseEntities(_)))
+}
+
+/// Sends with `parse_mode` and, if Telegram cannot parse the entities, once
+/// more as plain text so the user still gets the content. `send` receives the
+/// parse mode to use, `None` meaning plain text. `text` is only logged.
+pub(crate) async fn send_with_plain_text_fallback<T, F, Fut>(
+    context: &str,
+    text: &str,
+    parse_mode: ParseMode,
+    mut send: F,
+) -> std::result::Result<T, RequestError>
+where
+    F: FnMut(Option<ParseMode>) -> Fut,
+    Fut: Future<Output = std::result::Result<T, RequestError>>,
+{
+    match send(Some(parse_mode)).await {
+        Err(err) if is_entity_parse_error(&err) => {
+            warn!("{context}: {parse_mode:?} rejected ({err}); resending as plain text. Content: {text:?}");
+            send(None).await
+        }
+        result => result,
+    }
+}
 
 async fn edit_text_with_retry(
     bot: &Bot,
@@ -17,29 +50,30 @@ async fn edit_text_with_retry(
     message_id: MessageId,
     text: &str,
     parse_mode: Option<ParseMode>,
-) -> Result<()> {
-    let mut delay = Duration::from_secs_f32(1.5);
-    for attempt in 0..3 {
-        let request = bot.edit_message_text(chat_id, message_id, text.to_string());
-        let request = if let Some(mode) = parse_mode {
-            request.parse_mode(mode)
-        } else {
-            request
-        };
-
-        match request.await {
-            Ok(_) => return Ok(()),
-            Err(err) => {
-                if attempt == 2 {
-                    return Err(err.into());
-                }
-                warn!("edit_message_text failed: {err}");
-                tokio::time::sleep(delay).await;
-                delay *= 2;
+) -> std::result::Result<(), RequestError> {
+    // Unlike the send helpers this retries most errors: a Markdown edit that
+    // Telegram rejects sometimes goes through on a second try. Entity parse
+    // errors are final and left to the plain-text fallback.
+    retry_async(
+        &telegram_retry_policy(),
+        |_| {
+            let request = bot.edit_message_text(chat_id, message_id, text.to_string());
+            let request = if let Some(mode) = parse_mode {
+                request.parse_mode(mode)
+            } else {
+                request
+            };
+            request.into_future()
+        },
+        |err, _| {
+            if is_entity_parse_error(err) {
+                return RetryDecision::Stop;
             }
-        }
-    }
-
+            warn!("edit_message_text failed: {err}");
+            RetryDecision::Retry
+        },
+    )
+    .await?;
     Ok(())
 }
 
@@ -51,21 +85,78 @@ pub async fn send_response(
     response: &str,
     title: &str,
     parse_mode: ParseMode,
+    author: &TelegraphAuthor,
 ) -> Result<()> {
+    deliver_response(
+        response,
+        title,
+        parse_mode,
+        author,
+        |text, parse_mode| async move {
+            edit_text_with_retry(bot, chat_id, message_id, &text, parse_mode).await
+        },
+    )
+    .await
+}
+
+/// Like [`send_response`], but sends the answer as a new reply to `reply_to`
+/// for requests that have no processing message to edit. Returns the id of
+/// the sent answer.
+#[allow(deprecated)]
+pub async fn reply_response(
+    bot: &Bot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    response: &str,
+    title: &str,
+    parse_mode: ParseMode,
+    author: &TelegraphAuthor,
+) -> Result<MessageId> {
+    deliver_response(response, title, parse_mode, author, |text, parse_mode| {
+        let request = bot
+            .send_message(chat_id, text)
+            .reply_parameters(ReplyParameters::new(reply_to));
+        let request = match parse_mode {
+            Some(mode) => request.parse_mode(mode),
+            None => request,
+        };
+        async move { Ok(request.await?.id) }
+    })
+    .await
+}
+
+/// Shared body of [`send_response`] and [`reply_response`]: long answers go
+/// to a Telegraph page signed by `author` (or are truncated), short ones are
+/// delivered with `parse_mode` and a plain-text fallback.
+#[allow(deprecated)]
+async fn deliver_response<T, F, Fut>(
+    response: &str,
+    title: &str,
+    parse_mode: ParseMode,
+    author: &TelegraphAuthor,
+    mut deliver: F,
+) -> Result<T>
+where
+    F: FnMut(String, Option<ParseMode>) -> Fut,
+    Fut: Future<Output = std::result::Result<T, RequestError>>,
+{
+    let sanitized;
+    let response = if CONFIG.sanitize_response_markup {
+        sanitized = sanitize_markup(response, parse_mode);
+        sanitized.as_str()
+    } else {
+        response
+    };
     let line_count = response.lines().count();
 
     if line_count > 22 || response.len() > CONFIG.telegram_max_length {
-        let telegraph_url = create_telegraph_page(title, response).await;
+        let telegraph_url = create_telegraph_page(title, response, author).await;
         if let Some(url) = telegraph_url {
-            edit_text_with_retry(
-                bot,
-                chat_id,
-                message_id,
-                &format!("I have too much to say. [View it here]({})", url),
+            return Ok(deliver(
+                format!("I have too much to say. [View it here]({})", url),
                 Some(ParseMode::Markdown),
             )
-            .await?;
-            return Ok(());
+            .await?);
         }
 
         let truncated = if response.len() > CONFIG.telegram_max_length {
@@ -76,31 +167,215 @@ pub async fn send_response(
         } else {
             response.to_string()
         };
-        edit_text_with_retry(bot, chat_id, message_id, &truncated, None).await?;
-        return Ok(());
+        return Ok(deliver(truncated, None).await?);
     }
 
-    if let Err(err) =
-        edit_text_with_retry(bot, chat_id, message_id, response, Some(parse_mode)).await
-    {
-        warn!("Failed to send formatted response: {err}");
-        edit_text_with_retry(bot, chat_id, message_id, response, None).await?;
+    Ok(
+        send_with_plain_text_fallback("send_response", response, parse_mode, |parse_mode| {
+            deliver(response.to_string(), parse_mode)
+        })
+        .await?,
+    )
+}
+
+/// A command's processing message, edited with status updates and finally
+/// replaced by the answer. In quiet mode nothing is sent up front: updates
+/// are dropped and the outcome goes out as one reply to the command.
+pub struct StatusMessage {
+    bot: Bot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    message_id: Option<MessageId>,
+    author: TelegraphAuthor,
+}
+
+impl StatusMessage {
+    /// Sends `text` as a reply to `reply_to` unless the chat is in quiet mode.
+    pub async fn start(
+        bot: &Bot,
+        settings: &ChatSettingsRow,
+        reply_to: MessageId,
+        text: &str,
+    ) -> Result<Self> {
+        let chat_id = ChatId(settings.chat_id);
+        let message_id = if chat_quiet_mode(settings) {
+            None
+        } else {
+            let sent = bot
+                .send_message(chat_id, text)
+                .reply_parameters(ReplyParameters::new(reply_to))
+                .await?;
+            Some(sent.id)
+        };
+        Ok(Self {
+            bot: bot.clone(),
+            chat_id,
+            reply_to,
+            message_id,
+            author: TelegraphAuthor::for_chat(settings),
+        })
     }
 
-    Ok(())
+    /// Best-effort intermediate status; failures are ignored.
+    pub async fn update(&self, text: &str) {
+        if let Some(message_id) = self.message_id {
+            let _ = self
+                .bot
+                .edit_message_text(self.chat_id, message_id, text)
+                .await;
+        }
+    }
+
+    pub fn progress_reporter(&self) -> ProgressReporter {
+        ProgressReporter::for_message(self.bot.clone(), self.chat_id, self.message_id)
+    }
+
+    /// The processing message, or `None` in quiet mode.
+    pub fn message_id(&self) -> Option<MessageId> {
+        self.message_id
+    }
+
+    /// Deletes the processing message once the answer went out on its own.
+    pub async fn dismiss(&mut self) {
+        if let Some(message_id) = self.message_id.take() {
+            let _ = self.bot.delete_message(self.chat_id, message_id).await;
+        }
+    }
+
+    /// Shows a plain-text outcome such as an error.
+    pub async fn finish(&mut self, text: impl Into<String>) -> Result<()> {
+        match self.message_id {
+            Some(message_id) => {
+                self.bot
+                    .edit_message_text(self.chat_id, message_id, text.into())
+                    .await?;
+            }
+            None => {
+                let sent = self
+                    .bot
+                    .send_message(self.chat_id, text.into())
+                    .reply_parameters(ReplyParameters::new(self.reply_to))
+                    .await?;
+                self.message_id = Some(sent.id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Delivers the answer through [`send_response`] or, in quiet mode,
+    /// [`reply_response`]. Returns the id of the message holding it.
+    pub async fn finish_response(
+        &mut self,
+        response: &str,
+        title: &str,
+        parse_mode: ParseMode,
+    ) -> Result<MessageId> {
+        let message_id = match self.message_id {
+            Some(message_id) => {
+                send_response(
+                    &self.bot,
+                    self.chat_id,
+                    message_id,
+                    response,
+                    title,
+                    parse_mode,
+                    &self.author,
+                )
+                .await?;
+                message_id
+            }
+            None => {
+                reply_response(
+                    &self.bot,
+                    self.chat_id,
+                    self.reply_to,
+                    response,
+                    title,
+                    parse_mode,
+                    &self.author,
+                )
+                .await?
+            }
+        };
+        self.message_id = Some(message_id);
+        Ok(message_id)
+    }
 }
 
-pub async fn log_message(state: &AppState, message: &Message) {
-    let text = message
-        .text()
-        .map(|value| value.to_string())
-        .or_else(|| message.caption().map(|value| value.to_string()));
+/// Commands quick enough that a reaction is acknowledgment enough.
+const REACTION_ACK_COMMANDS: &[&str] = &["qq"];
 
-    let Some(text) = text else {
-        return;
-    };
+const ACK_REACTION_EMOJI: &str = "\u{1F440}";
+
+/// Whether commands in the chat answer without status messages: the chat's
+/// `/quiet` setting, else `QUIET_MODE`.
+pub fn chat_quiet_mode(settings: &ChatSettingsRow) -> bool {
+    settings.quiet_mode.unwrap_or(CONFIG.quiet_mode)
+}
+
+/// With `USE_REACTIONS_FOR_ACK`, fast commands acknowledge with a reaction
+/// instead of a processing message; in quiet mode the rest send nothing.
+pub fn command_ack_mode(use_reactions: bool, quiet: bool, command_name: &str) -> CommandAck {
+    if use_reactions && REACTION_ACK_COMMANDS.contains(&command_name) {
+        CommandAck::Reaction
+    } else if quiet {
+        CommandAck::Silent
+    } else {
+        CommandAck::Message
+    }
+}
+
+/// Acknowledges `message` the way [`command_ack_mode`] picks for its chat.
+/// A reaction the chat rejects falls back to what the command would do
+/// without reactions. For `Message` the caller still sends the processing
+/// message.
+pub async fn acknowledge_command(
+    bot: &Bot,
+    state: &AppState,
+    message: &Message,
+    command_name: &str,
+) -> CommandAck {
+    let quiet = chat_quiet_mode(&state.chat_settings.get(message.chat.id.0));
+    match command_ack_mode(CONFIG.use_reactions_for_ack, quiet, command_name) {
+        CommandAck::Reaction => match set_ack_reaction(bot, message.chat.id, message.id).await {
+            Ok(()) => CommandAck::Reaction,
+            Err(err) => {
+                let fallback = command_ack_mode(false, quiet, command_name);
+                warn!("Ack reaction failed; falling back to {fallback:?}: {err}");
+                fallback
+            }
+        },
+        ack => ack,
+    }
+}
+
+/// Sets the ack reaction on `message_id`. Fails when the chat disallows
+/// reactions or the Bot API predates them; callers then fall back to a
+/// processing message.
+pub async fn set_ack_reaction(bot: &Bot, chat_id: ChatId, message_id: MessageId) -> Result<()> {
+    bot.set_message_reaction(chat_id, message_id)
+        .reaction(vec![ReactionType::Emoji {
+            emoji: ACK_REACTION_EMOJI.to_string(),
+        }])
+        .await?;
+    Ok(())
+}
 
-    let username = if let Some(user) = message.from.as_ref() {
+pub async fn clear_ack_reaction(bot: &Bot, chat_id: ChatId, message_id: MessageId) {
+    if let Err(err) = bot
+        .set_message_reaction(chat_id, message_id)
+        .reaction(Vec::new())
+        .await
+    {
+        warn!(
+            "Failed to clear ack reaction on message {}: {err}",
+            message_id.0
+        );
+    }
+}
+
+pub(crate) fn message_sender_display_name(message: &Message) -> String {
+    if let Some(user) = message.from.as_ref() {
         if !user.full_name().is_empty() {
             user.full_name()
         } else if let Some(username) = &user.username {
@@ -110,16 +385,84 @@ pub async fn log_message(state: &AppState, message: &Message) {
         }
     } else {
         "Anonymous".to_string()
+    }
+}
+
+/// Where a forwarded message came from, e.g.
+/// `forwarded from channel "Daily News" (@dailynews), 2026-03-01 09:15 UTC`.
+pub(crate) fn forward_origin_label(origin: &MessageOrigin) -> String {
+    let chat_label = |chat: &Chat, kind: &str| {
+        let title = chat.title().unwrap_or("unknown");
+        match chat.username() {
+            Some(username) => format!("{kind} \"{title}\" (@{username})"),
+            None => format!("{kind} \"{title}\""),
+        }
+    };
+    let source = match origin {
+        MessageOrigin::User { sender_user, .. } => match &sender_user.username {
+            Some(username) => format!("{} (@{username})", sender_user.full_name()),
+            None => sender_user.full_name(),
+        },
+        MessageOrigin::HiddenUser {
+            sender_user_name, ..
+        } => format!("{sender_user_name} (hidden account)"),
+        MessageOrigin::Chat { sender_chat, .. } => chat_label(sender_chat, "chat"),
+        MessageOrigin::Channel { chat, .. } => chat_label(chat, "channel"),
     };
+    format!(
+        "forwarded from {source}, {}",
+        origin.date().format("%Y-%m-%d %H:%M UTC")
+    )
+}
+
+/// Prompt text for a live message, prefixed with its forward origin.
+pub(crate) fn with_forward_origin(text: &str, origin: Option<&MessageOrigin>) -> String {
+    prefix_forward_origin(text, origin.map(forward_origin_label).as_deref())
+}
+
+/// Whether `message` was posted by a bot other than this one. Anonymous
+/// admins and linked channels post through Telegram's service bots but carry
+/// a `sender_chat`, so they are not counted as bots.
+pub(crate) fn is_from_other_bot(message: &Message, bot_user_id: i64) -> bool {
+    message.sender_chat.is_none()
+        && message
+            .from
+            .as_ref()
+            .is_some_and(|user| user.is_bot && i64::try_from(user.id.0).ok() != Some(bot_user_id))
+}
 
+/// `IGNORE_OTHER_BOTS` keeps other bots' messages out of the history that
+/// summaries and searches read.
+pub(crate) fn should_log_message(
+    message: &Message,
+    bot_user_id: i64,
+    ignore_other_bots: bool,
+) -> bool {
+    !(ignore_other_bots && is_from_other_bot(message, bot_user_id))
+}
+
+pub async fn log_message(state: &AppState, message: &Message) {
+    if !should_log_message(message, state.bot_user_id, CONFIG.ignore_other_bots) {
+        return;
+    }
+    let text = message
+        .text()
+        .map(|value| value.to_string())
+        .or_else(|| message.caption().map(|value| value.to_string()));
+
+    let Some(text) = text else {
+        return;
+    };
+
+    let username = message_sender_display_name(message);
     let provenance = derive_search_provenance(&text);
-    let insert = build_message_insert(
+    let mut insert = build_message_insert(
         message
             .from
             .as_ref()
             .and_then(|user| i64::try_from(user.id.0).ok()),
         Some(username),
-        Some(text.clone()),
+        Some(text),
         None,
         message.date,
         message.reply_to_message().map(|msg| msg.id.0 as i64),
@@ -131,8 +474,268 @@ pub async fn log_message(state: &AppState, message: &Message) {
         provenance.is_command,
         false,
     );
+    insert.forward_origin = message.forward_origin().map(forward_origin_label);
 
     if let Err(err) = state.db.queue_message_insert(insert).await {
         error!("Failed to queue message insert: {err}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn origin(value: serde_json::Value) -> MessageOrigin {
+        serde_json::from_value(value).expect("origin JSON should parse")
+    }
+
+    #[test]
+    fn other_bots_are_left_out_of_logged_history_when_ignored() {
+        let message = |from: serde_json::Value, sender_chat: Option<serde_json::Value>| {
+            let mut value = serde_json::json!({
+                "message_id": 1,
+                "date": 1,
+                "chat": { "id": -100123, "type": "supergroup", "title": "test group" },
+                "from": from,
+                "text": "daily price alert"
+            });
+            if let Some(sender_chat) = sender_chat {
+                value["sender_chat"] = sender_chat;
+            }
+            serde_json::from_value::<Message>(value).expect("test message should deserialize")
+        };
+        let other_bot = message(
+            serde_json::json!({ "id": 500, "is_bot": true, "first_name": "PriceBot" }),
+            None,
+        );
+        let this_bot = message(
+            serde_json::json!({ "id": 42, "is_bot": true, "first_name": "Helper" }),
+            None,
+        );
+        let anonymous_admin = message(
+            serde_json::json!({ "id": 1_087_968_824_u64, "is_bot": true, "first_name": "Group" }),
+            Some(serde_json::json!({ "id": -100123, "type": "supergroup", "title": "test group" })),
+        );
+        let human = message(
+            serde_json::json!({ "id": 7, "is_bot": false, "first_name": "Alice" }),
+            None,
+        );
+
+        assert!(!should_log_message(&other_bot, 42, true));
+        assert!(should_log_message(&other_bot, 42, false));
+        assert!(should_log_message(&this_bot, 42, true));
+        assert!(should_log_message(&anonymous_admin, 42, true));
+        assert!(should_log_message(&human, 42, true));
+    }
+
+    #[test]
+    fn reaction_ack_only_applies_to_fast_commands_when_enabled() {
+        assert_eq!(command_ack_mode(true, false, "qq"), CommandAck::Reaction);
+        assert_eq!(command_ack_mode(false, false, "qq"), CommandAck::Message);
+        assert_eq!(command_ack_mode(true, false, "q"), CommandAck::Message);
+        assert_eq!(command_ack_mode(true, false, "tldr"), CommandAck::Message);
+        assert_eq!(command_ack_mode(true, true, "qq"), CommandAck::Reaction);
+        assert_eq!(command_ack_mode(false, true, "qq"), CommandAck::Silent);
+        assert_eq!(command_ack_mode(true, true, "q"), CommandAck::Silent);
+    }
+
+    #[test]
+    fn chat_quiet_mode_override_falls_back_to_the_global_default() {
+        let mut settings = ChatSettingsRow::new(-5151);
+        settings.quiet_mode = Some(!CONFIG.quiet_mode);
+        assert_eq!(chat_quiet_mode(&settings), !CONFIG.quiet_mode);
+        settings.quiet_mode = None;
+        assert_eq!(chat_quiet_mode(&settings), CONFIG.quiet_mode);
+    }
+
+    /// Fake Bot API that answers every call with a message and records the
+    /// method names in order.
+    async fn fake_bot_api() -> (Bot, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("loopback listener should bind");
+        let url = format!("http://{}/", listener.local_addr().expect("local addr"));
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        tokio::spawn(async move {
+            let mut next_message_id = 100;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                let body_start = loop {
+                    let read = socket.read(&mut buffer).await.unwrap_or(0);
+                    if read == 0 {
+                        break None;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                    if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break Some(end + 4);
+                    }
+                };
+                let Some(body_start) = body_start else {
+                    continue;
+                };
+                let head = String::from_utf8_lossy(&request[..body_start]).to_lowercase();
+                let content_length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .and_then(|value| value.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                while request.len() < body_start + content_length {
+                    let read = socket.read(&mut buffer).await.unwrap_or(0);
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let method = head
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|path| path.rsplit('/').next())
+                    .unwrap_or_default()
+                    .to_string();
+                recorded.lock().push(method);
+                next_message_id += 1;
+                let body = serde_json::json!({
+                    "ok": true,
+                    "result": {
+                        "message_id": next_message_id,
+                        "date": 1,
+                        "chat": { "id": -100123, "type": "supergroup", "title": "test group" },
+                        "from": { "id": 42, "is_bot": true, "first_name": "Helper" },
+                        "text": "ok"
+                    }
+                })
+                .to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        let bot = Bot::new("123:TEST").set_api_url(url.parse().expect("fake API url"));
+        (bot, calls)
+    }
+
+    /// Runs a command the way `/tldr` does: processing message, status
+    /// updates, progress, then the answer.
+    async fn run_status_command(bot: &Bot, quiet: bool) -> MessageId {
+        let settings = ChatSettingsRow {
+            quiet_mode: Some(quiet),
+            ..ChatSettingsRow::new(-100123)
+        };
+        let mut status = StatusMessage::start(
+            bot,
+            &settings,
+            MessageId(7),
+            "Summarizing recent messages...",
+        )
+        .await
+        .expect("status should start");
+        status
+            .progress_reporter()
+            .update_now("Summarizing part 1/2...")
+            .await;
+        status.update("Finalizing response...").await;
+        status
+            .finish_response("Short summary.", "Message Summary", ParseMode::Html)
+            .await
+            .expect("answer should be delivered")
+    }
+
+    #[tokio::test]
+    async fn quiet_mode_sends_exactly_one_message_per_command() {
+        let (bot, calls) = fake_bot_api().await;
+        let answer_id = run_status_command(&bot, true).await;
+        assert_eq!(*calls.lock(), vec!["sendmessage"]);
+        assert_eq!(answer_id, MessageId(101));
+
+        calls.lock().clear();
+        run_status_command(&bot, false).await;
+        assert_eq!(
+            *calls.lock(),
+            vec![
+                "sendmessage",
+                "editmessagetext",
+                "editmessagetext",
+                "editmessagetext"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn entity_parse_errors_fall_back_to_plain_text() {
+        let mut modes = Vec::new();
+        let result = send_with_plain_text_fallback(
+            "test",
+            "*unclosed",
+            ParseMode::MarkdownV2,
+            |parse_mode| {
+                modes.push(parse_mode);
+                async move {
+                    match parse_mode {
+                        Some(_) => Err(RequestError::Api(ApiError::CantParseEntities(
+                            "Bad Request: can't parse entities: Can't find end of the entity"
+                                .to_string(),
+                        ))),
+                        None => Ok("sent"),
+                    }
+                }
+            },
+        )
+        .await;
+        assert_eq!(result.ok(), Some("sent"));
+        assert_eq!(modes, vec![Some(ParseMode::MarkdownV2), None]);
+
+        let mut attempts = 0;
+        let result: std::result::Result<(), RequestError> =
+            send_with_plain_text_fallback("test", "text", ParseMode::Html, |_| {
+                attempts += 1;
+                async { Err(RequestError::Api(ApiError::MessageToEditNotFound)) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn forward_origin_prefixes_name_the_source_and_date() {
+        let channel = origin(serde_json::json!({
+            "type": "channel",
+            "date": 1_772_356_500,
+            "chat": { "id": -1001234567890_i64, "type": "channel", "title": "Daily News", "username": "dailynews" },
+            "message_id": 42
+        }));
+        assert_eq!(
+            with_forward_origin("Prices double tomorrow", Some(&channel)),
+            "[forwarded from channel \"Daily News\" (@dailynews), 2026-03-01 09:15 UTC] Prices double tomorrow"
+        );
+
+        let hidden = origin(serde_json::json!({
+            "type": "hidden_user",
+            "date": 1_772_356_500,
+            "sender_user_name": "Someone"
+        }));
+        assert!(with_forward_origin("hi", Some(&hidden))
+            .starts_with("[forwarded from Someone (hidden account), "));
+
+        let user = origin(serde_json::json!({
+            "type": "user",
+            "date": 1_772_356_500,
+            "sender_user": { "id": 7, "is_bot": false, "first_name": "Ada", "username": "ada" }
+        }));
+        assert!(with_forward_origin("hi", Some(&user)).starts_with("[forwarded from Ada (@ada), "));
+    }
+
+    #[test]
+    fn messages_without_forward_origin_are_unchanged() {
+        assert_eq!(with_forward_origin("plain text", None), "plain text");
+    }
+}
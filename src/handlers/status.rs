@@ -0,0 +1,289 @@
+//! Shared health snapshot behind `/status` and `/status json`.
+//!
+//! The snapshot only carries counters, readiness flags, and non-secret config
+//! values; API keys, tokens, and account identities never enter it, so the
+//! JSON form is safe to hand to monitoring.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::config::{ThirdPartyProvider, CONFIG};
+use crate::db::database::{PoolStats, PragmaState};
+use crate::llm::runtime_models::{is_runtime_provider_ready, runtime_model_count};
+use crate::llm::web_search::is_search_enabled;
+use crate::state::{AppState, ChatInFlight};
+
+/// Busiest chats listed in `per_chat_in_flight`; the rest are only counted.
+pub const STATUS_IN_FLIGHT_MAX_CHATS: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusSnapshot {
+    pub time_utc: String,
+    pub db: DbStatus,
+    pub heavy_commands: HeavyCommandStatus,
+    pub pending_requests: PendingRequestStatus,
+    pub media_groups_cached: usize,
+    pub ignored_updates: BTreeMap<&'static str, u64>,
+    pub providers: ProviderStatus,
+    pub web_search: WebSearchStatus,
+    pub third_party_models_count: usize,
+    pub whitelist_present: bool,
+    pub logs_dir_present: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DbStatus {
+    pub ok: bool,
+    pub error: Option<String>,
+    pub queue_pending: usize,
+    pub queue_available: usize,
+    pub queue_max: usize,
+    pub search_ready: bool,
+    pub max_connections: u32,
+    pub pool: PoolStats,
+    /// `None` when the pragmas could not be read.
+    pub pragmas: Option<PragmaState>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeavyCommandStatus {
+    pub active: usize,
+    pub waiting: usize,
+    pub max: usize,
+    /// `0` means no per-chat cap.
+    pub per_chat_max: usize,
+    /// Chats with heavy commands running or queued.
+    pub chats_in_flight: usize,
+    /// The busiest [`STATUS_IN_FLIGHT_MAX_CHATS`] of them, busiest first.
+    pub per_chat_in_flight: Vec<ChatInFlightStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatInFlightStatus {
+    pub chat_id: i64,
+    pub active: usize,
+    pub waiting: usize,
+}
+
+impl From<ChatInFlight> for ChatInFlightStatus {
+    fn from(chat: ChatInFlight) -> Self {
+        Self {
+            chat_id: chat.chat_id,
+            active: chat.active,
+            waiting: chat.waiting,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingRequestStatus {
+    pub q: usize,
+    pub image: usize,
+    pub codex_model: usize,
+    pub codex_reasoning: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderStatus {
+    pub gemini_configured: bool,
+    pub tldr_infographic_enabled: bool,
+    pub openrouter_ready: bool,
+    pub nvidia_ready: bool,
+    pub ollama_ready: bool,
+    pub openai_ready: bool,
+    pub img2_ready: bool,
+    pub openai_codex_ready: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebSearchStatus {
+    pub enabled: bool,
+    pub providers_order: Vec<String>,
+    pub brave_ready: bool,
+    pub exa_ready: bool,
+    pub jina_ready: bool,
+}
+
+pub async fn collect_status_snapshot(state: &AppState) -> StatusSnapshot {
+    let db_result = state.db.health_check().await;
+    let in_flight = state.chat_concurrency.in_flight();
+    StatusSnapshot {
+        time_utc: Utc::now().to_rfc3339(),
+        db: DbStatus {
+            ok: db_result.is_ok(),
+            error: db_result.err().map(|err| err.to_string()),
+            queue_pending: state.db.queue_len(),
+            queue_available: state.db.queue_available_capacity(),
+            queue_max: state.db.queue_max_capacity(),
+            search_ready: state.db.is_search_ready(),
+            max_connections: CONFIG.db_max_connections,
+            pool: state.db.pool_stats(),
+            pragmas: state.db.pragma_state().await.ok(),
+        },
+        heavy_commands: HeavyCommandStatus {
+            active: state.heavy_command_active(),
+            waiting: state.heavy_command_waiting(),
+            max: CONFIG.heavy_command_max_concurrency,
+            per_chat_max: CONFIG.max_concurrent_per_chat,
+            chats_in_flight: in_flight.len(),
+            per_chat_in_flight: in_flight
+                .into_iter()
+                .take(STATUS_IN_FLIGHT_MAX_CHATS)
+                .map(ChatInFlightStatus::from)
+                .collect(),
+        },
+        pending_requests: PendingRequestStatus {
+            q: state.pending_q_requests.lock().len(),
+            image: state.pending_image_requests.lock().len(),
+            codex_model: state.pending_codex_model_requests.lock().len(),
+            codex_reasoning: state.pending_codex_reasoning_requests.lock().len(),
+        },
+        media_groups_cached: state.media_group_count(),
+        ignored_updates: state
+            .ignored_updates
+            .snapshot()
+            .into_iter()
+            .map(|(kind, count)| (kind.label(), count))
+            .collect(),
+        providers: ProviderStatus {
+            gemini_configured: !CONFIG.gemini_api_key.trim().is_empty(),
+            tldr_infographic_enabled: CONFIG.enable_tldr_infographic,
+            openrouter_ready: CONFIG.is_third_party_provider_ready(ThirdPartyProvider::OpenRouter),
+            nvidia_ready: CONFIG.is_third_party_provider_ready(ThirdPartyProvider::Nvidia),
+            ollama_ready: CONFIG.is_third_party_provider_ready(ThirdPartyProvider::Ollama),
+            openai_ready: CONFIG.is_third_party_provider_ready(ThirdPartyProvider::OpenAI),
+            img2_ready: crate::llm::img2_image::img2_available(),
+            openai_codex_ready: is_runtime_provider_ready(ThirdPartyProvider::OpenAICodex),
+        },
+        web_search: WebSearchStatus {
+            enabled: is_search_enabled(),
+            providers_order: CONFIG.web_search_providers.clone(),
+            brave_ready: CONFIG.enable_brave_search
+                && !CONFIG.brave_search_api_key.trim().is_empty(),
+            exa_ready: CONFIG.enable_exa_search && !CONFIG.exa_api_key.trim().is_empty(),
+            jina_ready: CONFIG.enable_jina_mcp,
+        },
+        third_party_models_count: runtime_model_count(),
+        whitelist_present: Path::new(&CONFIG.whitelist_file_path).exists(),
+        logs_dir_present: Path::new("logs").exists(),
+    }
+}
+
+/// Compact JSON, so monitoring gets one line and the reply stays short.
+pub fn status_snapshot_json(snapshot: &StatusSnapshot) -> String {
+    serde_json::to_string(snapshot).unwrap_or_else(|err| {
+        serde_json::json!({ "error": format!("failed to serialize status: {err}") }).to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_snapshot() -> StatusSnapshot {
+        StatusSnapshot {
+            time_utc: "2026-01-01T00:00:00+00:00".to_string(),
+            db: DbStatus {
+                ok: true,
+                error: None,
+                queue_pending: 1,
+                queue_available: 9,
+                queue_max: 10,
+                search_ready: true,
+                max_connections: 5,
+                pool: PoolStats {
+                    size: 2,
+                    idle: 1,
+                    in_use: 1,
+                    max: 5,
+                },
+                pragmas: Some(PragmaState {
+                    journal_mode: "wal".to_string(),
+                    synchronous: "normal",
+                    busy_timeout_ms: 5000,
+                }),
+            },
+            heavy_commands: HeavyCommandStatus {
+                active: 1,
+                waiting: 0,
+                max: 5,
+                per_chat_max: 2,
+                chats_in_flight: 1,
+                per_chat_in_flight: vec![ChatInFlightStatus {
+                    chat_id: -100,
+                    active: 1,
+                    waiting: 0,
+                }],
+            },
+            pending_requests: PendingRequestStatus {
+                q: 0,
+                image: 0,
+                codex_model: 0,
+                codex_reasoning: 0,
+            },
+            media_groups_cached: 0,
+            ignored_updates: BTreeMap::from([("poll", 3)]),
+            providers: ProviderStatus {
+                gemini_configured: true,
+                tldr_infographic_enabled: false,
+                openrouter_ready: false,
+                nvidia_ready: false,
+                ollama_ready: false,
+                openai_ready: false,
+                img2_ready: false,
+                openai_codex_ready: false,
+            },
+            web_search: WebSearchStatus {
+                enabled: true,
+                providers_order: vec!["brave".to_string(), "exa".to_string()],
+                brave_ready: true,
+                exa_ready: false,
+                jina_ready: false,
+            },
+            third_party_models_count: 0,
+            whitelist_present: true,
+            logs_dir_present: false,
+        }
+    }
+
+    #[test]
+    fn status_json_contains_expected_keys() {
+        let json: serde_json::Value =
+            serde_json::from_str(&status_snapshot_json(&sample_snapshot()))
+                .expect("status JSON should parse");
+
+        for key in [
+            "time_utc",
+            "db",
+            "heavy_commands",
+            "pending_requests",
+            "providers",
+            "web_search",
+            "whitelist_present",
+        ] {
+            assert!(json.get(key).is_some(), "missing key {key}");
+        }
+        assert_eq!(json["db"]["queue_pending"], 1);
+        assert_eq!(json["db"]["pool"]["in_use"], 1);
+        assert_eq!(json["db"]["pragmas"]["journal_mode"], "wal");
+        assert_eq!(json["web_search"]["providers_order"][0], "brave");
+        assert_eq!(json["ignored_updates"]["poll"], 3);
+        assert_eq!(
+            json["heavy_commands"]["per_chat_in_flight"][0]["chat_id"],
+            -100
+        );
+        assert_eq!(json["heavy_commands"]["chats_in_flight"], 1);
+        assert!(!status_snapshot_json(&sample_snapshot()).contains('\n'));
+
+        let serialized = json.to_string().to_lowercase();
+        for secret_marker in ["api_key", "token\"", "password", "email"] {
+            assert!(
+                !serialized.contains(secret_marker),
+                "status JSON should not expose {secret_marker}"
+            );
+        }
+    }
+}
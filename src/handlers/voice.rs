@@ -0,0 +1,319 @@
+//! Voice-note transcription.
+//!
+//! With `ENABLE_VOICE_TRANSCRIPTION=true`, caption-less voice notes and audio
+//! files are sent to Gemini for a verbatim transcript, which is then logged as
+//! the message text so `/tldr`, `/search`, and the chat-context modes see what
+//! was said. `/q` replies to a voice note already forward the audio itself.
+//!
+//! Nobody asks for a transcript, so it only runs in whitelisted chats, skips
+//! audio longer than `VOICE_TRANSCRIPTION_MAX_SECONDS`, is capped per chat by
+//! `VOICE_TRANSCRIPTIONS_PER_CHAT_HOUR`, and is left out of the sender's
+//! daily token quota.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use teloxide::prelude::*;
+use tracing::{info, warn};
+
+use crate::config::CONFIG;
+use crate::db::database::build_message_insert;
+use crate::db::models::LlmInvocationInsert;
+use crate::handlers::access::is_chat_whitelisted;
+use crate::handlers::media::{exceeds_media_download_limit, get_file_url};
+use crate::handlers::responses::{message_sender_display_name, should_log_message};
+use crate::llm::audit::{LlmAuditContext, LLM_TRIGGER_KIND_TRANSCRIPTION};
+use crate::llm::gemini::call_gemini;
+use crate::llm::media::{download_media, MediaFile, MediaKind};
+use crate::state::AppState;
+
+const TRANSCRIPTION_PROMPT: &str = "Transcribe the attached audio verbatim in its original language. Output only the transcript text, without timestamps, speaker labels, or commentary. If there is no intelligible speech, output nothing.";
+const TRANSCRIPT_PREFIX: &str = "[voice]";
+const TRANSCRIPTION_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Start times of recent transcriptions per chat.
+static CHAT_TRANSCRIPTIONS: Lazy<Mutex<HashMap<i64, Vec<Instant>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Records a transcription for `chat_id` unless the chat already had `limit`
+/// within the last hour. `0` means no limit.
+fn take_transcription_slot(
+    transcriptions: &mut HashMap<i64, Vec<Instant>>,
+    chat_id: i64,
+    now: Instant,
+    limit: usize,
+) -> bool {
+    if limit == 0 {
+        return true;
+    }
+    transcriptions.retain(|_, started| {
+        started.retain(|at| now.duration_since(*at) < TRANSCRIPTION_WINDOW);
+        !started.is_empty()
+    });
+    let started = transcriptions.entry(chat_id).or_default();
+    if started.len() >= limit {
+        return false;
+    }
+    started.push(now);
+    true
+}
+
+fn exceeds_transcription_duration(seconds: u32, max_seconds: u32) -> bool {
+    max_seconds > 0 && seconds > max_seconds
+}
+
+/// Returns the MIME type to upload for a transcribable attachment, or `None`
+/// when the attachment is not audio. Voice notes are always OGG/Opus; audio
+/// files fall back to their extension and finally to MP3.
+fn transcription_audio_mime(
+    is_voice: bool,
+    declared_mime: Option<&str>,
+    file_name: Option<&str>,
+) -> Option<String> {
+    if is_voice {
+        return Some("audio/ogg".to_string());
+    }
+    if let Some(mime) = declared_mime.map(str::trim).filter(|mime| !mime.is_empty()) {
+        return mime
+            .to_ascii_lowercase()
+            .starts_with("audio/")
+            .then(|| mime.to_ascii_lowercase());
+    }
+    let extension_mime = file_name.and_then(|name| {
+        let lower = name.to_ascii_lowercase();
+        if lower.ends_with(".ogg") || lower.ends_with(".oga") || lower.ends_with(".opus") {
+            Some("audio/ogg")
+        } else if lower.ends_with(".wav") {
+            Some("audio/wav")
+        } else if lower.ends_with(".m4a") {
+            Some("audio/mp4")
+        } else if lower.ends_with(".mp3") {
+            Some("audio/mpeg")
+        } else {
+            None
+        }
+    });
+    Some(extension_mime.unwrap_or("audio/mpeg").to_string())
+}
+
+fn format_transcript_text(transcript: &str) -> Option<String> {
+    let transcript = transcript.trim();
+    if transcript.is_empty() {
+        return None;
+    }
+    Some(format!("{TRANSCRIPT_PREFIX} {transcript}"))
+}
+
+pub fn is_transcribable_message(message: &Message) -> bool {
+    message.voice().is_some() || message.audio().is_some()
+}
+
+pub async fn transcribe_and_log_message(bot: &Bot, state: &AppState, message: &Message) {
+    if let Err(err) = transcribe_and_log_message_inner(bot, state, message).await {
+        warn!(
+            "Voice transcription failed: chat_id={}, message_id={}, error={err:#}",
+            message.chat.id.0, message.id.0
+        );
+    }
+}
+
+async fn transcribe_and_log_message_inner(
+    bot: &Bot,
+    state: &AppState,
+    message: &Message,
+) -> Result<()> {
+    if !should_log_message(message, state.bot_user_id, CONFIG.ignore_other_bots)
+        || !is_chat_whitelisted(message.chat.id.0)
+    {
+        return Ok(());
+    }
+    let (file, duration, mime_type) = if let Some(voice) = message.voice() {
+        (
+            &voice.file,
+            voice.duration,
+            transcription_audio_mime(true, None, None),
+        )
+    } else if let Some(audio) = message.audio() {
+        (
+            &audio.file,
+            audio.duration,
+            transcription_audio_mime(
+                false,
+                audio.mime_type.as_ref().map(|mime| mime.essence_str()),
+                audio.file_name.as_deref(),
+            ),
+        )
+    } else {
+        return Ok(());
+    };
+    let Some(mime_type) = mime_type else {
+        return Ok(());
+    };
+    if exceeds_transcription_duration(duration.seconds(), CONFIG.voice_transcription_max_seconds) {
+        info!(
+            "Skipping transcription of long audio: chat_id={}, message_id={}, seconds={}",
+            message.chat.id.0,
+            message.id.0,
+            duration.seconds()
+        );
+        return Ok(());
+    }
+    if exceeds_media_download_limit(Some(u64::from(file.size)), CONFIG.max_media_download_bytes) {
+        info!(
+            "Skipping transcription of oversized audio: chat_id={}, message_id={}, size={}",
+            message.chat.id.0, message.id.0, file.size
+        );
+        return Ok(());
+    }
+    if !take_transcription_slot(
+        &mut CHAT_TRANSCRIPTIONS.lock(),
+        message.chat.id.0,
+        Instant::now(),
+        CONFIG.voice_transcriptions_per_chat_hour,
+    ) {
+        info!(
+            "Skipping transcription over the hourly chat limit: chat_id={}, message_id={}",
+            message.chat.id.0, message.id.0
+        );
+        return Ok(());
+    }
+    let file_id = &file.id;
+
+    let url = get_file_url(bot, file_id).await?;
+    let data = download_media(&url)
+        .await
+        .ok_or_else(|| anyhow!("failed to download audio attachment"))?;
+
+    let user_id = message
+        .from
+        .as_ref()
+        .and_then(|user| i64::try_from(user.id.0).ok());
+    let username = message_sender_display_name(message);
+    let audit_context = match state
+        .db
+        .insert_llm_invocation(LlmInvocationInsert {
+            trigger_kind: LLM_TRIGGER_KIND_TRANSCRIPTION.to_string(),
+            trigger_name: "voice".to_string(),
+            chat_id: message.chat.id.0,
+            user_id,
+            username: Some(username.clone()),
+            message_id: message.id.0 as i64,
+            reply_to_message_id: message.reply_to_message().map(|msg| msg.id.0 as i64),
+            message_text: None,
+            created_at: chrono::Utc::now(),
+        })
+        .await
+    {
+        Ok(invocation_id) => Some(LlmAuditContext::new(state.db.clone(), invocation_id)),
+        Err(err) => {
+            warn!("Failed to create transcription invocation record: {err}");
+            None
+        }
+    };
+
+    let media = MediaFile::new(data, mime_type, MediaKind::Audio, None);
+    let result = call_gemini(
+        TRANSCRIPTION_PROMPT,
+        "Transcribe this audio.",
+        false,
+        false,
+        None,
+        None,
+        false,
+        Some(vec![media]),
+        None,
+        Some("TRANSCRIPTION_PROMPT"),
+        audit_context.as_ref(),
+        None,
+        None,
+    )
+    .await?;
+
+    let Some(text) = format_transcript_text(&result.text) else {
+        info!(
+            "Voice transcription produced no speech: chat_id={}, message_id={}",
+            message.chat.id.0, message.id.0
+        );
+        return Ok(());
+    };
+
+    let insert = build_message_insert(
+        user_id,
+        Some(username),
+        Some(text),
+        None,
+        message.date,
+        message.reply_to_message().map(|msg| msg.id.0 as i64),
+        Some(message.chat.id.0),
+        Some(message.id.0 as i64),
+        None,
+        false,
+        None,
+        false,
+        false,
+    );
+    state.db.queue_message_insert(insert).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcription_audio_mime_routes_voice_and_audio_kinds() {
+        assert_eq!(
+            transcription_audio_mime(true, Some("video/mp4"), None).as_deref(),
+            Some("audio/ogg")
+        );
+        assert_eq!(
+            transcription_audio_mime(false, Some("Audio/MPEG"), Some("song.ogg")).as_deref(),
+            Some("audio/mpeg")
+        );
+        assert_eq!(
+            transcription_audio_mime(false, None, Some("memo.M4A")).as_deref(),
+            Some("audio/mp4")
+        );
+        assert_eq!(
+            transcription_audio_mime(false, None, None).as_deref(),
+            Some("audio/mpeg")
+        );
+        assert_eq!(
+            transcription_audio_mime(false, Some("application/zip"), None),
+            None
+        );
+    }
+
+    #[test]
+    fn transcriptions_are_limited_per_chat_per_hour() {
+        let mut transcriptions = HashMap::new();
+        let start = Instant::now();
+        assert!(take_transcription_slot(&mut transcriptions, 1, start, 2));
+        assert!(take_transcription_slot(&mut transcriptions, 1, start, 2));
+        assert!(!take_transcription_slot(&mut transcriptions, 1, start, 2));
+        assert!(take_transcription_slot(&mut transcriptions, 2, start, 2));
+
+        let later = start + TRANSCRIPTION_WINDOW;
+        assert!(take_transcription_slot(&mut transcriptions, 1, later, 2));
+        assert!(take_transcription_slot(&mut transcriptions, 1, later, 0));
+    }
+
+    #[test]
+    fn long_audio_is_not_transcribed() {
+        assert!(!exceeds_transcription_duration(300, 300));
+        assert!(exceeds_transcription_duration(301, 300));
+        assert!(!exceeds_transcription_duration(7_200, 0));
+    }
+
+    #[test]
+    fn format_transcript_text_prefixes_and_skips_empty_output() {
+        assert_eq!(
+            format_transcript_text("  hello there \n").as_deref(),
+            Some("[voice] hello there")
+        );
+        assert_eq!(format_transcript_text(" \n "), None);
+    }
+}
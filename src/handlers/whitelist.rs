@@ -0,0 +1,287 @@
+//! In-chat management of the access whitelist file.
+//!
+//! Edits keep comments and unrelated lines intact, are written to a sibling
+//! temp file and renamed into place, and then the in-memory cache is reloaded.
+//! Only users already on the whitelist may edit it, so the file and its first
+//! id have to be created by hand.
+
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use teloxide::prelude::*;
+use teloxide::types::ReplyParameters;
+use tracing::{info, warn};
+
+use crate::config::CONFIG;
+use crate::handlers::access::{
+    check_whitelist_owner_access, parse_whitelist_content, reload_whitelist,
+};
+
+const WHITELIST_LIST_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WhitelistCommand {
+    List,
+    Add(i64),
+    Remove(i64),
+}
+
+fn parse_whitelist_command(arg: Option<&str>) -> Option<WhitelistCommand> {
+    let Some(arg) = arg.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Some(WhitelistCommand::List);
+    };
+    let mut parts = arg.split_whitespace();
+    let action = parts.next()?.to_lowercase();
+    let id = parts.next();
+    if parts.next().is_some() {
+        return None;
+    }
+    let parse_id = |value: Option<&str>| {
+        value
+            .and_then(|value| value.parse::<i64>().ok())
+            .filter(|id| *id != 0)
+    };
+    match action.as_str() {
+        "list" if id.is_none() => Some(WhitelistCommand::List),
+        "add" => parse_id(id).map(WhitelistCommand::Add),
+        "remove" | "rm" | "del" => parse_id(id).map(WhitelistCommand::Remove),
+        _ => None,
+    }
+}
+
+/// Returns the new file content, or `None` when `id` is already listed.
+fn whitelist_content_with(content: &str, id: i64) -> Option<String> {
+    if parse_whitelist_content(content).contains(&id) {
+        return None;
+    }
+    let mut updated = content.to_string();
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated.push_str(&format!("{id}\n"));
+    Some(updated)
+}
+
+/// Returns the new file content, or `None` when `id` is not listed.
+fn whitelist_content_without(content: &str, id: i64) -> Option<String> {
+    let mut removed = false;
+    let mut updated = String::with_capacity(content.len());
+    for line in content.lines() {
+        if line.trim().parse::<i64>().ok() == Some(id) {
+            removed = true;
+            continue;
+        }
+        updated.push_str(line);
+        updated.push('\n');
+    }
+    removed.then_some(updated)
+}
+
+fn read_whitelist_file(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::new()),
+        result => result.with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+fn write_whitelist_atomically(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let mut temp_name = path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_else(|| "whitelist".into());
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    std::fs::write(&temp_path, content)
+        .with_context(|| format!("failed to write {}", temp_path.display()))?;
+    std::fs::rename(&temp_path, path)
+        .with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+fn describe_whitelist(path: &str, content: &str) -> String {
+    let mut ids = parse_whitelist_content(content)
+        .into_iter()
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    let mut reply = format!("Whitelist: {} entries ({path})", ids.len());
+    for id in ids.iter().take(WHITELIST_LIST_LIMIT) {
+        let kind = if *id < 0 { "chat" } else { "user" };
+        reply.push_str(&format!("\n- {id} ({kind})"));
+    }
+    if ids.len() > WHITELIST_LIST_LIMIT {
+        reply.push_str(&format!(
+            "\n… and {} more",
+            ids.len() - WHITELIST_LIST_LIMIT
+        ));
+    }
+    reply
+}
+
+/// Applies `command` to the whitelist file at `path` and returns the reply.
+fn run_whitelist_command(
+    path: &str,
+    command: WhitelistCommand,
+    requester_id: Option<i64>,
+) -> Result<String> {
+    let content = read_whitelist_file(Path::new(path))?;
+    let reply = match command {
+        WhitelistCommand::List => describe_whitelist(path, &content),
+        WhitelistCommand::Add(id) => match whitelist_content_with(&content, id) {
+            None => format!("{id} is already whitelisted."),
+            Some(updated) => {
+                write_whitelist_atomically(Path::new(path), &updated)?;
+                reload_whitelist();
+                info!("Whitelist entry {id} added by user {requester_id:?}");
+                format!(
+                    "Added {id}. Whitelist now has {} entries ({path}).",
+                    parse_whitelist_content(&updated).len()
+                )
+            }
+        },
+        WhitelistCommand::Remove(id) if Some(id) == requester_id => {
+            "Refusing to remove your own user id; ask another administrator.".to_string()
+        }
+        WhitelistCommand::Remove(id) => match whitelist_content_without(&content, id) {
+            None => format!("{id} is not in the whitelist."),
+            Some(updated) => {
+                write_whitelist_atomically(Path::new(path), &updated)?;
+                reload_whitelist();
+                info!("Whitelist entry {id} removed by user {requester_id:?}");
+                format!(
+                    "Removed {id}. Whitelist now has {} entries ({path}).",
+                    parse_whitelist_content(&updated).len()
+                )
+            }
+        },
+    };
+    Ok(reply)
+}
+
+pub async fn whitelist_handler(bot: Bot, message: Message, arg: Option<String>) -> Result<()> {
+    if !check_whitelist_owner_access(&bot, &message, "whitelist").await {
+        return Ok(());
+    }
+
+    let Some(command) = parse_whitelist_command(arg.as_deref()) else {
+        bot.send_message(
+            message.chat.id,
+            "Usage: /whitelist list, /whitelist add <id>, or /whitelist remove <id>",
+        )
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+        return Ok(());
+    };
+
+    let requester_id = message
+        .from
+        .as_ref()
+        .and_then(|user| i64::try_from(user.id.0).ok());
+    let reply = match run_whitelist_command(&CONFIG.whitelist_file_path, command, requester_id) {
+        Ok(reply) => reply,
+        Err(err) => {
+            warn!("/whitelist failed: {err:#}");
+            format!("Whitelist file error: {err:#}")
+        }
+    };
+
+    bot.send_message(message.chat.id, reply)
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_whitelist_command_validates_ids() {
+        assert_eq!(parse_whitelist_command(None), Some(WhitelistCommand::List));
+        assert_eq!(
+            parse_whitelist_command(Some("list")),
+            Some(WhitelistCommand::List)
+        );
+        assert_eq!(
+            parse_whitelist_command(Some("ADD -1001234")),
+            Some(WhitelistCommand::Add(-1001234))
+        );
+        assert_eq!(
+            parse_whitelist_command(Some("remove 42")),
+            Some(WhitelistCommand::Remove(42))
+        );
+        assert_eq!(parse_whitelist_command(Some("add")), None);
+        assert_eq!(parse_whitelist_command(Some("add 0")), None);
+        assert_eq!(parse_whitelist_command(Some("add abc")), None);
+        assert_eq!(parse_whitelist_command(Some("add 1 2")), None);
+    }
+
+    #[test]
+    fn whitelist_edits_preserve_comments_and_skip_noops() {
+        let content = "# admins\n42\n-1001\n";
+        assert_eq!(whitelist_content_with(content, 42), None);
+        assert_eq!(
+            whitelist_content_with("# admins\n42", 7).as_deref(),
+            Some("# admins\n42\n7\n")
+        );
+        assert_eq!(
+            whitelist_content_without(content, -1001).as_deref(),
+            Some("# admins\n42\n")
+        );
+        assert_eq!(whitelist_content_without(content, 7), None);
+    }
+
+    #[test]
+    fn write_whitelist_atomically_replaces_file() {
+        let dir = std::env::temp_dir().join(format!("whitelist-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("temp dir should be created");
+        let path = dir.join("whitelist.txt");
+        std::fs::write(&path, "1\n").expect("seed file should be written");
+
+        write_whitelist_atomically(&path, "1\n2\n").expect("atomic write should succeed");
+
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("file should be readable"),
+            "1\n2\n"
+        );
+        assert!(!dir.join("whitelist.txt.tmp").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn missing_whitelist_file_lists_empty_and_is_created_on_add() {
+        let dir =
+            std::env::temp_dir().join(format!("whitelist-missing-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("config").join("whitelist.txt");
+        let path_str = path.to_str().expect("temp path should be utf-8");
+
+        assert!(read_whitelist_file(&path)
+            .expect("missing file should read as empty")
+            .is_empty());
+        assert_eq!(
+            run_whitelist_command(path_str, WhitelistCommand::Remove(7), None)
+                .expect("remove should not fail"),
+            "7 is not in the whitelist."
+        );
+        assert!(!path.exists());
+
+        run_whitelist_command(path_str, WhitelistCommand::Add(-1001), None)
+            .expect("add should create the file");
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("file should exist"),
+            "-1001\n"
+        );
+
+        std::fs::create_dir_all(dir.join("not-a-file")).expect("dir should be created");
+        assert!(read_whitelist_file(&dir.join("not-a-file")).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -8,6 +8,9 @@ use crate::db::models::LlmRequestInsert;
 
 pub const LLM_TRIGGER_KIND_AUTO_Q: &str = "auto_q";
 pub const LLM_TRIGGER_KIND_COMMAND: &str = "command";
+pub const LLM_TRIGGER_KIND_INLINE: &str = "inline";
+pub const LLM_TRIGGER_KIND_SCHEDULED: &str = "scheduled";
+pub const LLM_TRIGGER_KIND_TRANSCRIPTION: &str = "transcription";
 
 #[derive(Clone)]
 pub struct LlmAuditContext {
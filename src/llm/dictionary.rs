@@ -0,0 +1,234 @@
+//! Read-only dictionary lookups for the `dictionary_define` agent tool.
+//!
+//! Queries the Free Dictionary API (`DICTIONARY_API_ENDPOINT`) and trims the
+//! response to a few definitions and examples per part of speech so the tool
+//! result stays small.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::CONFIG;
+use crate::llm::provider_stats::track_provider_call;
+use crate::utils::http::get_http_client;
+
+const DEFAULT_TIMEOUT_SECONDS: u64 = 10;
+const MAX_WORD_CHARS: usize = 64;
+const MAX_ENTRIES: usize = 2;
+const MAX_MEANINGS_PER_ENTRY: usize = 4;
+const MAX_DEFINITIONS_PER_MEANING: usize = 3;
+
+#[derive(Debug, Deserialize)]
+struct ApiEntry {
+    word: Option<String>,
+    phonetic: Option<String>,
+    #[serde(default)]
+    meanings: Vec<ApiMeaning>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiMeaning {
+    part_of_speech: Option<String>,
+    #[serde(default)]
+    definitions: Vec<ApiDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiDefinition {
+    definition: Option<String>,
+    example: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DictionaryEntry {
+    pub word: String,
+    pub phonetic: Option<String>,
+    pub meanings: Vec<DictionaryMeaning>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DictionaryMeaning {
+    pub part_of_speech: String,
+    pub definitions: Vec<DictionaryDefinition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DictionaryDefinition {
+    pub definition: String,
+    pub example: Option<String>,
+}
+
+pub fn is_dictionary_enabled() -> bool {
+    CONFIG.enable_dictionary_tool && !CONFIG.dictionary_api_endpoint.trim().is_empty()
+}
+
+/// Trims `word` and rejects anything that is not a plausible single headword
+/// or short phrase, so model output never becomes an arbitrary URL path.
+pub fn normalize_word(word: &str) -> Option<String> {
+    let word = word.trim();
+    if word.is_empty() || word.chars().count() > MAX_WORD_CHARS {
+        return None;
+    }
+    word.chars()
+        .all(|ch| ch.is_alphabetic() || matches!(ch, ' ' | '-' | '\''))
+        .then(|| word.to_lowercase())
+}
+
+fn lookup_url(endpoint: &str, word: &str) -> Result<Url> {
+    let mut url = Url::parse(endpoint.trim().trim_end_matches('/'))
+        .map_err(|err| anyhow!("DICTIONARY_API_ENDPOINT is invalid: {err}"))?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("DICTIONARY_API_ENDPOINT cannot take a path"))?
+        .push(word);
+    Ok(url)
+}
+
+/// Parses a Free Dictionary API body, keeping only non-empty definitions.
+pub fn parse_dictionary_response(body: &str) -> Result<Vec<DictionaryEntry>> {
+    let entries: Vec<ApiEntry> = serde_json::from_str(body)?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| {
+            let meanings = entry
+                .meanings
+                .into_iter()
+                .filter_map(|meaning| {
+                    let definitions = meaning
+                        .definitions
+                        .into_iter()
+                        .filter_map(|definition| {
+                            let text = definition.definition?.trim().to_string();
+                            (!text.is_empty()).then(|| DictionaryDefinition {
+                                definition: text,
+                                example: definition
+                                    .example
+                                    .map(|example| example.trim().to_string())
+                                    .filter(|example| !example.is_empty()),
+                            })
+                        })
+                        .take(MAX_DEFINITIONS_PER_MEANING)
+                        .collect::<Vec<_>>();
+                    (!definitions.is_empty()).then(|| DictionaryMeaning {
+                        part_of_speech: meaning.part_of_speech.unwrap_or_default(),
+                        definitions,
+                    })
+                })
+                .take(MAX_MEANINGS_PER_ENTRY)
+                .collect::<Vec<_>>();
+            (!meanings.is_empty()).then(|| DictionaryEntry {
+                word: entry.word.unwrap_or_default(),
+                phonetic: entry.phonetic.filter(|value| !value.trim().is_empty()),
+                meanings,
+            })
+        })
+        .take(MAX_ENTRIES)
+        .collect())
+}
+
+/// Looks up `word`. An unknown word yields an empty list, not an error.
+pub async fn define_word(word: &str) -> Result<Vec<DictionaryEntry>> {
+    if !is_dictionary_enabled() {
+        return Err(anyhow!("The dictionary tool is disabled."));
+    }
+    let word = normalize_word(word).ok_or_else(|| {
+        anyhow!("word must be 1-{MAX_WORD_CHARS} letters, spaces, hyphens or apostrophes")
+    })?;
+    let url = lookup_url(&CONFIG.dictionary_api_endpoint, &word)?;
+    info!("Looking up dictionary definition: {}", url);
+
+    track_provider_call("dictionary", async {
+        let response = get_http_client()
+            .get(url)
+            .timeout(Duration::from_secs(DEFAULT_TIMEOUT_SECONDS))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Dictionary lookup failed with status {}",
+                response.status()
+            ));
+        }
+        parse_dictionary_response(&response.text().await?)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_word_rejects_paths_and_queries() {
+        assert_eq!(
+            normalize_word("  Serendipity "),
+            Some("serendipity".to_string())
+        );
+        assert_eq!(
+            normalize_word("hand-me-down"),
+            Some("hand-me-down".to_string())
+        );
+        assert_eq!(normalize_word(""), None);
+        assert_eq!(normalize_word("../admin"), None);
+        assert_eq!(normalize_word("word?x=1"), None);
+        assert_eq!(normalize_word(&"a".repeat(MAX_WORD_CHARS + 1)), None);
+    }
+
+    #[test]
+    fn lookup_url_escapes_the_word_as_one_segment() {
+        let url = lookup_url(
+            "https://api.dictionaryapi.dev/api/v2/entries/en/",
+            "ice cream",
+        )
+        .expect("url should build");
+        assert_eq!(
+            url.as_str(),
+            "https://api.dictionaryapi.dev/api/v2/entries/en/ice%20cream"
+        );
+    }
+
+    #[test]
+    fn parse_dictionary_response_keeps_definitions_and_examples() {
+        let body = r#"[
+            {
+                "word": "hello",
+                "phonetic": "həˈləʊ",
+                "phonetics": [{"text": "həˈləʊ", "audio": ""}],
+                "meanings": [
+                    {
+                        "partOfSpeech": "exclamation",
+                        "definitions": [
+                            {"definition": "used as a greeting", "example": "hello there, Katie!", "synonyms": []},
+                            {"definition": "  "},
+                            {"definition": "expressing surprise"},
+                            {"definition": "third"},
+                            {"definition": "fourth is dropped"}
+                        ]
+                    },
+                    {"partOfSpeech": "noun", "definitions": []}
+                ]
+            }
+        ]"#;
+        let entries = parse_dictionary_response(body).expect("body should parse");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].word, "hello");
+        assert_eq!(entries[0].phonetic.as_deref(), Some("həˈləʊ"));
+        assert_eq!(entries[0].meanings.len(), 1);
+        let meaning = &entries[0].meanings[0];
+        assert_eq!(meaning.part_of_speech, "exclamation");
+        assert_eq!(meaning.definitions.len(), MAX_DEFINITIONS_PER_MEANING);
+        assert_eq!(
+            meaning.definitions[0].example.as_deref(),
+            Some("hello there, Katie!")
+        );
+        assert_eq!(meaning.definitions[1].definition, "expressing surprise");
+
+        assert!(parse_dictionary_response("{\"title\":\"No Definitions Found\"}").is_err());
+    }
+}
@@ -1,8 +1,10 @@
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::Lazy;
 use reqwest::header::CONTENT_TYPE;
 use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
@@ -17,8 +19,34 @@ use crate::llm::audit::{
     log_llm_request_started, record_llm_request_success, LlmAuditContext, LlmUsageRecord,
 };
 use crate::llm::media::{detect_mime_type, download_media, kind_for_mime, MediaFile, MediaKind};
+use crate::llm::provider_stats::track_provider_call;
+use crate::llm::sampling::{temperature_or, top_p_or};
 use crate::llm::tool_runtime::ToolRuntime;
 use crate::utils::http::get_http_client;
+use crate::utils::retry::{retry_async, ClassifiedError, RetryPolicy};
+
+/// Upload slots shared by every request, so several commands uploading at
+/// once stay under `GEMINI_MAX_CONCURRENT_UPLOADS` in total.
+static GEMINI_UPLOAD_SLOTS: Lazy<Arc<Semaphore>> =
+    Lazy::new(|| Arc::new(Semaphore::new(CONFIG.gemini_max_concurrent_uploads)));
+
+/// Uploads in flight across all requests, and the configured maximum.
+pub fn gemini_upload_concurrency() -> (usize, usize) {
+    let max = CONFIG.gemini_max_concurrent_uploads;
+    (
+        max.saturating_sub(GEMINI_UPLOAD_SLOTS.available_permits()),
+        max,
+    )
+}
+
+/// Runs `upload` once `slots` has a free permit.
+async fn with_upload_slot<F: Future>(slots: &Semaphore, upload: F) -> F::Output {
+    let _permit = slots
+        .acquire()
+        .await
+        .expect("gemini upload semaphore should remain open");
+    upload.await
+}
 
 #[derive(Debug, thiserror::Error)]
 #[error("Image generation failed: {0}")]
@@ -28,12 +56,41 @@ pub struct ImageGenerationError(pub String);
 pub struct GeminiImageConfig {
     pub aspect_ratio: Option<String>,
     pub image_size: Option<String>,
+    /// Sent as `generationConfig.seed` so the same prompt can be reproduced.
+    pub seed: Option<i32>,
+}
+
+/// Non-success HTTP status from `generateContent`, kept typed so the Pro
+/// fallback can recognise quota errors.
+#[derive(Debug, thiserror::Error)]
+#[error("Gemini request failed with status {status}: {detail}")]
+struct GeminiStatusError {
+    status: StatusCode,
+    detail: String,
+}
+
+fn is_gemini_quota_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<GeminiStatusError>()
+        .is_some_and(|err| err.status == StatusCode::TOO_MANY_REQUESTS)
 }
 
 #[derive(Debug, Clone)]
 pub struct GeminiCallResult {
     pub text: String,
     pub model_used: String,
+    /// Pro model that ran out of quota before `model_used` answered.
+    pub downgraded_from: Option<String>,
+}
+
+impl GeminiCallResult {
+    /// Model name for response footers; notes a quota downgrade so it is
+    /// visible to the user.
+    pub fn model_display(&self) -> String {
+        match &self.downgraded_from {
+            Some(pro_model) => format!("{} (downgraded from {pro_model}: quota)", self.model_used),
+            None => self.model_used.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -160,9 +217,18 @@ fn gemini_should_retry_status(status: StatusCode) -> bool {
         || status.is_server_error()
 }
 
-fn gemini_retry_delay(attempt: usize) -> Duration {
-    let attempt = attempt.max(1) as u64;
-    Duration::from_millis(GEMINI_RETRY_BASE_DELAY_MS.saturating_mul(attempt))
+/// With `GEMINI_PRO_QUOTA_FALLBACK`, a Pro 429 goes straight to the default
+/// model instead of spending the retry budget on Pro.
+fn skips_retry_for_pro_quota(status: StatusCode, operation: &str, quota_fallback: bool) -> bool {
+    quota_fallback && operation == "call_gemini_pro" && status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn gemini_retry_policy() -> RetryPolicy {
+    RetryPolicy::linear(
+        GEMINI_MAX_RETRY_ATTEMPTS,
+        Duration::from_millis(GEMINI_RETRY_BASE_DELAY_MS),
+    )
+    .with_configured_jitter()
 }
 
 fn gemini_generate_content_timeout() -> Duration {
@@ -688,7 +754,11 @@ async fn upload_media_files(files: &[MediaFile]) -> Result<Vec<UploadedFileRef>>
                 );
                 return Ok((index, None));
             };
-            let info = upload_file_bytes(&display_name, &mime_type, file.bytes()).await?;
+            let info = with_upload_slot(
+                &GEMINI_UPLOAD_SLOTS,
+                upload_file_bytes(&display_name, &mime_type, file.bytes()),
+            )
+            .await?;
             let info = wait_for_file_active(info).await?;
             if let Some(uploaded_mime_type) = info
                 .mime_type
@@ -990,96 +1060,102 @@ async fn call_gemini_api_value_with_timeout(
         debug!(target: "llm.gemini", model = model, payload = %payload_summary);
     }
 
-    let mut attempt = 0usize;
-    loop {
-        attempt += 1;
-        let response = match client
-            .post(&url)
-            .header("x-goog-api-key", &CONFIG.gemini_api_key)
-            .timeout(timeout)
-            .json(&payload)
-            .send()
-            .await
-        {
-            Ok(response) => response,
-            Err(err) => {
-                let err_text = redact_gemini_api_key(&err.to_string());
-                let url = err.url().map(|url| redact_gemini_api_key(url.as_str()));
-                let should_retry =
-                    gemini_should_retry_error(&err) && attempt < GEMINI_MAX_RETRY_ATTEMPTS;
+    let payload = &payload;
+    let url = url.as_str();
+    let policy = gemini_retry_policy();
+    let request = retry_async(
+        &policy,
+        |attempt| async move {
+            let retries_left = attempt < policy.max_attempts;
+            let response = client
+                .post(url)
+                .header("x-goog-api-key", &CONFIG.gemini_api_key)
+                .timeout(timeout)
+                .json(payload)
+                .send()
+                .await
+                .map_err(|err| {
+                    let err_text = redact_gemini_api_key(&err.to_string());
+                    let url = err.url().map(|url| redact_gemini_api_key(url.as_str()));
+                    let should_retry = gemini_should_retry_error(&err);
+                    warn!(
+                        "Gemini request failed to send: {} (timeout={}, connect={}, status={:?}, url={:?}, retrying={})",
+                        err_text,
+                        err.is_timeout(),
+                        err.is_connect(),
+                        err.status(),
+                        url,
+                        should_retry && retries_left
+                    );
+                    ClassifiedError::new(
+                        anyhow!("Gemini request failed: {}", err_text),
+                        should_retry,
+                    )
+                })?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                let (message, body_summary) = summarize_error_body(&body);
+                let should_retry = gemini_should_retry_status(status)
+                    && !skips_retry_for_pro_quota(
+                        status,
+                        operation,
+                        CONFIG.gemini_pro_quota_fallback,
+                    );
                 warn!(
-                    "Gemini request failed to send: {} (timeout={}, connect={}, status={:?}, url={:?}, retrying={})",
-                    err_text,
-                    err.is_timeout(),
-                    err.is_connect(),
-                    err.status(),
-                    url,
-                    should_retry
+                    "Gemini API error: status={}, body={}, retrying={}",
+                    status,
+                    body_summary,
+                    should_retry && retries_left
                 );
-                if should_retry {
-                    tokio::time::sleep(gemini_retry_delay(attempt)).await;
-                    continue;
+                if tracing::enabled!(tracing::Level::DEBUG) {
+                    debug!(
+                        target: "llm.gemini",
+                        status = %status,
+                        body = %truncate_for_log(&body, 4000)
+                    );
                 }
-                return Err(anyhow!("Gemini request failed: {}", err_text));
-            }
-        };
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            let (message, body_summary) = summarize_error_body(&body);
-            let should_retry =
-                gemini_should_retry_status(status) && attempt < GEMINI_MAX_RETRY_ATTEMPTS;
-            warn!(
-                "Gemini API error: status={}, body={}, retrying={}",
-                status, body_summary, should_retry
-            );
-            if tracing::enabled!(tracing::Level::DEBUG) {
-                debug!(
-                    target: "llm.gemini",
-                    status = %status,
-                    body = %truncate_for_log(&body, 4000)
-                );
-            }
-            if should_retry {
-                tokio::time::sleep(gemini_retry_delay(attempt)).await;
-                continue;
+                let detail = message.unwrap_or(body_summary);
+                return Err(ClassifiedError::new(
+                    anyhow::Error::new(GeminiStatusError { status, detail }),
+                    should_retry,
+                ));
             }
-            let detail = message.unwrap_or(body_summary);
-            return Err(anyhow!(
-                "Gemini request failed with status {}: {}",
-                status,
-                detail
-            ));
-        }
-
-        let value = decode_json_response::<Value>(response, "Gemini generateContent").await?;
-        if tracing::enabled!(tracing::Level::DEBUG) {
-            let parsed = serde_json::from_value::<GeminiResponse>(value.clone()).ok();
-            let response_summary = parsed
-                .as_ref()
-                .map(summarize_gemini_response)
-                .unwrap_or_else(|| {
-                    json!({
-                        "rawResponsePreview": truncate_for_log(&value.to_string(), 400)
-                    })
-                });
-            debug!(target: "llm.gemini", model = model, response = %response_summary);
-        }
+            Ok(response)
+        },
+        |err, _| err.decision(),
+    );
+    let response = track_provider_call("gemini", request)
+        .await
+        .map_err(|err| err.error)?;
 
-        let usage = extract_gemini_usage(&value);
-        record_llm_request_success(
-            audit_context,
-            "gemini",
-            model,
-            operation,
-            started_at,
-            chrono::Utc::now(),
-            usage,
-        )
-        .await;
-        return Ok(value);
+    let value = decode_json_response::<Value>(response, "Gemini generateContent").await?;
+    if tracing::enabled!(tracing::Level::DEBUG) {
+        let parsed = serde_json::from_value::<GeminiResponse>(value.clone()).ok();
+        let response_summary = parsed
+            .as_ref()
+            .map(summarize_gemini_response)
+            .unwrap_or_else(|| {
+                json!({
+                    "rawResponsePreview": truncate_for_log(&value.to_string(), 400)
+                })
+            });
+        debug!(target: "llm.gemini", model = model, response = %response_summary);
     }
+
+    let usage = extract_gemini_usage(&value);
+    record_llm_request_success(
+        audit_context,
+        "gemini",
+        model,
+        operation,
+        started_at,
+        chrono::Utc::now(),
+        usage,
+    )
+    .await;
+    Ok(value)
 }
 
 fn text_part_looks_like_music_metadata(text: &str) -> bool {
@@ -1243,13 +1319,94 @@ fn extract_function_calls(content: &Value) -> Vec<Value> {
 
 fn base_generation_config() -> Value {
     json!({
-        "temperature": CONFIG.gemini_temperature,
+        "temperature": temperature_or(CONFIG.gemini_temperature),
         "topK": CONFIG.gemini_top_k,
-        "topP": CONFIG.gemini_top_p,
+        "topP": top_p_or(CONFIG.gemini_top_p),
         "maxOutputTokens": CONFIG.gemini_max_output_tokens,
     })
 }
 
+/// Token budget for a `GEMINI_THINKING_LEVEL` value. Named levels map to
+/// fixed budgets, `dynamic` lets the model decide (`-1`), and a plain number
+/// is used as-is. Unknown values yield `None`.
+fn thinking_budget_for_level(level: &str) -> Option<i64> {
+    let level = level.trim().to_lowercase();
+    match level.as_str() {
+        "off" | "none" | "minimal" => Some(0),
+        "low" => Some(1_024),
+        "medium" => Some(8_192),
+        "high" => Some(24_576),
+        "dynamic" | "auto" => Some(-1),
+        _ => level.parse::<i64>().ok().filter(|budget| *budget >= -1),
+    }
+}
+
+/// Gemini 2.5+ text models accept `thinkingConfig`; image, speech, and older
+/// models reject it.
+fn model_supports_thinking(model: &str) -> bool {
+    let model = model.trim().to_lowercase();
+    if ["image", "tts", "audio", "lyria", "embedding"]
+        .iter()
+        .any(|marker| model.contains(marker))
+    {
+        return false;
+    }
+    model.contains("gemini-2.5") || model.contains("gemini-3") || model.ends_with("-latest")
+}
+
+fn thinking_config_for_model(model: &str, thinking_level: Option<&str>) -> Option<Value> {
+    let budget = thinking_budget_for_level(thinking_level?)?;
+    if !model_supports_thinking(model) {
+        return None;
+    }
+    // Pro models cannot turn thinking off; leave them on their default.
+    if budget == 0 && model.to_lowercase().contains("pro") {
+        return None;
+    }
+    Some(json!({ "thinkingBudget": budget }))
+}
+
+/// Returns `payload` with a `thinkingConfig` suited to `model`, so one payload
+/// can be retried across the primary, fallback, and lite models.
+fn with_thinking_config(mut payload: Value, model: &str, thinking_level: Option<&str>) -> Value {
+    let Some(config) = payload
+        .get_mut("generationConfig")
+        .and_then(Value::as_object_mut)
+    else {
+        return payload;
+    };
+    match thinking_config_for_model(model, thinking_level) {
+        Some(thinking) => {
+            config.insert("thinkingConfig".to_string(), thinking);
+        }
+        None => {
+            config.remove("thinkingConfig");
+        }
+    }
+    payload
+}
+
+/// Built-in tools for a [`call_gemini`] request. Code execution is left out
+/// for video/audio input; `url_context` lets the model fetch links in the
+/// prompt and can be combined with `google_search`.
+fn build_call_tools(
+    has_time_based_media: bool,
+    use_search_grounding: bool,
+    use_url_context: bool,
+) -> Vec<Value> {
+    let mut tools = Vec::new();
+    if !has_time_based_media {
+        tools.push(json!({ "code_execution": {} }));
+    }
+    if use_search_grounding {
+        tools.push(json!({ "google_search": {} }));
+    }
+    if use_url_context {
+        tools.push(json!({ "url_context": {} }));
+    }
+    tools
+}
+
 fn with_response_json_schema(config: Value, response_json_schema: Option<&Value>) -> Value {
     let Some(schema) = response_json_schema else {
         return config;
@@ -1264,6 +1421,81 @@ fn with_response_json_schema(config: Value, response_json_schema: Option<&Value>
     Value::Object(config_object)
 }
 
+/// Output format requested from [`call_gemini`]: a `responseMimeType` such as
+/// `application/json`, optionally constrained by a `responseSchema`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeminiResponseFormat {
+    pub mime_type: String,
+    pub schema: Option<Value>,
+}
+
+impl GeminiResponseFormat {
+    #[allow(dead_code)]
+    pub fn json(schema: Option<Value>) -> Self {
+        Self {
+            mime_type: "application/json".to_string(),
+            schema,
+        }
+    }
+
+    fn is_json(&self) -> bool {
+        self.mime_type.eq_ignore_ascii_case("application/json")
+    }
+}
+
+fn with_response_format(config: Value, response_format: Option<&GeminiResponseFormat>) -> Value {
+    let Some(format) = response_format else {
+        return config;
+    };
+
+    let mut config_object = config.as_object().cloned().unwrap_or_default();
+    config_object.insert(
+        "responseMimeType".to_string(),
+        Value::String(format.mime_type.clone()),
+    );
+    if let Some(schema) = &format.schema {
+        config_object.insert("responseSchema".to_string(), schema.clone());
+    }
+    Value::Object(config_object)
+}
+
+/// Rejects a JSON-mode answer that does not parse, so callers never see a
+/// truncated or prose reply where they asked for a structured one.
+fn validate_response_format(
+    text: String,
+    response_format: Option<&GeminiResponseFormat>,
+) -> Result<String> {
+    if !response_format.is_some_and(GeminiResponseFormat::is_json) {
+        return Ok(text);
+    }
+    let trimmed = text.trim();
+    serde_json::from_str::<Value>(trimmed)
+        .map_err(|err| anyhow!("Gemini returned invalid JSON for a JSON-mode request: {err}"))?;
+    Ok(trimmed.to_string())
+}
+
+fn build_call_payload(
+    system_prompt: &str,
+    parts: Vec<Value>,
+    tools: Vec<Value>,
+    max_output_tokens: i32,
+    response_format: Option<&GeminiResponseFormat>,
+) -> Value {
+    let generation_config = json!({
+        "temperature": temperature_or(CONFIG.gemini_temperature),
+        "topK": CONFIG.gemini_top_k,
+        "topP": top_p_or(CONFIG.gemini_top_p),
+        "maxOutputTokens": max_output_tokens,
+    });
+    json!({
+        "systemInstruction": { "parts": [{ "text": system_prompt }] },
+        "contents": [{ "role": "user", "parts": parts }],
+        "generationConfig": with_response_format(generation_config, response_format),
+        "safetySettings": build_safety_settings(),
+        "tools": tools,
+    })
+}
+
 fn build_function_response_part(function_call: &Value, result_json: &str) -> Value {
     let name = function_call
         .get("name")
@@ -1355,6 +1587,7 @@ pub async fn call_gemini_with_tool_runtime(
                 return Ok(GeminiCallResult {
                     text: extract_text_from_response_value(&response),
                     model_used: model.to_string(),
+                    downgraded_from: None,
                 });
             }
             break;
@@ -1406,6 +1639,7 @@ pub async fn call_gemini_with_tool_runtime(
     Ok(GeminiCallResult {
         text: extract_text_from_response_value(&final_response),
         model_used: model.to_string(),
+        downgraded_from: None,
     })
 }
 
@@ -1455,11 +1689,13 @@ pub async fn call_gemini_model_simple(
     Ok(GeminiCallResult {
         text: extract_text_from_response_value(&response),
         model_used: model.to_string(),
+        downgraded_from: None,
     })
 }
 
 async fn call_gemini_lite_fallback(
     payload: &serde_json::Value,
+    thinking_level: Option<&str>,
     system_prompt_label: Option<&str>,
     previous_model: &str,
     previous_err: &anyhow::Error,
@@ -1495,7 +1731,7 @@ async fn call_gemini_lite_fallback(
         let result = async {
             let response = call_gemini_api(
                 lite_model,
-                payload.clone(),
+                with_thinking_config(payload.clone(), lite_model, thinking_level),
                 system_prompt_label,
                 audit_context,
                 "call_gemini_lite_fallback",
@@ -1510,6 +1746,7 @@ async fn call_gemini_lite_fallback(
                 return Ok(GeminiCallResult {
                     text,
                     model_used: lite_model.to_string(),
+                    downgraded_from: None,
                 });
             }
             Err(err) => {
@@ -1533,19 +1770,23 @@ async fn call_gemini_lite_fallback(
     ))
 }
 
+/// `max_output_tokens` overrides `GEMINI_MAX_OUTPUT_TOKENS`, for callers that
+/// let the user ask for shorter or longer answers.
 #[allow(clippy::too_many_arguments)]
 pub async fn call_gemini(
     system_prompt: &str,
     user_content: &str,
     use_search_grounding: bool,
-    _use_url_context: bool,
-    _thinking_level: Option<&str>,
+    use_url_context: bool,
+    thinking_level: Option<&str>,
     image_url: Option<&str>,
     use_pro_model: bool,
     media_files: Option<Vec<MediaFile>>,
     youtube_urls: Option<Vec<String>>,
     system_prompt_label: Option<&str>,
     audit_context: Option<&LlmAuditContext>,
+    max_output_tokens: Option<i32>,
+    response_format: Option<&GeminiResponseFormat>,
 ) -> Result<GeminiCallResult> {
     ensure_gemini_api_available()?;
     let content = user_content.to_string();
@@ -1579,123 +1820,199 @@ pub async fn call_gemini(
 
     let text_after_media = !uploaded_files.is_empty() || !youtube_urls.is_empty();
     let parts = build_gemini_file_parts(&content, &uploaded_files, &youtube_urls, text_after_media);
-    let tools = {
-        let mut tools = Vec::new();
-        if !has_video_or_audio && youtube_urls.is_empty() {
-            tools.push(json!({ "code_execution": {} }));
-        }
-        if use_search_grounding {
-            tools.push(json!({ "google_search": {} }));
-        }
-        tools
-    };
-
-    let payload = json!({
-        "systemInstruction": { "parts": [{ "text": system_prompt }] },
-        "contents": [{ "role": "user", "parts": parts }],
-        "generationConfig": {
-            "temperature": CONFIG.gemini_temperature,
-            "topK": CONFIG.gemini_top_k,
-            "topP": CONFIG.gemini_top_p,
-            "maxOutputTokens": CONFIG.gemini_max_output_tokens,
-        },
-        "safetySettings": build_safety_settings(),
-        "tools": tools,
-    });
+    let tools = build_call_tools(
+        has_video_or_audio || !youtube_urls.is_empty(),
+        use_search_grounding,
+        use_url_context,
+    );
 
-    let primary_model = if use_pro_model {
-        &CONFIG.gemini_pro_model
-    } else {
-        &CONFIG.gemini_model
-    };
-    let primary_operation = if use_pro_model {
-        "call_gemini_pro"
-    } else {
-        "call_gemini"
-    };
+    let payload = build_call_payload(
+        system_prompt,
+        parts,
+        tools,
+        max_output_tokens.unwrap_or(CONFIG.gemini_max_output_tokens),
+        response_format,
+    );
+    let result = call_gemini_payload_with_fallbacks(
+        &payload,
+        use_pro_model,
+        thinking_level,
+        system_prompt_label,
+        audit_context,
+    )
+    .await?;
+    Ok(GeminiCallResult {
+        text: validate_response_format(result.text, response_format)?,
+        model_used: result.model_used,
+        downgraded_from: result.downgraded_from,
+    })
+}
 
-    let primary_attempt = async {
+/// Runs `payload` on the default or pro model, then the default model (for
+/// pro), then `GEMINI_LITE_MODEL`.
+async fn call_gemini_payload_with_fallbacks(
+    payload: &Value,
+    use_pro_model: bool,
+    thinking_level: Option<&str>,
+    system_prompt_label: Option<&str>,
+    audit_context: Option<&LlmAuditContext>,
+) -> Result<GeminiCallResult> {
+    let attempt = |model: String, operation: &'static str| async move {
         let response = call_gemini_api(
-            primary_model,
-            payload.clone(),
+            &model,
+            with_thinking_config(payload.clone(), &model, thinking_level),
             system_prompt_label,
             audit_context,
-            primary_operation,
+            operation,
         )
         .await?;
         Ok::<_, anyhow::Error>(extract_text_from_response(response))
-    }
-    .await;
+    };
 
-    match primary_attempt {
-        Ok(text) => Ok(GeminiCallResult {
-            text,
-            model_used: primary_model.to_string(),
-        }),
-        Err(primary_err) => {
-            if !use_pro_model {
-                return call_gemini_lite_fallback(
-                    &payload,
+    if !use_pro_model {
+        let primary_model = CONFIG.gemini_model.as_str();
+        return match attempt(primary_model.to_string(), "call_gemini").await {
+            Ok(text) => Ok(GeminiCallResult {
+                text,
+                model_used: primary_model.to_string(),
+                downgraded_from: None,
+            }),
+            Err(primary_err) => {
+                call_gemini_lite_fallback(
+                    payload,
+                    thinking_level,
                     system_prompt_label,
                     primary_model,
                     &primary_err,
                     audit_context,
                 )
-                .await;
-            }
-
-            let fallback_model = CONFIG.gemini_model.as_str();
-            warn!(
-                "Gemini Pro model '{}' failed after retries; falling back to default model '{}': {}",
-                primary_model, fallback_model, primary_err
-            );
-
-            let fallback_text = async {
-                let response = call_gemini_api(
-                    fallback_model,
-                    payload.clone(),
-                    system_prompt_label,
-                    audit_context,
-                    "call_gemini_fallback",
-                )
-                .await?;
-                Ok::<_, anyhow::Error>(extract_text_from_response(response))
+                .await
             }
-            .await;
+        };
+    }
 
-            let fallback_text = match fallback_text {
-                Ok(text) => text,
-                Err(fallback_err) => {
-                    return call_gemini_lite_fallback(
-                        &payload,
-                        system_prompt_label,
-                        fallback_model,
-                        &fallback_err,
-                        audit_context,
-                    )
-                    .await
-                    .map_err(|lite_err| {
-                        anyhow!(
-                            "Gemini request failed on primary model '{}' and fallback model '{}'. \
+    let primary_model = CONFIG.gemini_pro_model.as_str();
+    let fallback_model = CONFIG.gemini_model.as_str();
+    match call_pro_then_default(
+        primary_model,
+        fallback_model,
+        CONFIG.gemini_pro_quota_fallback,
+        attempt,
+    )
+    .await
+    {
+        Ok(result) => Ok(result),
+        Err((primary_err, fallback_err)) => call_gemini_lite_fallback(
+            payload,
+            thinking_level,
+            system_prompt_label,
+            fallback_model,
+            &fallback_err,
+            audit_context,
+        )
+        .await
+        .map_err(|lite_err| {
+            anyhow!(
+                "Gemini request failed on primary model '{}' and fallback model '{}'. \
 Primary error: {}. Fallback error: {}. Lite fallback error: {}",
-                            primary_model,
-                            fallback_model,
-                            primary_err,
-                            fallback_err,
-                            lite_err
-                        )
-                    });
-                }
-            };
+                primary_model,
+                fallback_model,
+                primary_err,
+                fallback_err,
+                lite_err
+            )
+        }),
+    }
+}
 
-            Ok(GeminiCallResult {
-                text: fallback_text,
-                model_used: fallback_model.to_string(),
+/// Tries the Pro model, then the default model when Pro fails. Returns both
+/// errors when neither answers so the caller can try the lite model.
+async fn call_pro_then_default<F, Fut>(
+    pro_model: &str,
+    fallback_model: &str,
+    quota_fallback: bool,
+    attempt: F,
+) -> std::result::Result<GeminiCallResult, (anyhow::Error, anyhow::Error)>
+where
+    F: Fn(String, &'static str) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let primary_err = match attempt(pro_model.to_string(), "call_gemini_pro").await {
+        Ok(text) => {
+            return Ok(GeminiCallResult {
+                text,
+                model_used: pro_model.to_string(),
+                downgraded_from: None,
             })
         }
+        Err(err) => err,
+    };
+
+    let quota_downgrade = quota_fallback && is_gemini_quota_error(&primary_err);
+    if quota_downgrade {
+        warn!(
+            "Gemini Pro model '{}' is rate limited; retrying on default model '{}': {}",
+            pro_model, fallback_model, primary_err
+        );
+    } else {
+        warn!(
+            "Gemini Pro model '{}' failed after retries; falling back to default model '{}': {}",
+            pro_model, fallback_model, primary_err
+        );
+    }
+
+    match attempt(fallback_model.to_string(), "call_gemini_fallback").await {
+        Ok(text) => Ok(GeminiCallResult {
+            text,
+            model_used: fallback_model.to_string(),
+            downgraded_from: quota_downgrade.then(|| pro_model.to_string()),
+        }),
+        Err(fallback_err) => Err((primary_err, fallback_err)),
     }
 }
 
+/// Search grounding for image generation is opt-in via
+/// `IMAGE_GEN_SEARCH_GROUNDING`; most prompts do not need it and it adds
+/// search cost to every request.
+fn image_generation_tools(search_grounding: bool) -> Vec<Value> {
+    if search_grounding {
+        vec![json!({ "google_search": {"searchTypes": {"webSearch": {}, "imageSearch": {}}} })]
+    } else {
+        Vec::new()
+    }
+}
+
+fn build_image_generation_payload(
+    system_instruction: &str,
+    parts: Vec<Value>,
+    image_config: Option<&GeminiImageConfig>,
+    search_grounding: bool,
+) -> Value {
+    let mut generation_config = json!({
+        "responseModalities": ["IMAGE"]
+    });
+    if let Some(seed) = image_config.and_then(|config| config.seed) {
+        generation_config["seed"] = json!(seed);
+    }
+    if let Some(image_config) = build_image_config(image_config) {
+        if let Some(config_object) = generation_config.as_object_mut() {
+            config_object.insert("imageConfig".to_string(), image_config);
+        }
+    }
+
+    let mut payload = json!({
+        "systemInstruction": { "parts": [{ "text": system_instruction }] },
+        "contents": [{ "role": "user", "parts": parts }],
+        "generationConfig": generation_config,
+        "safetySettings": build_safety_settings(),
+    });
+    let tools = image_generation_tools(search_grounding);
+    if !tools.is_empty() {
+        payload["tools"] = Value::Array(tools);
+    }
+    payload
+}
+
 pub async fn generate_image_with_gemini(
     prompt: &str,
     image_urls: &[String],
@@ -1719,24 +2036,13 @@ pub async fn generate_image_with_gemini(
         "Edit the provided images according to the prompt. Respond with an image, not text."
     };
 
-    let system_instruction = base_instruction.to_string();
     let parts = build_gemini_parts(prompt, &images, None, None, &[], false);
-    let mut generation_config = json!({
-        "responseModalities": ["IMAGE"]
-    });
-    if let Some(image_config) = build_image_config(image_config.as_ref()) {
-        if let Some(config_object) = generation_config.as_object_mut() {
-            config_object.insert("imageConfig".to_string(), image_config);
-        }
-    }
-
-    let payload = json!({
-        "systemInstruction": { "parts": [{ "text": system_instruction }] },
-        "contents": [{ "role": "user", "parts": parts }],
-        "generationConfig": generation_config,
-        "safetySettings": build_safety_settings(),
-        "tools": [{ "google_search": {"searchTypes": {"webSearch": {}, "imageSearch": {}}} }],
-    });
+    let payload = build_image_generation_payload(
+        base_instruction,
+        parts,
+        image_config.as_ref(),
+        CONFIG.image_gen_search_grounding,
+    );
 
     let model = &CONFIG.gemini_image_model;
     let response = call_gemini_api_with_timeout(
@@ -2012,6 +2318,100 @@ pub async fn generate_video_with_veo(
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn upload_slots_bound_concurrent_uploads() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let slots = Arc::new(Semaphore::new(2));
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let mut join_set = JoinSet::new();
+        for _ in 0..8 {
+            let (slots, active, peak) = (slots.clone(), active.clone(), peak.clone());
+            join_set.spawn(async move {
+                with_upload_slot(&slots, async {
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                })
+                .await;
+            });
+        }
+        while join_set.join_next().await.is_some() {}
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(slots.available_permits(), 2);
+    }
+
+    #[test]
+    fn image_generation_payload_includes_seed_when_set() {
+        let parts = vec![json!({ "text": "a lighthouse at dusk" })];
+        let config = GeminiImageConfig {
+            aspect_ratio: Some("16:9".to_string()),
+            image_size: None,
+            seed: Some(42),
+        };
+        let payload =
+            build_image_generation_payload("Generate", parts.clone(), Some(&config), false);
+        assert_eq!(payload["generationConfig"]["seed"], json!(42));
+        assert_eq!(
+            payload["generationConfig"]["imageConfig"],
+            json!({ "aspectRatio": "16:9" })
+        );
+
+        let unseeded = GeminiImageConfig {
+            seed: None,
+            ..config
+        };
+        let payload = build_image_generation_payload("Generate", parts, Some(&unseeded), false);
+        assert!(payload["generationConfig"].get("seed").is_none());
+    }
+
+    #[test]
+    fn call_payload_requests_json_only_when_asked() {
+        let parts = vec![json!({ "text": "Is the sky green?" })];
+        let schema = json!({
+            "type": "OBJECT",
+            "properties": { "verdict": { "type": "STRING" } }
+        });
+        let format = GeminiResponseFormat::json(Some(schema.clone()));
+        let payload = build_call_payload("Check", parts.clone(), Vec::new(), 512, Some(&format));
+        assert_eq!(
+            payload["generationConfig"]["responseMimeType"],
+            json!("application/json")
+        );
+        assert_eq!(payload["generationConfig"]["responseSchema"], schema);
+        assert_eq!(payload["generationConfig"]["maxOutputTokens"], json!(512));
+
+        let payload = build_call_payload("Check", parts, Vec::new(), 512, None);
+        assert!(payload["generationConfig"]
+            .get("responseMimeType")
+            .is_none());
+
+        assert_eq!(
+            validate_response_format(" {\"verdict\":\"false\"}\n".to_string(), Some(&format))
+                .unwrap(),
+            "{\"verdict\":\"false\"}"
+        );
+        assert!(validate_response_format("The sky is blue.".to_string(), Some(&format)).is_err());
+        assert!(validate_response_format("The sky is blue.".to_string(), None).is_ok());
+    }
+
+    #[test]
+    fn image_generation_payload_only_grounds_when_enabled() {
+        let parts = vec![json!({ "text": "a lighthouse at dusk" })];
+        assert!(image_generation_tools(false).is_empty());
+        let payload = build_image_generation_payload("Generate", parts.clone(), None, false);
+        assert!(payload.get("tools").is_none());
+        assert_eq!(
+            payload["generationConfig"]["responseModalities"],
+            json!(["IMAGE"])
+        );
+
+        let payload = build_image_generation_payload("Generate", parts, None, true);
+        assert!(payload["tools"][0].get("google_search").is_some());
+    }
+
     #[test]
     fn gemini_generate_content_url_does_not_embed_api_key() {
         let url = gemini_generate_content_url("gemini-test-model");
@@ -2105,6 +2505,55 @@ mod tests {
             .contains("No audio returned by Lyria (model: lyria-3-pro-preview)"));
     }
 
+    #[test]
+    fn thinking_level_maps_to_budget_for_supported_models() {
+        let payload = json!({ "generationConfig": { "temperature": 1.0 } });
+
+        let flash = with_thinking_config(payload.clone(), "gemini-2.5-flash", Some("medium"));
+        assert_eq!(
+            flash["generationConfig"]["thinkingConfig"],
+            json!({ "thinkingBudget": 8192 })
+        );
+        let latest = with_thinking_config(payload.clone(), "gemini-flash-latest", Some("high"));
+        assert_eq!(
+            latest["generationConfig"]["thinkingConfig"]["thinkingBudget"],
+            24576
+        );
+
+        // Pro cannot disable thinking, and image/older models get no config.
+        for (model, level) in [
+            ("gemini-2.5-pro", "off"),
+            ("gemini-2.5-flash-image", "high"),
+            ("gemini-2.0-flash", "high"),
+            ("gemini-2.5-flash", "extreme"),
+        ] {
+            let result = with_thinking_config(flash.clone(), model, Some(level));
+            assert!(
+                result["generationConfig"].get("thinkingConfig").is_none(),
+                "{model} with {level} should omit thinkingConfig"
+            );
+        }
+        assert_eq!(thinking_budget_for_level("2048"), Some(2048));
+        assert_eq!(thinking_budget_for_level("dynamic"), Some(-1));
+    }
+
+    #[test]
+    fn call_tools_include_url_context_alongside_search() {
+        assert_eq!(
+            build_call_tools(false, true, true),
+            vec![
+                json!({ "code_execution": {} }),
+                json!({ "google_search": {} }),
+                json!({ "url_context": {} }),
+            ]
+        );
+        assert_eq!(
+            build_call_tools(true, false, true),
+            vec![json!({ "url_context": {} })]
+        );
+        assert!(!build_call_tools(false, true, false).contains(&json!({ "url_context": {} })));
+    }
+
     #[test]
     fn gemini_timeout_helpers_use_general_and_image_specific_config() {
         assert_eq!(
@@ -2116,4 +2565,60 @@ mod tests {
             CONFIG.gemini_image_request_timeout_secs
         );
     }
+
+    #[tokio::test]
+    async fn pro_quota_error_retries_on_default_model_and_notes_downgrade() {
+        use parking_lot::Mutex;
+
+        let calls = Mutex::new(Vec::new());
+        let attempt = |model: String, operation: &'static str| {
+            calls.lock().push((model.clone(), operation));
+            async move {
+                if model == "pro" {
+                    Err(anyhow::Error::new(GeminiStatusError {
+                        status: StatusCode::TOO_MANY_REQUESTS,
+                        detail: "Resource has been exhausted".to_string(),
+                    }))
+                } else {
+                    Ok(format!("answer from {model}"))
+                }
+            }
+        };
+
+        let result = call_pro_then_default("pro", "flash", true, &attempt)
+            .await
+            .expect("flash should answer");
+        assert_eq!(result.text, "answer from flash");
+        assert_eq!(result.model_used, "flash");
+        assert_eq!(result.downgraded_from.as_deref(), Some("pro"));
+        assert_eq!(result.model_display(), "flash (downgraded from pro: quota)");
+        assert_eq!(
+            *calls.lock(),
+            vec![
+                ("pro".to_string(), "call_gemini_pro"),
+                ("flash".to_string(), "call_gemini_fallback")
+            ]
+        );
+
+        let result = call_pro_then_default("pro", "flash", false, &attempt)
+            .await
+            .expect("flash should answer");
+        assert_eq!(result.model_display(), "flash");
+
+        assert!(skips_retry_for_pro_quota(
+            StatusCode::TOO_MANY_REQUESTS,
+            "call_gemini_pro",
+            true
+        ));
+        assert!(!skips_retry_for_pro_quota(
+            StatusCode::TOO_MANY_REQUESTS,
+            "call_gemini",
+            true
+        ));
+        assert!(!skips_retry_for_pro_quota(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "call_gemini_pro",
+            true
+        ));
+    }
 }
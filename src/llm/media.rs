@@ -1,6 +1,7 @@
 use std::sync::Arc;
 use std::time::Duration;
 
+use base64::{engine::general_purpose, Engine as _};
 use reqwest::StatusCode;
 use tracing::{error, warn};
 
@@ -139,6 +140,37 @@ impl MediaFile {
     }
 }
 
+/// MIME type to declare when sending media bytes to a model: sniffed from
+/// the bytes first, then the declared type if it matches `kind`, then a
+/// per-kind default. Telegram often reports documents as
+/// `application/octet-stream`, which providers reject for image parts.
+pub fn resolve_media_mime(bytes: &[u8], kind: MediaKind, declared: Option<&str>) -> String {
+    if let Some(detected) = detect_mime_type(bytes) {
+        return detected;
+    }
+    let declared = declared.map(str::trim).unwrap_or_default();
+    let fallback = match kind {
+        MediaKind::Image => "image/png",
+        MediaKind::Video => "video/mp4",
+        MediaKind::Audio => "audio/mpeg",
+        MediaKind::Document => "application/octet-stream",
+    };
+    if !declared.is_empty() && (kind == MediaKind::Document || kind_for_mime(declared) == kind) {
+        return declared.to_string();
+    }
+    fallback.to_string()
+}
+
+/// `data:` URL for inline media parts in OpenAI-compatible and Responses
+/// requests.
+pub fn media_data_url(bytes: &[u8], kind: MediaKind, declared: Option<&str>) -> String {
+    format!(
+        "data:{};base64,{}",
+        resolve_media_mime(bytes, kind, declared),
+        general_purpose::STANDARD.encode(bytes)
+    )
+}
+
 pub fn kind_for_mime(mime_type: &str) -> MediaKind {
     if mime_type.starts_with("image/") {
         MediaKind::Image
@@ -2,17 +2,23 @@ pub mod analytics;
 pub mod audit;
 pub mod brave_search;
 pub mod codex_image;
+pub mod dictionary;
 pub mod exa_search;
 pub mod gemini;
 pub mod img2_image;
 pub mod jina_search;
 pub mod media;
 pub mod openai_codex;
+pub mod openrouter_catalog;
+pub mod pricing;
+pub mod provider_stats;
 pub mod responses_provider;
 pub mod runtime_models;
+pub mod sampling;
 pub mod third_party;
 pub mod tool_prompts;
 pub mod tool_runtime;
+pub mod web_fetch;
 pub mod web_search;
 
 pub use audit::{audit_context_from_id, create_audit_context_from_message, LlmAuditContext};
@@ -24,4 +30,5 @@ pub use gemini::{
 pub use img2_image::generate_image_with_img2;
 pub use third_party::{
     call_third_party, call_third_party_with_reasoning_config, call_third_party_with_tool_runtime,
+    configured_max_tokens, resolve_third_party_model_config,
 };
@@ -0,0 +1,271 @@
+//! Reconciles configured OpenRouter model capabilities with OpenRouter's
+//! `/models` catalog.
+//!
+//! The third-party models file still decides which models are offered; this
+//! only corrects the `image`/`video`/`audio`/`tools` flags when the catalog
+//! disagrees. Enabled with `OPENROUTER_MODEL_AUTO_REFRESH`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::config::{ThirdPartyModelConfig, ThirdPartyProvider, CONFIG};
+use crate::llm::runtime_models::{
+    is_runtime_provider_ready, set_model_capability_overrides, ModelCapabilities,
+};
+use crate::llm::third_party::{OPENROUTER_REFERER, OPENROUTER_TITLE};
+use crate::utils::http::get_http_client;
+
+const CATALOG_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Deserialize)]
+struct OpenRouterModelsResponse {
+    data: Vec<OpenRouterCatalogModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenRouterCatalogModel {
+    id: String,
+    #[serde(default)]
+    architecture: Option<OpenRouterArchitecture>,
+    #[serde(default)]
+    supported_parameters: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenRouterArchitecture {
+    #[serde(default)]
+    input_modalities: Vec<String>,
+}
+
+impl OpenRouterCatalogModel {
+    fn capabilities(&self) -> ModelCapabilities {
+        let inputs = self
+            .architecture
+            .as_ref()
+            .map(|architecture| architecture.input_modalities.as_slice())
+            .unwrap_or_default();
+        let accepts = |modality: &str| {
+            inputs
+                .iter()
+                .any(|value| value.eq_ignore_ascii_case(modality))
+        };
+        ModelCapabilities {
+            image: accepts("image"),
+            video: accepts("video"),
+            audio: accepts("audio"),
+            tools: self
+                .supported_parameters
+                .iter()
+                .any(|value| value == "tools"),
+        }
+    }
+}
+
+fn parse_openrouter_catalog(body: &str) -> Result<HashMap<String, ModelCapabilities>> {
+    let response: OpenRouterModelsResponse = serde_json::from_str(body)?;
+    Ok(response
+        .data
+        .into_iter()
+        .map(|model| {
+            let capabilities = model.capabilities();
+            (model.id, capabilities)
+        })
+        .collect())
+}
+
+/// Looks up a configured slug, falling back to the base slug for variants
+/// such as `:free` or `:nitro` that the catalog may list only once.
+fn catalog_capabilities(
+    catalog: &HashMap<String, ModelCapabilities>,
+    slug: &str,
+) -> Option<ModelCapabilities> {
+    catalog.get(slug).copied().or_else(|| {
+        slug.rsplit_once(':')
+            .and_then(|(base, _)| catalog.get(base).copied())
+    })
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CatalogReconciliation {
+    overrides: HashMap<String, ModelCapabilities>,
+    mismatches: Vec<String>,
+    missing: Vec<String>,
+}
+
+fn reconcile_capabilities(
+    configured: &[ThirdPartyModelConfig],
+    catalog: &HashMap<String, ModelCapabilities>,
+) -> CatalogReconciliation {
+    let mut result = CatalogReconciliation::default();
+    for model in configured
+        .iter()
+        .filter(|model| model.provider == ThirdPartyProvider::OpenRouter)
+    {
+        let Some(remote) = catalog_capabilities(catalog, &model.model) else {
+            result.missing.push(model.model.clone());
+            continue;
+        };
+        let local = ModelCapabilities::of(model);
+        if local != remote {
+            result.mismatches.push(format!(
+                "{}: configured {local:?}, catalog {remote:?}",
+                model.id
+            ));
+            result.overrides.insert(model.id.clone(), remote);
+        }
+    }
+    result
+}
+
+async fn fetch_openrouter_catalog() -> Result<HashMap<String, ModelCapabilities>> {
+    let url = format!(
+        "{}/models",
+        CONFIG.openrouter_base_url.trim_end_matches('/')
+    );
+    let response = get_http_client()
+        .get(&url)
+        .bearer_auth(&CONFIG.openrouter_api_key)
+        .header("HTTP-Referer", OPENROUTER_REFERER)
+        .header("X-Title", OPENROUTER_TITLE)
+        .timeout(CATALOG_REQUEST_TIMEOUT)
+        .send()
+        .await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(anyhow!("OpenRouter /models returned {status}"));
+    }
+    parse_openrouter_catalog(&body)
+}
+
+/// Fetches the catalog once and applies capability corrections. Returns the
+/// number of configured models whose flags were overridden.
+pub async fn refresh_openrouter_capabilities() -> Result<usize> {
+    let catalog = fetch_openrouter_catalog().await?;
+    let reconciliation = reconcile_capabilities(&CONFIG.third_party_models, &catalog);
+    for mismatch in &reconciliation.mismatches {
+        warn!("OpenRouter capability mismatch: {mismatch}");
+    }
+    for model in &reconciliation.missing {
+        warn!("Configured OpenRouter model {model} is not in the /models catalog");
+    }
+    let overridden = reconciliation.overrides.len();
+    set_model_capability_overrides(reconciliation.overrides);
+    info!(
+        "OpenRouter catalog refreshed: {} catalog models, {overridden} capability overrides",
+        catalog.len()
+    );
+    Ok(overridden)
+}
+
+pub fn spawn_openrouter_model_refresh() {
+    if !CONFIG.openrouter_model_auto_refresh {
+        return;
+    }
+    if !is_runtime_provider_ready(ThirdPartyProvider::OpenRouter) {
+        warn!("OPENROUTER_MODEL_AUTO_REFRESH is set but OpenRouter is not configured; skipping");
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = refresh_openrouter_capabilities().await {
+                warn!("OpenRouter catalog refresh failed: {err:#}");
+            }
+            if CONFIG.openrouter_model_refresh_interval_secs == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(
+                CONFIG.openrouter_model_refresh_interval_secs,
+            ))
+            .await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_MODELS_RESPONSE: &str = r#"{
+        "data": [
+            {
+                "id": "google/gemini-2.5-flash",
+                "name": "Google: Gemini 2.5 Flash",
+                "architecture": {
+                    "modality": "text+image->text",
+                    "input_modalities": ["text", "image", "file", "audio", "video"],
+                    "output_modalities": ["text"]
+                },
+                "supported_parameters": ["max_tokens", "temperature", "tools", "tool_choice"]
+            },
+            {
+                "id": "qwen/qwen3-next-80b-a3b-instruct",
+                "architecture": { "input_modalities": ["text"] },
+                "supported_parameters": ["max_tokens"]
+            }
+        ]
+    }"#;
+
+    fn configured(model: &str, image: bool, tools: bool) -> ThirdPartyModelConfig {
+        ThirdPartyModelConfig {
+            id: format!("openrouter:{model}"),
+            provider: ThirdPartyProvider::OpenRouter,
+            name: model.to_string(),
+            model: model.to_string(),
+            image,
+            video: false,
+            audio: false,
+            tools,
+            max_tokens: None,
+            stop: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_capabilities_from_models_response() {
+        let catalog = parse_openrouter_catalog(SAMPLE_MODELS_RESPONSE).expect("sample parses");
+        assert_eq!(
+            catalog.get("google/gemini-2.5-flash"),
+            Some(&ModelCapabilities {
+                image: true,
+                video: true,
+                audio: true,
+                tools: true,
+            })
+        );
+        assert_eq!(
+            catalog.get("qwen/qwen3-next-80b-a3b-instruct"),
+            Some(&ModelCapabilities {
+                image: false,
+                video: false,
+                audio: false,
+                tools: false,
+            })
+        );
+    }
+
+    #[test]
+    fn reconcile_overrides_only_mismatched_models() {
+        let catalog = parse_openrouter_catalog(SAMPLE_MODELS_RESPONSE).expect("sample parses");
+        let models = vec![
+            configured("google/gemini-2.5-flash", true, false),
+            configured("qwen/qwen3-next-80b-a3b-instruct:free", false, false),
+            configured("unknown/model", false, false),
+        ];
+
+        let result = reconcile_capabilities(&models, &catalog);
+
+        assert_eq!(result.overrides.len(), 1);
+        assert!(result
+            .overrides
+            .get("openrouter:google/gemini-2.5-flash")
+            .is_some_and(|caps| caps.tools && caps.video));
+        assert_eq!(result.mismatches.len(), 1);
+        assert_eq!(result.missing, vec!["unknown/model".to_string()]);
+    }
+}
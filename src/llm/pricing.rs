@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::config::CONFIG;
+use crate::db::models::ModelUsageStat;
+
+/// USD price per one million tokens for a single model.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CostEstimate {
+    pub usd: f64,
+    pub unpriced_models: usize,
+}
+
+/// Parses `COST_TABLE`, a JSON object mapping model ids to
+/// `{"input": <usd per 1M>, "output": <usd per 1M>}`. Keys are matched
+/// case-insensitively; invalid JSON or negative prices disable the entry.
+pub fn parse_cost_table(raw: &str) -> HashMap<String, ModelPrice> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return HashMap::new();
+    }
+
+    let parsed: HashMap<String, ModelPrice> = match serde_json::from_str(trimmed) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            warn!("Failed to parse COST_TABLE JSON; cost estimates disabled: {err}");
+            return HashMap::new();
+        }
+    };
+
+    parsed
+        .into_iter()
+        .filter_map(|(model, price)| {
+            let model = model.trim().to_lowercase();
+            if model.is_empty() || price.input < 0.0 || price.output < 0.0 {
+                return None;
+            }
+            Some((model, price))
+        })
+        .collect()
+}
+
+fn lookup_price<'a>(
+    table: &'a HashMap<String, ModelPrice>,
+    provider: &str,
+    model: &str,
+) -> Option<&'a ModelPrice> {
+    let model = model.trim().to_lowercase();
+    let provider = provider.trim().to_lowercase();
+    table
+        .get(&format!("{provider}:{model}"))
+        .or_else(|| table.get(&model))
+        .or_else(|| {
+            // OpenRouter-style ids carry a vendor prefix ("openai/gpt-4.1").
+            model.rsplit_once('/').and_then(|(_, bare)| table.get(bare))
+        })
+}
+
+pub fn estimate_cost_with_table(
+    table: &HashMap<String, ModelPrice>,
+    usage: &[ModelUsageStat],
+) -> CostEstimate {
+    let mut estimate = CostEstimate::default();
+    for row in usage {
+        if row.input_tokens == 0 && row.output_tokens == 0 {
+            continue;
+        }
+        match lookup_price(table, &row.provider, &row.model) {
+            Some(price) => {
+                estimate.usd += (row.input_tokens.max(0) as f64 * price.input
+                    + row.output_tokens.max(0) as f64 * price.output)
+                    / 1_000_000.0;
+            }
+            None => estimate.unpriced_models += 1,
+        }
+    }
+    estimate
+}
+
+pub fn estimate_cost(usage: &[ModelUsageStat]) -> CostEstimate {
+    estimate_cost_with_table(&CONFIG.cost_table, usage)
+}
+
+pub fn cost_estimation_enabled() -> bool {
+    !CONFIG.cost_table.is_empty()
+}
+
+pub fn format_usd(usd: f64) -> String {
+    if usd > 0.0 && usd < 0.0001 {
+        return "<$0.0001".to_string();
+    }
+    if usd < 1.0 {
+        format!("${usd:.4}")
+    } else {
+        format!("${usd:.2}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(provider: &str, model: &str, input: i64, output: i64) -> ModelUsageStat {
+        ModelUsageStat {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            input_tokens: input,
+            output_tokens: output,
+        }
+    }
+
+    #[test]
+    fn parse_cost_table_normalizes_keys_and_drops_invalid_entries() {
+        let table = parse_cost_table(
+            r#"{"Gemini-2.5-Pro":{"input":1.25,"output":10},"bad":{"input":-1,"output":1}}"#,
+        );
+        assert_eq!(table.len(), 1);
+        assert_eq!(
+            table.get("gemini-2.5-pro"),
+            Some(&ModelPrice {
+                input: 1.25,
+                output: 10.0
+            })
+        );
+        assert!(parse_cost_table("not json").is_empty());
+        assert!(parse_cost_table("  ").is_empty());
+    }
+
+    #[test]
+    fn estimate_cost_prices_known_models_and_counts_unknown_ones() {
+        let table = parse_cost_table(
+            r#"{"gemini-2.5-pro":{"input":1.25,"output":10},"gpt-4.1":{"input":2,"output":8},"openrouter:x-ai/grok-4":{"input":3,"output":15}}"#,
+        );
+        let estimate = estimate_cost_with_table(
+            &table,
+            &[
+                usage("gemini", "gemini-2.5-pro", 1_000_000, 100_000),
+                usage("openrouter", "openai/gpt-4.1", 500_000, 0),
+                usage("openrouter", "x-ai/grok-4", 0, 1_000_000),
+                usage("nvidia", "mystery-model", 10, 10),
+                usage("nvidia", "idle-model", 0, 0),
+            ],
+        );
+        assert!((estimate.usd - (1.25 + 1.0 + 1.0 + 15.0)).abs() < 1e-9);
+        assert_eq!(estimate.unpriced_models, 1);
+    }
+
+    #[test]
+    fn format_usd_keeps_small_amounts_readable() {
+        assert_eq!(format_usd(0.0), "$0.0000");
+        assert_eq!(format_usd(0.00002), "<$0.0001");
+        assert_eq!(format_usd(0.0021), "$0.0021");
+        assert_eq!(format_usd(12.345), "$12.35");
+    }
+}
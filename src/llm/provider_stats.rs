@@ -0,0 +1,212 @@
+//! Rolling success/error counters for outbound provider calls.
+//!
+//! Gemini, the OpenAI-compatible providers (OpenRouter and friends), and the
+//! Brave/Exa/Jina web search backends record the outcome and latency of every
+//! call here. Outcomes older than `PROVIDER_STATS_WINDOW_MINUTES` are
+//! dropped and nothing is persisted, so the numbers start over on restart.
+//! `/stats_providers` and `/diagnose` report them.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::config::CONFIG;
+
+/// Bounds memory for a provider that is called very often.
+const MAX_OUTCOMES_PER_PROVIDER: usize = 1_000;
+
+#[derive(Debug, Clone, Copy)]
+struct CallOutcome {
+    at: Instant,
+    success: bool,
+    latency: Duration,
+}
+
+type OutcomeLog = HashMap<String, VecDeque<CallOutcome>>;
+
+static PROVIDER_OUTCOMES: Lazy<Mutex<OutcomeLog>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderSummary {
+    pub provider: String,
+    pub calls: usize,
+    pub successes: usize,
+    pub avg_latency_ms: u64,
+}
+
+impl ProviderSummary {
+    pub fn success_percent(&self) -> f64 {
+        if self.calls == 0 {
+            return 0.0;
+        }
+        self.successes as f64 * 100.0 / self.calls as f64
+    }
+}
+
+fn stats_window() -> Duration {
+    Duration::from_secs(CONFIG.provider_stats_window_minutes.saturating_mul(60))
+}
+
+fn prune(outcomes: &mut VecDeque<CallOutcome>, now: Instant, window: Duration) {
+    while outcomes
+        .front()
+        .is_some_and(|outcome| now.saturating_duration_since(outcome.at) > window)
+    {
+        outcomes.pop_front();
+    }
+    while outcomes.len() > MAX_OUTCOMES_PER_PROVIDER {
+        outcomes.pop_front();
+    }
+}
+
+fn record_at(log: &mut OutcomeLog, provider: &str, outcome: CallOutcome, window: Duration) {
+    let outcomes = log.entry(provider.to_lowercase()).or_default();
+    outcomes.push_back(outcome);
+    prune(outcomes, outcome.at, window);
+}
+
+fn summarize(log: &mut OutcomeLog, now: Instant, window: Duration) -> Vec<ProviderSummary> {
+    let mut summaries = Vec::new();
+    for (provider, outcomes) in log.iter_mut() {
+        prune(outcomes, now, window);
+        if outcomes.is_empty() {
+            continue;
+        }
+        let total_latency_ms: u128 = outcomes
+            .iter()
+            .map(|outcome| outcome.latency.as_millis())
+            .sum();
+        summaries.push(ProviderSummary {
+            provider: provider.clone(),
+            calls: outcomes.len(),
+            successes: outcomes.iter().filter(|outcome| outcome.success).count(),
+            avg_latency_ms: (total_latency_ms / outcomes.len() as u128) as u64,
+        });
+    }
+    log.retain(|_, outcomes| !outcomes.is_empty());
+    summaries.sort_by(|a, b| a.provider.cmp(&b.provider));
+    summaries
+}
+
+pub fn record_provider_call(provider: &str, success: bool, latency: Duration) {
+    let outcome = CallOutcome {
+        at: Instant::now(),
+        success,
+        latency,
+    };
+    record_at(
+        &mut PROVIDER_OUTCOMES.lock(),
+        provider,
+        outcome,
+        stats_window(),
+    );
+}
+
+/// Awaits `call` and records whether it succeeded and how long it took.
+pub async fn track_provider_call<T, E, F>(provider: &str, call: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let started = Instant::now();
+    let result = call.await;
+    record_provider_call(provider, result.is_ok(), started.elapsed());
+    result
+}
+
+/// Per-provider totals over the current window, sorted by provider name.
+pub fn provider_summaries() -> Vec<ProviderSummary> {
+    summarize(
+        &mut PROVIDER_OUTCOMES.lock(),
+        Instant::now(),
+        stats_window(),
+    )
+}
+
+pub fn format_provider_stats(summaries: &[ProviderSummary], window_minutes: u64) -> String {
+    if summaries.is_empty() {
+        return format!("No provider calls in the last {window_minutes} min.");
+    }
+    let mut report = format!("Provider calls in the last {window_minutes} min");
+    for summary in summaries {
+        report.push_str(&format!(
+            "\n{}: {} calls, {:.1}% ok, {} failed, avg {} ms",
+            summary.provider,
+            summary.calls,
+            summary.success_percent(),
+            summary.calls - summary.successes,
+            summary.avg_latency_ms
+        ));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(at: Instant, success: bool, latency_ms: u64) -> CallOutcome {
+        CallOutcome {
+            at,
+            success,
+            latency: Duration::from_millis(latency_ms),
+        }
+    }
+
+    #[test]
+    fn aggregates_outcomes_within_the_window() {
+        let window = Duration::from_secs(3600);
+        let start = Instant::now();
+        let mut log = OutcomeLog::new();
+        record_at(&mut log, "Gemini", outcome(start, true, 900), window);
+        record_at(&mut log, "brave", outcome(start, false, 50), window);
+        let later = start + Duration::from_secs(1800);
+        record_at(&mut log, "gemini", outcome(later, true, 1100), window);
+        record_at(&mut log, "gemini", outcome(later, false, 400), window);
+
+        let summaries = summarize(&mut log, later, window);
+        assert_eq!(
+            summaries,
+            vec![
+                ProviderSummary {
+                    provider: "brave".to_string(),
+                    calls: 1,
+                    successes: 0,
+                    avg_latency_ms: 50,
+                },
+                ProviderSummary {
+                    provider: "gemini".to_string(),
+                    calls: 3,
+                    successes: 2,
+                    avg_latency_ms: 800,
+                },
+            ]
+        );
+        assert_eq!(
+            format_provider_stats(&summaries[1..], 60),
+            "Provider calls in the last 60 min\ngemini: 3 calls, 66.7% ok, 1 failed, avg 800 ms"
+        );
+
+        let summaries = summarize(&mut log, start + Duration::from_secs(4000), window);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].calls, 2);
+        assert!(!log.contains_key("brave"));
+        assert_eq!(
+            format_provider_stats(&[], 60),
+            "No provider calls in the last 60 min."
+        );
+    }
+
+    #[test]
+    fn caps_outcomes_per_provider() {
+        let window = Duration::from_secs(3600);
+        let start = Instant::now();
+        let mut log = OutcomeLog::new();
+        for _ in 0..MAX_OUTCOMES_PER_PROVIDER + 5 {
+            record_at(&mut log, "exa", outcome(start, true, 10), window);
+        }
+        assert_eq!(log["exa"].len(), MAX_OUTCOMES_PER_PROVIDER);
+    }
+}
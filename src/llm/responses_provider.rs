@@ -5,7 +5,6 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
-use base64::{engine::general_purpose, Engine as _};
 use reqwest::StatusCode;
 use serde_json::{json, Value};
 use tracing::{debug, error, info, warn};
@@ -14,6 +13,7 @@ use crate::config::{ThirdPartyModelConfig, ThirdPartyProvider, CONFIG};
 use crate::llm::audit::{
     log_llm_request_started, record_llm_request_success, LlmAuditContext, LlmUsageRecord,
 };
+use crate::llm::media::{media_data_url, MediaKind};
 use crate::llm::openai_codex;
 use crate::llm::runtime_models::{
     selected_codex_model_record, CodexSelectedModelRecord, OPENAI_CODEX_SELECTED_MODEL_ID,
@@ -599,10 +599,7 @@ fn build_responses_user_input(user_content: &str, image_data_list: &[Vec<u8>]) -
     })];
 
     for image_data in image_data_list {
-        let mime_type = crate::llm::media::detect_mime_type(image_data)
-            .unwrap_or_else(|| "image/png".to_string());
-        let encoded = general_purpose::STANDARD.encode(image_data);
-        let data_url = format!("data:{};base64,{}", mime_type, encoded);
+        let data_url = media_data_url(image_data, MediaKind::Image, None);
         content.push(json!({
             "type": "input_image",
             "detail": "auto",
@@ -1945,6 +1942,8 @@ mod tests {
             video: false,
             audio: false,
             tools: true,
+            max_tokens: None,
+            stop: Vec::new(),
         }
     }
 
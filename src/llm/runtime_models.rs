@@ -61,6 +61,35 @@ struct RuntimeModelsState {
     codex_selected_model: Option<CodexSelectedModelRecord>,
 }
 
+/// Capability flags reported by a provider catalog, keyed by model id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    pub image: bool,
+    pub video: bool,
+    pub audio: bool,
+    pub tools: bool,
+}
+
+impl ModelCapabilities {
+    pub fn of(model: &ThirdPartyModelConfig) -> Self {
+        Self {
+            image: model.image,
+            video: model.video,
+            audio: model.audio,
+            tools: model.tools,
+        }
+    }
+
+    fn apply_to(self, model: &mut ThirdPartyModelConfig) {
+        model.image = self.image;
+        model.video = self.video;
+        model.audio = self.audio;
+        model.tools = self.tools;
+    }
+}
+
+static CAPABILITY_OVERRIDES: Lazy<RwLock<HashMap<String, ModelCapabilities>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
 static RUNTIME_MODELS: Lazy<RwLock<RuntimeModelsState>> =
     Lazy::new(|| RwLock::new(build_runtime_models_state()));
 static CODEX_MODEL_STATE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
@@ -204,6 +233,8 @@ fn dynamic_codex_model_config(record: &CodexSelectedModelRecord) -> ThirdPartyMo
         video: false,
         audio: false,
         tools: true,
+        max_tokens: None,
+        stop: Vec::new(),
     }
 }
 
@@ -220,6 +251,14 @@ fn build_runtime_models_state() -> RuntimeModelsState {
         }
         matches
     });
+    {
+        let overrides = CAPABILITY_OVERRIDES.read();
+        for model in &mut models {
+            if let Some(capabilities) = overrides.get(&model.id) {
+                capabilities.apply_to(model);
+            }
+        }
+    }
     if let Some(record) = codex_selected_model.as_ref() {
         models.push(dynamic_codex_model_config(record));
     }
@@ -241,6 +280,13 @@ pub fn reload_runtime_models() {
     *state = build_runtime_models_state();
 }
 
+/// Replaces the catalog-derived capability overrides and rebuilds the runtime
+/// model list so they take effect immediately.
+pub fn set_model_capability_overrides(overrides: HashMap<String, ModelCapabilities>) {
+    *CAPABILITY_OVERRIDES.write() = overrides;
+    reload_runtime_models();
+}
+
 pub fn runtime_models() -> Vec<ThirdPartyModelConfig> {
     RUNTIME_MODELS.read().models.clone()
 }
@@ -612,6 +658,16 @@ pub async fn refresh_selected_codex_model_for_etag(
     result
 }
 
+/// Whether any configured third-party model has a usable provider. Gemini
+/// readiness is checked separately through `CONFIG.gemini_api_available()`.
+pub fn any_third_party_model_ready() -> bool {
+    RUNTIME_MODELS
+        .read()
+        .models
+        .iter()
+        .any(|model| is_runtime_provider_ready(model.provider))
+}
+
 pub fn is_runtime_provider_ready(provider: ThirdPartyProvider) -> bool {
     match provider {
         ThirdPartyProvider::OpenRouter => {
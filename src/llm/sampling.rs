@@ -0,0 +1,84 @@
+//! Per-chat temperature and top_p overrides.
+//!
+//! `/temperature` stores the values in `chat_settings`. Command handlers run
+//! inside [`with_chat_sampling`], and the Gemini and OpenAI-compatible payload
+//! builders read the active override through [`temperature_or`] and
+//! [`top_p_or`], falling back to the provider's global
+//! `*_TEMPERATURE`/`*_TOP_P` when the chat has none.
+
+use std::future::Future;
+
+use crate::db::models::ChatSettingsRow;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChatSampling {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+}
+
+impl From<&ChatSettingsRow> for ChatSampling {
+    fn from(settings: &ChatSettingsRow) -> Self {
+        Self {
+            temperature: settings.temperature.map(|value| value as f32),
+            top_p: settings.top_p.map(|value| value as f32),
+        }
+    }
+}
+
+tokio::task_local! {
+    static ACTIVE_SAMPLING: ChatSampling;
+}
+
+/// Runs `future` with the chat's overrides applied to every LLM call it makes.
+pub async fn with_chat_sampling<F: Future>(sampling: ChatSampling, future: F) -> F::Output {
+    ACTIVE_SAMPLING.scope(sampling, future).await
+}
+
+/// The overrides in scope. Task-locals do not follow `spawn`, so work fanned
+/// out to other tasks captures this and re-enters [`with_chat_sampling`].
+pub fn active_sampling() -> ChatSampling {
+    ACTIVE_SAMPLING
+        .try_with(|sampling| *sampling)
+        .unwrap_or_default()
+}
+
+pub fn temperature_or(default: f32) -> f32 {
+    ACTIVE_SAMPLING
+        .try_with(|sampling| sampling.temperature)
+        .ok()
+        .flatten()
+        .unwrap_or(default)
+}
+
+pub fn top_p_or(default: f32) -> f32 {
+    ACTIVE_SAMPLING
+        .try_with(|sampling| sampling.top_p)
+        .ok()
+        .flatten()
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn spawned_tasks_keep_the_sampling_they_are_given() {
+        let sampling = ChatSampling {
+            temperature: Some(0.1),
+            top_p: Some(0.5),
+        };
+        let (bare, carried) = with_chat_sampling(sampling, async {
+            assert_eq!(active_sampling(), sampling);
+            let bare = tokio::spawn(async { temperature_or(0.9) });
+            let carried = tokio::spawn(with_chat_sampling(active_sampling(), async {
+                (temperature_or(0.9), top_p_or(0.9))
+            }));
+            (bare.await.unwrap(), carried.await.unwrap())
+        })
+        .await;
+        assert_eq!(bare, 0.9);
+        assert_eq!(carried, (0.1, 0.5));
+        assert_eq!(active_sampling(), ChatSampling::default());
+    }
+}
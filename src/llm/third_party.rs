@@ -1,7 +1,7 @@
+use std::future::Future;
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
-use base64::{engine::general_purpose, Engine as _};
 use regex::Regex;
 use reqwest::StatusCode;
 use serde_json::{json, Value};
@@ -11,21 +11,27 @@ use crate::config::{ThirdPartyModelConfig, ThirdPartyProvider, CONFIG};
 use crate::llm::audit::{
     log_llm_request_started, record_llm_request_success, LlmAuditContext, LlmUsageRecord,
 };
-use crate::llm::media::{MediaFile, MediaKind};
+use crate::llm::media::{media_data_url, MediaFile, MediaKind};
+use crate::llm::provider_stats::track_provider_call;
 use crate::llm::responses_provider::{
     call_responses_provider, call_responses_provider_with_tool_runtime,
 };
 use crate::llm::runtime_models::{is_runtime_provider_ready, runtime_model_config};
+use crate::llm::sampling::{temperature_or, top_p_or};
 use crate::llm::tool_prompts::{tool_limit_guidance, TOOL_LIMIT_SYSTEM_PROMPT};
 use crate::llm::tool_runtime::ToolRuntime;
 use crate::llm::web_search::{self, web_search_tool};
 use crate::utils::http::get_http_client;
+use crate::utils::retry::{retry_async, ClassifiedError, RetryPolicy};
 
 const MAX_TOOL_CALL_ITERATIONS: usize = 3;
 const THIRD_PARTY_MAX_ATTEMPTS: usize = 3;
 const THIRD_PARTY_RETRY_BASE_DELAY_MS: u64 = 900;
-const OPENROUTER_REFERER: &str = "https://github.com/sailself/TelegramGroupHelperBot";
-const OPENROUTER_TITLE: &str = "TelegramGroupHelperBot";
+const TRUNCATION_NOTICE: &str = "[response truncated — ask to continue]";
+const CONTINUE_PROMPT: &str =
+    "Continue exactly where your previous reply stopped. Do not repeat anything you already wrote.";
+pub(crate) const OPENROUTER_REFERER: &str = "https://github.com/sailself/TelegramGroupHelperBot";
+pub(crate) const OPENROUTER_TITLE: &str = "TelegramGroupHelperBot";
 
 #[derive(Debug, Clone)]
 struct ProviderRuntimeConfig {
@@ -36,7 +42,10 @@ struct ProviderRuntimeConfig {
     temperature: f32,
     top_p: f32,
     top_k: Option<i32>,
+    max_tokens: Option<u32>,
+    stop: Vec<String>,
     request_timeout_secs: u64,
+    provider_preferences: Option<Value>,
 }
 
 #[derive(Debug, Clone)]
@@ -128,9 +137,12 @@ fn third_party_should_retry_status(status: StatusCode) -> bool {
         || status.is_server_error()
 }
 
-fn third_party_retry_delay(attempt: usize) -> Duration {
-    let attempt = attempt.max(1) as u64;
-    Duration::from_millis(THIRD_PARTY_RETRY_BASE_DELAY_MS.saturating_mul(attempt))
+fn third_party_retry_policy() -> RetryPolicy {
+    RetryPolicy::linear(
+        THIRD_PARTY_MAX_ATTEMPTS,
+        Duration::from_millis(THIRD_PARTY_RETRY_BASE_DELAY_MS),
+    )
+    .with_configured_jitter()
 }
 
 fn build_third_party_system_prompt(
@@ -290,17 +302,7 @@ fn build_message_content(user_content: &str, media_files: &[MediaFile]) -> Value
     }));
 
     for file in supported_media {
-        let fallback_mime_type = match file.kind {
-            MediaKind::Image => "image/png",
-            MediaKind::Video => "video/mp4",
-            MediaKind::Audio => "audio/mpeg",
-            MediaKind::Document => continue,
-        };
-        let mime_type = crate::llm::media::detect_mime_type(file.bytes())
-            .or_else(|| (!file.mime_type.trim().is_empty()).then(|| file.mime_type.clone()))
-            .unwrap_or_else(|| fallback_mime_type.to_string());
-        let encoded = general_purpose::STANDARD.encode(file.bytes());
-        let data_url = format!("data:{};base64,{}", mime_type, encoded);
+        let data_url = media_data_url(file.bytes(), file.kind, Some(&file.mime_type));
         match file.kind {
             MediaKind::Image => parts.push(json!({
                 "type": "image_url",
@@ -321,6 +323,26 @@ fn build_message_content(user_content: &str, media_files: &[MediaFile]) -> Value
     Value::Array(parts)
 }
 
+/// OpenRouter `provider` routing object built from `OPENROUTER_PROVIDER_ORDER`
+/// and `OPENROUTER_ALLOW_FALLBACKS`. `None` when neither is set, so OpenRouter
+/// keeps its default routing.
+fn openrouter_provider_preferences(
+    order: &[String],
+    allow_fallbacks: Option<bool>,
+) -> Option<Value> {
+    if order.is_empty() && allow_fallbacks.is_none() {
+        return None;
+    }
+    let mut preferences = serde_json::Map::new();
+    if !order.is_empty() {
+        preferences.insert("order".to_string(), json!(order));
+    }
+    if let Some(allow_fallbacks) = allow_fallbacks {
+        preferences.insert("allow_fallbacks".to_string(), json!(allow_fallbacks));
+    }
+    Some(Value::Object(preferences))
+}
+
 fn provider_runtime_config(provider: ThirdPartyProvider) -> Result<ProviderRuntimeConfig> {
     let config = match provider {
         ThirdPartyProvider::OpenRouter => ProviderRuntimeConfig {
@@ -331,7 +353,13 @@ fn provider_runtime_config(provider: ThirdPartyProvider) -> Result<ProviderRunti
             temperature: CONFIG.openrouter_temperature,
             top_p: CONFIG.openrouter_top_p,
             top_k: Some(CONFIG.openrouter_top_k),
+            max_tokens: CONFIG.openrouter_max_tokens,
+            stop: CONFIG.openrouter_stop.clone(),
             request_timeout_secs: CONFIG.openrouter_request_timeout_secs,
+            provider_preferences: openrouter_provider_preferences(
+                &CONFIG.openrouter_provider_order,
+                CONFIG.openrouter_allow_fallbacks,
+            ),
         },
         ThirdPartyProvider::Nvidia => ProviderRuntimeConfig {
             provider,
@@ -341,7 +369,10 @@ fn provider_runtime_config(provider: ThirdPartyProvider) -> Result<ProviderRunti
             temperature: CONFIG.nvidia_temperature,
             top_p: CONFIG.nvidia_top_p,
             top_k: None,
+            max_tokens: None,
+            stop: Vec::new(),
             request_timeout_secs: CONFIG.nvidia_request_timeout_secs,
+            provider_preferences: None,
         },
         ThirdPartyProvider::Ollama => ProviderRuntimeConfig {
             provider,
@@ -351,7 +382,10 @@ fn provider_runtime_config(provider: ThirdPartyProvider) -> Result<ProviderRunti
             temperature: CONFIG.ollama_temperature,
             top_p: CONFIG.ollama_top_p,
             top_k: None,
+            max_tokens: None,
+            stop: Vec::new(),
             request_timeout_secs: CONFIG.ollama_request_timeout_secs,
+            provider_preferences: None,
         },
         ThirdPartyProvider::OpenAI | ThirdPartyProvider::OpenAICodex => {
             return Err(anyhow!(
@@ -389,14 +423,30 @@ fn build_request_details_for_runtime(
     let mut payload = json!({
         "model": model_config.model,
         "messages": messages,
-        "temperature": runtime.temperature,
-        "top_p": runtime.top_p,
+        "temperature": temperature_or(runtime.temperature),
+        "top_p": top_p_or(runtime.top_p),
     });
 
     if let Some(top_k) = runtime.top_k {
         payload["top_k"] = json!(top_k);
     }
 
+    if let Some(max_tokens) = model_config.max_tokens.or(runtime.max_tokens) {
+        payload["max_tokens"] = json!(max_tokens);
+    }
+    let stop = if model_config.stop.is_empty() {
+        &runtime.stop
+    } else {
+        &model_config.stop
+    };
+    if !stop.is_empty() {
+        payload["stop"] = json!(stop);
+    }
+
+    if let Some(preferences) = &runtime.provider_preferences {
+        payload["provider"] = preferences.clone();
+    }
+
     if let Some(tools) = tools {
         payload["tools"] = Value::Array(tools);
         payload["tool_choice"] = Value::String(tool_choice.unwrap_or("auto").to_string());
@@ -459,26 +509,24 @@ async fn call_provider_api(
     );
 
     let client = get_http_client();
-    for attempt in 1..=THIRD_PARTY_MAX_ATTEMPTS {
-        let mut request = client
-            .post(&details.url)
-            .timeout(Duration::from_secs(details.request_timeout_secs));
-        for (name, value) in &details.headers {
-            request = request.header(name, value);
-        }
-        debug!(
-            "{} request timeout configured: model={}, timeout_secs={}, attempt={}/{}",
-            details.display_name,
-            model,
-            details.request_timeout_secs,
-            attempt,
-            THIRD_PARTY_MAX_ATTEMPTS
-        );
-        let response = match request.json(&details.payload).send().await {
-            Ok(response) => response,
-            Err(err) => {
-                let should_retry =
-                    third_party_should_retry_error(&err) && attempt < THIRD_PARTY_MAX_ATTEMPTS;
+    let details = &details;
+    let policy = third_party_retry_policy();
+    let request = retry_async(
+        &policy,
+        |attempt| async move {
+            let max_attempts = policy.max_attempts;
+            let mut request = client
+                .post(&details.url)
+                .timeout(Duration::from_secs(details.request_timeout_secs));
+            for (name, value) in &details.headers {
+                request = request.header(name, value);
+            }
+            debug!(
+                "{} request timeout configured: model={}, timeout_secs={}, attempt={}/{}",
+                details.display_name, model, details.request_timeout_secs, attempt, max_attempts
+            );
+            let response = request.json(&details.payload).send().await.map_err(|err| {
+                let should_retry = third_party_should_retry_error(&err);
                 warn!(
                     "{} request failed to send: {} (timeout={}, connect={}, status={:?}, attempt={}/{}, retrying={})",
                     details.display_name,
@@ -487,65 +535,65 @@ async fn call_provider_api(
                     err.is_connect(),
                     err.status(),
                     attempt,
-                    THIRD_PARTY_MAX_ATTEMPTS,
-                    should_retry
+                    max_attempts,
+                    should_retry && attempt < max_attempts
                 );
-                if should_retry {
-                    tokio::time::sleep(third_party_retry_delay(attempt)).await;
-                    continue;
-                }
-                return Err(anyhow!("{} request failed: {}", details.display_name, err));
-            }
-        };
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            let (message, body_summary) = summarize_error_body(&body);
-            let should_retry =
-                third_party_should_retry_status(status) && attempt < THIRD_PARTY_MAX_ATTEMPTS;
-            warn!(
-                "{} API error: status={}, body={}, attempt={}/{}, retrying={}",
-                details.display_name,
-                status,
-                body_summary,
-                attempt,
-                THIRD_PARTY_MAX_ATTEMPTS,
-                should_retry
-            );
-            if should_retry {
-                tokio::time::sleep(third_party_retry_delay(attempt)).await;
-                continue;
+                ClassifiedError::new(
+                    anyhow!("{} request failed: {}", details.display_name, err),
+                    should_retry,
+                )
+            })?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                let (message, body_summary) = summarize_error_body(&body);
+                let should_retry = third_party_should_retry_status(status);
+                warn!(
+                    "{} API error: status={}, body={}, attempt={}/{}, retrying={}",
+                    details.display_name,
+                    status,
+                    body_summary,
+                    attempt,
+                    max_attempts,
+                    should_retry && attempt < max_attempts
+                );
+                let detail = message.unwrap_or(body_summary);
+                return Err(ClassifiedError::new(
+                    anyhow!(
+                        "{} request failed with status {}: {}",
+                        details.display_name,
+                        status,
+                        detail
+                    ),
+                    should_retry,
+                ));
             }
-            let detail = message.unwrap_or(body_summary);
-            return Err(anyhow!(
-                "{} request failed with status {}: {}",
-                details.display_name,
-                status,
-                detail
-            ));
-        }
-
-        let value = response.json::<Value>().await?;
-        debug!(
-            "{} response received for model={}",
-            details.display_name, model
-        );
-        let usage = extract_openai_compatible_usage(&value);
-        record_llm_request_success(
-            audit_context,
-            details.display_name,
-            model,
-            operation,
-            started_at,
-            chrono::Utc::now(),
-            usage,
-        )
-        .await;
-        return Ok(value);
-    }
+            Ok(response)
+        },
+        |err, _| err.decision(),
+    );
+    let response = track_provider_call(details.display_name, request)
+        .await
+        .map_err(|err| err.error)?;
 
-    unreachable!("third-party provider retry loop exhausted")
+    let value = response.json::<Value>().await?;
+    debug!(
+        "{} response received for model={}",
+        details.display_name, model
+    );
+    let usage = extract_openai_compatible_usage(&value);
+    record_llm_request_success(
+        audit_context,
+        details.display_name,
+        model,
+        operation,
+        started_at,
+        chrono::Utc::now(),
+        usage,
+    )
+    .await;
+    Ok(value)
 }
 
 fn extract_response_message(response: &Value) -> Value {
@@ -557,6 +605,92 @@ fn extract_response_message(response: &Value) -> Value {
         .unwrap_or(Value::Null)
 }
 
+fn finish_reason(response: &Value) -> Option<&str> {
+    response
+        .pointer("/choices/0/finish_reason")
+        .and_then(Value::as_str)
+}
+
+/// Asks for up to `max_continuations` follow-ups while the reply keeps
+/// stopping at the output limit, feeding the text so far back as the
+/// assistant turn. Returns the joined reply and whether it is still cut off;
+/// a failed follow-up keeps what was already received.
+async fn continue_truncated_reply<F, Fut>(
+    messages: &[Value],
+    response: &Value,
+    max_continuations: usize,
+    mut request: F,
+) -> (String, bool)
+where
+    F: FnMut(Vec<Value>) -> Fut,
+    Fut: Future<Output = Result<Value>>,
+{
+    let mut content = extract_message_content(&extract_response_message(response));
+    let mut truncated = finish_reason(response) == Some("length");
+    for attempt in 1..=max_continuations {
+        if !truncated {
+            break;
+        }
+        let mut follow_up = messages.to_vec();
+        follow_up.push(json!({ "role": "assistant", "content": content }));
+        follow_up.push(json!({ "role": "user", "content": CONTINUE_PROMPT }));
+        let next = match request(follow_up).await {
+            Ok(next) => next,
+            Err(err) => {
+                warn!("Continuation {attempt} of a truncated reply failed: {err}");
+                break;
+            }
+        };
+        let piece = extract_response_message(&next)
+            .get("content")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        if piece.trim().is_empty() {
+            break;
+        }
+        content.push_str(&piece);
+        truncated = finish_reason(&next) == Some("length");
+    }
+    (content, truncated)
+}
+
+/// Final answer text for a chat-completions response without tool calls,
+/// continuing OpenRouter replies cut off by the output limit when
+/// `OPENROUTER_AUTO_CONTINUE_MAX` allows and flagging any that stay cut off.
+async fn finish_reply(
+    messages: &[Value],
+    response: &Value,
+    model_config: &ThirdPartyModelConfig,
+    audit_context: Option<&LlmAuditContext>,
+    operation: &str,
+) -> String {
+    let max_continuations = if model_config.provider == ThirdPartyProvider::OpenRouter {
+        CONFIG.openrouter_auto_continue_max
+    } else {
+        0
+    };
+    let (content, truncated) = continue_truncated_reply(
+        messages,
+        response,
+        max_continuations,
+        |messages| async move {
+            let details = build_request_details(model_config, messages, None, None)?;
+            call_provider_api(&details, audit_context, operation).await
+        },
+    )
+    .await;
+    let text = parse_third_party_response(model_config, content.trim());
+    if truncated {
+        warn!(
+            "{} reply stopped at the output limit ({operation})",
+            model_config.provider.as_str()
+        );
+        return format!("{}\n\n{TRUNCATION_NOTICE}", text.trim_end());
+    }
+    text
+}
+
 fn extract_tool_calls(message: &Value) -> Vec<Value> {
     message
         .get("tool_calls")
@@ -618,7 +752,7 @@ async fn request_final_answer_after_tool_limit(
         model_config.provider.as_str()
     );
 
-    let details = build_request_details(model_config, messages, None, None)?;
+    let details = build_request_details(model_config, messages.clone(), None, None)?;
     let response = call_provider_api(&details, audit_context, operation).await?;
     let message = extract_response_message(&response);
     let tool_calls = extract_tool_calls(&message);
@@ -639,7 +773,7 @@ async fn request_final_answer_after_tool_limit(
         );
     }
 
-    Ok(parse_third_party_response(model_config, &content))
+    Ok(finish_reply(&messages, &response, model_config, audit_context, operation).await)
 }
 
 async fn execute_function_tool(name: &str, arguments: &Value) -> Result<String> {
@@ -708,7 +842,9 @@ async fn chat_completion_with_tools(
                     truncate_for_log(&response.to_string(), 2000)
                 );
             }
-            return Ok(parse_third_party_response(model_config, &content));
+            return Ok(
+                finish_reply(&messages, &response, model_config, audit_context, operation).await,
+            );
         }
 
         messages.push(message.clone());
@@ -788,7 +924,9 @@ async fn chat_completion_with_tool_runtime(
                     truncate_for_log(&response.to_string(), 2000)
                 );
             }
-            return Ok(parse_third_party_response(model_config, &content));
+            return Ok(
+                finish_reply(&messages, &response, model_config, audit_context, operation).await,
+            );
         }
 
         messages.push(message.clone());
@@ -910,15 +1048,7 @@ pub async fn call_third_party_with_reasoning(
     audit_context: Option<&LlmAuditContext>,
     reasoning_override: Option<&str>,
 ) -> Result<String> {
-    if model_id.trim().is_empty() {
-        return Err(anyhow!("Model identifier is required"));
-    }
-
-    let model_config = CONFIG
-        .get_third_party_model_config(model_id)
-        .cloned()
-        .or_else(|| runtime_model_config(model_id))
-        .ok_or_else(|| anyhow!("Unknown third-party model '{}'", model_id))?;
+    let model_config = resolve_third_party_model_config(model_id)?;
     call_third_party_with_reasoning_config(
         system_prompt,
         user_content,
@@ -932,6 +1062,28 @@ pub async fn call_third_party_with_reasoning(
     .await
 }
 
+/// Looks `model_id` up in the configured models, then the runtime catalog.
+pub fn resolve_third_party_model_config(model_id: &str) -> Result<ThirdPartyModelConfig> {
+    if model_id.trim().is_empty() {
+        return Err(anyhow!("Model identifier is required"));
+    }
+    CONFIG
+        .get_third_party_model_config(model_id)
+        .cloned()
+        .or_else(|| runtime_model_config(model_id))
+        .ok_or_else(|| anyhow!("Unknown third-party model '{}'", model_id))
+}
+
+/// The `max_tokens` a chat-completions request for `model_config` carries:
+/// the per-model value, else the provider default. `None` sends no limit.
+pub fn configured_max_tokens(model_config: &ThirdPartyModelConfig) -> Option<u32> {
+    model_config.max_tokens.or_else(|| {
+        provider_runtime_config(model_config.provider)
+            .ok()
+            .and_then(|runtime| runtime.max_tokens)
+    })
+}
+
 /// Variant of [`call_third_party_with_reasoning`] that takes an already
 /// resolved model config, so callers can use synthesized configs that are not
 /// in the runtime catalog (e.g. a foreign Codex slug used as agent step model).
@@ -980,20 +1132,22 @@ pub async fn call_third_party_with_reasoning_config(
             .await;
     }
 
-    let details = build_request_details(&model_config, messages, None, None)?;
+    let details = build_request_details(&model_config, messages.clone(), None, None)?;
     let response = call_provider_api(&details, audit_context, &operation).await?;
-    let content = response
-        .get("choices")
-        .and_then(|v| v.get(0))
-        .and_then(|v| v.get("message"))
-        .map(extract_message_content)
-        .unwrap_or_default();
-    Ok(parse_third_party_response(&model_config, &content))
+    Ok(finish_reply(
+        &messages,
+        &response,
+        &model_config,
+        audit_context,
+        &operation,
+    )
+    .await)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use base64::{engine::general_purpose, Engine as _};
 
     fn model(provider: ThirdPartyProvider, name: &str, raw_model: &str) -> ThirdPartyModelConfig {
         ThirdPartyModelConfig {
@@ -1005,9 +1159,52 @@ mod tests {
             video: false,
             audio: false,
             tools: true,
+            max_tokens: None,
+            stop: Vec::new(),
         }
     }
 
+    #[tokio::test]
+    async fn chat_sampling_override_reaches_the_payload() {
+        use crate::llm::sampling::{with_chat_sampling, ChatSampling};
+
+        let runtime = ProviderRuntimeConfig {
+            provider: ThirdPartyProvider::OpenRouter,
+            display_name: "OpenRouter",
+            base_url: "https://openrouter.ai/api/v1".to_string(),
+            api_key: "test-openrouter".to_string(),
+            temperature: 0.7,
+            top_p: 0.95,
+            top_k: None,
+            max_tokens: None,
+            stop: Vec::new(),
+            request_timeout_secs: 75,
+            provider_preferences: None,
+        };
+        let build = || {
+            build_request_details_for_runtime(
+                &model(ThirdPartyProvider::OpenRouter, "Qwen 3", "qwen/qwen3"),
+                &runtime,
+                vec![json!({ "role": "user", "content": "hello" })],
+                None,
+                None,
+            )
+            .payload
+        };
+        let sampling = ChatSampling {
+            temperature: Some(0.2),
+            top_p: None,
+        };
+
+        let payload = with_chat_sampling(sampling, async { build() }).await;
+        assert_eq!(payload["temperature"], json!(0.2f32));
+        assert_eq!(payload["top_p"], json!(0.95f32));
+
+        let payload = with_chat_sampling(ChatSampling::default(), async { build() }).await;
+        assert_eq!(payload["temperature"], json!(0.7f32));
+        assert_eq!(build()["temperature"], json!(0.7f32));
+    }
+
     #[test]
     fn openrouter_request_details_keep_headers_and_top_k() {
         let runtime = ProviderRuntimeConfig {
@@ -1018,7 +1215,13 @@ mod tests {
             temperature: 0.7,
             top_p: 0.95,
             top_k: Some(40),
+            max_tokens: Some(1024),
+            stop: vec!["</answer>".to_string()],
             request_timeout_secs: 75,
+            provider_preferences: openrouter_provider_preferences(
+                &["deepinfra".to_string(), "together".to_string()],
+                Some(false),
+            ),
         };
         let details = build_request_details_for_runtime(
             &model(
@@ -1046,6 +1249,83 @@ mod tests {
             Some(40)
         );
         assert_eq!(details.request_timeout_secs, 75);
+        assert_eq!(
+            details.payload["provider"],
+            json!({ "order": ["deepinfra", "together"], "allow_fallbacks": false })
+        );
+        assert_eq!(details.payload["max_tokens"], json!(1024));
+        assert_eq!(details.payload["stop"], json!(["</answer>"]));
+
+        let mut capped = model(
+            ThirdPartyProvider::OpenRouter,
+            "Llama 4",
+            "meta-llama/llama-4",
+        );
+        capped.max_tokens = Some(256);
+        capped.stop = vec!["###".to_string()];
+        let details = build_request_details_for_runtime(
+            &capped,
+            &runtime,
+            vec![json!({ "role": "user", "content": "hello" })],
+            None,
+            None,
+        );
+        assert_eq!(details.payload["max_tokens"], json!(256));
+        assert_eq!(details.payload["stop"], json!(["###"]));
+    }
+
+    fn completion(content: &str, finish_reason: &str) -> Value {
+        json!({
+            "choices": [{
+                "message": { "role": "assistant", "content": content },
+                "finish_reason": finish_reason
+            }]
+        })
+    }
+
+    #[tokio::test]
+    async fn length_finish_reason_continues_then_flags_truncation() {
+        let messages = vec![json!({ "role": "user", "content": "write a long story" })];
+        let first = completion("Once upon a", "length");
+
+        let mut follow_ups = Vec::new();
+        let (content, truncated) =
+            continue_truncated_reply(&messages, &first, 2, |messages: Vec<Value>| {
+                follow_ups.push(messages);
+                async { Ok(completion(" time, the end.", "stop")) }
+            })
+            .await;
+        assert_eq!(content, "Once upon a time, the end.");
+        assert!(!truncated);
+        assert_eq!(follow_ups.len(), 1);
+        assert_eq!(follow_ups[0][1]["content"], "Once upon a");
+        assert_eq!(follow_ups[0][2]["content"], CONTINUE_PROMPT);
+
+        let (content, truncated) =
+            continue_truncated_reply(&messages, &first, 0, |_: Vec<Value>| async {
+                Ok(completion("unused", "stop"))
+            })
+            .await;
+        assert_eq!(content, "Once upon a");
+        assert!(truncated);
+
+        let (_, truncated) = continue_truncated_reply(
+            &messages,
+            &completion("Done.", "stop"),
+            2,
+            |_: Vec<Value>| async { Err(anyhow!("should not be called")) },
+        )
+        .await;
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn openrouter_provider_preferences_are_omitted_by_default() {
+        assert_eq!(openrouter_provider_preferences(&[], None), None);
+        assert_eq!(
+            openrouter_provider_preferences(&[], Some(true)),
+            Some(json!({ "allow_fallbacks": true }))
+        );
     }
 
     #[test]
@@ -1058,7 +1338,10 @@ mod tests {
             temperature: 0.4,
             top_p: 0.8,
             top_k: None,
+            max_tokens: None,
+            stop: Vec::new(),
             request_timeout_secs: 120,
+            provider_preferences: None,
         };
         let details = build_request_details_for_runtime(
             &model(
@@ -1081,6 +1364,8 @@ mod tests {
             .iter()
             .any(|(name, _)| name == "HTTP-Referer" || name == "X-Title"));
         assert!(details.payload.get("top_k").is_none());
+        assert!(details.payload.get("max_tokens").is_none());
+        assert!(details.payload.get("stop").is_none());
         assert_eq!(details.request_timeout_secs, 120);
     }
 
@@ -1107,6 +1392,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn message_content_uses_detected_image_mime_type() {
+        let jpeg = vec![
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00,
+        ];
+        let media = vec![
+            MediaFile::new(
+                jpeg.clone(),
+                "image/png".to_string(),
+                MediaKind::Image,
+                None,
+            ),
+            MediaFile::new(
+                b"not-a-known-format".to_vec(),
+                "application/octet-stream".to_string(),
+                MediaKind::Image,
+                None,
+            ),
+        ];
+
+        let content = build_message_content("what is this?", &media);
+        let parts = content.as_array().expect("content should be media parts");
+
+        assert_eq!(parts[1]["type"], "image_url");
+        assert_eq!(
+            parts[1]["image_url"]["url"],
+            format!(
+                "data:image/jpeg;base64,{}",
+                general_purpose::STANDARD.encode(&jpeg)
+            )
+        );
+        let fallback_url = parts[2]["image_url"]["url"]
+            .as_str()
+            .expect("image url should be a string");
+        assert!(fallback_url.starts_with("data:image/png;base64,"));
+    }
+
     #[test]
     fn message_content_includes_audio_url_parts_for_audio_media() {
         let media = vec![MediaFile::new(
@@ -1159,7 +1481,10 @@ mod tests {
             temperature: 0.3,
             top_p: 0.7,
             top_k: None,
+            max_tokens: None,
+            stop: Vec::new(),
             request_timeout_secs: 90,
+            provider_preferences: None,
         };
         let details = build_request_details_for_runtime(
             &model(ThirdPartyProvider::Ollama, "Qwen 3 32B", "qwen3:32b"),
@@ -1212,9 +1537,18 @@ mod tests {
 
     #[test]
     fn retry_delay_grows_by_attempt() {
-        assert_eq!(third_party_retry_delay(1), Duration::from_millis(900));
-        assert_eq!(third_party_retry_delay(2), Duration::from_millis(1800));
-        assert_eq!(third_party_retry_delay(3), Duration::from_millis(2700));
+        assert_eq!(
+            third_party_retry_policy().delay_for_attempt(1),
+            Duration::from_millis(900)
+        );
+        assert_eq!(
+            third_party_retry_policy().delay_for_attempt(2),
+            Duration::from_millis(1800)
+        );
+        assert_eq!(
+            third_party_retry_policy().delay_for_attempt(3),
+            Duration::from_millis(2700)
+        );
     }
 
     #[test]
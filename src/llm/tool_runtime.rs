@@ -1,13 +1,20 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
 
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, info};
 
 use crate::config::CONFIG;
 use crate::db::database::Database;
 use crate::db::models::{ChatSearchHit, MessageRow};
 use crate::db::search::SEARCH_INDEX_REBUILDING_ERROR;
+use crate::db::store::Store;
+use crate::llm::dictionary;
+use crate::llm::web_fetch::{self, FetchedPage};
 use crate::llm::web_search::{self, web_search_tool};
 use crate::utils::telegram::build_message_link;
 
@@ -32,6 +39,8 @@ pub struct ToolBudgetConfig {
     pub max_web_search_calls: usize,
     pub max_chat_context_query_calls: usize,
     pub max_chat_analytics_query_calls: usize,
+    pub max_dictionary_define_calls: usize,
+    pub max_web_fetch_calls: usize,
 }
 
 #[derive(Debug, Clone, Copy, Serialize)]
@@ -40,6 +49,8 @@ struct ToolBudgetSnapshot {
     web_search_remaining: usize,
     chat_context_query_remaining: usize,
     chat_analytics_query_remaining: usize,
+    dictionary_define_remaining: usize,
+    web_fetch_remaining: usize,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -48,6 +59,8 @@ enum ToolBudgetErrorKind {
     WebSearch,
     ChatContextQuery,
     ChatAnalytics,
+    DictionaryDefine,
+    WebFetch,
     Disabled,
 }
 
@@ -56,9 +69,10 @@ struct ToolBudgetError {
     kind: ToolBudgetErrorKind,
 }
 
+/// Generic over the [`Store`] its chat tools read from; SQLite by default.
 #[derive(Clone)]
-pub struct ToolRuntime {
-    db: Database,
+pub struct ToolRuntime<S: Store = Database> {
+    db: S,
     chat_id: i64,
     profile: ToolProfile,
     budget: ToolBudgetConfig,
@@ -66,6 +80,8 @@ pub struct ToolRuntime {
     web_search_calls: usize,
     chat_context_query_calls: usize,
     chat_analytics_query_calls: usize,
+    dictionary_define_calls: usize,
+    web_fetch_calls: usize,
     force_final_answer: bool,
     accumulated_hits: BTreeMap<i64, ChatSearchHit>,
     // Every message id surfaced to the model — search hits plus their context
@@ -73,6 +89,13 @@ pub struct ToolRuntime {
     returned_message_ids: BTreeSet<i64>,
     // Authoritative analytics results accumulated across tool calls (A3).
     analytics_results: Vec<Value>,
+    // How often each (tool, arguments hash) pair was requested, so a model
+    // stuck re-issuing the same call is stopped before the budget runs out.
+    call_repeats: HashMap<(String, u64), usize>,
+    max_identical_calls: usize,
+    // Receives one "Step N: ..." line per requested tool call.
+    progress: Option<UnboundedSender<String>>,
+    steps: usize,
 }
 
 #[derive(Debug, Deserialize)]
@@ -102,6 +125,8 @@ struct ToolMessage {
     asks_ai: bool,
     ai_command: Option<String>,
     is_synthetic_record: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forward_origin: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -120,8 +145,8 @@ struct ToolSearchHit {
     context_messages: Vec<ToolMessage>,
 }
 
-impl ToolRuntime {
-    pub fn for_qc(db: Database, chat_id: i64) -> Self {
+impl<S: Store> ToolRuntime<S> {
+    pub fn for_qc(db: S, chat_id: i64) -> Self {
         Self {
             db,
             chat_id,
@@ -131,19 +156,27 @@ impl ToolRuntime {
                 max_web_search_calls: 3,
                 max_chat_context_query_calls: 5,
                 max_chat_analytics_query_calls: 0,
+                max_dictionary_define_calls: 3,
+                max_web_fetch_calls: 3,
             },
             successful_calls: 0,
             web_search_calls: 0,
             chat_context_query_calls: 0,
             chat_analytics_query_calls: 0,
+            dictionary_define_calls: 0,
+            web_fetch_calls: 0,
             force_final_answer: false,
             accumulated_hits: BTreeMap::new(),
             returned_message_ids: BTreeSet::new(),
             analytics_results: Vec::new(),
+            call_repeats: HashMap::new(),
+            max_identical_calls: CONFIG.agent_max_identical_tool_calls,
+            progress: None,
+            steps: 0,
         }
     }
 
-    pub fn for_search(db: Database, chat_id: i64) -> Self {
+    pub fn for_search(db: S, chat_id: i64) -> Self {
         Self {
             db,
             chat_id,
@@ -153,19 +186,27 @@ impl ToolRuntime {
                 max_web_search_calls: 0,
                 max_chat_context_query_calls: 5,
                 max_chat_analytics_query_calls: 0,
+                max_dictionary_define_calls: 0,
+                max_web_fetch_calls: 0,
             },
             successful_calls: 0,
             web_search_calls: 0,
             chat_context_query_calls: 0,
             chat_analytics_query_calls: 0,
+            dictionary_define_calls: 0,
+            web_fetch_calls: 0,
             force_final_answer: false,
             accumulated_hits: BTreeMap::new(),
             returned_message_ids: BTreeSet::new(),
             analytics_results: Vec::new(),
+            call_repeats: HashMap::new(),
+            max_identical_calls: CONFIG.agent_max_identical_tool_calls,
+            progress: None,
+            steps: 0,
         }
     }
 
-    pub fn for_analytics(db: Database, chat_id: i64) -> Self {
+    pub fn for_analytics(db: S, chat_id: i64) -> Self {
         Self {
             db,
             chat_id,
@@ -175,18 +216,33 @@ impl ToolRuntime {
                 max_web_search_calls: 0,
                 max_chat_context_query_calls: 1,
                 max_chat_analytics_query_calls: CONFIG.qc_analytics_max_query_calls,
+                max_dictionary_define_calls: 0,
+                max_web_fetch_calls: 0,
             },
             successful_calls: 0,
             web_search_calls: 0,
             chat_context_query_calls: 0,
             chat_analytics_query_calls: 0,
+            dictionary_define_calls: 0,
+            web_fetch_calls: 0,
             force_final_answer: false,
             accumulated_hits: BTreeMap::new(),
             returned_message_ids: BTreeSet::new(),
             analytics_results: Vec::new(),
+            call_repeats: HashMap::new(),
+            max_identical_calls: CONFIG.agent_max_identical_tool_calls,
+            progress: None,
+            steps: 0,
         }
     }
 
+    /// Reports each requested tool call as a step on `progress`. Only the tool
+    /// is named; arguments (queries, user text) never reach the chat.
+    pub fn with_progress(mut self, progress: UnboundedSender<String>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
     pub fn analytics_results(&self) -> &[Value] {
         &self.analytics_results
     }
@@ -203,10 +259,33 @@ impl ToolRuntime {
         self.profile == ToolProfile::ChatQuestion
     }
 
+    fn allows_dictionary(&self) -> bool {
+        self.budget.max_dictionary_define_calls > 0 && dictionary::is_dictionary_enabled()
+    }
+
+    fn allows_web_fetch(&self) -> bool {
+        self.budget.max_web_fetch_calls > 0 && web_fetch::is_web_fetch_enabled()
+    }
+
     pub fn tool_limit_guidance(&self) -> String {
         match self.profile {
             ToolProfile::ChatQuestion => {
-                "Tool budgets for this request: use web_search at most 3 times and chat_context_query at most 5 times. Once a budget is exhausted, answer with the evidence you already have.".to_string()
+                let mut guidance = "Tool budgets for this request: use web_search at most 3 times and chat_context_query at most 5 times.".to_string();
+                if self.allows_dictionary() {
+                    guidance.push_str(&format!(
+                        " Use dictionary_define at most {} times, only to check what a word means.",
+                        self.budget.max_dictionary_define_calls
+                    ));
+                }
+                if self.allows_web_fetch() {
+                    guidance.push_str(&format!(
+                        " Use web_fetch at most {} times, only for https pages on {}.",
+                        self.budget.max_web_fetch_calls,
+                        CONFIG.web_fetch_allowed_domains.join(", ")
+                    ));
+                }
+                guidance.push_str(" Once a budget is exhausted, answer with the evidence you already have.");
+                guidance
             }
             ToolProfile::ChatSearch => {
                 "Tool budgets for this request: use chat_context_query at most 5 times total. Search is keyword-based FTS, not semantic, so inspect snippets carefully and refine your query if needed.".to_string()
@@ -248,6 +327,28 @@ impl ToolRuntime {
             }));
         }
 
+        if self.allows_web_fetch() {
+            tools.push(json!({
+                "type": "function",
+                "function": {
+                    "name": "web_fetch",
+                    "description": web_fetch_description(),
+                    "parameters": web_fetch_schema()
+                }
+            }));
+        }
+
+        if self.allows_dictionary() {
+            tools.push(json!({
+                "type": "function",
+                "function": {
+                    "name": "dictionary_define",
+                    "description": DICTIONARY_DEFINE_DESCRIPTION,
+                    "parameters": dictionary_define_schema()
+                }
+            }));
+        }
+
         if self.profile == ToolProfile::ChatAnalytics {
             tools.push(json!({
                 "type": "function",
@@ -335,6 +436,22 @@ impl ToolRuntime {
             }));
         }
 
+        if self.allows_web_fetch() {
+            declarations.push(json!({
+                "name": "web_fetch",
+                "description": web_fetch_description(),
+                "parameters": web_fetch_schema()
+            }));
+        }
+
+        if self.allows_dictionary() {
+            declarations.push(json!({
+                "name": "dictionary_define",
+                "description": DICTIONARY_DEFINE_DESCRIPTION,
+                "parameters": dictionary_define_schema()
+            }));
+        }
+
         if self.profile == ToolProfile::ChatAnalytics {
             declarations.push(json!({
                 "name": "chat_analytics",
@@ -440,7 +557,26 @@ impl ToolRuntime {
         web_search_tool(query, Some(max_results.clamp(1, MAX_WEB_RESULTS))).await
     }
 
+    /// Runs a model-requested tool and returns the payload sent back to the
+    /// model, capped at `AGENT_TOOL_RESULT_MAX_CHARS` so oversized results are
+    /// not re-sent in full on every loop iteration.
     pub async fn execute_tool(&mut self, name: &str, arguments: &Value) -> String {
+        self.report_step(name);
+        if let Some(repeats) = self.register_call(name, arguments) {
+            return self.error_payload(
+                name,
+                "repeated_tool_call",
+                &format!(
+                    "This exact {name} call was already made {} time(s) with identical arguments. Do not repeat it; answer using the evidence already gathered.",
+                    repeats - 1
+                ),
+            );
+        }
+        let result = self.execute_tool_uncapped(name, arguments).await;
+        cap_tool_result(name, result, CONFIG.agent_tool_result_max_chars)
+    }
+
+    async fn execute_tool_uncapped(&mut self, name: &str, arguments: &Value) -> String {
         match name {
             "web_search" => match self.begin_tool_call(ToolName::WebSearch) {
                 Ok(()) => self.execute_web_search(arguments).await,
@@ -454,6 +590,19 @@ impl ToolRuntime {
                 Ok(()) => self.execute_analytics(arguments).await,
                 Err(err) => self.tool_budget_error_payload("chat_analytics", err),
             },
+            // The URL is checked before the call is counted or any request is
+            // made, so a rejected URL never reaches the network.
+            "web_fetch" => match self.validate_web_fetch_arguments(arguments) {
+                Ok(url) => match self.begin_tool_call(ToolName::WebFetch) {
+                    Ok(()) => self.execute_web_fetch(url).await,
+                    Err(err) => self.tool_budget_error_payload("web_fetch", err),
+                },
+                Err(payload) => payload,
+            },
+            "dictionary_define" => match self.begin_tool_call(ToolName::DictionaryDefine) {
+                Ok(()) => self.execute_dictionary_define(arguments).await,
+                Err(err) => self.tool_budget_error_payload("dictionary_define", err),
+            },
             _ => {
                 self.force_final_answer = true;
                 self.error_payload(
@@ -465,6 +614,43 @@ impl ToolRuntime {
         }
     }
 
+    fn report_step(&mut self, name: &str) {
+        self.steps += 1;
+        if let Some(progress) = &self.progress {
+            // The receiver goes away once the answer is being delivered.
+            let _ = progress.send(tool_step_text(self.steps, name));
+        }
+    }
+
+    /// Counts the call and returns the repeat count once the same tool and
+    /// arguments exceed `AGENT_MAX_IDENTICAL_TOOL_CALLS`; the runtime then
+    /// forces a final answer.
+    fn register_call(&mut self, name: &str, arguments: &Value) -> Option<usize> {
+        if self.max_identical_calls == 0 {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        arguments.to_string().hash(&mut hasher);
+        let key = (name.to_string(), hasher.finish());
+        let repeats = self.call_repeats.entry(key).or_insert(0);
+        *repeats += 1;
+        if *repeats <= self.max_identical_calls {
+            return None;
+        }
+
+        let repeats = *repeats;
+        self.force_final_answer = true;
+        info!(
+            target: "bot.timing",
+            "event=agent_tool_loop_detected chat_id={} tool={} repeats={} arguments={}",
+            self.chat_id,
+            name,
+            repeats,
+            arguments
+        );
+        Some(repeats)
+    }
+
     fn begin_tool_call(&mut self, tool: ToolName) -> std::result::Result<(), ToolBudgetError> {
         if self.force_final_answer {
             return Err(ToolBudgetError {
@@ -512,6 +698,36 @@ impl ToolRuntime {
                 }
                 self.chat_analytics_query_calls += 1;
             }
+            ToolName::DictionaryDefine => {
+                if !dictionary::is_dictionary_enabled() {
+                    self.force_final_answer = true;
+                    return Err(ToolBudgetError {
+                        kind: ToolBudgetErrorKind::Disabled,
+                    });
+                }
+                if self.dictionary_define_calls >= self.budget.max_dictionary_define_calls {
+                    self.force_final_answer = true;
+                    return Err(ToolBudgetError {
+                        kind: ToolBudgetErrorKind::DictionaryDefine,
+                    });
+                }
+                self.dictionary_define_calls += 1;
+            }
+            ToolName::WebFetch => {
+                if !web_fetch::is_web_fetch_enabled() {
+                    self.force_final_answer = true;
+                    return Err(ToolBudgetError {
+                        kind: ToolBudgetErrorKind::Disabled,
+                    });
+                }
+                if self.web_fetch_calls >= self.budget.max_web_fetch_calls {
+                    self.force_final_answer = true;
+                    return Err(ToolBudgetError {
+                        kind: ToolBudgetErrorKind::WebFetch,
+                    });
+                }
+                self.web_fetch_calls += 1;
+            }
         }
 
         self.successful_calls += 1;
@@ -536,6 +752,14 @@ impl ToolRuntime {
                 .budget
                 .max_chat_analytics_query_calls
                 .saturating_sub(self.chat_analytics_query_calls),
+            dictionary_define_remaining: self
+                .budget
+                .max_dictionary_define_calls
+                .saturating_sub(self.dictionary_define_calls),
+            web_fetch_remaining: self
+                .budget
+                .max_web_fetch_calls
+                .saturating_sub(self.web_fetch_calls),
         }
     }
 
@@ -573,6 +797,62 @@ impl ToolRuntime {
         }
     }
 
+    fn validate_web_fetch_arguments(
+        &self,
+        arguments: &Value,
+    ) -> std::result::Result<reqwest::Url, String> {
+        let url = arguments.get("url").and_then(Value::as_str).unwrap_or("");
+        web_fetch::validate_fetch_url(url, &CONFIG.web_fetch_allowed_domains)
+            .map_err(|err| self.error_payload("web_fetch", err.code(), &err.to_string()))
+    }
+
+    async fn execute_web_fetch(&self, url: reqwest::Url) -> String {
+        match web_fetch::fetch_page(url).await {
+            Ok(FetchedPage {
+                url,
+                content_type,
+                text,
+                truncated,
+            }) => self.success_payload(
+                "web_fetch",
+                json!({
+                    "url": url,
+                    "content_type": content_type,
+                    "truncated": truncated,
+                    "text": text,
+                }),
+            ),
+            Err(err) => self.error_payload("web_fetch", "tool_execution_failed", &err.to_string()),
+        }
+    }
+
+    async fn execute_dictionary_define(&self, arguments: &Value) -> String {
+        let word = arguments.get("word").and_then(Value::as_str).unwrap_or("");
+        let Some(word) = dictionary::normalize_word(word) else {
+            return self.error_payload(
+                "dictionary_define",
+                "invalid_arguments",
+                "The dictionary_define tool requires a single word or short phrase of letters, spaces, hyphens or apostrophes.",
+            );
+        };
+
+        match dictionary::define_word(&word).await {
+            Ok(entries) => self.success_payload(
+                "dictionary_define",
+                json!({
+                    "word": word,
+                    "found": !entries.is_empty(),
+                    "entries": entries,
+                }),
+            ),
+            Err(err) => self.error_payload(
+                "dictionary_define",
+                "tool_execution_failed",
+                &err.to_string(),
+            ),
+        }
+    }
+
     async fn execute_chat_context_query(&mut self, arguments: &Value) -> String {
         let args: ChatContextQueryArgs = match serde_json::from_value(arguments.clone()) {
             Ok(args) => args,
@@ -831,6 +1111,32 @@ impl ToolRuntime {
     }
 }
 
+fn tool_step_text(step: usize, tool: &str) -> String {
+    let action = match tool {
+        "web_search" => "searching the web",
+        "chat_context_query" => "searching chat history",
+        "chat_analytics" => "running chat analytics",
+        "dictionary_define" => "looking up a definition",
+        "web_fetch" => "reading a web page",
+        _ => "running a tool",
+    };
+    format!("Step {step}: {action}...")
+}
+
+fn cap_tool_result(tool: &str, result: String, max_chars: usize) -> String {
+    let total_chars = result.chars().count();
+    if max_chars == 0 || total_chars <= max_chars {
+        return result;
+    }
+    let omitted = total_chars - max_chars;
+    debug!(
+        "Truncating {tool} tool result from {total_chars} to {max_chars} chars before returning it to the model"
+    );
+    let mut capped: String = result.chars().take(max_chars).collect();
+    capped.push_str(&format!("\n[truncated {omitted} chars]"));
+    capped
+}
+
 fn tool_budget_error_parts(error: ToolBudgetError) -> (&'static str, &'static str) {
     match error.kind {
         ToolBudgetErrorKind::Total => (
@@ -849,6 +1155,14 @@ fn tool_budget_error_parts(error: ToolBudgetError) -> (&'static str, &'static st
             "chat_analytics_budget_exhausted",
             "The chat_analytics budget for this request is exhausted. Answer using the results already gathered.",
         ),
+        ToolBudgetErrorKind::DictionaryDefine => (
+            "dictionary_define_budget_exhausted",
+            "The dictionary_define budget for this request is exhausted. Answer using the definitions already gathered.",
+        ),
+        ToolBudgetErrorKind::WebFetch => (
+            "web_fetch_budget_exhausted",
+            "The web_fetch budget for this request is exhausted. Answer using the pages already read.",
+        ),
         ToolBudgetErrorKind::Disabled => (
             "tool_disabled",
             "This tool is unavailable for the current request. Answer using the evidence already gathered.",
@@ -861,6 +1175,44 @@ enum ToolName {
     WebSearch,
     ChatContextQuery,
     ChatAnalytics,
+    DictionaryDefine,
+    WebFetch,
+}
+
+fn web_fetch_description() -> String {
+    format!(
+        "Read one https web page and return its text, truncated to {} bytes. Only these domains and their subdomains are allowed: {}. Read-only.",
+        CONFIG.web_fetch_max_bytes,
+        CONFIG.web_fetch_allowed_domains.join(", ")
+    )
+}
+
+fn web_fetch_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "url": {
+                "type": "string",
+                "description": "Absolute https URL on an allowed domain."
+            }
+        },
+        "required": ["url"]
+    })
+}
+
+const DICTIONARY_DEFINE_DESCRIPTION: &str = "Look up dictionary definitions, parts of speech and example sentences for an English word. Read-only; use it only when the meaning of a word matters to the answer.";
+
+fn dictionary_define_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "word": {
+                "type": "string",
+                "description": "The English word or short phrase to define."
+            }
+        },
+        "required": ["word"]
+    })
 }
 
 fn message_row_to_tool_message(row: MessageRow) -> ToolMessage {
@@ -873,6 +1225,7 @@ fn message_row_to_tool_message(row: MessageRow) -> ToolMessage {
         asks_ai: row.asks_ai,
         ai_command: row.ai_command,
         is_synthetic_record: row.is_synthetic_record,
+        forward_origin: row.forward_origin,
     }
 }
 
@@ -1030,6 +1383,90 @@ mod tests {
         });
     }
 
+    fn function_tool<'a>(tools: &'a [Value], name: &str) -> Option<&'a Value> {
+        tools
+            .iter()
+            .find(|tool| tool["function"]["name"].as_str() == Some(name))
+    }
+
+    #[test]
+    fn dictionary_define_is_offered_to_qc_only_with_a_word_schema() {
+        let runtime = Runtime::new().expect("tokio runtime should initialize");
+        runtime.block_on(async {
+            let db = init_test_db("dictionary-tool-shape").await;
+            let qc = ToolRuntime::for_qc(db.clone(), -1001374348669);
+            let tools = qc.build_openai_function_tools();
+            let tool = function_tool(&tools, "dictionary_define")
+                .expect("qc should offer dictionary_define");
+            let parameters = &tool["function"]["parameters"];
+            assert_eq!(parameters["type"], "object");
+            assert_eq!(parameters["properties"]["word"]["type"], "string");
+            assert_eq!(parameters["required"], json!(["word"]));
+            let gemini = qc.build_gemini_tools();
+            assert!(gemini[0]["functionDeclarations"]
+                .as_array()
+                .expect("declarations should be an array")
+                .iter()
+                .any(|declaration| declaration["name"] == "dictionary_define"));
+            assert!(qc.tool_limit_guidance().contains("dictionary_define"));
+
+            let search = ToolRuntime::for_search(db.clone(), -1001374348669);
+            assert!(
+                function_tool(&search.build_openai_function_tools(), "dictionary_define").is_none()
+            );
+
+            // The search profile has no dictionary budget, so a requested call is
+            // rejected before any network access happens.
+            let mut search = ToolRuntime::for_search(db, -1001374348669);
+            let payload: Value = serde_json::from_str(
+                &search
+                    .execute_tool("dictionary_define", &json!({ "word": "hello" }))
+                    .await,
+            )
+            .expect("payload should be json");
+            assert_eq!(payload["error_code"], "dictionary_define_budget_exhausted");
+        });
+    }
+
+    #[test]
+    fn web_fetch_rejects_urls_before_counting_the_call() {
+        let runtime = Runtime::new().expect("tokio runtime should initialize");
+        runtime.block_on(async {
+            let db = init_test_db("web-fetch-validation").await;
+            let mut qc = ToolRuntime::for_qc(db, -1001374348669);
+            for (arguments, code) in [
+                (json!({}), "invalid_url"),
+                (json!({ "url": "http://example.com/" }), "invalid_url"),
+                (
+                    json!({ "url": "https://not-allowlisted.invalid/" }),
+                    "domain_not_allowed",
+                ),
+            ] {
+                let payload: Value =
+                    serde_json::from_str(&qc.execute_tool("web_fetch", &arguments).await)
+                        .expect("payload should be json");
+                assert_eq!(payload["error_code"], code);
+            }
+            assert_eq!(qc.web_fetch_calls, 0);
+            assert_eq!(qc.successful_calls, 0);
+        });
+    }
+
+    #[test]
+    fn dictionary_define_rejects_non_word_arguments() {
+        let runtime = Runtime::new().expect("tokio runtime should initialize");
+        runtime.block_on(async {
+            let db = init_test_db("dictionary-tool-args").await;
+            let mut qc = ToolRuntime::for_qc(db, -1001374348669);
+            for arguments in [json!({}), json!({ "word": "../../etc/passwd" })] {
+                let payload: Value =
+                    serde_json::from_str(&qc.execute_tool("dictionary_define", &arguments).await)
+                        .expect("payload should be json");
+                assert_eq!(payload["error_code"], "invalid_arguments");
+            }
+        });
+    }
+
     #[test]
     fn programmatic_search_errors_once_budget_is_exhausted() {
         let runtime = Runtime::new().expect("tokio runtime should initialize");
@@ -1044,6 +1481,93 @@ mod tests {
         });
     }
 
+    #[test]
+    fn identical_tool_calls_stop_a_looping_model_early() {
+        let runtime = Runtime::new().expect("tokio runtime should initialize");
+        runtime.block_on(async {
+            let db = init_test_db("tool-loop").await;
+            let chat_id = -1001374348669_i64;
+            insert_test_message(&db, 31, chat_id, "loop detection sample").await;
+
+            let mut tool_runtime = ToolRuntime::for_qc(db, chat_id);
+            tool_runtime.max_identical_calls = 2;
+            let looping_call = json!({ "operation": "search", "query": "loop" });
+
+            // Mock model: keeps asking for the same search until the runtime
+            // tells it to stop.
+            let mut payloads = Vec::new();
+            for _ in 0..tool_runtime.max_total_successful_calls() {
+                payloads.push(
+                    tool_runtime
+                        .execute_tool("chat_context_query", &looping_call)
+                        .await,
+                );
+                if tool_runtime.force_final_answer() {
+                    break;
+                }
+            }
+
+            assert_eq!(payloads.len(), 3);
+            assert_eq!(tool_runtime.chat_context_query_calls, 2);
+            let last: Value = serde_json::from_str(payloads.last().expect("payload"))
+                .expect("payload should be JSON");
+            assert_eq!(last["error_code"], "repeated_tool_call");
+
+            // A different query is still refused once the loop has been flagged.
+            let other = tool_runtime
+                .execute_tool(
+                    "chat_context_query",
+                    &json!({ "operation": "search", "query": "x" }),
+                )
+                .await;
+            assert!(other.contains("tool_disabled"));
+        });
+    }
+
+    #[test]
+    fn progress_reports_one_redacted_step_per_tool_call() {
+        let runtime = Runtime::new().expect("tokio runtime should initialize");
+        runtime.block_on(async {
+            let db = init_test_db("tool-progress").await;
+            let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+            let mut tool_runtime =
+                ToolRuntime::for_search(db, -1001374348669).with_progress(sender);
+
+            for query in ["secret plans", "more secrets"] {
+                tool_runtime
+                    .execute_tool(
+                        "chat_context_query",
+                        &json!({ "operation": "search", "query": query }),
+                    )
+                    .await;
+            }
+            tool_runtime.execute_tool("unknown_tool", &json!({})).await;
+
+            let mut steps = Vec::new();
+            while let Ok(step) = receiver.try_recv() {
+                steps.push(step);
+            }
+            assert_eq!(
+                steps,
+                vec![
+                    "Step 1: searching chat history...",
+                    "Step 2: searching chat history...",
+                    "Step 3: running a tool...",
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn cap_tool_result_truncates_with_marker() {
+        let result = "é".repeat(30);
+        assert_eq!(cap_tool_result("web_search", result.clone(), 30), result);
+        assert_eq!(cap_tool_result("web_search", result.clone(), 0), result);
+
+        let capped = cap_tool_result("web_search", result, 10);
+        assert_eq!(capped, format!("{}\n[truncated 20 chars]", "é".repeat(10)));
+    }
+
     #[test]
     fn qc_budget_stops_after_expected_counts() {
         let runtime = Runtime::new().expect("tokio runtime should initialize");
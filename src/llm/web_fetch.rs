@@ -0,0 +1,309 @@
+//! Allowlisted page reads for the `web_fetch` agent tool.
+//!
+//! Only `https` URLs whose host is on `WEB_FETCH_ALLOWED_DOMAINS` (or a
+//! subdomain of one) are fetched, redirects included. Bodies are read up to
+//! `WEB_FETCH_MAX_BYTES` within `WEB_FETCH_TIMEOUT_SECS`, and HTML is reduced to
+//! readable text before it reaches the model.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{redirect, Client, Response, Url};
+use thiserror::Error;
+use tracing::info;
+
+use crate::config::CONFIG;
+use crate::llm::provider_stats::track_provider_call;
+use crate::utils::http::{
+    build_http_client_with_redirect_policy, host_matches_domain, HttpClientSettings,
+};
+
+const MAX_REDIRECTS: usize = 5;
+
+static DROPPED_ELEMENT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<!--.*?-->|<(script|style|noscript|template|svg|head)\b.*?</(script|style|noscript|template|svg|head)\s*>")
+        .expect("valid dropped element regex")
+});
+static BLOCK_BREAK_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)<br\s*/?>|</?(p|div|li|ul|ol|tr|table|section|article|h[1-6]|blockquote|pre)\b[^>]*>",
+    )
+    .expect("valid block break regex")
+});
+static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<[^>]*>").expect("valid tag regex"));
+
+static FETCH_CLIENT: Lazy<Client> = Lazy::new(|| {
+    let policy = redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if let Err(err) =
+            validate_fetch_url(attempt.url().as_str(), &CONFIG.web_fetch_allowed_domains)
+        {
+            attempt.error(err)
+        } else {
+            attempt.follow()
+        }
+    });
+    build_http_client_with_redirect_policy(&HttpClientSettings::from_config(), policy)
+        .expect("Failed to build web fetch HTTP client")
+});
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FetchUrlError {
+    #[error("url is not a valid absolute URL")]
+    Invalid,
+    #[error("only https URLs on the default port can be fetched")]
+    NotHttps,
+    #[error("{0} is not on the web_fetch domain allowlist")]
+    DomainNotAllowed(String),
+}
+
+impl FetchUrlError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Invalid | Self::NotHttps => "invalid_url",
+            Self::DomainNotAllowed(_) => "domain_not_allowed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedPage {
+    pub url: String,
+    pub content_type: String,
+    pub text: String,
+    pub truncated: bool,
+}
+
+pub fn is_web_fetch_enabled() -> bool {
+    !CONFIG.web_fetch_allowed_domains.is_empty()
+}
+
+/// Checks `raw` against the fetch rules before any request is made: `https`
+/// on the default port, no credentials, and a host on `allowed_domains`.
+pub fn validate_fetch_url(raw: &str, allowed_domains: &[String]) -> Result<Url, FetchUrlError> {
+    let url = Url::parse(raw.trim()).map_err(|_| FetchUrlError::Invalid)?;
+    if url.scheme() != "https" || url.port().is_some_and(|port| port != 443) {
+        return Err(FetchUrlError::NotHttps);
+    }
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err(FetchUrlError::Invalid);
+    }
+    let host = url
+        .host_str()
+        .map(|host| host.trim_end_matches('.').to_lowercase())
+        .filter(|host| !host.is_empty())
+        .ok_or(FetchUrlError::Invalid)?;
+    if !allowed_domains
+        .iter()
+        .any(|domain| host_matches_domain(&host, domain.trim_start_matches('.')))
+    {
+        return Err(FetchUrlError::DomainNotAllowed(host));
+    }
+    Ok(url)
+}
+
+fn is_textual_content_type(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    mime.is_empty()
+        || mime.starts_with("text/")
+        || mime == "application/json"
+        || mime == "application/xml"
+        || mime == "application/xhtml+xml"
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Reduces an HTML document to its visible text, one block per line.
+pub fn html_to_text(html: &str) -> String {
+    let without_hidden = DROPPED_ELEMENT_RE.replace_all(html, " ");
+    let with_breaks = BLOCK_BREAK_RE.replace_all(&without_hidden, "\n");
+    let text = decode_entities(&TAG_RE.replace_all(&with_breaks, " "));
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reads at most `max_bytes` of the body. Returns whether anything was cut.
+async fn read_capped_body(mut response: Response, max_bytes: usize) -> Result<(Vec<u8>, bool)> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        let remaining = max_bytes - body.len();
+        if chunk.len() > remaining {
+            body.extend_from_slice(&chunk[..remaining]);
+            return Ok((body, true));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((body, false))
+}
+
+/// Fetches an already validated URL and returns its readable text.
+pub async fn fetch_page(url: Url) -> Result<FetchedPage> {
+    info!("Fetching page for web_fetch tool: {}", url);
+    track_provider_call("web_fetch", async {
+        let response = FETCH_CLIENT
+            .get(url)
+            .timeout(Duration::from_secs(CONFIG.web_fetch_timeout_secs))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Fetch failed with status {}", response.status()));
+        }
+        let final_url = response.url().to_string();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if !is_textual_content_type(&content_type) {
+            return Err(anyhow!("Unsupported content type {content_type}"));
+        }
+
+        let (body, truncated) = read_capped_body(response, CONFIG.web_fetch_max_bytes).await?;
+        let body = String::from_utf8_lossy(&body);
+        let text = if content_type.to_lowercase().contains("html") {
+            html_to_text(&body)
+        } else {
+            body.trim().to_string()
+        };
+        Ok(FetchedPage {
+            url: final_url,
+            content_type,
+            text,
+            truncated,
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::http::build_http_client;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn allowlist() -> Vec<String> {
+        vec!["example.com".to_string(), "docs.rs".to_string()]
+    }
+
+    #[test]
+    fn validate_fetch_url_enforces_https_and_the_allowlist() {
+        let allowed = allowlist();
+        assert!(validate_fetch_url("https://example.com/page", &allowed).is_ok());
+        assert!(validate_fetch_url("https://www.Example.com./a?b=c", &allowed).is_ok());
+        assert!(validate_fetch_url("https://docs.rs:443/regex", &allowed).is_ok());
+
+        assert_eq!(
+            validate_fetch_url("http://example.com/", &allowed),
+            Err(FetchUrlError::NotHttps)
+        );
+        assert_eq!(
+            validate_fetch_url("https://example.com:8443/", &allowed),
+            Err(FetchUrlError::NotHttps)
+        );
+        assert_eq!(
+            validate_fetch_url("file:///etc/passwd", &allowed),
+            Err(FetchUrlError::NotHttps)
+        );
+        assert_eq!(
+            validate_fetch_url("https://user:pw@example.com/", &allowed),
+            Err(FetchUrlError::Invalid)
+        );
+        assert_eq!(
+            validate_fetch_url("not a url", &allowed),
+            Err(FetchUrlError::Invalid)
+        );
+        assert_eq!(
+            validate_fetch_url("https://evilexample.com/", &allowed),
+            Err(FetchUrlError::DomainNotAllowed(
+                "evilexample.com".to_string()
+            ))
+        );
+        assert_eq!(
+            validate_fetch_url("https://example.com.attacker.net/", &allowed),
+            Err(FetchUrlError::DomainNotAllowed(
+                "example.com.attacker.net".to_string()
+            ))
+        );
+        assert_eq!(
+            validate_fetch_url("https://127.0.0.1/", &allowed).map_err(|err| err.code()),
+            Err("domain_not_allowed")
+        );
+        assert!(validate_fetch_url("https://example.com/", &[]).is_err());
+    }
+
+    #[test]
+    fn html_to_text_keeps_visible_text_only() {
+        let html = "<html><head><title>T</title><style>p{}</style></head><body>\
+            <script>alert(1)</script><h1>Title</h1><p>Fish &amp; chips<br>daily</p>\
+            <!-- hidden --><ul><li>one</li><li>two</li></ul></body></html>";
+        assert_eq!(html_to_text(html), "Title\nFish & chips\ndaily\none\ntwo");
+    }
+
+    #[test]
+    fn only_textual_content_types_are_read() {
+        assert!(is_textual_content_type("text/html; charset=utf-8"));
+        assert!(is_textual_content_type("application/ld+json"));
+        assert!(is_textual_content_type(""));
+        assert!(!is_textual_content_type("image/png"));
+        assert!(!is_textual_content_type("application/octet-stream"));
+    }
+
+    #[tokio::test]
+    async fn read_capped_body_truncates_at_the_byte_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("loopback listener should bind");
+        let url = format!("http://{}/", listener.local_addr().expect("local addr"));
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("client should connect");
+            let mut buffer = vec![0u8; 4096];
+            let _ = socket.read(&mut buffer).await;
+            let body = "x".repeat(10_000);
+            let _ = socket
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+                        body.len()
+                    )
+                    .as_bytes(),
+                )
+                .await;
+        });
+        let client =
+            build_http_client(&HttpClientSettings::from_config()).expect("client should build");
+        let response = client
+            .get(&url)
+            .send()
+            .await
+            .expect("request should succeed");
+        let (body, truncated) = read_capped_body(response, 1_000)
+            .await
+            .expect("body should read");
+        assert_eq!(body.len(), 1_000);
+        assert!(truncated);
+        server.abort();
+    }
+}
@@ -10,6 +10,7 @@ use crate::config::CONFIG;
 use crate::llm::brave_search::brave_search;
 use crate::llm::exa_search::exa_search;
 use crate::llm::jina_search::search_jina_web;
+use crate::llm::provider_stats::track_provider_call;
 
 const DEFAULT_MAX_RESULTS: usize = 5;
 const MAX_RESULTS_LIMIT: usize = 10;
@@ -190,7 +191,7 @@ async fn search_with_provider(
 ) -> Result<Vec<SearchResult>> {
     match provider {
         WebSearchProvider::Brave => {
-            let results = brave_search(query, max_results).await?;
+            let results = track_provider_call("brave", brave_search(query, max_results)).await?;
             Ok(results
                 .into_iter()
                 .filter_map(|item| {
@@ -203,7 +204,7 @@ async fn search_with_provider(
                 .collect())
         }
         WebSearchProvider::Exa => {
-            let results = exa_search(query, Some(max_results)).await?;
+            let results = track_provider_call("exa", exa_search(query, Some(max_results))).await?;
             Ok(results
                 .into_iter()
                 .filter_map(|(title, url, snippet)| {
@@ -216,7 +217,7 @@ async fn search_with_provider(
                 .collect())
         }
         WebSearchProvider::Jina => {
-            let response = search_jina_web(query, max_results).await?;
+            let response = track_provider_call("jina", search_jina_web(query, max_results)).await?;
             Ok(response
                 .results
                 .into_iter()
@@ -1,11 +1,13 @@
+use std::collections::HashMap;
 use std::error::Error;
+use std::future::Future;
 
 use anyhow::anyhow;
 use dotenvy::dotenv;
 use serde::{Deserialize, Serialize};
 use teloxide::dispatching::UpdateFilterExt;
 use teloxide::prelude::*;
-use teloxide::types::BotCommand;
+use teloxide::types::{BotCommand, Me};
 use teloxide::utils::command::BotCommands;
 use tracing::{error, info, warn};
 
@@ -24,11 +26,15 @@ use handlers::codex_admin::{
     CODEX_MODEL_PAGE_CALLBACK_PREFIX, CODEX_MODEL_SELECT_CALLBACK_PREFIX,
     CODEX_REASONING_SELECT_CALLBACK_PREFIX,
 };
+use handlers::content::with_extraction_budget;
 use handlers::qa::MODEL_CALLBACK_PREFIX;
 use handlers::{commands, qa};
-use state::AppState;
+use llm::sampling::{with_chat_sampling, ChatSampling};
+use state::{AppState, IgnoredUpdateKind};
+use utils::command_alias;
 use utils::http::get_http_client;
 use utils::logging::init_logging;
+use utils::request_id::spawn_traced;
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase")]
@@ -43,16 +49,27 @@ enum Command {
         description = "回复一条文字/图片/视频/音频消息进行事实核查，支持消息内的 Telegraph/Twitter/YouTube 链接"
     )]
     Factcheck(String),
+    #[command(description = "深度分析附带或回复的文档/图片，输出摘要、关键实体和待办事项")]
+    Analyze(String),
     #[command(
         description = "提问或分析媒体，弹出模型选择（默认 Gemini，自动隐藏不支持当前媒体的模型）"
     )]
     Q(String),
+    #[command(description = "预览 /q 会收集的上下文（链接、媒体、字数），不调用模型")]
+    Context(String),
+    #[command(
+        rename = "model_info",
+        description = "查看某个模型的配置：ID、能力、工具支持、是否为默认模型"
+    )]
+    ModelInfo(String),
     #[command(description = "询问本群聊里的历史内容，可检索当前聊天记录并在需要时联网搜索")]
     Qc(String),
     #[command(
         description = "Quick Question（快问快答），小喵会用Gemini的低思考级别尽量快捷地回答你的问题"
     )]
     Qq(String),
+    #[command(description = "continue a cut-off answer (reply to it)")]
+    Continue,
     #[command(
         rename = "burn_baby_burn",
         description = "show how many tokens you have used in this chat"
@@ -83,15 +100,56 @@ enum Command {
     Paintme,
     #[command(description = "基于你在本群的聊天记录生成肖像")]
     Portraitme,
-    #[command(description = "查看机器人状态（管理员）")]
-    Status,
+    #[command(description = "根据本群最近的话题随机生成一张趣味图片")]
+    Random,
+    #[command(description = "用同一提示词一次生成多张不同的图片")]
+    Imagine(String),
+    #[command(description = "查看机器人状态（管理员），加 json 输出结构化结果")]
+    Status(String),
     #[command(description = "查看诊断信息（管理员）")]
     Diagnose,
+    #[command(description = "查看等待选择模型的请求（管理员）")]
+    Queue,
     #[command(
         rename = "token_stats",
         description = "show bot-wide token statistics (admin)"
     )]
     TokenStats(String),
+    #[command(
+        rename = "stats_providers",
+        description = "show recent provider success rates and latency (admin)"
+    )]
+    StatsProviders,
+    #[command(
+        rename = "stats_tokens",
+        description = "show your own token usage for recent days"
+    )]
+    StatsTokens(String),
+    #[command(description = "configure the scheduled daily digest for this chat (admin)")]
+    Digest(String),
+    #[command(description = "list, add, or remove whitelist entries (admin)")]
+    Whitelist(String),
+    #[command(description = "show or reset a user's rate limit (admin)")]
+    Ratelimit(String),
+    #[command(description = "show a user's activity in this chat (admin)")]
+    Whois(String),
+    #[command(description = "set the Telegraph byline for this chat (admin)")]
+    Telegraphauthor(String),
+    #[command(description = "toggle link extraction for this chat (admin)")]
+    Extraction(String),
+    #[command(
+        rename = "command",
+        description = "turn a command on or off for this chat (admin)"
+    )]
+    Toggle(String),
+    #[command(description = "set the LLM temperature and top_p for this chat (admin)")]
+    Temperature(String),
+    #[command(description = "set the timezone timestamps are shown in for this chat (admin)")]
+    Timezone(String),
+    #[command(
+        description = "send only final answers, without processing messages, in this chat (admin)"
+    )]
+    Quiet(String),
     #[command(description = "投喂AI小喵")]
     #[command(description = "ç™»å½• ChatGPT Codexï¼ˆç®¡ç†å‘˜ï¼‰")]
     Codexlogin,
@@ -204,6 +262,10 @@ fn public_bot_commands_with_gemini(gemini_available: bool) -> Vec<BotCommand> {
             "factcheck",
             "回复一条文字/图片/视频/音频消息进行事实核查，支持消息内的 Telegraph/Twitter/YouTube 链接",
         ),
+        BotCommand::new(
+            "analyze",
+            "深度分析附带或回复的文档/图片，输出摘要、关键实体和待办事项",
+        ),
         BotCommand::new(
             "q",
             "提问或分析媒体，弹出模型选择（默认 Gemini，自动隐藏不支持当前媒体的模型）",
@@ -227,10 +289,17 @@ fn public_bot_commands_with_gemini(gemini_available: bool) -> Vec<BotCommand> {
         BotCommand::new("paintme", "基于你在本群的聊天记录生成艺术形象"),
         BotCommand::new("portraitme", "基于你在本群的聊天记录生成肖像"),
         BotCommand::new("mysong", "基于你在本群的聊天记录生成你的主题歌"),
+        BotCommand::new("random", "根据本群最近的话题随机生成一张趣味图片"),
+        BotCommand::new("imagine", "用同一提示词一次生成多张不同的图片（/imagine 3 提示词）"),
         BotCommand::new("support", "投喂AI小喵"),
     ];
     if !gemini_available {
-        commands.retain(|command| !matches!(command.command.as_str(), "vid" | "mysong"));
+        commands.retain(|command| {
+            !matches!(
+                command.command.as_str(),
+                "analyze" | "vid" | "mysong" | "random" | "imagine"
+            )
+        });
     }
     commands
 }
@@ -258,6 +327,12 @@ async fn main() -> HandlerResult {
     let state = AppState::new(db, bot_user_id, bot_username_lower);
 
     handlers::access::load_whitelist();
+    match state.load_chat_settings().await {
+        Ok(count) => info!("Loaded settings for {count} chats"),
+        Err(err) => warn!("Failed to load per-chat settings: {err:#}"),
+    }
+    handlers::digest::spawn_digest_scheduler(bot.clone(), state.clone());
+    llm::openrouter_catalog::spawn_openrouter_model_refresh();
     if CONFIG.publish_bot_commands {
         let mut commands = public_bot_commands();
         commands.push(BotCommand::new(
@@ -284,9 +359,18 @@ async fn main() -> HandlerResult {
     }
 
     let command_handler = dptree::entry()
+        .map(|message: Message, me: Me| {
+            resolve_command_aliases(message, me.username(), &CONFIG.command_aliases)
+        })
         .filter_command::<Command>()
         .endpoint(handle_command);
 
+    // Channel posts only ever carry commands; without CHANNEL_COMMANDS_ENABLED
+    // they are dropped instead of answered into the channel.
+    let channel_handler = Update::filter_channel_post()
+        .filter(|_: Message| CONFIG.channel_commands_enabled)
+        .branch(command_handler.clone());
+
     let message_handler = Update::filter_message()
         .branch(command_handler)
         .branch(
@@ -297,6 +381,12 @@ async fn main() -> HandlerResult {
             dptree::filter(|msg: Message| msg.text().is_some() || msg.caption().is_some())
                 .endpoint(handle_text_message),
         )
+        .branch(
+            dptree::filter(|msg: Message| {
+                CONFIG.enable_voice_transcription && handlers::voice::is_transcribable_message(&msg)
+            })
+            .endpoint(handle_voice_message),
+        )
         .endpoint(ignore_message);
 
     let callback_state = state.clone();
@@ -306,9 +396,15 @@ async fn main() -> HandlerResult {
             async move { handle_callback_query(bot, state, query).await }
         });
 
+    let inline_handler = Update::filter_inline_query()
+        .filter(|_: InlineQuery| CONFIG.enable_inline_queries)
+        .endpoint(handle_inline_query);
+
     let handler = dptree::entry()
         .branch(message_handler)
-        .branch(callback_handler);
+        .branch(channel_handler)
+        .branch(callback_handler)
+        .branch(inline_handler);
 
     Dispatcher::builder(bot, handler)
         .dependencies(dptree::deps![state])
@@ -320,6 +416,37 @@ async fn main() -> HandlerResult {
     Ok(())
 }
 
+/// Rewrites a `COMMAND_ALIASES` alias to its canonical command unless the
+/// message already parses as a built-in command.
+fn resolve_command_aliases(
+    message: Message,
+    bot_name: &str,
+    aliases: &HashMap<String, String>,
+) -> Message {
+    let is_builtin = message
+        .text()
+        .or_else(|| message.caption())
+        .is_some_and(|text| Command::parse(text, bot_name).is_ok());
+    if is_builtin {
+        return message;
+    }
+    command_alias::apply_command_alias(message, aliases)
+}
+
+/// Scope every command handler runs in: the chat's /temperature override and
+/// a fresh link-extraction budget.
+async fn command_scope<F: Future>(sampling: ChatSampling, future: F) -> F::Output {
+    with_chat_sampling(sampling, with_extraction_budget(future)).await
+}
+
+/// Spawns a command handler in [`command_scope`] under a new request id.
+fn spawn_command<F>(command: &str, sampling: ChatSampling, future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    spawn_traced(command, command_scope(sampling, future));
+}
+
 async fn handle_command(
     bot: Bot,
     state: AppState,
@@ -334,15 +461,16 @@ async fn handle_command(
         }
     }
 
+    let sampling = ChatSampling::from(&state.chat_settings.get(message.chat.id.0));
     match command {
         Command::Start => commands::start_handler(bot, message).await?,
-        Command::Help => commands::help_handler(bot, message).await?,
+        Command::Help => commands::help_handler(bot, state, message).await?,
         Command::Tldr(arg) => {
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("tldr", sampling, async move {
                 if let Err(err) = commands::tldr_handler(bot, state, message, arg).await {
                     error!("tldr handler failed: {err}");
                 }
@@ -353,29 +481,61 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("factcheck", sampling, async move {
                 if let Err(err) = commands::factcheck_handler(bot, state, message, arg).await {
                     error!("factcheck handler failed: {err}");
                 }
             });
         }
+        Command::Analyze(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("analyze", sampling, async move {
+                if let Err(err) = commands::analyze_handler(bot, state, message, arg).await {
+                    error!("analyze handler failed: {err}");
+                }
+            });
+        }
         Command::Q(arg) => {
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("q", sampling, async move {
                 if let Err(err) = qa::q_handler(bot, state, message, arg, false, "q").await {
                     error!("q handler failed: {err}");
                 }
             });
         }
+        Command::Context(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("context", sampling, async move {
+                if let Err(err) = qa::context_handler(bot, state, message, arg).await {
+                    error!("context handler failed: {err}");
+                }
+            });
+        }
+        Command::ModelInfo(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            spawn_command("model_info", sampling, async move {
+                if let Err(err) = qa::model_info_handler(bot, state, message, arg).await {
+                    error!("model_info handler failed: {err}");
+                }
+            });
+        }
         Command::Qc(arg) => {
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("qc", sampling, async move {
                 if let Err(err) = qa::qc_handler(bot, state, message, arg).await {
                     error!("qc handler failed: {err}");
                 }
@@ -386,17 +546,27 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("qq", sampling, async move {
                 if let Err(err) = qa::qq_handler(bot, state, message, arg).await {
                     error!("qq handler failed: {err}");
                 }
             });
         }
+        Command::Continue => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            spawn_command("continue", sampling, async move {
+                if let Err(err) = qa::continue_handler(bot, state, message).await {
+                    error!("continue handler failed: {err}");
+                }
+            });
+        }
         Command::BurnBabyBurn => {
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
-            tokio::spawn(async move {
+            spawn_command("burn_baby_burn", sampling, async move {
                 if let Err(err) = commands::burn_baby_burn_handler(bot, state, message).await {
                     error!("burn_baby_burn handler failed: {err}");
                 }
@@ -407,7 +577,7 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("token_devourers", sampling, async move {
                 if let Err(err) = commands::token_devourers_handler(bot, state, message, arg).await
                 {
                     error!("token_devourers handler failed: {err}");
@@ -419,7 +589,7 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("s", sampling, async move {
                 if let Err(err) = qa::s_handler(bot, state, message, arg).await {
                     error!("s handler failed: {err}");
                 }
@@ -430,7 +600,7 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("img", sampling, async move {
                 if let Err(err) = commands::img_handler(bot, state, message, arg).await {
                     error!("img handler failed: {err}");
                 }
@@ -441,7 +611,7 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_traced("img2", async move {
                 if let Err(err) = commands::img2_handler(bot, state, message, arg).await {
                     error!("img2 handler failed: {err}");
                 }
@@ -452,7 +622,7 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("image", sampling, async move {
                 if let Err(err) = commands::image_handler(bot, state, message, arg).await {
                     error!("image handler failed: {err}");
                 }
@@ -463,7 +633,7 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("vid", sampling, async move {
                 if let Err(err) = commands::vid_handler(bot, state, message, arg).await {
                     error!("vid handler failed: {err}");
                 }
@@ -474,7 +644,7 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("mysong", sampling, async move {
                 if let Err(err) = commands::mysong_handler(bot, state, message, arg).await {
                     error!("mysong handler failed: {err}");
                 }
@@ -485,7 +655,7 @@ async fn handle_command(
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("profileme", sampling, async move {
                 if let Err(err) = commands::profileme_handler(bot, state, message, arg).await {
                     error!("profileme handler failed: {err}");
                 }
@@ -495,7 +665,7 @@ async fn handle_command(
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
-            tokio::spawn(async move {
+            spawn_command("paintme", sampling, async move {
                 if let Err(err) = commands::paintme_handler(bot, state, message, false).await {
                     error!("paintme handler failed: {err}");
                 }
@@ -505,48 +675,221 @@ async fn handle_command(
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
-            tokio::spawn(async move {
+            spawn_command("portraitme", sampling, async move {
                 if let Err(err) = commands::paintme_handler(bot, state, message, true).await {
                     error!("portraitme handler failed: {err}");
                 }
             });
         }
-        Command::Status => {
+        Command::Random => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            spawn_command("random", sampling, async move {
+                if let Err(err) = commands::random_handler(bot, state, message).await {
+                    error!("random handler failed: {err}");
+                }
+            });
+        }
+        Command::Imagine(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("imagine", sampling, async move {
+                if let Err(err) = commands::imagine_handler(bot, state, message, arg).await {
+                    error!("imagine handler failed: {err}");
+                }
+            });
+        }
+        Command::Status(arg) => {
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
-            tokio::spawn(async move {
-                if let Err(err) = commands::status_handler(bot, state, message).await {
+            let args = optional_arg(arg);
+            spawn_command("status", sampling, async move {
+                if let Err(err) = commands::status_handler(bot, state, message, args).await {
                     error!("status handler failed: {err}");
                 }
             });
         }
+        Command::Queue => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            spawn_command("queue", sampling, async move {
+                if let Err(err) = commands::queue_handler(bot, state, message).await {
+                    error!("queue handler failed: {err}");
+                }
+            });
+        }
         Command::Diagnose => {
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
-            tokio::spawn(async move {
+            spawn_command("diagnose", sampling, async move {
                 if let Err(err) = commands::diagnose_handler(bot, state, message).await {
                     error!("diagnose handler failed: {err}");
                 }
             });
         }
+        Command::StatsProviders => {
+            let bot = bot.clone();
+            let message = message.clone();
+            spawn_command("stats_providers", sampling, async move {
+                if let Err(err) = commands::stats_providers_handler(bot, message).await {
+                    error!("stats_providers handler failed: {err}");
+                }
+            });
+        }
         Command::TokenStats(arg) => {
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
             let arg = optional_arg(arg);
-            tokio::spawn(async move {
+            spawn_command("token_stats", sampling, async move {
                 if let Err(err) = commands::token_stats_handler(bot, state, message, arg).await {
                     error!("token_stats handler failed: {err}");
                 }
             });
         }
+        Command::StatsTokens(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("stats_tokens", sampling, async move {
+                if let Err(err) = commands::stats_tokens_handler(bot, state, message, arg).await {
+                    error!("stats_tokens handler failed: {err}");
+                }
+            });
+        }
+        Command::Digest(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("digest", sampling, async move {
+                if let Err(err) = handlers::digest::digest_handler(bot, state, message, arg).await {
+                    error!("digest handler failed: {err}");
+                }
+            });
+        }
+        Command::Whitelist(arg) => {
+            let bot = bot.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("whitelist", sampling, async move {
+                if let Err(err) = handlers::whitelist::whitelist_handler(bot, message, arg).await {
+                    error!("whitelist handler failed: {err}");
+                }
+            });
+        }
+        Command::Ratelimit(arg) => {
+            let bot = bot.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("ratelimit", sampling, async move {
+                if let Err(err) = commands::ratelimit_handler(bot, message, arg).await {
+                    error!("ratelimit handler failed: {err}");
+                }
+            });
+        }
+        Command::Whois(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("whois", sampling, async move {
+                if let Err(err) = commands::whois_handler(bot, state, message, arg).await {
+                    error!("whois handler failed: {err}");
+                }
+            });
+        }
+        Command::Telegraphauthor(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("telegraphauthor", sampling, async move {
+                if let Err(err) =
+                    handlers::chat_settings::telegraph_author_handler(bot, state, message, arg)
+                        .await
+                {
+                    error!("telegraphauthor handler failed: {err}");
+                }
+            });
+        }
+        Command::Extraction(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("extraction", sampling, async move {
+                if let Err(err) =
+                    handlers::chat_settings::extraction_handler(bot, state, message, arg).await
+                {
+                    error!("extraction handler failed: {err}");
+                }
+            });
+        }
+        Command::Toggle(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("command", sampling, async move {
+                if let Err(err) =
+                    handlers::chat_settings::command_toggle_handler(bot, state, message, arg).await
+                {
+                    error!("command handler failed: {err}");
+                }
+            });
+        }
+        Command::Temperature(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("temperature", sampling, async move {
+                if let Err(err) =
+                    handlers::chat_settings::temperature_handler(bot, state, message, arg).await
+                {
+                    error!("temperature handler failed: {err}");
+                }
+            });
+        }
+        Command::Timezone(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("timezone", sampling, async move {
+                if let Err(err) =
+                    handlers::chat_settings::timezone_handler(bot, state, message, arg).await
+                {
+                    error!("timezone handler failed: {err}");
+                }
+            });
+        }
+        Command::Quiet(arg) => {
+            let bot = bot.clone();
+            let state = state.clone();
+            let message = message.clone();
+            let arg = optional_arg(arg);
+            spawn_command("quiet", sampling, async move {
+                if let Err(err) =
+                    handlers::chat_settings::quiet_handler(bot, state, message, arg).await
+                {
+                    error!("quiet handler failed: {err}");
+                }
+            });
+        }
         Command::Codexlogin => {
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
-            tokio::spawn(async move {
+            spawn_command("codexlogin", sampling, async move {
                 if let Err(err) =
                     handlers::codex_admin::codex_login_handler(bot, state, message).await
                 {
@@ -558,7 +901,7 @@ async fn handle_command(
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
-            tokio::spawn(async move {
+            spawn_command("codexlogout", sampling, async move {
                 if let Err(err) =
                     handlers::codex_admin::codex_logout_handler(bot, state, message).await
                 {
@@ -570,7 +913,7 @@ async fn handle_command(
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
-            tokio::spawn(async move {
+            spawn_command("codexmodel", sampling, async move {
                 if let Err(err) =
                     handlers::codex_admin::codex_model_handler(bot, state, message).await
                 {
@@ -582,7 +925,7 @@ async fn handle_command(
             let bot = bot.clone();
             let state = state.clone();
             let message = message.clone();
-            tokio::spawn(async move {
+            spawn_command("codexreasoning", sampling, async move {
                 if let Err(err) =
                     handlers::codex_admin::codex_reasoning_handler(bot, state, message).await
                 {
@@ -593,13 +936,13 @@ async fn handle_command(
         Command::Codexusage => {
             let bot = bot.clone();
             let message = message.clone();
-            tokio::spawn(async move {
+            spawn_command("codexusage", sampling, async move {
                 if let Err(err) = handlers::codex_admin::codex_usage_handler(bot, message).await {
                     error!("codexusage handler failed: {err}");
                 }
             });
         }
-        Command::Support => commands::support_handler(bot, message).await?,
+        Command::Support => commands::support_handler(bot, state, message).await?,
     }
     Ok(())
 }
@@ -609,13 +952,20 @@ async fn handle_callback_query(bot: Bot, state: AppState, query: CallbackQuery)
         return Ok(());
     };
     if data.starts_with(MODEL_CALLBACK_PREFIX) {
+        // The callback re-enters the request id of the /q that asked for the
+        // model, so it is not spawned with a new one.
+        let sampling = query
+            .message
+            .as_ref()
+            .map(|message| ChatSampling::from(&state.chat_settings.get(message.chat().id.0)))
+            .unwrap_or_default();
         let bot = bot.clone();
         let state = state.clone();
-        tokio::spawn(async move {
+        tokio::spawn(command_scope(sampling, async move {
             if let Err(err) = qa::model_selection_callback(bot, state, query).await {
                 error!("model selection callback failed: {err}");
             }
-        });
+        }));
         return Ok(());
     }
     if data.starts_with(CODEX_MODEL_SELECT_CALLBACK_PREFIX)
@@ -661,10 +1011,11 @@ async fn handle_text_message(bot: Bot, state: AppState, message: Message) -> Han
 
     if qa::should_auto_q_trigger(&message, state.bot_user_id, &state.bot_username_lower) {
         let query = qa::build_auto_q_query(&message, state.bot_user_id, &state.bot_username_lower);
+        let sampling = ChatSampling::from(&state.chat_settings.get(message.chat.id.0));
         let bot = bot.clone();
         let state = state.clone();
         let message = message.clone();
-        tokio::spawn(async move {
+        spawn_command("q", sampling, async move {
             if let Err(err) = qa::q_handler(bot, state, message, query, false, "q").await {
                 error!("auto q handler failed: {err}");
             }
@@ -676,7 +1027,26 @@ async fn handle_text_message(bot: Bot, state: AppState, message: Message) -> Han
     Ok(())
 }
 
-async fn ignore_message(_message: Message) -> HandlerResult {
+async fn handle_inline_query(bot: Bot, state: AppState, query: InlineQuery) -> HandlerResult {
+    tokio::spawn(async move {
+        if let Err(err) = handlers::inline::inline_query_handler(bot, state, query).await {
+            error!("inline query handler failed: {err}");
+        }
+    });
+    Ok(())
+}
+
+async fn handle_voice_message(bot: Bot, state: AppState, message: Message) -> HandlerResult {
+    tokio::spawn(async move {
+        handlers::voice::transcribe_and_log_message(&bot, &state, &message).await;
+    });
+    Ok(())
+}
+
+async fn ignore_message(state: AppState, message: Message) -> HandlerResult {
+    state
+        .ignored_updates
+        .record(IgnoredUpdateKind::classify(&message));
     Ok(())
 }
 
@@ -696,6 +1066,70 @@ mod tests {
         assert!(!commands.iter().any(|command| command == "img2"));
     }
 
+    #[tokio::test]
+    async fn command_scope_applies_the_link_budget_and_chat_sampling() {
+        use handlers::content::reserve_extraction_budget;
+        use llm::sampling::active_sampling;
+
+        let sampling = ChatSampling {
+            temperature: Some(0.2),
+            top_p: None,
+        };
+        // Auto-triggered /q is spawned through the same scope as commands.
+        let (first, second, active) = command_scope(sampling, async {
+            (
+                reserve_extraction_budget(usize::MAX),
+                reserve_extraction_budget(1),
+                active_sampling(),
+            )
+        })
+        .await;
+        assert_eq!(active, sampling);
+        if CONFIG.max_extracted_urls_total > 0 {
+            assert_eq!(first, CONFIG.max_extracted_urls_total);
+            assert_eq!(second, 0);
+        }
+        assert_eq!(reserve_extraction_budget(3), 3);
+    }
+
+    fn text_message(text: &str) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 1,
+            "date": 1,
+            "chat": { "id": -100123, "type": "group", "title": "test group" },
+            "from": { "id": 7, "is_bot": false, "first_name": "Alice" },
+            "text": text
+        }))
+        .expect("test message should deserialize")
+    }
+
+    fn parse_with_aliases(text: &str, aliases: &HashMap<String, String>) -> Option<Command> {
+        let message = resolve_command_aliases(text_message(text), "test_bot", aliases);
+        Command::parse(message.text()?, "test_bot").ok()
+    }
+
+    #[test]
+    fn command_alias_dispatches_like_the_canonical_command() {
+        let aliases = HashMap::from([
+            ("sum".to_string(), "tldr".to_string()),
+            ("q".to_string(), "tldr".to_string()),
+        ]);
+
+        assert!(matches!(
+            parse_with_aliases("/sum@test_bot last hour", &aliases),
+            Some(Command::Tldr(arg)) if arg == "last hour"
+        ));
+        assert!(matches!(
+            parse_with_aliases("/tldr last hour", &aliases),
+            Some(Command::Tldr(arg)) if arg == "last hour"
+        ));
+        assert!(matches!(
+            parse_with_aliases("/q what is rust", &aliases),
+            Some(Command::Q(arg)) if arg == "what is rust"
+        ));
+        assert!(parse_with_aliases("/sum last hour", &HashMap::new()).is_none());
+    }
+
     #[test]
     fn published_commands_keep_search_when_gemini_is_disabled() {
         let commands = public_bot_commands_with_gemini(false)
@@ -706,6 +1140,7 @@ mod tests {
         assert!(commands.iter().any(|command| command == "s"));
         assert!(!commands.iter().any(|command| command == "vid"));
         assert!(!commands.iter().any(|command| command == "mysong"));
+        assert!(!commands.iter().any(|command| command == "analyze"));
         assert!(commands.iter().any(|command| command == "q"));
     }
 }
@@ -1,14 +1,15 @@
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 
-use parking_lot::Mutex;
-use teloxide::types::{FileId, MediaGroupId};
+use parking_lot::{Mutex, RwLock};
+use teloxide::types::{FileId, MediaGroupId, Message, MessageKind};
 use tokio::sync::{Mutex as AsyncMutex, OwnedSemaphorePermit, Semaphore};
 
 use crate::config::CONFIG;
 use crate::db::database::Database;
+use crate::db::models::ChatSettingsRow;
 use crate::llm::media::MediaFile;
 use crate::llm::openai_codex::{CodexReasoningEffortOption, CodexRemoteModel};
 use crate::utils::timing::CommandTimer;
@@ -30,6 +31,64 @@ impl QaCommandMode {
     }
 }
 
+/// How a command shows it is working before the answer arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandAck {
+    /// A "Processing..." reply that the answer later replaces.
+    Message,
+    /// A reaction on the command message, cleared when the command finishes.
+    Reaction,
+    /// Nothing until the answer, which is sent as a reply (`QUIET_MODE`).
+    Silent,
+}
+
+/// User-requested answer length for `/q` (`/q short ...`, `/q long ...`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnswerLength {
+    #[default]
+    Default,
+    Short,
+    Long,
+}
+
+impl AnswerLength {
+    const SHORT_MAX_OUTPUT_TOKENS: i32 = 512;
+    const LONG_MIN_OUTPUT_TOKENS: i32 = 8192;
+
+    pub fn prompt_instruction(self) -> Option<&'static str> {
+        match self {
+            Self::Default => None,
+            Self::Short => Some(
+                "Answer length: the user asked for a short answer. Reply in at most three short sentences or a few bullet points, with no preamble.",
+            ),
+            Self::Long => Some(
+                "Answer length: the user asked for a detailed answer. Cover the topic thoroughly, with structure and examples where useful.",
+            ),
+        }
+    }
+
+    pub fn max_output_tokens(self, configured: i32) -> i32 {
+        match self {
+            Self::Default => configured,
+            Self::Short => configured.min(Self::SHORT_MAX_OUTPUT_TOKENS),
+            Self::Long => configured.max(Self::LONG_MIN_OUTPUT_TOKENS),
+        }
+    }
+
+    /// [`Self::max_output_tokens`] for a chat-completions `max_tokens`, where
+    /// `None` leaves the limit to the provider.
+    pub fn max_tokens(self, configured: Option<u32>) -> Option<u32> {
+        let short = Self::SHORT_MAX_OUTPUT_TOKENS as u32;
+        match self {
+            Self::Default => configured,
+            Self::Short => Some(configured.map_or(short, |configured| configured.min(short))),
+            Self::Long => {
+                configured.map(|configured| configured.max(Self::LONG_MIN_OUTPUT_TOKENS as u32))
+            }
+        }
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct PendingQRequest {
@@ -52,6 +111,18 @@ pub struct PendingQRequest {
     pub timestamp: i64,
     pub command_timer: Option<CommandTimer>,
     pub mode: QaCommandMode,
+    pub answer_length: AnswerLength,
+    /// Command that created the request, used for `COMMAND_MODEL_ROUTING`.
+    pub command_name: String,
+    /// The question links to pages no extractor handled; Gemini fetches them
+    /// with `url_context`.
+    pub use_url_context: bool,
+    /// Unless this is `Message`, there is no processing message and
+    /// `selection_message_id` has nothing to edit until the answer is sent.
+    pub ack: CommandAck,
+    /// Request id of the command that asked for the model choice, re-entered
+    /// when the selection callback runs the request.
+    pub request_id: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -85,6 +156,8 @@ pub struct PendingImageRequest {
     pub codex_size: Option<String>,
     pub resolution: Option<String>,
     pub aspect_ratio: Option<String>,
+    pub seed: Option<i32>,
+    pub timestamp: i64,
 }
 
 #[derive(Debug, Clone)]
@@ -132,6 +205,349 @@ pub struct MediaGroupState {
     pub last_updated: Instant,
 }
 
+/// Coarse buckets for messages that no handler picked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoredUpdateKind {
+    Service,
+    Sticker,
+    Poll,
+    Location,
+    Media,
+    Other,
+}
+
+impl IgnoredUpdateKind {
+    pub const ALL: [IgnoredUpdateKind; 6] = [
+        Self::Service,
+        Self::Sticker,
+        Self::Poll,
+        Self::Location,
+        Self::Media,
+        Self::Other,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Service => "service",
+            Self::Sticker => "sticker",
+            Self::Poll => "poll",
+            Self::Location => "location",
+            Self::Media => "media",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn classify(message: &Message) -> Self {
+        if !matches!(message.kind, MessageKind::Common(_)) {
+            return Self::Service;
+        }
+        if message.sticker().is_some() {
+            Self::Sticker
+        } else if message.poll().is_some() {
+            Self::Poll
+        } else if message.location().is_some() || message.venue().is_some() {
+            Self::Location
+        } else if message.photo().is_some()
+            || message.video().is_some()
+            || message.animation().is_some()
+            || message.voice().is_some()
+            || message.audio().is_some()
+            || message.video_note().is_some()
+            || message.document().is_some()
+        {
+            Self::Media
+        } else {
+            Self::Other
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct IgnoredUpdateCounters {
+    counts: [AtomicU64; IgnoredUpdateKind::ALL.len()],
+}
+
+impl IgnoredUpdateCounters {
+    pub fn record(&self, kind: IgnoredUpdateKind) {
+        self.counts[kind as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Vec<(IgnoredUpdateKind, u64)> {
+        IgnoredUpdateKind::ALL
+            .iter()
+            .map(|kind| (*kind, self.counts[*kind as usize].load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+struct ChatSlot {
+    semaphore: Arc<Semaphore>,
+    // Holders plus waiters; the slot is only pruned once this drops to zero.
+    pending: Arc<AtomicUsize>,
+}
+
+/// Caps heavy work per chat so one busy group cannot take every global slot.
+/// A limit of 0 disables the per-chat cap but still tracks in-flight counts.
+pub struct ChatConcurrencyLimiter {
+    per_chat_limit: usize,
+    slots: Mutex<HashMap<i64, ChatSlot>>,
+}
+
+pub struct ChatPermit {
+    permit: Option<OwnedSemaphorePermit>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl Drop for ChatPermit {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChatInFlight {
+    pub chat_id: i64,
+    pub active: usize,
+    pub waiting: usize,
+}
+
+impl ChatConcurrencyLimiter {
+    pub fn new(per_chat_limit: usize) -> Self {
+        Self {
+            per_chat_limit,
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        if self.per_chat_limit == 0 {
+            Semaphore::MAX_PERMITS
+        } else {
+            self.per_chat_limit
+        }
+    }
+
+    pub async fn acquire(&self, chat_id: i64) -> ChatPermit {
+        let (semaphore, pending) = {
+            let mut slots = self.slots.lock();
+            slots.retain(|_, slot| slot.pending.load(Ordering::Relaxed) > 0);
+            let capacity = self.capacity();
+            let slot = slots.entry(chat_id).or_insert_with(|| ChatSlot {
+                semaphore: Arc::new(Semaphore::new(capacity)),
+                pending: Arc::new(AtomicUsize::new(0)),
+            });
+            slot.pending.fetch_add(1, Ordering::Relaxed);
+            (slot.semaphore.clone(), slot.pending.clone())
+        };
+        // Build the guard before waiting so a cancelled acquire still
+        // releases its pending count.
+        let mut chat_permit = ChatPermit {
+            permit: None,
+            pending,
+        };
+        chat_permit.permit = Some(
+            semaphore
+                .acquire_owned()
+                .await
+                .expect("chat concurrency semaphore should remain open"),
+        );
+        chat_permit
+    }
+
+    /// Chats with work in flight, busiest first.
+    pub fn in_flight(&self) -> Vec<ChatInFlight> {
+        let capacity = self.capacity();
+        let mut chats = self
+            .slots
+            .lock()
+            .iter()
+            .filter_map(|(chat_id, slot)| {
+                let pending = slot.pending.load(Ordering::Relaxed);
+                if pending == 0 {
+                    return None;
+                }
+                let active = capacity.saturating_sub(slot.semaphore.available_permits());
+                Some(ChatInFlight {
+                    chat_id: *chat_id,
+                    active,
+                    waiting: pending.saturating_sub(active),
+                })
+            })
+            .collect::<Vec<_>>();
+        chats.sort_by(|left, right| {
+            (right.active + right.waiting)
+                .cmp(&(left.active + left.waiting))
+                .then(left.chat_id.cmp(&right.chat_id))
+        });
+        chats
+    }
+}
+
+type InFlightKey = (i64, i64, String);
+
+/// Heavy commands currently running, keyed by chat, user, and command name.
+/// Unlike the rate limit this only refuses overlapping runs: once the first
+/// run ends, the same command can be started again straight away.
+#[derive(Clone, Default)]
+pub struct InFlightCommands {
+    active: Arc<Mutex<HashSet<InFlightKey>>>,
+}
+
+/// Marks a command as running until dropped, which also covers handlers that
+/// return early with an error.
+pub struct InFlightCommandGuard {
+    release: Option<(Arc<Mutex<HashSet<InFlightKey>>>, InFlightKey)>,
+}
+
+impl InFlightCommandGuard {
+    /// A guard that tracks nothing, for when deduplication is turned off.
+    pub fn untracked() -> Self {
+        Self { release: None }
+    }
+}
+
+impl Drop for InFlightCommandGuard {
+    fn drop(&mut self) {
+        if let Some((active, key)) = self.release.take() {
+            active.lock().remove(&key);
+        }
+    }
+}
+
+impl InFlightCommands {
+    /// Returns `None` when the same user already has `command` running in
+    /// `chat_id`.
+    pub fn try_begin(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        command: &str,
+    ) -> Option<InFlightCommandGuard> {
+        let key = (chat_id, user_id, command.to_string());
+        if !self.active.lock().insert(key.clone()) {
+            return None;
+        }
+        Some(InFlightCommandGuard {
+            release: Some((self.active.clone(), key)),
+        })
+    }
+}
+
+/// Holds the per-chat slot and the global heavy-command slot for one command.
+pub struct HeavyCommandPermit {
+    _global: OwnedSemaphorePermit,
+    _chat: ChatPermit,
+}
+
+/// One entry awaiting a model choice, as listed by `/queue`. Carries ids and
+/// ages only; prompts and questions stay out of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingQueueEntry {
+    pub kind: &'static str,
+    pub command: String,
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub age_secs: i64,
+}
+
+/// Pending selections across all maps, oldest first.
+pub fn collect_pending_queue(
+    q_requests: &HashMap<String, PendingQRequest>,
+    image_requests: &HashMap<String, PendingImageRequest>,
+    codex_model_requests: &HashMap<String, PendingCodexModelRequest>,
+    codex_reasoning_requests: &HashMap<String, PendingCodexReasoningRequest>,
+    now: i64,
+) -> Vec<PendingQueueEntry> {
+    let age = |timestamp: i64| now.saturating_sub(timestamp).max(0);
+    let mut entries = Vec::new();
+    entries.extend(q_requests.values().map(|request| PendingQueueEntry {
+        kind: "q",
+        command: request.command_name.clone(),
+        chat_id: request.chat_id,
+        user_id: request.original_user_id,
+        age_secs: age(request.timestamp),
+    }));
+    entries.extend(image_requests.values().map(|request| {
+        PendingQueueEntry {
+            kind: "image",
+            command: match request.command {
+                PendingImageCommand::Img => "img",
+                PendingImageCommand::Image => "image",
+            }
+            .to_string(),
+            chat_id: request.chat_id,
+            user_id: request.user_id,
+            age_secs: age(request.timestamp),
+        }
+    }));
+    entries.extend(
+        codex_model_requests
+            .values()
+            .map(|request| PendingQueueEntry {
+                kind: "codex_model",
+                command: "codexmodel".to_string(),
+                chat_id: request.chat_id,
+                user_id: request.admin_user_id,
+                age_secs: age(request.timestamp),
+            }),
+    );
+    entries.extend(
+        codex_reasoning_requests
+            .values()
+            .map(|request| PendingQueueEntry {
+                kind: "codex_reasoning",
+                command: "codexreasoning".to_string(),
+                chat_id: request.chat_id,
+                user_id: request.admin_user_id,
+                age_secs: age(request.timestamp),
+            }),
+    );
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.age_secs));
+    entries
+}
+
+/// Every chat's `chat_settings` row, loaded once at startup so handlers read
+/// settings without a database round trip. [`AppState::update_chat_settings`]
+/// is the only writer.
+#[derive(Clone, Default)]
+pub struct ChatSettingsCache {
+    rows: Arc<RwLock<HashMap<i64, ChatSettingsRow>>>,
+    write_lock: Arc<AsyncMutex<()>>,
+}
+
+impl ChatSettingsCache {
+    /// The chat's settings, or the column defaults when it has no row.
+    pub fn get(&self, chat_id: i64) -> ChatSettingsRow {
+        self.rows
+            .read()
+            .get(&chat_id)
+            .cloned()
+            .unwrap_or_else(|| ChatSettingsRow::new(chat_id))
+    }
+
+    /// Rows matching `predicate`, ordered by chat id.
+    pub fn matching(&self, predicate: impl Fn(&ChatSettingsRow) -> bool) -> Vec<ChatSettingsRow> {
+        let mut rows = self
+            .rows
+            .read()
+            .values()
+            .filter(|row| predicate(row))
+            .cloned()
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|row| row.chat_id);
+        rows
+    }
+
+    fn insert(&self, row: ChatSettingsRow) {
+        self.rows.write().insert(row.chat_id, row);
+    }
+
+    fn replace_all(&self, rows: Vec<ChatSettingsRow>) {
+        *self.rows.write() = rows.into_iter().map(|row| (row.chat_id, row)).collect();
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: Database,
@@ -146,6 +562,12 @@ pub struct AppState {
     pub media_groups: Arc<Mutex<HashMap<MediaGroupId, MediaGroupState>>>,
     pub heavy_command_semaphore: Arc<Semaphore>,
     pub heavy_command_waiters: Arc<AtomicUsize>,
+    /// Shared by every fan-out sub-call, e.g. one `/imagine` variation.
+    pub fanout_semaphore: Arc<Semaphore>,
+    pub ignored_updates: Arc<IgnoredUpdateCounters>,
+    pub chat_concurrency: Arc<ChatConcurrencyLimiter>,
+    pub in_flight_commands: InFlightCommands,
+    pub chat_settings: ChatSettingsCache,
 }
 
 impl AppState {
@@ -163,10 +585,43 @@ impl AppState {
             media_groups: Arc::new(Mutex::new(HashMap::new())),
             heavy_command_semaphore: Arc::new(Semaphore::new(CONFIG.heavy_command_max_concurrency)),
             heavy_command_waiters: Arc::new(AtomicUsize::new(0)),
+            fanout_semaphore: Arc::new(Semaphore::new(CONFIG.fanout_max_concurrency)),
+            ignored_updates: Arc::new(IgnoredUpdateCounters::default()),
+            chat_concurrency: Arc::new(ChatConcurrencyLimiter::new(CONFIG.max_concurrent_per_chat)),
+            in_flight_commands: InFlightCommands::default(),
+            chat_settings: ChatSettingsCache::default(),
         }
     }
 
-    pub async fn acquire_heavy_command_permit(&self) -> OwnedSemaphorePermit {
+    /// Fills the settings cache from `chat_settings`. Returns the row count.
+    pub async fn load_chat_settings(&self) -> anyhow::Result<usize> {
+        let rows = self.db.select_chat_settings().await?;
+        let count = rows.len();
+        self.chat_settings.replace_all(rows);
+        Ok(count)
+    }
+
+    /// Applies `update` to the chat's settings and stores the whole row. The
+    /// cache changes only after the write succeeds, and writes are serialized
+    /// so concurrent updates to one chat cannot drop each other's fields.
+    pub async fn update_chat_settings(
+        &self,
+        chat_id: i64,
+        update: impl FnOnce(&mut ChatSettingsRow),
+    ) -> anyhow::Result<ChatSettingsRow> {
+        let _write = self.chat_settings.write_lock.lock().await;
+        let mut settings = self.chat_settings.get(chat_id);
+        update(&mut settings);
+        self.db.upsert_chat_settings(&settings).await?;
+        self.chat_settings.insert(settings.clone());
+        Ok(settings)
+    }
+
+    /// Waits for a slot in `chat_id` first, so a chat that is already at its
+    /// `MAX_CONCURRENT_PER_CHAT` limit queues behind itself without occupying
+    /// global slots that other chats could use.
+    pub async fn acquire_heavy_command_permit(&self, chat_id: i64) -> HeavyCommandPermit {
+        let chat_permit = self.chat_concurrency.acquire(chat_id).await;
         self.heavy_command_waiters.fetch_add(1, Ordering::Relaxed);
         let permit = self
             .heavy_command_semaphore
@@ -175,7 +630,20 @@ impl AppState {
             .await
             .expect("heavy command semaphore should remain open");
         self.heavy_command_waiters.fetch_sub(1, Ordering::Relaxed);
-        permit
+        HeavyCommandPermit {
+            _global: permit,
+            _chat: chat_permit,
+        }
+    }
+
+    pub fn pending_queue(&self, now: i64) -> Vec<PendingQueueEntry> {
+        collect_pending_queue(
+            &self.pending_q_requests.lock(),
+            &self.pending_image_requests.lock(),
+            &self.pending_codex_model_requests.lock(),
+            &self.pending_codex_reasoning_requests.lock(),
+            now,
+        )
     }
 
     pub fn heavy_command_active(&self) -> usize {
@@ -237,3 +705,217 @@ fn prune_media_groups(groups: &mut HashMap<MediaGroupId, MediaGroupState>) {
         groups.remove(&group_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending_q(chat_id: i64, timestamp: i64) -> PendingQRequest {
+        PendingQRequest {
+            user_id: 7,
+            username: "Alice".to_string(),
+            query: "secret question".to_string(),
+            original_query: "secret question".to_string(),
+            db_query_text: "secret question".to_string(),
+            telegram_language_code: None,
+            media_files: Vec::new(),
+            youtube_urls: Vec::new(),
+            telegraph_contents: Vec::new(),
+            twitter_contents: Vec::new(),
+            chat_id,
+            message_id: 1,
+            selection_message_id: 2,
+            original_user_id: 7,
+            reply_to_message_id: None,
+            llm_invocation_id: None,
+            timestamp,
+            command_timer: None,
+            mode: QaCommandMode::Standard,
+            answer_length: AnswerLength::Default,
+            command_name: "qq".to_string(),
+            use_url_context: false,
+            ack: CommandAck::Message,
+            request_id: None,
+        }
+    }
+
+    fn pending_image(timestamp: i64) -> PendingImageRequest {
+        PendingImageRequest {
+            user_id: 8,
+            chat_id: -300,
+            message_id: 3,
+            command: PendingImageCommand::Image,
+            prompt: "secret prompt".to_string(),
+            image_urls: Vec::new(),
+            telegraph_contents: Vec::new(),
+            original_message_text: "/image secret prompt".to_string(),
+            selection_message_id: 4,
+            llm_invocation_id: None,
+            model: None,
+            codex_size: None,
+            resolution: None,
+            aspect_ratio: None,
+            seed: None,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn pending_queue_counts_every_map_oldest_first() {
+        let q_requests = HashMap::from([
+            ("a".to_string(), pending_q(-100, 990)),
+            ("b".to_string(), pending_q(-200, 940)),
+        ]);
+        let image_requests = HashMap::from([("c".to_string(), pending_image(970))]);
+        let codex_model_requests = HashMap::from([(
+            "d".to_string(),
+            PendingCodexModelRequest {
+                admin_user_id: 9,
+                account_id: "account".to_string(),
+                chat_id: 9,
+                selection_message_id: 5,
+                timestamp: 1_010,
+                page: 0,
+                etag: None,
+                models: Vec::new(),
+            },
+        )]);
+
+        let entries = collect_pending_queue(
+            &q_requests,
+            &image_requests,
+            &codex_model_requests,
+            &HashMap::new(),
+            1_000,
+        );
+
+        assert_eq!(entries.len(), 4);
+        let summary = entries
+            .iter()
+            .map(|entry| (entry.kind, entry.command.as_str(), entry.age_secs))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("q", "qq", 60),
+                ("image", "image", 30),
+                ("q", "qq", 10),
+                ("codex_model", "codexmodel", 0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn busy_chat_does_not_block_other_chats() {
+        let limiter = ChatConcurrencyLimiter::new(1);
+        let _chat_a = limiter.acquire(-100).await;
+
+        let queued_a =
+            tokio::time::timeout(std::time::Duration::from_millis(50), limiter.acquire(-100)).await;
+        assert!(queued_a.is_err(), "chat A should be at its limit");
+
+        let chat_b =
+            tokio::time::timeout(std::time::Duration::from_millis(50), limiter.acquire(-200)).await;
+        assert!(chat_b.is_ok(), "chat B should not wait on chat A");
+
+        // The cancelled wait in chat A must not leave a phantom waiter.
+        assert_eq!(
+            limiter.in_flight(),
+            vec![
+                ChatInFlight {
+                    chat_id: -200,
+                    active: 1,
+                    waiting: 0
+                },
+                ChatInFlight {
+                    chat_id: -100,
+                    active: 1,
+                    waiting: 0
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn repeated_command_is_refused_until_the_first_run_ends() {
+        let commands = InFlightCommands::default();
+        let first = commands.try_begin(-100, 7, "tldr");
+        assert!(first.is_some());
+        assert!(commands.try_begin(-100, 7, "tldr").is_none());
+        assert!(commands.try_begin(-100, 8, "tldr").is_some());
+        assert!(commands.try_begin(-100, 7, "factcheck").is_some());
+        assert!(commands.try_begin(-200, 7, "tldr").is_some());
+        drop(first);
+        assert!(commands.try_begin(-100, 7, "tldr").is_some());
+
+        async fn failing_run(commands: &InFlightCommands) -> anyhow::Result<()> {
+            let _guard = commands
+                .try_begin(-100, 7, "tldr")
+                .expect("no run should be in flight");
+            anyhow::bail!("handler failed")
+        }
+        assert!(failing_run(&commands).await.is_err());
+        assert!(failing_run(&commands).await.is_err());
+        assert!(commands.try_begin(-100, 7, "tldr").is_some());
+    }
+
+    #[test]
+    fn ignored_update_counters_track_each_kind_separately() {
+        let counters = IgnoredUpdateCounters::default();
+        counters.record(IgnoredUpdateKind::Poll);
+        counters.record(IgnoredUpdateKind::Poll);
+        counters.record(IgnoredUpdateKind::Service);
+
+        let snapshot = counters.snapshot();
+        assert_eq!(snapshot.len(), IgnoredUpdateKind::ALL.len());
+        for (kind, count) in snapshot {
+            let expected = match kind {
+                IgnoredUpdateKind::Poll => 2,
+                IgnoredUpdateKind::Service => 1,
+                _ => 0,
+            };
+            assert_eq!(count, expected, "unexpected count for {}", kind.label());
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_settings_updates_persist_and_keep_other_fields() {
+        let path = std::path::PathBuf::from("target").join("test-dbs");
+        std::fs::create_dir_all(&path).expect("test db directory should exist");
+        let path = path.join(format!("telegram-chat-bot-state-{}.db", std::process::id()));
+        let _ = std::fs::File::create(&path).expect("test db file should be creatable");
+        let url = format!("sqlite://{}", path.to_string_lossy().replace('\\', "/"));
+        let db = Database::init(&url)
+            .await
+            .expect("test database should initialize");
+        let state = AppState::new(db.clone(), 1, "bot".to_string());
+
+        let chat_id = -100_127_100;
+        assert_eq!(
+            state.chat_settings.get(chat_id),
+            ChatSettingsRow::new(chat_id)
+        );
+        let (quiet, timezone) = tokio::join!(
+            state.update_chat_settings(chat_id, |settings| settings.quiet_mode = Some(true)),
+            state.update_chat_settings(chat_id, |settings| {
+                settings.timezone = Some("Asia/Shanghai".to_string())
+            }),
+        );
+        quiet.expect("quiet update should succeed");
+        timezone.expect("timezone update should succeed");
+
+        let cached = state.chat_settings.get(chat_id);
+        assert_eq!(cached.quiet_mode, Some(true));
+        assert_eq!(cached.timezone.as_deref(), Some("Asia/Shanghai"));
+
+        let reloaded = AppState::new(db, 1, "bot".to_string());
+        assert_eq!(reloaded.load_chat_settings().await.expect("load"), 1);
+        assert_eq!(reloaded.chat_settings.get(chat_id), cached);
+        assert_eq!(
+            reloaded
+                .chat_settings
+                .matching(|settings| settings.quiet_mode == Some(true)),
+            vec![cached]
+        );
+    }
+}
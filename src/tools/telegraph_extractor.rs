@@ -5,7 +5,7 @@ use serde::Deserialize;
 use tracing::info;
 use url::Url;
 
-use crate::utils::http::get_http_client;
+use crate::utils::http::extraction_get;
 
 #[derive(Debug, Deserialize)]
 struct TelegraphResponse {
@@ -38,9 +38,7 @@ pub async fn extract_telegraph_content(url: &str) -> Result<TelegraphContent> {
         "https://api.telegra.ph/getPage/{}?return_content=true",
         path
     );
-    let client = get_http_client();
-    let response = client
-        .get(&api_url)
+    let response = extraction_get(&api_url)
         .timeout(Duration::from_secs(15))
         .send()
         .await?;
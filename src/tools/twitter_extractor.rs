@@ -7,7 +7,7 @@ use regex::Regex;
 use tracing::{debug, info};
 use url::Url;
 
-use crate::utils::http::get_http_client;
+use crate::utils::http::extraction_get;
 
 #[derive(Debug, Clone)]
 pub struct TwitterContent {
@@ -20,8 +20,6 @@ pub struct TwitterContent {
 }
 
 const REQUEST_TIMEOUT: u64 = 20;
-const USER_AGENT: &str =
-    "TelegramGroupHelperBot/0.1 (+https://github.com/sailself/TelegramGroupHelperBot)";
 
 static TIMESTAMP_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"\d{1,2}:\d{2}\s?[AP]M").expect("valid timestamp regex"));
@@ -424,11 +422,8 @@ pub async fn extract_twitter_content(url: &str) -> Result<TwitterContent> {
     let proxy_url = build_proxy_url(&normalized_url);
     info!("Fetching Twitter/X content via proxy: {}", proxy_url);
 
-    let client = get_http_client();
-    let response = client
-        .get(proxy_url)
+    let response = extraction_get(&proxy_url)
         .timeout(Duration::from_secs(REQUEST_TIMEOUT))
-        .header("User-Agent", USER_AGENT)
         .send()
         .await?;
 
@@ -0,0 +1,122 @@
+//! Rewrites `COMMAND_ALIASES` commands to their canonical names.
+//!
+//! Aliases are resolved on the message itself before command dispatch, so the
+//! parsed arguments and any handler that re-reads the message text both see
+//! the canonical command (`/sum@bot last hour` becomes `/tldr@bot last hour`).
+
+use std::collections::HashMap;
+
+use teloxide::types::{MediaKind, Message, MessageEntity, MessageKind};
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Returns `text` with its leading command replaced by the canonical command
+/// when the command is a known alias. The `@botname` suffix and arguments
+/// are kept as-is.
+pub fn rewrite_command_alias(text: &str, aliases: &HashMap<String, String>) -> Option<String> {
+    let command_token = text.strip_prefix('/')?;
+    let token_end = command_token
+        .find(char::is_whitespace)
+        .unwrap_or(command_token.len());
+    let (token, rest) = command_token.split_at(token_end);
+    let (name, mention) = match token.split_once('@') {
+        Some((name, mention)) => (name, Some(mention)),
+        None => (token, None),
+    };
+    let canonical = aliases.get(&name.to_lowercase())?;
+    let mut rewritten = format!("/{canonical}");
+    if let Some(mention) = mention {
+        rewritten.push('@');
+        rewritten.push_str(mention);
+    }
+    rewritten.push_str(rest);
+    Some(rewritten)
+}
+
+fn shift_entities(entities: &mut [MessageEntity], old_command_len: usize, new_command_len: usize) {
+    for entity in entities {
+        if entity.offset == 0 && entity.length == old_command_len {
+            entity.length = new_command_len;
+        } else if entity.offset >= old_command_len {
+            entity.offset = entity.offset + new_command_len - old_command_len;
+        }
+    }
+}
+
+fn message_text_mut(message: &mut Message) -> Option<(&mut String, &mut Vec<MessageEntity>)> {
+    let MessageKind::Common(common) = &mut message.kind else {
+        return None;
+    };
+    match &mut common.media_kind {
+        MediaKind::Text(media) => Some((&mut media.text, &mut media.entities)),
+        MediaKind::Photo(media) => Some((media.caption.as_mut()?, &mut media.caption_entities)),
+        MediaKind::Document(media) => Some((media.caption.as_mut()?, &mut media.caption_entities)),
+        MediaKind::Video(media) => Some((media.caption.as_mut()?, &mut media.caption_entities)),
+        MediaKind::Animation(media) => Some((media.caption.as_mut()?, &mut media.caption_entities)),
+        MediaKind::Audio(media) => Some((media.caption.as_mut()?, &mut media.caption_entities)),
+        MediaKind::Voice(media) => Some((media.caption.as_mut()?, &mut media.caption_entities)),
+        _ => None,
+    }
+}
+
+/// Rewrites an aliased command in the message text or caption, keeping the
+/// bot-command entity and later entity offsets in step with the new text.
+pub fn apply_command_alias(mut message: Message, aliases: &HashMap<String, String>) -> Message {
+    if aliases.is_empty() {
+        return message;
+    }
+    let Some((text, entities)) = message_text_mut(&mut message) else {
+        return message;
+    };
+    let Some(rewritten) = rewrite_command_alias(text, aliases) else {
+        return message;
+    };
+    let command_len =
+        |value: &str| utf16_len(value.split(char::is_whitespace).next().unwrap_or_default());
+    let (old_len, new_len) = (command_len(text), command_len(&rewritten));
+    shift_entities(entities, old_len, new_len);
+    *text = rewritten;
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn aliases() -> HashMap<String, String> {
+        HashMap::from([("sum".to_string(), "tldr".to_string())])
+    }
+
+    #[test]
+    fn alias_rewrite_keeps_mention_arguments_and_entity_offsets() {
+        assert_eq!(
+            rewrite_command_alias("/SUM@HelperBot last hour", &aliases()).as_deref(),
+            Some("/tldr@HelperBot last hour")
+        );
+        assert_eq!(rewrite_command_alias("/summary", &aliases()), None);
+        assert_eq!(rewrite_command_alias("sum it up", &aliases()), None);
+
+        let message: Message = serde_json::from_value(json!({
+            "message_id": 1,
+            "date": 1,
+            "chat": { "id": -100123, "type": "group", "title": "test group" },
+            "from": { "id": 7, "is_bot": false, "first_name": "Alice" },
+            "text": "/sum see https://example.com",
+            "entities": [
+                { "type": "bot_command", "offset": 0, "length": 4 },
+                { "type": "url", "offset": 9, "length": 19 }
+            ]
+        }))
+        .expect("test message should deserialize");
+
+        let rewritten = apply_command_alias(message, &aliases());
+        assert_eq!(rewritten.text(), Some("/tldr see https://example.com"));
+        let entities = rewritten.entities().unwrap();
+        assert_eq!((entities[0].offset, entities[0].length), (0, 5));
+        assert_eq!((entities[1].offset, entities[1].length), (10, 19));
+    }
+}
@@ -1,29 +1,76 @@
-﻿use once_cell::sync::Lazy;
-use reqwest::Client;
+use once_cell::sync::Lazy;
+use reqwest::header::USER_AGENT;
+use reqwest::{Client, RequestBuilder, Url};
 use std::time::Duration;
 
+use crate::config::{ExtractionHeader, CONFIG};
+
 // Send TCP keepalive probes so long-lived (especially streaming SSE) connections
 // that go idle while a model reasons are kept warm and dead peers are detected,
 // reducing intermediary idle-connection drops that surface as body-decode errors.
 const TCP_KEEPALIVE: Duration = Duration::from_secs(30);
 
-static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
-    Client::builder()
-        .timeout(Duration::from_secs(30))
-        .tcp_keepalive(TCP_KEEPALIVE)
-        .build()
-        .expect("Failed to build HTTP client")
-});
+/// Settings shared by both process-wide clients. The request timeout is only
+/// a default: LLM calls override it per request with `RequestBuilder::timeout`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpClientSettings {
+    pub connect_timeout: Duration,
+    pub request_timeout: Duration,
+    pub pool_max_idle_per_host: usize,
+    pub user_agent: String,
+}
 
-static HTTP_CLIENT_NO_COMPRESSION: Lazy<Client> = Lazy::new(|| {
-    Client::builder()
-        .timeout(Duration::from_secs(30))
-        .tcp_keepalive(TCP_KEEPALIVE)
+impl HttpClientSettings {
+    pub fn from_config() -> Self {
+        Self {
+            connect_timeout: Duration::from_millis(CONFIG.http_connect_timeout_ms),
+            request_timeout: Duration::from_secs(CONFIG.http_request_timeout_secs),
+            pool_max_idle_per_host: CONFIG.http_pool_max_idle,
+            user_agent: CONFIG.http_user_agent.clone(),
+        }
+    }
+}
+
+fn client_builder(settings: &HttpClientSettings) -> reqwest::ClientBuilder {
+    let mut builder = Client::builder()
+        .connect_timeout(settings.connect_timeout)
+        .timeout(settings.request_timeout)
+        .pool_max_idle_per_host(settings.pool_max_idle_per_host)
+        .tcp_keepalive(TCP_KEEPALIVE);
+    if !settings.user_agent.trim().is_empty() {
+        builder = builder.user_agent(settings.user_agent.trim());
+    }
+    builder
+}
+
+pub fn build_http_client(settings: &HttpClientSettings) -> reqwest::Result<Client> {
+    client_builder(settings).build()
+}
+
+/// Like [`build_http_client`] but with a caller-chosen redirect policy, for
+/// fetches whose redirects must be re-checked hop by hop.
+pub fn build_http_client_with_redirect_policy(
+    settings: &HttpClientSettings,
+    policy: reqwest::redirect::Policy,
+) -> reqwest::Result<Client> {
+    client_builder(settings).redirect(policy).build()
+}
+
+fn build_http_client_no_compression(settings: &HttpClientSettings) -> reqwest::Result<Client> {
+    client_builder(settings)
         .no_gzip()
         .no_brotli()
         .no_deflate()
         .no_zstd()
         .build()
+}
+
+static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
+    build_http_client(&HttpClientSettings::from_config()).expect("Failed to build HTTP client")
+});
+
+static HTTP_CLIENT_NO_COMPRESSION: Lazy<Client> = Lazy::new(|| {
+    build_http_client_no_compression(&HttpClientSettings::from_config())
         .expect("Failed to build HTTP client without compression")
 });
 
@@ -34,3 +81,156 @@ pub fn get_http_client() -> &'static Client {
 pub fn get_http_client_no_compression() -> &'static Client {
     &HTTP_CLIENT_NO_COMPRESSION
 }
+
+pub(crate) fn host_matches_domain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+fn apply_extraction_headers(
+    mut request: RequestBuilder,
+    url: &str,
+    user_agent: &str,
+    domain_headers: &[ExtractionHeader],
+) -> RequestBuilder {
+    if !user_agent.trim().is_empty() {
+        request = request.header(USER_AGENT, user_agent.trim());
+    }
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .unwrap_or_default();
+    for header in domain_headers
+        .iter()
+        .filter(|header| host_matches_domain(&host, &header.domain))
+    {
+        request = request.header(header.name.as_str(), header.value.as_str());
+    }
+    request
+}
+
+/// GET `url` for content extraction, sending `EXTRACTION_USER_AGENT` and any
+/// `EXTRACTION_DOMAIN_HEADERS` matching its host.
+pub fn extraction_get(url: &str) -> RequestBuilder {
+    apply_extraction_headers(
+        get_http_client().get(url),
+        url,
+        &CONFIG.extraction_user_agent,
+        &CONFIG.extraction_domain_headers,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn test_settings(request_timeout: Duration) -> HttpClientSettings {
+        HttpClientSettings {
+            connect_timeout: Duration::from_secs(2),
+            request_timeout,
+            pool_max_idle_per_host: 4,
+            user_agent: "group-helper-test/1.0".to_string(),
+        }
+    }
+
+    /// Accepts one connection, returns the raw request head, and answers
+    /// after `delay`.
+    async fn serve_once(delay: Duration) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("loopback listener should bind");
+        let url = format!("http://{}/", listener.local_addr().expect("local addr"));
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("client should connect");
+            let mut buffer = vec![0u8; 4096];
+            let read = socket.read(&mut buffer).await.unwrap_or(0);
+            tokio::time::sleep(delay).await;
+            let _ = socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .await;
+            String::from_utf8_lossy(&buffer[..read]).to_string()
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn configured_user_agent_is_sent() {
+        let (url, server) = serve_once(Duration::ZERO).await;
+        let client =
+            build_http_client(&test_settings(Duration::from_secs(5))).expect("client should build");
+        let response = client
+            .get(&url)
+            .send()
+            .await
+            .expect("request should succeed");
+        assert!(response.status().is_success());
+        let head = server
+            .await
+            .expect("server task should finish")
+            .to_lowercase();
+        assert!(head.contains("user-agent: group-helper-test/1.0"), "{head}");
+    }
+
+    #[tokio::test]
+    async fn extraction_requests_send_configured_agent_and_domain_headers() {
+        let (url, server) = serve_once(Duration::ZERO).await;
+        let client =
+            build_http_client(&test_settings(Duration::from_secs(5))).expect("client should build");
+        let headers = [
+            ExtractionHeader {
+                domain: "127.0.0.1".to_string(),
+                name: "Referer".to_string(),
+                value: "https://x.com/".to_string(),
+            },
+            ExtractionHeader {
+                domain: "example.org".to_string(),
+                name: "X-Other".to_string(),
+                value: "1".to_string(),
+            },
+        ];
+        let response =
+            apply_extraction_headers(client.get(&url), &url, "ExtractorBrowser/2.0", &headers)
+                .send()
+                .await
+                .expect("request should succeed");
+        assert!(response.status().is_success());
+        let head = server
+            .await
+            .expect("server task should finish")
+            .to_lowercase();
+        assert!(head.contains("user-agent: extractorbrowser/2.0"), "{head}");
+        assert!(!head.contains("group-helper-test"), "{head}");
+        assert!(head.contains("referer: https://x.com/"), "{head}");
+        assert!(!head.contains("x-other"), "{head}");
+
+        assert!(host_matches_domain("pbs.twimg.com", "twimg.com"));
+        assert!(!host_matches_domain("nottwimg.com", "twimg.com"));
+    }
+
+    #[tokio::test]
+    async fn default_timeout_applies_and_per_request_override_wins() {
+        let client = build_http_client(&test_settings(Duration::from_millis(100)))
+            .expect("client should build");
+
+        let (url, _server) = serve_once(Duration::from_millis(600)).await;
+        let err = client
+            .get(&url)
+            .send()
+            .await
+            .expect_err("client default timeout should fire");
+        assert!(err.is_timeout());
+
+        let (url, _server) = serve_once(Duration::from_millis(300)).await;
+        let response = client
+            .get(&url)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .expect("per-request timeout should override the client default");
+        assert!(response.status().is_success());
+    }
+}
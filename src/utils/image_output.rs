@@ -0,0 +1,151 @@
+//! Re-encoding of generated images before they are sent to Telegram.
+//!
+//! Image models mostly return large PNGs. `IMAGE_OUTPUT_FORMAT=jpeg` (with
+//! `IMAGE_JPEG_QUALITY`) or `webp` shrinks the upload, while the default
+//! `png` keeps them lossless. An image that cannot be decoded, or that would
+//! only grow, is sent unchanged.
+
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::DynamicImage;
+use tracing::warn;
+
+use crate::config::CONFIG;
+use crate::llm::media::detect_mime_type;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageOutputFormat {
+    Png,
+    Jpeg,
+    /// Lossless WebP; the `image` crate has no lossy WebP encoder.
+    WebP,
+}
+
+impl ImageOutputFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "png" | "" => Some(ImageOutputFormat::Png),
+            "jpeg" | "jpg" => Some(ImageOutputFormat::Jpeg),
+            "webp" => Some(ImageOutputFormat::WebP),
+            _ => None,
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            ImageOutputFormat::Png => "image/png",
+            ImageOutputFormat::Jpeg => "image/jpeg",
+            ImageOutputFormat::WebP => "image/webp",
+        }
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageOutputFormat, jpeg_quality: u8) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let result = match format {
+        ImageOutputFormat::Png => {
+            image.write_to(&mut Cursor::new(&mut output), image::ImageFormat::Png)
+        }
+        // JPEG has no alpha channel.
+        ImageOutputFormat::Jpeg => JpegEncoder::new_with_quality(&mut output, jpeg_quality)
+            .encode_image(&DynamicImage::ImageRgb8(image.to_rgb8())),
+        ImageOutputFormat::WebP => WebPEncoder::new_lossless(&mut output).encode(
+            image.to_rgba8().as_raw(),
+            image.width(),
+            image.height(),
+            image::ExtendedColorType::Rgba8,
+        ),
+    };
+    match result {
+        Ok(()) => Some(output),
+        Err(err) => {
+            warn!("Failed to re-encode generated image as {format:?}: {err}");
+            None
+        }
+    }
+}
+
+/// Re-encodes `bytes` into `format`, keeping the original when it is already
+/// in that format or the re-encoded image would not be smaller.
+pub fn reencode_image(bytes: Vec<u8>, format: ImageOutputFormat, jpeg_quality: u8) -> Vec<u8> {
+    let source_mime = detect_mime_type(&bytes);
+    if source_mime.as_deref() == Some(format.mime_type()) {
+        return bytes;
+    }
+    let image = match image::load_from_memory(&bytes) {
+        Ok(image) => image,
+        Err(err) => {
+            warn!("Sending generated image as-is; cannot decode {source_mime:?}: {err}");
+            return bytes;
+        }
+    };
+    match encode(&image, format, jpeg_quality.clamp(1, 100)) {
+        Some(encoded) if encoded.len() < bytes.len() => encoded,
+        _ => bytes,
+    }
+}
+
+/// Applies `IMAGE_OUTPUT_FORMAT` and `IMAGE_JPEG_QUALITY` to generated images.
+pub fn reencode_output_images(images: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    images
+        .into_iter()
+        .map(|image| reencode_image(image, CONFIG.image_output_format, CONFIG.image_jpeg_quality))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageFormat, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn sample_png() -> Vec<u8> {
+        let image = RgbaImage::from_fn(256, 256, |x, y| {
+            Rgba([
+                (x * 7 % 256) as u8,
+                (y * 13 % 256) as u8,
+                ((x ^ y) % 256) as u8,
+                255,
+            ])
+        });
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .expect("sample PNG should encode");
+        png
+    }
+
+    #[test]
+    fn png_reencodes_to_a_smaller_jpeg() {
+        let png = sample_png();
+        let jpeg = reencode_image(png.clone(), ImageOutputFormat::Jpeg, 80);
+        assert_eq!(detect_mime_type(&jpeg).as_deref(), Some("image/jpeg"));
+        assert!(jpeg.len() < png.len(), "{} >= {}", jpeg.len(), png.len());
+
+        assert_eq!(reencode_image(png.clone(), ImageOutputFormat::Png, 80), png);
+        assert_eq!(
+            reencode_image(jpeg.clone(), ImageOutputFormat::Jpeg, 80),
+            jpeg
+        );
+        assert_eq!(
+            reencode_image(b"not an image".to_vec(), ImageOutputFormat::Jpeg, 80),
+            b"not an image".to_vec()
+        );
+    }
+
+    #[test]
+    fn parses_output_formats() {
+        assert_eq!(
+            ImageOutputFormat::parse(" JPG "),
+            Some(ImageOutputFormat::Jpeg)
+        );
+        assert_eq!(
+            ImageOutputFormat::parse("webp"),
+            Some(ImageOutputFormat::WebP)
+        );
+        assert_eq!(ImageOutputFormat::parse(""), Some(ImageOutputFormat::Png));
+        assert_eq!(ImageOutputFormat::parse("gif"), None);
+    }
+}
@@ -0,0 +1,229 @@
+//! Script-based language check behind `ENFORCE_RESPONSE_LANGUAGE`.
+//!
+//! Detection only looks at which writing system dominates, which is enough to
+//! catch the common failure (a Chinese question answered in English or the
+//! reverse) without a language model or an extra dependency. Languages that
+//! share a script, such as English and Spanish, are not told apart.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Minimum share of letters in the expected script for a response to count
+/// as written in the requested language.
+const MIN_EXPECTED_SCRIPT_SHARE: f64 = 0.25;
+/// Texts with fewer letters than this are too short to classify.
+const MIN_CLASSIFIABLE_LETTERS: usize = 4;
+
+static IGNORED_SPANS_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)```.*?```|`[^`]*`|https?://\S+|(?:^|\s)[/@]\w+")
+        .expect("ignored spans regex should compile")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptLanguage {
+    Chinese,
+    Japanese,
+    Korean,
+    Cyrillic,
+    Arabic,
+    Latin,
+}
+
+impl ScriptLanguage {
+    fn instruction_name(self) -> &'static str {
+        match self {
+            ScriptLanguage::Chinese => "Chinese",
+            ScriptLanguage::Japanese => "Japanese",
+            ScriptLanguage::Korean => "Korean",
+            ScriptLanguage::Cyrillic => "the user's Cyrillic-script language",
+            ScriptLanguage::Arabic => "the user's Arabic-script language",
+            ScriptLanguage::Latin => "the user's Latin-script language",
+        }
+    }
+
+    fn from_language_code(code: &str) -> Self {
+        let primary = code
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match primary.as_str() {
+            "zh" => ScriptLanguage::Chinese,
+            "ja" => ScriptLanguage::Japanese,
+            "ko" => ScriptLanguage::Korean,
+            "ru" | "uk" | "be" | "bg" | "sr" | "kk" | "mk" | "mn" => ScriptLanguage::Cyrillic,
+            "ar" | "fa" | "ur" => ScriptLanguage::Arabic,
+            _ => ScriptLanguage::Latin,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ScriptCounts {
+    han: usize,
+    kana: usize,
+    hangul: usize,
+    cyrillic: usize,
+    arabic: usize,
+    latin: usize,
+}
+
+impl ScriptCounts {
+    fn of(text: &str) -> Self {
+        let cleaned = IGNORED_SPANS_RE.replace_all(text, " ");
+        let mut counts = Self::default();
+        for ch in cleaned.chars() {
+            match ch {
+                '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' => counts.han += 1,
+                '\u{3040}'..='\u{30FF}' => counts.kana += 1,
+                '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}' => counts.hangul += 1,
+                '\u{0400}'..='\u{04FF}' => counts.cyrillic += 1,
+                '\u{0600}'..='\u{06FF}' => counts.arabic += 1,
+                ch if ch.is_alphabetic() && ch.is_ascii()
+                    || ('\u{00C0}'..='\u{024F}').contains(&ch) =>
+                {
+                    counts.latin += 1
+                }
+                _ => {}
+            }
+        }
+        counts
+    }
+
+    fn total(&self) -> usize {
+        self.han + self.kana + self.hangul + self.cyrillic + self.arabic + self.latin
+    }
+
+    fn dominant(&self) -> Option<ScriptLanguage> {
+        if self.total() < MIN_CLASSIFIABLE_LETTERS {
+            return None;
+        }
+        // Japanese mixes kanji and kana; any meaningful kana marks it.
+        if self.kana > 0 && self.kana * 10 >= self.han + self.kana {
+            return Some(ScriptLanguage::Japanese);
+        }
+        [
+            (self.han, ScriptLanguage::Chinese),
+            (self.hangul, ScriptLanguage::Korean),
+            (self.cyrillic, ScriptLanguage::Cyrillic),
+            (self.arabic, ScriptLanguage::Arabic),
+            (self.latin, ScriptLanguage::Latin),
+        ]
+        .into_iter()
+        .max_by_key(|(count, _)| *count)
+        .map(|(_, language)| language)
+    }
+
+    fn share_of(&self, language: ScriptLanguage) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            return 1.0;
+        }
+        let matching = match language {
+            ScriptLanguage::Chinese => self.han,
+            ScriptLanguage::Japanese if self.kana == 0 => 0,
+            ScriptLanguage::Japanese => self.han + self.kana,
+            ScriptLanguage::Korean => self.hangul,
+            ScriptLanguage::Cyrillic => self.cyrillic,
+            ScriptLanguage::Arabic => self.arabic,
+            ScriptLanguage::Latin => self.latin,
+        };
+        matching as f64 / total as f64
+    }
+}
+
+/// Dominant script language of `text`, or the language implied by the
+/// Telegram `fallback_code` when the text is too short to classify.
+pub fn detect_language_or_fallback(
+    text: &str,
+    fallback_code: Option<&str>,
+) -> Option<ScriptLanguage> {
+    ScriptCounts::of(text)
+        .dominant()
+        .or_else(|| fallback_code.map(ScriptLanguage::from_language_code))
+}
+
+/// Returns a stronger language instruction when `response` is not written in
+/// the language of `question`, or `None` when it matches or the expected
+/// language cannot be determined.
+pub fn response_language_retry_instruction(
+    question: &str,
+    telegram_language_code: Option<&str>,
+    response: &str,
+) -> Option<String> {
+    let expected = detect_language_or_fallback(question, telegram_language_code)?;
+    let counts = ScriptCounts::of(response);
+    if counts.total() < MIN_CLASSIFIABLE_LETTERS
+        || counts.share_of(expected) >= MIN_EXPECTED_SCRIPT_SHARE
+    {
+        return None;
+    }
+    Some(format!(
+        "IMPORTANT: Your previous answer was not written in the language of the user's request. Write the entire answer in {}, regardless of the language of any quoted or linked material, unless the user explicitly asked for a different output language.",
+        expected.instruction_name()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_dominant_script_and_falls_back_for_short_text() {
+        assert_eq!(
+            detect_language_or_fallback("这个函数为什么会报错？", None),
+            Some(ScriptLanguage::Chinese)
+        );
+        assert_eq!(
+            detect_language_or_fallback("これは何ですか", None),
+            Some(ScriptLanguage::Japanese)
+        );
+        assert_eq!(
+            detect_language_or_fallback("why does `cargo build` fail here?", None),
+            Some(ScriptLanguage::Latin)
+        );
+        assert_eq!(
+            detect_language_or_fallback("?? https://example.com", Some("zh-hans")),
+            Some(ScriptLanguage::Chinese)
+        );
+        assert_eq!(detect_language_or_fallback("ok", None), None);
+    }
+
+    #[test]
+    fn retries_when_response_is_in_another_script() {
+        let instruction = response_language_retry_instruction(
+            "请解释一下 Rust 的所有权",
+            Some("en"),
+            "Ownership in Rust means every value has a single owner.",
+        )
+        .expect("English answer to a Chinese question should be retried");
+        assert!(instruction.contains("Chinese"));
+
+        assert!(response_language_retry_instruction(
+            "What does this mean?",
+            Some("zh-hans"),
+            "这句话的意思是：明天会下雨。",
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn accepts_matching_or_mixed_responses() {
+        assert_eq!(
+            response_language_retry_instruction(
+                "请解释一下 Rust 的所有权",
+                None,
+                "Rust 的所有权（ownership）规则是：每个值都有一个所有者。\n```rust\nlet s = String::from(\"hello\");\n```",
+            ),
+            None
+        );
+        assert_eq!(
+            response_language_retry_instruction("翻译成英文：你好", None, "Hi!"),
+            None
+        );
+        assert_eq!(
+            response_language_retry_instruction("ok", None, "完全不同的语言"),
+            None
+        );
+    }
+}
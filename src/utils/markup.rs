@@ -0,0 +1,127 @@
+//! Repairs for model answers whose formatting Telegram would reject.
+//!
+//! A reply cut off mid code block leaves an odd number of ``` fences, and
+//! models sometimes emit `<pre>`/`<code>` tags that are unclosed, closed in
+//! the wrong order, or nested. Telegram refuses the whole message in each
+//! case, so [`sanitize_markup`] closes what is open and drops tags it cannot
+//! place before the text is sent. `SANITIZE_RESPONSE_MARKUP=false` turns
+//! this off.
+
+use teloxide::types::ParseMode;
+
+const CODE_FENCE: &str = "```";
+const HTML_CODE_TAGS: [&str; 2] = ["pre", "code"];
+
+/// Appends a closing fence when the text has an odd number of ``` fences.
+pub fn balance_code_fences(text: &str) -> String {
+    if text.matches(CODE_FENCE).count().is_multiple_of(2) {
+        return text.to_string();
+    }
+    let separator = if text.ends_with('\n') { "" } else { "\n" };
+    format!("{text}{separator}{CODE_FENCE}")
+}
+
+/// Parses `<pre ...>`, `</pre>`, `<code ...>`, or `</code>` at the start of
+/// `rest`, returning the tag name, whether it closes, and its length.
+fn code_tag_at(rest: &str) -> Option<(&'static str, bool, usize)> {
+    let end = rest.find('>')?;
+    let inner = &rest[1..end];
+    let (closing, inner) = match inner.strip_prefix('/') {
+        Some(inner) => (true, inner),
+        None => (false, inner),
+    };
+    let name_len = inner
+        .find(|ch: char| ch.is_whitespace())
+        .unwrap_or(inner.len());
+    let name = inner[..name_len].to_ascii_lowercase();
+    let tag = HTML_CODE_TAGS.into_iter().find(|tag| *tag == name)?;
+    if closing && name_len != inner.len() {
+        return None;
+    }
+    Some((tag, closing, end + 1))
+}
+
+/// Closes unclosed `<pre>`/`<code>` tags, closes inner tags before an outer
+/// one that ends first, and drops closing tags with no matching opener and
+/// openers nested where Telegram does not accept them.
+pub fn balance_html_code_tags(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut open: Vec<&str> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some((tag, closing, len)) = code_tag_at(rest) else {
+            output.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        if !closing {
+            // Telegram only allows `<code>` directly inside `<pre>`; any
+            // other nested code tag is dropped.
+            if open.is_empty() || (open == ["pre"] && tag == "code") {
+                open.push(tag);
+                output.push_str(&rest[..len]);
+            }
+        } else if let Some(position) = open.iter().rposition(|open_tag| *open_tag == tag) {
+            for inner in open.drain(position..).rev() {
+                output.push_str(&format!("</{inner}>"));
+            }
+        }
+        rest = &rest[len..];
+    }
+    output.push_str(rest);
+    for tag in open.into_iter().rev() {
+        output.push_str(&format!("</{tag}>"));
+    }
+    output
+}
+
+/// Applies the repair that matches `parse_mode`.
+pub fn sanitize_markup(text: &str, parse_mode: ParseMode) -> String {
+    match parse_mode {
+        ParseMode::Html => balance_html_code_tags(text),
+        #[allow(deprecated)]
+        ParseMode::Markdown | ParseMode::MarkdownV2 => balance_code_fences(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closes_unterminated_code_fence() {
+        let cut_off = "Here is the fix:\n```rust\nfn main() {\n    println!(\"hi\");";
+        let repaired = balance_code_fences(cut_off);
+        assert_eq!(repaired, format!("{cut_off}\n```"));
+        assert_eq!(repaired.matches(CODE_FENCE).count(), 2);
+
+        let balanced = "```\nok\n```\nand `inline` code";
+        assert_eq!(balance_code_fences(balanced), balanced);
+        assert_eq!(
+            sanitize_markup("```sh\nls\n", ParseMode::MarkdownV2),
+            "```sh\nls\n```"
+        );
+    }
+
+    #[test]
+    fn repairs_unbalanced_and_misnested_html_code_tags() {
+        assert_eq!(
+            balance_html_code_tags("<pre><code class=\"language-rust\">let x = 1;"),
+            "<pre><code class=\"language-rust\">let x = 1;</code></pre>"
+        );
+        assert_eq!(
+            balance_html_code_tags("<pre><code>a</pre></code> done"),
+            "<pre><code>a</code></pre> done"
+        );
+        assert_eq!(
+            balance_html_code_tags("x < y </code><b>bold</b>"),
+            "x < y <b>bold</b>"
+        );
+        assert_eq!(
+            sanitize_markup("<code>1 <pre>2</pre>", ParseMode::Html),
+            "<code>1 2</code>"
+        );
+    }
+}
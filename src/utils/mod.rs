@@ -1,5 +1,15 @@
+pub mod command_alias;
 pub mod http;
+pub mod image_output;
+pub mod language;
 pub mod logging;
+pub mod markup;
 pub mod progress;
+pub mod prompt_budget;
+pub mod redaction;
+pub mod request_id;
+pub mod retry;
+pub mod send_guard;
 pub mod telegram;
+pub mod timezone;
 pub mod timing;
@@ -12,6 +12,8 @@ use std::time::{Duration, Instant};
 use teloxide::prelude::*;
 use teloxide::types::MessageId;
 use teloxide::RequestError;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
 use tracing::{debug, warn};
 
 const MIN_EDIT_INTERVAL: Duration = Duration::from_millis(2_500);
@@ -20,7 +22,8 @@ const UPDATE_NOW_MAX_ATTEMPTS: usize = 2;
 pub struct ProgressReporter {
     bot: Bot,
     chat_id: ChatId,
-    message_id: MessageId,
+    /// `None` for a silent reporter (quiet mode has no message to edit).
+    message_id: Option<MessageId>,
     min_interval: Duration,
     /// Earliest moment the next edit may be sent (advanced on success and on
     /// `RetryAfter`, so flood-control waits are respected across updates).
@@ -47,6 +50,12 @@ fn should_emit(
 
 impl ProgressReporter {
     pub fn new(bot: Bot, chat_id: ChatId, message_id: MessageId) -> Self {
+        Self::for_message(bot, chat_id, Some(message_id))
+    }
+
+    /// With `None` every update is dropped, for commands running without a
+    /// processing message.
+    pub fn for_message(bot: Bot, chat_id: ChatId, message_id: Option<MessageId>) -> Self {
         Self {
             bot,
             chat_id,
@@ -91,9 +100,12 @@ impl ProgressReporter {
     }
 
     async fn try_edit(&mut self, text: &str) -> bool {
+        let Some(message_id) = self.message_id else {
+            return true;
+        };
         match self
             .bot
-            .edit_message_text(self.chat_id, self.message_id, text)
+            .edit_message_text(self.chat_id, message_id, text)
             .await
         {
             Ok(_) => {
@@ -118,6 +130,36 @@ impl ProgressReporter {
     }
 }
 
+/// Feeds step messages from a tool loop into a [`ProgressReporter`] on a
+/// background task, so the loop never waits on Telegram.
+pub struct ProgressForwarder {
+    sender: UnboundedSender<String>,
+    task: JoinHandle<()>,
+}
+
+impl ProgressForwarder {
+    pub fn spawn(mut reporter: ProgressReporter) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+        let task = tokio::spawn(async move {
+            while let Some(text) = receiver.recv().await {
+                reporter.update(&text).await;
+            }
+        });
+        Self { sender, task }
+    }
+
+    pub fn sender(&self) -> UnboundedSender<String> {
+        self.sender.clone()
+    }
+
+    /// Stops forwarding before the final answer is written, so a late step
+    /// edit cannot overwrite it.
+    pub async fn finish(self) {
+        self.task.abort();
+        let _ = self.task.await;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,170 @@
+//! `MAX_PROMPT_CHARS` enforcement for `/q` and `/factcheck`.
+//!
+//! A prompt is split into the user's own question, which is never cut, and
+//! context sections ordered from least to most expendable: text pulled from
+//! Telegraph or Twitter links goes first, then the replied-to message. Cut
+//! sections end with [`CONTEXT_TRUNCATED_MARKER`] so the model knows it is
+//! not seeing everything.
+
+pub const CONTEXT_TRUNCATED_MARKER: &str = "[context truncated]";
+
+/// Splits text returned by the link extractors into the user's own text and
+/// the extracted blocks the extractors appended after it.
+pub fn split_extracted<'a>(processed: &'a str, original: &str) -> (&'a str, &'a str) {
+    match processed.strip_prefix(original) {
+        Some(extracted) => (&processed[..original.len()], extracted),
+        None => (processed, ""),
+    }
+}
+
+fn char_len(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// The marker on its own line, which is also what a fully dropped section
+/// becomes.
+fn marker_line_chars() -> usize {
+    char_len(CONTEXT_TRUNCATED_MARKER) + 1
+}
+
+fn truncate_with_marker(text: &str, target_chars: usize) -> String {
+    let kept: String = text
+        .chars()
+        .take(target_chars.saturating_sub(marker_line_chars()))
+        .collect();
+    format!("{}\n{CONTEXT_TRUNCATED_MARKER}", kept.trim_end())
+}
+
+/// Shrinks `sections`, earliest first, until they plus `reserved_chars` of
+/// untouchable text fit in `max_chars` (`0` disables the limit). A section
+/// that has to go entirely is replaced by the marker. Returns whether
+/// anything was cut; when the reserved text alone is over the limit every
+/// section is cut and the prompt still ends up longer than `max_chars`.
+pub fn fit_context_sections(
+    sections: &mut [String],
+    reserved_chars: usize,
+    max_chars: usize,
+) -> bool {
+    if max_chars == 0 {
+        return false;
+    }
+    let total = reserved_chars + sections.iter().map(|s| char_len(s)).sum::<usize>();
+    let mut overflow = total.saturating_sub(max_chars);
+    if overflow == 0 {
+        return false;
+    }
+    for section in sections.iter_mut() {
+        let len = char_len(section);
+        if len <= marker_line_chars() {
+            continue;
+        }
+        let truncated = truncate_with_marker(section, len.saturating_sub(overflow));
+        overflow = overflow.saturating_sub(len - char_len(&truncated));
+        *section = truncated;
+        if overflow == 0 {
+            break;
+        }
+    }
+    true
+}
+
+/// Applies the limit to a question and the message it replies to, each as
+/// returned by the link extractors alongside its raw text. Link extracts are
+/// cut before the replied-to message; the question, or the reply when it
+/// stands in for an empty question, is kept whole. `wrapper_chars` is the
+/// fixed text the caller puts around the two parts.
+pub fn fit_question_and_reply(
+    query_text: &str,
+    query_text_raw: &str,
+    reply_text: &str,
+    reply_text_raw: &str,
+    wrapper_chars: usize,
+    max_chars: usize,
+) -> (String, String) {
+    let (query_own, query_extracted) = split_extracted(query_text, query_text_raw);
+    let (reply_own, reply_extracted) = split_extracted(reply_text, reply_text_raw);
+    if query_own.trim().is_empty() {
+        let mut sections = [reply_extracted.to_string()];
+        fit_context_sections(&mut sections, char_len(reply_own), max_chars);
+        let [reply_extracted] = sections;
+        return (
+            query_text.to_string(),
+            format!("{reply_own}{reply_extracted}"),
+        );
+    }
+
+    let mut sections = [
+        reply_extracted.to_string(),
+        query_extracted.to_string(),
+        reply_own.to_string(),
+    ];
+    fit_context_sections(
+        &mut sections,
+        char_len(query_own) + wrapper_chars,
+        max_chars,
+    );
+    let [reply_extracted, query_extracted, reply_own] = sections;
+    (
+        format!("{query_own}{query_extracted}"),
+        format!("{reply_own}{reply_extracted}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_extracted_content_before_reply_and_keeps_question() {
+        let question = "Is this article accurate? https://telegra.ph/other";
+        let question_processed = format!(
+            "{question}\n[Telegraph content extracted from https://telegra.ph/other]\n{}\n",
+            "dolor sit ".repeat(100)
+        );
+        let reply = "Read this: https://telegra.ph/page";
+        let reply_processed = format!(
+            "{reply}\n[Telegraph content extracted from https://telegra.ph/page]\n{}\n",
+            "lorem ipsum ".repeat(200)
+        );
+        assert_eq!(split_extracted(&reply_processed, reply).0, reply);
+
+        let (query_text, reply_text) = fit_question_and_reply(
+            &question_processed,
+            question,
+            &reply_processed,
+            reply,
+            40,
+            600,
+        );
+
+        assert!(query_text.starts_with(question));
+        assert!(query_text.contains("dolor sit"));
+        assert!(query_text.ends_with(CONTEXT_TRUNCATED_MARKER));
+        assert_eq!(reply_text, format!("{reply}\n{CONTEXT_TRUNCATED_MARKER}"));
+        assert!(char_len(&query_text) + char_len(&reply_text) + 40 <= 600);
+
+        let (query_text, reply_text) =
+            fit_question_and_reply(question, question, &reply_processed, reply, 40, 10);
+        assert_eq!(query_text, question);
+        assert_eq!(
+            reply_text,
+            format!("\n{CONTEXT_TRUNCATED_MARKER}").repeat(2)
+        );
+    }
+
+    #[test]
+    fn cuts_reply_only_once_extracted_content_is_gone() {
+        let mut sections = vec!["x".repeat(100), "回复".repeat(100)];
+        assert!(fit_context_sections(&mut sections, 50, 120));
+        assert_eq!(sections[0], format!("\n{CONTEXT_TRUNCATED_MARKER}"));
+        assert!(sections[1].starts_with("回复"));
+        assert!(sections[1].ends_with(CONTEXT_TRUNCATED_MARKER));
+        let total: usize = sections.iter().map(|s| char_len(s)).sum();
+        assert_eq!(total + 50, 120);
+
+        let mut untouched = vec!["short".to_string()];
+        assert!(!fit_context_sections(&mut untouched, 10, 100));
+        assert!(!fit_context_sections(&mut untouched, 10_000, 0));
+        assert_eq!(untouched, vec!["short".to_string()]);
+    }
+}
@@ -0,0 +1,144 @@
+//! Optional masking of PII and profanity before messages are stored.
+//!
+//! Enabled with `MESSAGE_REDACTION_ENABLED`. Emails and phone numbers are
+//! always masked; profanity masking uses the words listed in
+//! `MESSAGE_REDACTION_WORDS`. Everything downstream (`/tldr`, `/search`, chat
+//! context) only ever sees the redacted text.
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+use crate::config::CONFIG;
+
+const EMAIL_MASK: &str = "[email]";
+const PHONE_MASK: &str = "[phone]";
+const PROFANITY_MASK: &str = "***";
+
+static EMAIL_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}")
+        .expect("email regex should compile")
+});
+
+static PHONE_CANDIDATE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\+?\d[\d\s().-]{6,}\d").expect("phone regex should compile"));
+
+static REDACTOR: Lazy<Redactor> = Lazy::new(|| Redactor::new(&CONFIG.message_redaction_words));
+
+pub struct Redactor {
+    profanity: Option<Regex>,
+}
+
+impl Redactor {
+    pub fn new(words: &[String]) -> Self {
+        let alternatives = words
+            .iter()
+            .map(|word| word.trim())
+            .filter(|word| !word.is_empty())
+            .map(regex::escape)
+            .collect::<Vec<_>>();
+        let profanity = if alternatives.is_empty() {
+            None
+        } else {
+            Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|"))).ok()
+        };
+        Self { profanity }
+    }
+
+    /// Returns the masked text, or `None` when nothing needed masking.
+    pub fn redact(&self, text: &str) -> Option<String> {
+        let mut changed = false;
+        let mut output = EMAIL_RE.replace_all(text, EMAIL_MASK).into_owned();
+        changed |= output != text;
+
+        let phone_masked = PHONE_CANDIDATE_RE
+            .replace_all(&output, |caps: &Captures| {
+                let candidate = &caps[0];
+                if looks_like_phone_number(candidate) {
+                    PHONE_MASK.to_string()
+                } else {
+                    candidate.to_string()
+                }
+            })
+            .into_owned();
+        changed |= phone_masked != output;
+        output = phone_masked;
+
+        if let Some(profanity) = &self.profanity {
+            let masked = profanity.replace_all(&output, PROFANITY_MASK).into_owned();
+            changed |= masked != output;
+            output = masked;
+        }
+
+        changed.then_some(output)
+    }
+}
+
+/// Phone-shaped digit runs have 9–15 digits, or 7+ with a leading `+`. This
+/// keeps dates (`2024-01-31`), short ids, and prices out of the mask.
+fn looks_like_phone_number(candidate: &str) -> bool {
+    let digits = candidate.chars().filter(char::is_ascii_digit).count();
+    if candidate.starts_with('+') {
+        (7..=15).contains(&digits)
+    } else {
+        (9..=15).contains(&digits)
+    }
+}
+
+/// Applies the configured redaction to `text`. Returns the text to store and
+/// whether anything was masked.
+pub fn redact_for_storage(text: Option<String>) -> (Option<String>, bool) {
+    if !CONFIG.message_redaction_enabled {
+        return (text, false);
+    }
+    match text.as_deref().and_then(|value| REDACTOR.redact(value)) {
+        Some(redacted) => (Some(redacted), true),
+        None => (text, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(words: &[&str]) -> Redactor {
+        Redactor::new(
+            &words
+                .iter()
+                .map(|word| word.to_string())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn masks_emails_and_phone_numbers() {
+        let redactor = redactor(&[]);
+        assert_eq!(
+            redactor
+                .redact("mail me at john.doe+bot@example.co.uk or call +1 (415) 555-0199")
+                .as_deref(),
+            Some("mail me at [email] or call [phone]")
+        );
+        assert_eq!(
+            redactor.redact("我的手机是 13812345678").as_deref(),
+            Some("我的手机是 [phone]")
+        );
+    }
+
+    #[test]
+    fn leaves_dates_ids_and_clean_text_alone() {
+        let redactor = redactor(&[]);
+        assert_eq!(redactor.redact("meeting on 2024-01-31 at 10:30"), None);
+        assert_eq!(redactor.redact("order 1234567 costs $19.99"), None);
+        assert_eq!(redactor.redact("nothing to see here"), None);
+    }
+
+    #[test]
+    fn masks_configured_profanity_on_word_boundaries() {
+        let redactor = redactor(&["darn", " heck "]);
+        assert_eq!(
+            redactor.redact("Darn it, what the HECK").as_deref(),
+            Some("*** it, what the ***")
+        );
+        assert_eq!(redactor.redact("darnell is here"), None);
+    }
+}
@@ -0,0 +1,107 @@
+//! Per-command correlation ids.
+//!
+//! Every command handler spawned from `handle_command` runs inside a
+//! `command` span carrying a short `request_id`, so the log lines one `/q`
+//! produces in `qa.rs`, `gemini.rs`, and `content.rs` can be grepped
+//! together. The id is also kept in a task-local so error replies can quote
+//! it back to the user.
+
+use std::future::Future;
+
+use tokio::task::JoinHandle;
+use tracing::{info_span, Instrument, Span};
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Eight hex characters; unique enough to tell concurrent requests apart.
+pub fn new_request_id() -> String {
+    format!("{:08x}", fastrand::u32(..))
+}
+
+/// The request id of the command this task is serving, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Appends the current request id to a user-facing error message.
+pub fn with_request_id(text: &str) -> String {
+    match current_request_id() {
+        Some(request_id) => format!("{text}\n\nRequest ID: {request_id}"),
+        None => text.to_string(),
+    }
+}
+
+/// Runs `future` with `request_id` set and inside a `command` span.
+pub async fn run_traced<F: Future>(command: &str, request_id: String, future: F) -> F::Output {
+    let span = command_span(command, &request_id);
+    REQUEST_ID.scope(request_id, future.instrument(span)).await
+}
+
+fn command_span(command: &str, request_id: &str) -> Span {
+    info_span!("command", command = %command, request_id = %request_id)
+}
+
+/// `tokio::spawn` for a command handler, with a fresh request id.
+pub fn spawn_traced<F>(command: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let command = command.to_string();
+    tokio::spawn(async move { run_traced(&command, new_request_id(), future).await })
+}
+
+/// `tokio::spawn` that keeps the current request id and span, for work a
+/// command hands off to a background task.
+pub fn spawn_in_current_request<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let future = future.in_current_span();
+    match current_request_id() {
+        Some(request_id) => tokio::spawn(REQUEST_ID.scope(request_id, future)),
+        None => tokio::spawn(future),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn nested_call() -> (Option<String>, Option<&'static str>) {
+        (
+            current_request_id(),
+            Span::current().metadata().map(|metadata| metadata.name()),
+        )
+    }
+
+    #[tokio::test]
+    async fn request_id_reaches_nested_calls() {
+        let subscriber = tracing_subscriber::fmt().with_test_writer().finish();
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let (request_id, span_name) = run_traced("q", "0badcafe".to_string(), nested_call()).await;
+        assert_eq!(request_id.as_deref(), Some("0badcafe"));
+        assert_eq!(span_name, Some("command"));
+
+        let reply = run_traced("q", "0badcafe".to_string(), async {
+            with_request_id("Something went wrong.")
+        })
+        .await;
+        assert_eq!(reply, "Something went wrong.\n\nRequest ID: 0badcafe");
+
+        let handed_off = run_traced("q", "0badcafe".to_string(), async {
+            spawn_in_current_request(nested_call()).await
+        })
+        .await
+        .expect("spawned task should finish");
+        assert_eq!(handed_off, (Some("0badcafe".to_string()), Some("command")));
+
+        assert_eq!(current_request_id(), None);
+        assert_eq!(with_request_id("plain"), "plain");
+        assert_eq!(new_request_id().len(), 8);
+    }
+}
@@ -0,0 +1,305 @@
+//! Shared retry loop for Telegram sends and provider HTTP calls.
+//!
+//! Callers describe the schedule with a [`RetryPolicy`] and decide per error
+//! whether another attempt is worthwhile; the loop itself only counts
+//! attempts and sleeps. Logging stays with the caller, which knows what the
+//! operation was.
+
+use std::future::Future;
+use std::time::Duration;
+
+use teloxide::RequestError;
+use tracing::warn;
+
+use crate::config::CONFIG;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// `base`, `2 * base`, `4 * base`, ...
+    Exponential,
+    /// `base`, `2 * base`, `3 * base`, ...
+    Linear,
+}
+
+/// How much of each backoff delay is randomized, so clients that failed
+/// together during an outage do not retry in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jitter {
+    None,
+    /// Uniform in `0..=delay`.
+    Full,
+    /// `delay / 2` plus uniform in `0..=delay / 2`.
+    Equal,
+}
+
+impl Jitter {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "none" | "off" | "false" => Some(Jitter::None),
+            "full" => Some(Jitter::Full),
+            "equal" | "" => Some(Jitter::Equal),
+            _ => None,
+        }
+    }
+
+    fn apply(self, delay: Duration) -> Duration {
+        match self {
+            Jitter::None => delay,
+            Jitter::Full => delay.mul_f64(fastrand::f64()),
+            Jitter::Equal => {
+                let half = delay / 2;
+                half + half.mul_f64(fastrand::f64())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one.
+    pub max_attempts: usize,
+    pub base_delay: Duration,
+    pub backoff: Backoff,
+    pub jitter: Jitter,
+}
+
+impl RetryPolicy {
+    pub const fn exponential(max_attempts: usize, base_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+            backoff: Backoff::Exponential,
+            jitter: Jitter::None,
+        }
+    }
+
+    pub const fn linear(max_attempts: usize, base_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+            backoff: Backoff::Linear,
+            jitter: Jitter::None,
+        }
+    }
+
+    pub const fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Un-jittered wait after the failed `attempt` (1-based).
+    pub fn delay_for_attempt(&self, attempt: usize) -> Duration {
+        let attempt = attempt.max(1);
+        let factor = match self.backoff {
+            Backoff::Exponential => 1u32.checked_shl((attempt - 1) as u32).unwrap_or(u32::MAX),
+            Backoff::Linear => u32::try_from(attempt).unwrap_or(u32::MAX),
+        };
+        self.base_delay.saturating_mul(factor)
+    }
+
+    /// Applies `RETRY_JITTER` to this policy.
+    pub fn with_configured_jitter(self) -> Self {
+        self.with_jitter(CONFIG.retry_jitter)
+    }
+
+    fn jittered_delay(&self, attempt: usize) -> Duration {
+        self.jitter.apply(self.delay_for_attempt(attempt))
+    }
+}
+
+/// Schedule for Bot API sends and edits: three tries, about 1.5s then 3s
+/// apart before jitter.
+pub fn telegram_retry_policy() -> RetryPolicy {
+    RetryPolicy::exponential(3, Duration::from_millis(1500)).with_configured_jitter()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    Stop,
+    /// Retry after the policy's backoff delay.
+    Retry,
+    /// Retry after a server-provided wait such as Telegram's `retry_after`.
+    RetryAfter(Duration),
+}
+
+/// An error whose retryability was decided where it was produced, for
+/// operations that fail in several ways (send errors, error statuses).
+#[derive(Debug)]
+pub struct ClassifiedError<E> {
+    pub error: E,
+    pub retryable: bool,
+}
+
+impl<E> ClassifiedError<E> {
+    pub fn new(error: E, retryable: bool) -> Self {
+        Self { error, retryable }
+    }
+
+    pub fn decision(&self) -> RetryDecision {
+        if self.retryable {
+            RetryDecision::Retry
+        } else {
+            RetryDecision::Stop
+        }
+    }
+}
+
+/// Runs `op` until it succeeds, `decide` returns [`RetryDecision::Stop`], or
+/// the policy's attempts are used up; the last error is returned. `op` and
+/// `decide` receive the 1-based attempt number. `decide` is only consulted
+/// when another attempt is still allowed.
+pub async fn retry_async<T, E, F, Fut, D>(
+    policy: &RetryPolicy,
+    mut op: F,
+    mut decide: D,
+) -> Result<T, E>
+where
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    D: FnMut(&E, usize) -> RetryDecision,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        let err = match op(attempt).await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        if attempt >= max_attempts {
+            return Err(err);
+        }
+        let wait = match decide(&err, attempt) {
+            RetryDecision::Stop => return Err(err),
+            RetryDecision::Retry => policy.jittered_delay(attempt),
+            RetryDecision::RetryAfter(wait) => wait,
+        };
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+/// Retries network failures and flood-control waits; other Bot API errors
+/// (bad request, forbidden, ...) would fail the same way again.
+pub fn telegram_retry_decision(
+    operation: &str,
+    err: &RequestError,
+    attempt: usize,
+) -> RetryDecision {
+    let decision = match err {
+        RequestError::RetryAfter(wait) => RetryDecision::RetryAfter(wait.duration()),
+        RequestError::Network(_) | RequestError::Io(_) => RetryDecision::Retry,
+        _ => RetryDecision::Stop,
+    };
+    if decision != RetryDecision::Stop {
+        warn!("{operation} attempt {attempt} failed: {err}");
+    }
+    decision
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    const FAST: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn delays_grow_with_the_backoff_kind() {
+        let exponential = RetryPolicy::exponential(5, Duration::from_millis(1500));
+        assert_eq!(
+            exponential.delay_for_attempt(1),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            exponential.delay_for_attempt(2),
+            Duration::from_millis(3000)
+        );
+        assert_eq!(
+            exponential.delay_for_attempt(3),
+            Duration::from_millis(6000)
+        );
+
+        let linear = RetryPolicy::linear(5, Duration::from_millis(900));
+        assert_eq!(linear.delay_for_attempt(0), Duration::from_millis(900));
+        assert_eq!(linear.delay_for_attempt(3), Duration::from_millis(2700));
+
+        assert_eq!(linear.jittered_delay(2), Duration::from_millis(1800));
+    }
+
+    #[test]
+    fn jittered_delays_stay_within_their_bounds() {
+        let policy = RetryPolicy::exponential(5, Duration::from_millis(1000));
+        let full = policy.with_jitter(Jitter::Full);
+        let equal = policy.with_jitter(Jitter::Equal);
+        let mut full_delays = Vec::new();
+        for _ in 0..200 {
+            let delay = full.jittered_delay(3);
+            assert!(delay <= Duration::from_millis(4000));
+            full_delays.push(delay);
+
+            let delay = equal.jittered_delay(3);
+            assert!(delay >= Duration::from_millis(2000) && delay <= Duration::from_millis(4000));
+        }
+        full_delays.dedup();
+        assert!(
+            full_delays.len() > 1,
+            "full jitter should vary between retries"
+        );
+
+        assert_eq!(Jitter::parse("FULL"), Some(Jitter::Full));
+        assert_eq!(Jitter::parse("off"), Some(Jitter::None));
+        assert_eq!(Jitter::parse("random"), None);
+    }
+
+    #[tokio::test]
+    async fn retries_until_success_or_attempts_run_out() {
+        let calls = AtomicUsize::new(0);
+        let result = retry_async(
+            &RetryPolicy::exponential(3, FAST),
+            |attempt| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if attempt < 3 {
+                        Err(attempt)
+                    } else {
+                        Ok("done")
+                    }
+                }
+            },
+            |_, _| RetryDecision::Retry,
+        )
+        .await;
+        assert_eq!(result, Ok("done"));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let calls = AtomicUsize::new(0);
+        let result: Result<(), usize> = retry_async(
+            &RetryPolicy::linear(2, FAST),
+            |attempt| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async move { Err(attempt) }
+            },
+            |_, _| RetryDecision::Retry,
+        )
+        .await;
+        assert_eq!(result, Err(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn non_retryable_errors_stop_immediately() {
+        let calls = AtomicUsize::new(0);
+        let result: Result<(), ClassifiedError<&str>> = retry_async(
+            &RetryPolicy::exponential(5, FAST),
+            |_| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async { Err(ClassifiedError::new("bad request", false)) }
+            },
+            |err, _| err.decision(),
+        )
+        .await;
+        assert_eq!(result.map_err(|err| err.error), Err("bad request"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
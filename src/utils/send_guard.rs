@@ -0,0 +1,167 @@
+//! Opt-in guard against sending the same text to a chat twice in a row.
+//!
+//! Retry and fallback paths occasionally post a message identical to the one
+//! just sent (a processing notice followed by the same notice, or the same
+//! error twice). With `DUPLICATE_SEND_WINDOW_SECONDS` above zero, a send whose
+//! text and reply target match the chat's previous send within the window is
+//! skipped and the earlier message is returned in its place. The guard is
+//! process-wide because the send helpers are called without `AppState`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use teloxide::types::{Message, MessageId};
+use tracing::info;
+
+use crate::config::CONFIG;
+
+#[derive(Debug, Clone)]
+struct LastSend {
+    text: String,
+    reply_to: Option<MessageId>,
+    sent_at: Instant,
+    message: Message,
+}
+
+#[derive(Debug, Default)]
+pub struct DuplicateSendGuard {
+    window: Duration,
+    last_sends: Mutex<HashMap<i64, LastSend>>,
+}
+
+impl DuplicateSendGuard {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last_sends: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    fn recent_duplicate(
+        &self,
+        chat_id: i64,
+        text: &str,
+        reply_to: Option<MessageId>,
+    ) -> Option<Message> {
+        let last_sends = self.last_sends.lock();
+        let last = last_sends.get(&chat_id)?;
+        (last.text == text && last.reply_to == reply_to && last.sent_at.elapsed() <= self.window)
+            .then(|| last.message.clone())
+    }
+
+    fn record(&self, chat_id: i64, text: &str, reply_to: Option<MessageId>, message: &Message) {
+        self.last_sends.lock().insert(
+            chat_id,
+            LastSend {
+                text: text.to_string(),
+                reply_to,
+                sent_at: Instant::now(),
+                message: message.clone(),
+            },
+        );
+    }
+
+    /// Calls `send` unless it would repeat the chat's previous send within the
+    /// window, in which case the earlier message is returned instead.
+    pub async fn send_once<F, Fut>(
+        &self,
+        chat_id: i64,
+        text: &str,
+        reply_to: Option<MessageId>,
+        send: F,
+    ) -> Result<Message>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Message>>,
+    {
+        if !self.is_enabled() {
+            return send().await;
+        }
+        if let Some(previous) = self.recent_duplicate(chat_id, text, reply_to) {
+            info!(
+                chat_id,
+                previous_message_id = previous.id.0,
+                "Suppressed duplicate consecutive bot message"
+            );
+            return Ok(previous);
+        }
+        let message = send().await?;
+        self.record(chat_id, text, reply_to, &message);
+        Ok(message)
+    }
+}
+
+pub static DUPLICATE_SENDS: Lazy<DuplicateSendGuard> = Lazy::new(|| {
+    DuplicateSendGuard::new(Duration::from_secs(CONFIG.duplicate_send_window_seconds))
+});
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn sent_message(message_id: i32, text: &str) -> Message {
+        serde_json::from_value(json!({
+            "message_id": message_id,
+            "date": 1,
+            "chat": { "id": -100123, "type": "group", "title": "test group" },
+            "from": { "id": 42, "is_bot": true, "first_name": "HelperBot" },
+            "text": text
+        }))
+        .expect("test message should deserialize")
+    }
+
+    #[tokio::test]
+    async fn identical_sends_within_the_window_collapse_to_one() {
+        let guard = DuplicateSendGuard::new(Duration::from_secs(30));
+        let sends = AtomicUsize::new(0);
+        let send = |text: &'static str| {
+            let id = sends.fetch_add(1, Ordering::SeqCst) as i32 + 100;
+            async move { Ok(sent_message(id, text)) }
+        };
+        let reply_to = Some(MessageId(7));
+
+        let first = guard
+            .send_once(-100123, "Processing...", reply_to, || send("Processing..."))
+            .await
+            .unwrap();
+        let second = guard
+            .send_once(-100123, "Processing...", reply_to, || send("Processing..."))
+            .await
+            .unwrap();
+        assert_eq!(sends.load(Ordering::SeqCst), 1);
+        assert_eq!(first.id, second.id);
+
+        guard
+            .send_once(-100123, "Processing...", Some(MessageId(8)), || {
+                send("Processing...")
+            })
+            .await
+            .unwrap();
+        guard
+            .send_once(-100456, "Processing...", reply_to, || send("Processing..."))
+            .await
+            .unwrap();
+        assert_eq!(sends.load(Ordering::SeqCst), 3);
+
+        let disabled = DuplicateSendGuard::new(Duration::ZERO);
+        for _ in 0..2 {
+            disabled
+                .send_once(-100123, "again", None, || send("again"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(sends.load(Ordering::SeqCst), 5);
+    }
+}
@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use teloxide::prelude::*;
-use teloxide::types::ChatAction;
+use teloxide::types::{ChatAction, ChatKind, PublicChatKind};
 use tokio::task::JoinHandle;
 use tracing::warn;
 
@@ -19,6 +19,43 @@ impl Drop for ChatActionHeartbeat {
     }
 }
 
+/// What a long-running command is doing while its chat action is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStage {
+    /// Waiting on a text model.
+    Thinking,
+    /// Rendering an image, video, or song.
+    Generating,
+    /// Sending the finished file.
+    Uploading,
+}
+
+/// The chat action Telegram should show for `command` at `stage`, so video
+/// generation shows "recording video" rather than "typing".
+pub fn chat_action_for(command: &str, stage: CommandStage) -> ChatAction {
+    match (command, stage) {
+        (_, CommandStage::Thinking) => ChatAction::Typing,
+        ("vid", CommandStage::Generating) => ChatAction::RecordVideo,
+        ("vid", CommandStage::Uploading) => ChatAction::UploadVideo,
+        ("mysong", CommandStage::Generating) => ChatAction::RecordVoice,
+        ("img" | "image" | "img2" | "paintme" | "portraitme" | "random", _) => {
+            ChatAction::UploadPhoto
+        }
+        (_, CommandStage::Generating) => ChatAction::Typing,
+        (_, CommandStage::Uploading) => ChatAction::UploadDocument,
+    }
+}
+
+/// Shows the chat action for `command` at `stage` until the heartbeat drops.
+pub fn start_command_chat_action(
+    bot: Bot,
+    chat_id: ChatId,
+    command: &str,
+    stage: CommandStage,
+) -> ChatActionHeartbeat {
+    start_chat_action_heartbeat(bot, chat_id, chat_action_for(command, stage))
+}
+
 pub fn start_chat_action_heartbeat(
     bot: Bot,
     chat_id: ChatId,
@@ -38,6 +75,30 @@ pub fn start_chat_action_heartbeat(
     }
 }
 
+/// Where a message was sent, as far as chat-history semantics are concerned.
+/// Stored history is keyed by `chat_id` either way; in a private chat that
+/// history is just the conversation between one user and the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatScope {
+    Private,
+    Group,
+    Channel,
+}
+
+pub fn chat_scope(kind: &ChatKind) -> ChatScope {
+    match kind {
+        ChatKind::Private(_) => ChatScope::Private,
+        ChatKind::Public(public) => match public.kind {
+            PublicChatKind::Group | PublicChatKind::Supergroup(_) => ChatScope::Group,
+            PublicChatKind::Channel(_) => ChatScope::Channel,
+        },
+    }
+}
+
+pub fn is_group_chat(message: &Message) -> bool {
+    chat_scope(&message.chat.kind) == ChatScope::Group
+}
+
 pub fn normalize_supergroup_chat_id_for_link(chat_id: i64) -> Option<String> {
     let raw = chat_id.to_string();
     if let Some(normalized) = raw.strip_prefix("-100") {
@@ -73,6 +134,75 @@ pub fn build_message_link(chat_id: i64, message_id: i64) -> Option<String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn chat_action_matches_command_and_stage() {
+        assert_eq!(
+            chat_action_for("vid", CommandStage::Generating),
+            ChatAction::RecordVideo
+        );
+        assert_eq!(
+            chat_action_for("vid", CommandStage::Uploading),
+            ChatAction::UploadVideo
+        );
+        assert_eq!(
+            chat_action_for("vid", CommandStage::Thinking),
+            ChatAction::Typing
+        );
+        assert_eq!(
+            chat_action_for("img", CommandStage::Generating),
+            ChatAction::UploadPhoto
+        );
+        assert_eq!(
+            chat_action_for("paintme", CommandStage::Thinking),
+            ChatAction::Typing
+        );
+        assert_eq!(
+            chat_action_for("mysong", CommandStage::Generating),
+            ChatAction::RecordVoice
+        );
+        assert_eq!(
+            chat_action_for("mysong", CommandStage::Uploading),
+            ChatAction::UploadDocument
+        );
+        assert_eq!(
+            chat_action_for("q", CommandStage::Thinking),
+            ChatAction::Typing
+        );
+    }
+
+    #[test]
+    fn chat_scope_distinguishes_private_group_and_channel_kinds() {
+        use teloxide::types::{ChatPrivate, ChatPublic, PublicChatChannel, PublicChatSupergroup};
+
+        let private = ChatKind::Private(ChatPrivate {
+            username: Some("alice".to_string()),
+            first_name: Some("Alice".to_string()),
+            last_name: None,
+        });
+        let public = |kind| {
+            ChatKind::Public(ChatPublic {
+                title: Some("Test".to_string()),
+                kind,
+            })
+        };
+
+        assert_eq!(chat_scope(&private), ChatScope::Private);
+        assert_eq!(chat_scope(&public(PublicChatKind::Group)), ChatScope::Group);
+        assert_eq!(
+            chat_scope(&public(PublicChatKind::Supergroup(PublicChatSupergroup {
+                username: None,
+                is_forum: false,
+            }))),
+            ChatScope::Group
+        );
+        assert_eq!(
+            chat_scope(&public(PublicChatKind::Channel(PublicChatChannel {
+                username: None
+            }))),
+            ChatScope::Channel
+        );
+    }
+
     #[test]
     fn normalizes_supergroup_chat_ids_for_links() {
         assert_eq!(
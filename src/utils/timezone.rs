@@ -0,0 +1,63 @@
+//! Per-chat display timezone.
+//!
+//! `/timezone` stores an IANA zone name in `chat_settings.timezone`.
+//! Timestamps in chat history fed to the LLM and in reports such as `/whois`
+//! are rendered in the chat's zone; chats without one stay on UTC.
+
+use chrono::{DateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::db::models::ChatSettingsRow;
+
+/// Parses an IANA zone name such as `Asia/Shanghai`; `UTC` is accepted too.
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    name.trim().parse::<Tz>().ok()
+}
+
+/// The chat's `/timezone`, or UTC when unset or no longer a known zone.
+pub fn chat_timezone(settings: &ChatSettingsRow) -> Tz {
+    settings
+        .timezone
+        .as_deref()
+        .and_then(parse_timezone)
+        .unwrap_or(Tz::UTC)
+}
+
+pub fn format_in_timezone(value: DateTime<Utc>, timezone: Tz, format: &str) -> String {
+    value.with_timezone(&timezone).format(format).to_string()
+}
+
+/// Minutes the zone is ahead of UTC at `at`.
+pub fn utc_offset_minutes(timezone: Tz, at: DateTime<Utc>) -> i32 {
+    timezone
+        .offset_from_utc_datetime(&at.naive_utc())
+        .fix()
+        .local_minus_utc()
+        / 60
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utc_timestamp_renders_in_the_chat_timezone() {
+        let value = DateTime::parse_from_rfc3339("2026-03-01T23:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let shanghai = parse_timezone(" Asia/Shanghai ").unwrap();
+        assert_eq!(
+            format_in_timezone(value, shanghai, "%Y-%m-%d %H:%M %Z"),
+            "2026-03-02 07:30 CST"
+        );
+        assert_eq!(utc_offset_minutes(shanghai, value), 480);
+        assert_eq!(parse_timezone("Mars/Olympus"), None);
+
+        let mut settings = ChatSettingsRow::new(-4242);
+        assert_eq!(chat_timezone(&settings), Tz::UTC);
+        settings.timezone = Some("Asia/Shanghai".to_string());
+        assert_eq!(chat_timezone(&settings), shanghai);
+        settings.timezone = Some("Mars/Olympus".to_string());
+        assert_eq!(chat_timezone(&settings), Tz::UTC);
+    }
+}